@@ -0,0 +1,43 @@
+//! Measure the microphone's noise floor - run with:
+//! cargo run --example calibrate -- [seconds]
+//!
+//! Records room tone from the configured microphone and prints the
+//! silence threshold it suggests. Stay quiet while it runs; pass `--save`
+//! after the duration to write the threshold to config.toml.
+
+use std::time::Duration;
+
+use doubao_voice_input::audio::AudioCapture;
+use doubao_voice_input::AppConfig;
+
+fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter("doubao_voice_input=info")
+        .init();
+
+    let mut args = std::env::args().skip(1);
+    let seconds: f32 = match args.next() {
+        Some(seconds) => seconds.parse()?,
+        None => 3.0,
+    };
+    let save = args.next().as_deref() == Some("--save");
+
+    let mut config = AppConfig::load_or_default()?;
+    println!("=== Calibrating for {:.1}s, please stay quiet ===", seconds);
+    let profile = AudioCapture::calibrate(&config.audio, Duration::from_secs_f32(seconds))?;
+
+    println!("Noise floor:  {:.1} dBFS", profile.rms_floor);
+    println!("Peak:         {:.1} dBFS", profile.peak);
+    println!(
+        "Recommended:  silence_threshold_db = {:.1} (currently {:.1})",
+        profile.recommended_threshold_db, config.audio.silence_threshold_db
+    );
+
+    if save {
+        config.audio.silence_threshold_db = profile.recommended_threshold_db;
+        config.save()?;
+        println!("Saved to config.toml");
+    }
+
+    Ok(())
+}
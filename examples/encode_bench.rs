@@ -0,0 +1,68 @@
+//! Compare allocations of the two Opus encode paths - run with:
+//! cargo run --release --example encode_bench
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use doubao_voice_input::audio::OpusEncoder;
+
+/// Counts every allocation made by the process
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// 10 seconds of 20ms frames
+const FRAMES: usize = 500;
+
+fn main() -> anyhow::Result<()> {
+    let mut encoder = OpusEncoder::new(16000, 1)?;
+    let frame_size = encoder.frame_size();
+
+    // A 440 Hz tone, as samples and as the little-endian bytes `encode` takes
+    let samples: Vec<i16> = (0..frame_size)
+        .map(|i| ((i as f32 * 440.0 * std::f32::consts::TAU / 16000.0).sin() * 8000.0) as i16)
+        .collect();
+    let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let started = Instant::now();
+    for _ in 0..FRAMES {
+        encoder.encode(&bytes)?;
+    }
+    report("encode(&[u8])", before, started);
+
+    let mut packet = Vec::new();
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let started = Instant::now();
+    for _ in 0..FRAMES {
+        encoder.encode_i16(&samples, &mut packet)?;
+    }
+    report("encode_i16(&[i16], &mut Vec<u8>)", before, started);
+
+    Ok(())
+}
+
+fn report(name: &str, allocations_before: u64, started: Instant) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations_before;
+    println!(
+        "{:<34} {:>5} allocations, {:>7.1} us/frame",
+        name,
+        allocations,
+        started.elapsed().as_secs_f64() * 1e6 / FRAMES as f64
+    );
+}
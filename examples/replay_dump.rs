@@ -0,0 +1,49 @@
+//! Replay a protocol capture - run with: cargo run --example replay_dump -- <file.bin>
+//!
+//! Re-parses every incoming frame of a capture written with
+//! `asr.debug_dump_dir` through `parse_response`, so decoding changes can be
+//! checked against real server traffic offline.
+
+use std::path::PathBuf;
+
+use doubao_voice_input::asr::{parse_response, read_dump, Direction, ResponseType};
+
+fn main() {
+    let Some(path) = std::env::args().nth(1).map(PathBuf::from) else {
+        eprintln!("Usage: cargo run --example replay_dump -- <capture.bin>");
+        std::process::exit(2);
+    };
+
+    let records = match read_dump(&path) {
+        Ok(records) => records,
+        Err(e) => {
+            eprintln!("Failed to read {:?}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+
+    println!("=== Replaying {:?} ({} frames) ===", path, records.len());
+
+    let mut outgoing = 0;
+    for (index, record) in records.iter().enumerate() {
+        if record.direction == Direction::Outgoing {
+            outgoing += 1;
+            continue;
+        }
+
+        let response = parse_response(&record.data);
+        match response.response_type {
+            ResponseType::Heartbeat => continue,
+            ResponseType::Error => println!(
+                "#{:<4} {:?} code={:?} {}",
+                index, response.response_type, response.error_code, response.error_msg
+            ),
+            _ => println!(
+                "#{:<4} {:?} {}",
+                index, response.response_type, response.text
+            ),
+        }
+    }
+
+    println!("=== Done ({} outgoing frames skipped) ===", outgoing);
+}
@@ -0,0 +1,43 @@
+//! Transcribe a recorded file - run with:
+//! cargo run --example transcribe_file -- <memo.wav> [speed]
+//!
+//! The file must be 16 kHz mono 16-bit PCM (WAV or raw). `speed` sends the
+//! audio faster than real time, e.g. `2` for double speed.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use doubao_voice_input::{AppConfig, AsrClient, CredentialStore};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter("doubao_voice_input=info")
+        .init();
+
+    let mut args = std::env::args().skip(1);
+    let Some(path) = args.next().map(PathBuf::from) else {
+        eprintln!("Usage: cargo run --example transcribe_file -- <memo.wav> [speed]");
+        std::process::exit(2);
+    };
+    let speed: f32 = match args.next() {
+        Some(speed) => speed.parse()?,
+        None => 1.0,
+    };
+
+    let config = AppConfig::load_or_default()?;
+    let credential_store = Arc::new(CredentialStore::new(&config)?);
+    let credentials = credential_store.ensure_credentials().await?;
+    let client = AsrClient::new(credentials, &config).with_credential_store(credential_store);
+
+    println!("=== Transcribing {:?} at {}x ===", path, speed);
+    match client.transcribe_file_with_speed(&path, speed).await {
+        Ok(text) => println!("{}", text),
+        Err(e) => {
+            eprintln!("Transcription failed: {} ({})", e, e.user_hint());
+            std::process::exit(1);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,40 @@
+//! ASR Backend Abstraction
+//!
+//! The interface VoiceController drives, so the pipeline can run against
+//! the Doubao client, a mock, or another engine.
+
+use futures_util::future::BoxFuture;
+use tokio::sync::mpsc;
+
+use super::client::AsrClient;
+use super::error::AsrResult;
+use super::session::SessionHandle;
+use crate::data::AppConfig;
+
+/// A speech recognition engine fed with encoded Opus frames
+pub trait AsrBackend: Send + Sync {
+    /// Start a session consuming `audio_rx`
+    ///
+    /// Results are read from the returned handle, which also finishes or
+    /// cancels the session.
+    fn start_session(
+        &self,
+        audio_rx: mpsc::Receiver<Vec<u8>>,
+    ) -> BoxFuture<'_, AsrResult<SessionHandle>>;
+
+    /// Apply configuration changes for subsequent sessions
+    fn update_config(&self, _config: &AppConfig) {}
+}
+
+impl AsrBackend for AsrClient {
+    fn start_session(
+        &self,
+        audio_rx: mpsc::Receiver<Vec<u8>>,
+    ) -> BoxFuture<'_, AsrResult<SessionHandle>> {
+        Box::pin(self.start_realtime(audio_rx))
+    }
+
+    fn update_config(&self, config: &AppConfig) {
+        AsrClient::update_config(self, config);
+    }
+}
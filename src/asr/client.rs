@@ -2,189 +2,1166 @@
 //!
 //! Handles the WebSocket connection to the Doubao ASR server.
 
-use anyhow::{anyhow, Result};
-use futures_util::{SinkExt, StreamExt};
-use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::sync::mpsc;
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{FutureExt, SinkExt, StreamExt};
+use rand::Rng;
+use std::future::Future;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{client_async_tls, connect_async, MaybeTlsStream, WebSocketStream};
 use uuid::Uuid;
 
 use super::constants::*;
 use super::device::DeviceCredentials;
+use super::dump::{dump_dir, FrameDump};
+use super::error::{AsrError, AsrResult};
+use super::error_codes::{describe_error_code, ErrorCategory};
+use super::frame_queue::FrameQueue;
 use super::proto::FrameState;
 use super::protocol::{
     build_finish_session, build_start_session, build_start_task, build_task_request,
     parse_response, AsrResponse, ResponseType, SessionConfig,
 };
+use super::proxy::{connect_via_proxy, resolve_proxy_url};
+use super::session::{FramePacing, SessionEnd, SessionHandle};
+use super::stats::SessionMetrics;
+use crate::audio::{read_pcm16_mono, OpusEncoder};
+use crate::data::{AppConfig, CredentialStore, EncoderConfig};
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+type WsWrite = SplitSink<WsStream, Message>;
+type WsRead = SplitStream<WsStream>;
+
+/// How long to wait for TaskStarted/SessionStarted before giving up
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Base delay between handshake attempts (doubled on every retry)
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(300);
+
+/// ... up to this many times (9.6s), however many retries are configured
+const RETRY_MAX_DOUBLINGS: u32 = 5;
+
+/// A session failing this soon after SessionStarted is retried once
+const RETRY_WINDOW: Duration = Duration::from_secs(1);
+
+/// ... and only while fewer than this many frames have been sent
+const RETRY_MAX_FRAMES: u64 = 10;
+
+/// Most frames kept for replaying on a retried session
+const RETRY_REPLAY_LIMIT: usize = 250;
+
+/// How often a kept-open connection is checked for messages from the server
+const PARKED_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Packets this small are Opus DTX "no data" frames
+const DTX_FRAME_MAX: usize = 2;
+
+/// An ASR connection whose task and session have been started
+struct Connection {
+    write: WsWrite,
+    read: WsRead,
+    request_id: String,
+    token: String,
+    /// Results that arrived while waiting for the handshake to complete
+    early_responses: Vec<AsrResponse>,
+    /// Frame capture, when `debug_dump_dir` is set
+    dump: Option<Arc<FrameDump>>,
+}
+
+/// The write side of a running session, as used by the sender task
+struct SessionLink {
+    write: WsWrite,
+    request_id: String,
+    token: String,
+    dump: Option<Arc<FrameDump>>,
+}
+
+impl SessionLink {
+    /// Send one audio frame, returning false if the socket is gone
+    async fn send_audio(&mut self, frame: Vec<u8>, state: FrameState, timestamp_ms: u64) -> bool {
+        let msg = build_task_request(&self.request_id, frame, state, timestamp_ms);
+        if let Some(dump) = &self.dump {
+            dump.outgoing(&msg);
+        }
+        self.write.send(Message::Binary(msg)).await.is_ok()
+    }
+
+    /// Resend frames on a retried session; returns how many went out
+    async fn replay(
+        &mut self,
+        frames: Vec<Vec<u8>>,
+        start_time: u64,
+        frame_ms: u64,
+        metrics: &SessionMetrics,
+    ) -> Option<u64> {
+        tracing::info!("Replaying {} audio frame(s) on the retried session", frames.len());
+        let mut sent = 0u64;
+        for frame in frames {
+            let state = frame_state(sent);
+            let len = frame.len();
+            if !self
+                .send_audio(frame, state, start_time + sent * frame_ms)
+                .await
+            {
+                return None;
+            }
+            metrics.record_frame(len, false);
+            sent += 1;
+        }
+        Some(sent)
+    }
+}
+
+/// Tags outgoing audio First, Middle... and Last
+///
+/// The newest frame is held back so it can be tagged Last on close.
+#[derive(Default)]
+struct FrameSequence {
+    pending: Option<Vec<u8>>,
+}
+
+impl FrameSequence {
+    /// Take `frame`, returning the one before it to send as frame `index`
+    fn push(&mut self, frame: Vec<u8>, index: u64) -> Option<(Vec<u8>, FrameState)> {
+        let previous = self.pending.replace(frame)?;
+        Some((previous, frame_state(index)))
+    }
+
+    /// Frames that end a session after `sent` frames went out
+    ///
+    /// The session still opens with First when at most one frame was
+    /// captured; a placeholder stands in for missing audio.
+    fn finish(self, sent: u64) -> Vec<(Vec<u8>, FrameState)> {
+        let placeholder = || vec![0u8; 100];
+        let mut last = self.pending;
+        let mut frames = Vec::new();
+        if sent == 0 {
+            frames.push((last.take().unwrap_or_else(placeholder), FrameState::First));
+        }
+        frames.push((last.unwrap_or_else(placeholder), FrameState::Last));
+        frames
+    }
+}
+
+/// State of frame `index` while more audio follows
+fn frame_state(index: u64) -> FrameState {
+    // Pre-roll audio is queued like any other, so it opens with First
+    if index == 0 {
+        FrameState::First
+    } else {
+        FrameState::Middle
+    }
+}
 
 /// ASR Client for real-time speech recognition
 pub struct AsrClient {
-    credentials: DeviceCredentials,
+    credentials: RwLock<DeviceCredentials>,
+    credential_store: Option<Arc<CredentialStore>>,
+    config: RwLock<AppConfig>,
+    /// Connection kept open after a session when `persistent_connection` is set
+    parked: Arc<Mutex<Option<Connection>>>,
 }
 
 impl AsrClient {
     /// Create a new ASR client with credentials
-    pub fn new(credentials: DeviceCredentials) -> Self {
-        Self { credentials }
+    pub fn new(credentials: DeviceCredentials, config: &AppConfig) -> Self {
+        Self {
+            credentials: RwLock::new(credentials),
+            credential_store: None,
+            config: RwLock::new(config.clone()),
+            parked: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Use a credential store to refresh the token when the server rejects it
+    pub fn with_credential_store(mut self, store: Arc<CredentialStore>) -> Self {
+        self.credential_store = Some(store);
+        self
+    }
+
+    /// Replace the configuration used for subsequent sessions
+    pub fn update_config(&self, config: &AppConfig) {
+        *self.config.write().unwrap() = config.clone();
     }
 
     /// Get WebSocket URL with parameters
-    fn ws_url(&self) -> String {
-        format!(
-            "{}?aid={}&device_id={}",
-            WEBSOCKET_URL, AID, self.credentials.device_id
-        )
+    fn ws_url(endpoint: &str, device_id: &str) -> String {
+        format!("{}?aid={}&device_id={}", endpoint, AID, device_id)
     }
 
     /// Start real-time ASR session
     ///
-    /// Returns a receiver for ASR responses
+    /// Returns a handle for reading results and finishing the session
     pub async fn start_realtime(
         &self,
-        mut audio_rx: mpsc::Receiver<Vec<u8>>,
-    ) -> Result<mpsc::Receiver<AsrResponse>> {
-        let url = self.ws_url();
-        let request_id = Uuid::new_v4().to_string();
-        let token = self.credentials.token.clone();
-        let device_id = self.credentials.device_id.clone();
+        audio_rx: mpsc::Receiver<Vec<u8>>,
+    ) -> AsrResult<SessionHandle> {
+        self.start_realtime_with_pacing(audio_rx, FramePacing::Immediate)
+            .await
+    }
 
-        // Build request with headers
-        let request = tokio_tungstenite::tungstenite::http::Request::builder()
-            .uri(&url)
-            .header("User-Agent", USER_AGENT)
-            .header("proto-version", "v2")
-            .header("x-custom-keepalive", "true")
-            .header("Host", "frontier-audio-ime-ws.doubao.com")
-            .header("Connection", "Upgrade")
-            .header("Upgrade", "websocket")
-            .header("Sec-WebSocket-Version", "13")
-            .header("Sec-WebSocket-Key", tokio_tungstenite::tungstenite::handshake::client::generate_key())
-            .body(())?;
+    /// Start real-time ASR session, spacing frames out as `pacing` requires
+    ///
+    /// Use `FramePacing::RealTime` for sources that produce audio faster than
+    /// real time, such as files; the server may reject bursts.
+    pub async fn start_realtime_with_pacing(
+        &self,
+        mut audio_rx: mpsc::Receiver<Vec<u8>>,
+        pacing: FramePacing,
+    ) -> AsrResult<SessionHandle> {
+        if let FramePacing::RealTime { speed } = pacing {
+            if !speed.is_finite() || speed <= 0.0 {
+                return Err(AsrError::InvalidConfig(format!("invalid pacing speed {}", speed)));
+            }
+        }
 
-        tracing::info!("Connecting to ASR WebSocket: {}", url);
-        let (ws_stream, _) = connect_async(request).await?;
-        tracing::info!("WebSocket connected successfully");
-        let (mut write, mut read) = ws_stream.split();
+        // Audio keeps flowing while we connect; hold it until the session is up
+        let mut buffered_audio = Vec::new();
+        let resumed = buffer_audio_while(self.resume_parked(), &mut audio_rx, &mut buffered_audio).await;
+        let connection = match resumed {
+            Some(connection) => connection,
+            None => {
+                self.connect_with_retry(&mut audio_rx, &mut buffered_audio)
+                    .await?
+            }
+        };
+        let Connection {
+            write,
+            mut read,
+            mut request_id,
+            mut token,
+            early_responses,
+            dump,
+        } = connection;
 
         // Create response channel
         let (result_tx, result_rx) = mpsc::channel::<AsrResponse>(100);
 
-        // Clone values for tasks
-        let request_id_clone = request_id.clone();
-        let token_clone = token.clone();
-
-        // Send StartTask
-        tracing::debug!("Sending StartTask (request_id: {})", &request_id[..8]);
-        let start_task_msg = build_start_task(&request_id, &token);
-        write.send(Message::Binary(start_task_msg)).await?;
+        // Deliver anything that arrived alongside SessionStarted first
+        let early_final = early_responses
+            .iter()
+            .rev()
+            .find(|r| r.is_final)
+            .cloned();
+        for response in early_responses {
+            let _ = result_tx.try_send(response);
+        }
 
-        // Wait for TaskStarted response
-        if let Some(Ok(Message::Binary(data))) = read.next().await {
-            let response = parse_response(&data);
-            if response.response_type == ResponseType::Error {
-                return Err(anyhow!("StartTask failed: {}", response.error_msg));
-            }
-            tracing::debug!("TaskStarted received");
+        // Bounded queue between capture and the socket so a stalled
+        // connection drops old audio instead of piling up latency
+        let (queue_depth, max_session_secs, keep_alive, interim_interval, dtx_silence, frame_ms) = {
+            let config = self.config.read().unwrap();
+            let frame_ms = config.encoder.frame_duration_ms;
+            let keep_alive = config
+                .asr
+                .persistent_connection
+                .then(|| Duration::from_secs(config.asr.connection_idle_secs));
+            let interim_interval = (config.asr.max_interims_per_sec > 0)
+                .then(|| Duration::from_secs(1) / config.asr.max_interims_per_sec);
+            let dtx_silence = config
+                .asr
+                .dtx_frames
+                .eq_ignore_ascii_case("silence")
+                .then(|| encoded_silence(frame_ms))
+                .flatten();
+            (
+                config.asr.send_queue_depth,
+                config.asr.max_session_secs,
+                keep_alive,
+                interim_interval,
+                dtx_silence,
+                frame_ms,
+            )
+        };
+        let max_frames = max_session_secs * 1000 / frame_ms as u64;
+        let frame_queue = Arc::new(FrameQueue::new(queue_depth));
+        for frame in buffered_audio {
+            frame_queue.push(frame);
         }
 
-        // Send StartSession
-        tracing::debug!("Sending StartSession");
-        let session_config = SessionConfig::new(&device_id);
-        let start_session_msg = build_start_session(&request_id, &token, &session_config);
-        write.send(Message::Binary(start_session_msg)).await?;
+        let forward_queue = frame_queue.clone();
+        let sender_queue = frame_queue.clone();
 
-        // Wait for SessionStarted response
-        if let Some(Ok(Message::Binary(data))) = read.next().await {
-            let response = parse_response(&data);
-            if response.response_type == ResponseType::Error {
-                return Err(anyhow!("StartSession failed: {}", response.error_msg));
+        // Counters shared by the sender and reader tasks and the handle
+        let metrics = Arc::new(SessionMetrics::new(frame_queue.clone(), frame_ms));
+        let sender_metrics = metrics.clone();
+        let reader_metrics = metrics.clone();
+        let mut reader_dump = dump.clone();
+        let frame_interval = pacing.frame_interval(frame_ms);
+        // Frames sent in about a second, for progress logging
+        let frames_per_sec = (1000 / frame_ms).max(1) as u64;
+        let frame_ms = frame_ms as u64;
+        tokio::spawn(async move {
+            while let Some(frame) = audio_rx.recv().await {
+                // DTX frames still count as a full frame, so timestamps keep pace
+                let frame = fill_dtx_frame(frame, dtx_silence.as_ref());
+                // Paced sources wait for room so nothing is dropped
+                if frame_interval.is_some() {
+                    forward_queue.push_wait(frame).await;
+                } else {
+                    forward_queue.push(frame);
+                }
             }
-            tracing::debug!("SessionStarted received");
-        }
+            forward_queue.close();
+        });
 
         // Spawn audio sending task
         tracing::info!("Starting audio frame sender task");
-        tokio::spawn(async move {
+        let sender_results = result_tx.clone();
+        // The sender hands the write half back so the connection can be kept
+        let (write_tx, write_rx) = oneshot::channel::<WsWrite>();
+        // The reader hands over a new session if it retries an early failure
+        let (retry_tx, mut retry_rx) = mpsc::channel::<SessionLink>(1);
+        let mut link = SessionLink {
+            write,
+            request_id: request_id.clone(),
+            token: token.clone(),
+            dump: dump.clone(),
+        };
+        let sender_task = tokio::spawn(async move {
+            let mut healthy = true;
             let mut frame_index = 0u64;
-            let start_time = current_time_ms();
+            let mut start_time = current_time_ms();
+            let mut sequence = FrameSequence::default();
+            let mut paced_start: Option<tokio::time::Instant> = None;
+            // Frames sent so far, replayed if the session is retried
+            let mut replay: Option<Vec<Vec<u8>>> = Some(Vec::new());
+            let mut retry_open = true;
 
-            // Process audio frames until channel is closed
-            while let Some(opus_frame) = audio_rx.recv().await {
-                let frame_state = if frame_index == 0 {
-                    FrameState::First
-                } else {
-                    FrameState::Middle
+            // Send queued frames until the audio channel is closed
+            loop {
+                let next = tokio::select! {
+                    biased;
+                    retried = retry_rx.recv(), if retry_open => {
+                        retry_open = false;
+                        if let Some(retried) = retried {
+                            link = retried;
+                            start_time = current_time_ms();
+                            paced_start = None;
+                            match link.replay(replay.take().unwrap_or_default(), start_time, frame_ms, &sender_metrics).await {
+                                Some(sent) => frame_index = sent,
+                                None => {
+                                    healthy = false;
+                                    break;
+                                }
+                            }
+                        }
+                        replay = None;
+                        continue;
+                    }
+                    frame = sender_queue.pop() => frame,
+                };
+                let Some(opus_frame) = next else {
+                    break;
                 };
 
-                let timestamp_ms = start_time + frame_index * FRAME_DURATION_MS as u64;
-                let msg = build_task_request(
-                    &request_id_clone,
-                    opus_frame,
-                    frame_state,
-                    timestamp_ms,
-                );
+                // Stop taking audio once the session length cap is reached
+                if max_frames > 0 && frame_index + 1 >= max_frames {
+                    tracing::warn!(
+                        "Session reached the {}s limit, finishing automatically",
+                        max_session_secs
+                    );
+                    sender_queue.close();
+                    let _ = sender_results.try_send(AsrResponse {
+                        response_type: ResponseType::SessionTruncated,
+                        ..Default::default()
+                    });
+                    break;
+                }
 
-                if write.send(Message::Binary(msg)).await.is_err() {
+                let Some((frame, frame_state)) = sequence.push(opus_frame, frame_index) else {
+                    continue;
+                };
+
+                if let Some(interval) = frame_interval {
+                    let start = *paced_start.get_or_insert_with(tokio::time::Instant::now);
+                    tokio::time::sleep_until(start + interval * frame_index as u32).await;
+                }
+
+                if let Some(frames) = replay.as_mut() {
+                    if frames.len() < RETRY_REPLAY_LIMIT {
+                        frames.push(frame.clone());
+                    } else {
+                        replay = None;
+                    }
+                }
+
+                let timestamp_ms = start_time + frame_index * frame_ms;
+                let frame_len = frame.len();
+                if !link.send_audio(frame, frame_state, timestamp_ms).await {
                     tracing::warn!("Failed to send audio frame {}", frame_index);
+                    // The reader may still be retrying the session; it replays this frame
+                    if retry_open && replay.is_some() {
+                        retry_open = false;
+                        if let Some(retried) = retry_rx.recv().await {
+                            link = retried;
+                            start_time = current_time_ms();
+                            paced_start = None;
+                            let frames = replay.take().unwrap_or_default();
+                            if let Some(sent) = link.replay(frames, start_time, frame_ms, &sender_metrics).await {
+                                frame_index = sent;
+                                continue;
+                            }
+                        }
+                    }
+                    healthy = false;
                     break;
                 }
+                sender_metrics.record_frame(frame_len, false);
 
                 frame_index += 1;
                 
-                // Log every 50 frames (about 1 second)
-                if frame_index % 50 == 0 {
-                    tracing::info!("Sent {} audio frames ({:.1}s)", frame_index, frame_index as f64 * 0.02);
+                // Log about once a second
+                if frame_index % frames_per_sec == 0 {
+                    tracing::info!(
+                        "Sent {} audio frames ({:.1}s)",
+                        frame_index,
+                        (frame_index * frame_ms) as f64 / 1000.0
+                    );
                 }
             }
 
-            tracing::info!("Audio channel closed, sent {} total frames", frame_index);
+            tracing::info!(
+                "Audio channel closed, sent {} total frames ({} dropped)",
+                frame_index,
+                sender_queue.dropped()
+            );
 
-            // Send last frame to signal end
-            if frame_index > 0 {
-                let timestamp_ms = start_time + frame_index * FRAME_DURATION_MS as u64;
-                let silent_frame = vec![0u8; 100];
-                let msg = build_task_request(
-                    &request_id_clone,
-                    silent_frame,
-                    FrameState::Last,
-                    timestamp_ms,
-                );
-                let _ = write.send(Message::Binary(msg)).await;
+            // Send the held-back frame as Last
+            for (frame, frame_state) in sequence.finish(frame_index) {
+                let timestamp_ms = start_time + frame_index * frame_ms;
+                let frame_len = frame.len();
+                if !link.send_audio(frame, frame_state, timestamp_ms).await {
+                    healthy = false;
+                    break;
+                }
+                sender_metrics.record_frame(frame_len, frame_state == FrameState::Last);
+                frame_index += 1;
+            }
+
+            // Send FinishSession
+            let finish_msg = build_finish_session(&link.request_id, &link.token);
+            if let Some(dump) = &link.dump {
+                dump.outgoing(&finish_msg);
+            }
+            if link.write.send(Message::Binary(finish_msg)).await.is_err() {
+                healthy = false;
+            }
+            tracing::info!("Sent FinishSession");
 
-                // Send FinishSession
-                let finish_msg = build_finish_session(&request_id_clone, &token_clone);
-                let _ = write.send(Message::Binary(finish_msg)).await;
-                tracing::info!("Sent FinishSession");
+            if healthy {
+                let _ = write_tx.send(link.write);
             }
         });
 
         // Spawn response receiving task
         let result_tx_clone = result_tx.clone();
-        tokio::spawn(async move {
-            while let Some(Ok(msg)) = read.next().await {
+        let (end_tx, end_rx) = oneshot::channel();
+        let parked = self.parked.clone();
+        let retry_config = self.config.read().unwrap().clone();
+        let retry_credentials = self.credentials.read().unwrap().clone();
+        let reader_task = tokio::spawn(async move {
+            let session_started = Instant::now();
+            let mut retry_tx = Some(retry_tx);
+            let mut last_final = early_final;
+            let mut end = SessionEnd::Disconnected;
+            // Newest interim not yet delivered, and when it may go out
+            let mut held_interim: Option<AsrResponse> = None;
+            let mut next_interim_at = tokio::time::Instant::now();
+
+            loop {
+                let msg = tokio::select! {
+                    msg = read.next() => msg,
+                    _ = tokio::time::sleep_until(next_interim_at), if held_interim.is_some() => {
+                        if let Some(interim) = held_interim.take() {
+                            let _ = result_tx_clone.send(interim).await;
+                        }
+                        if let Some(interval) = interim_interval {
+                            next_interim_at = tokio::time::Instant::now() + interval;
+                        }
+                        continue;
+                    }
+                };
+                let Some(Ok(msg)) = msg else {
+                    break;
+                };
                 if let Message::Binary(data) = msg {
+                    if let Some(dump) = &reader_dump {
+                        dump.incoming(&data);
+                    }
                     let response = parse_response(&data);
 
+                    // Only failures right at the start are retried
+                    let retry_window = session_started.elapsed() < RETRY_WINDOW
+                        && reader_metrics.snapshot().frames_sent < RETRY_MAX_FRAMES;
+                    if !retry_window {
+                        retry_tx = None;
+                    }
+                    if response.response_type == ResponseType::Error {
+                        if let Some(tx) = retry_tx.take() {
+                            tracing::warn!(
+                                "Session failed {}ms after starting ({}), retrying once",
+                                session_started.elapsed().as_millis(),
+                                response.error_msg
+                            );
+                            match Self::handshake(&retry_config, &retry_credentials).await {
+                                Ok(connection) => {
+                                    tracing::info!("Session retried, replaying buffered audio");
+                                    reader_metrics.restart();
+                                    read = connection.read;
+                                    reader_dump = connection.dump.clone();
+                                    request_id = connection.request_id.clone();
+                                    token = connection.token.clone();
+                                    for early in connection.early_responses {
+                                        let _ = result_tx_clone.send(early).await;
+                                    }
+                                    let _ = tx
+                                        .send(SessionLink {
+                                            write: connection.write,
+                                            request_id: connection.request_id,
+                                            token: connection.token,
+                                            dump: connection.dump,
+                                        })
+                                        .await;
+                                    continue;
+                                }
+                                Err(e) => tracing::warn!("Session retry failed: {}", e),
+                            }
+                        }
+                    }
+
+                    // Deliver the newest interim before the session ends
+                    if matches!(
+                        response.response_type,
+                        ResponseType::Error | ResponseType::SessionFinished
+                    ) {
+                        if let Some(interim) = held_interim.take() {
+                            let _ = result_tx_clone.send(interim).await;
+                        }
+                    }
+
                     match response.response_type {
-                        ResponseType::Error | ResponseType::SessionFinished => {
+                        ResponseType::Error => {
+                            let mut response = response;
+                            // Only 20ms frames are known to work with the server
+                            if frame_ms != FRAME_DURATION_MS as u64 {
+                                response.error_msg = format!(
+                                    "{} (sent {}ms frames; if this persists, set encoder.frame_duration_ms = {})",
+                                    response.error_msg, frame_ms, FRAME_DURATION_MS
+                                );
+                            }
+                            end = SessionEnd::Failed(response.clone());
+                            let _ = result_tx_clone.send(response).await;
+                            break;
+                        }
+                        ResponseType::SessionFinished => {
+                            end = SessionEnd::Finished(last_final.take());
                             let _ = result_tx_clone.send(response).await;
                             break;
                         }
                         ResponseType::Heartbeat => {
-                            // Ignore heartbeats
+                            // Heartbeats only carry the server's receive counter
+                            reader_metrics.record_ack(response.packet_number);
+                            continue;
+                        }
+                        ResponseType::InterimResult if interim_interval.is_some() => {
+                            // Only the newest interim matters; hold it until
+                            // the rate limit allows another one
+                            reader_metrics.record_result(false);
+                            held_interim = Some(response);
                             continue;
                         }
                         _ => {
-                            if result_tx_clone.send(response).await.is_err() {
-                                break;
+                            match response.response_type {
+                                ResponseType::FinalResult | ResponseType::RefinedResult => {
+                                    reader_metrics.record_result(true);
+                                    last_final = Some(response.clone());
+                                    held_interim = None;
+                                }
+                                ResponseType::InterimResult | ResponseType::VadEnd => {
+                                    reader_metrics.record_result(false);
+                                    held_interim = None;
+                                }
+                                _ => {}
                             }
+                            // Keep reading even if nobody consumes results, so
+                            // finish() still sees SessionFinished
+                            let _ = result_tx_clone.send(response).await;
                         }
                     }
                 }
             }
+
+            let finished = matches!(end, SessionEnd::Finished(_));
+            let _ = end_tx.send(end);
+
+            // Keep the task running for the next utterance
+            let Some(idle) = keep_alive.filter(|_| finished) else {
+                return;
+            };
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, write_rx).await {
+                Ok(Ok(write)) => {
+                    tracing::debug!("Keeping ASR connection open for {}s", idle.as_secs());
+                    *parked.lock().unwrap() = Some(Connection {
+                        write,
+                        read,
+                        request_id: request_id.clone(),
+                        token,
+                        early_responses: Vec::new(),
+                        dump: reader_dump,
+                    });
+                    watch_parked(parked, request_id, idle);
+                }
+                _ => tracing::debug!("Sender did not finish cleanly, not keeping the connection"),
+            }
         });
 
-        Ok(result_rx)
+        Ok(SessionHandle::new(
+            result_rx,
+            frame_queue,
+            metrics,
+            end_rx,
+            sender_task,
+            reader_task,
+        ))
+    }
+
+    /// Transcribe a recorded 16 kHz mono WAV/PCM file at real-time pace
+    pub async fn transcribe_file(&self, path: &Path) -> AsrResult<String> {
+        self.transcribe_file_with_speed(path, 1.0).await
+    }
+
+    /// Transcribe a recorded file, sending audio `speed` times faster than real time
+    ///
+    /// Returns the concatenated final results, or the last interim text if the
+    /// server never finalized anything.
+    pub async fn transcribe_file_with_speed(&self, path: &Path, speed: f32) -> AsrResult<String> {
+        if !speed.is_finite() || speed <= 0.0 {
+            return Err(AsrError::InvalidConfig(format!("invalid pacing speed {}", speed)));
+        }
+        let pacing = FramePacing::RealTime { speed };
+
+        let samples = read_pcm16_mono(path).map_err(|e| AsrError::InvalidAudio(format!("{:#}", e)))?;
+        let encoder_config = self.config.read().unwrap().encoder.clone();
+        let mut encoder = OpusEncoder::with_config(SAMPLE_RATE, CHANNELS, &encoder_config)
+            .map_err(|e| AsrError::InvalidConfig(format!("{:#}", e)))?;
+        let frame_size = encoder.frame_size();
+
+        let mut frames = Vec::with_capacity(samples.len() / frame_size + 1);
+        let mut pcm = Vec::with_capacity(frame_size);
+        for chunk in samples.chunks(frame_size) {
+            // Pad the tail with silence to a full frame
+            pcm.clear();
+            pcm.extend_from_slice(chunk);
+            pcm.resize(frame_size, 0);
+            let mut frame = Vec::new();
+            encoder
+                .encode_i16(&pcm, &mut frame)
+                .map_err(|e| AsrError::InvalidAudio(format!("{:#}", e)))?;
+            frames.push(frame);
+        }
+
+        let audio_duration =
+            Duration::from_millis(frames.len() as u64 * encoder.frame_duration_ms() as u64);
+        tracing::info!(
+            "Transcribing {:?}: {} frames ({:.1}s) at {}x",
+            path,
+            frames.len(),
+            audio_duration.as_secs_f32(),
+            speed
+        );
+
+        let (audio_tx, audio_rx) = mpsc::channel(100);
+        let mut session = self.start_realtime_with_pacing(audio_rx, pacing).await?;
+        let mut results = session
+            .take_results()
+            .expect("results are taken once per session");
+
+        // The sender paces the upload; dropping the sender ends the session
+        tokio::spawn(async move {
+            for frame in frames {
+                if audio_tx.send(frame).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let collect = async {
+            let mut finals = String::new();
+            let mut last_interim = String::new();
+            // Where the latest streaming final starts, so a refinement can replace it
+            let mut refinable_from: Option<usize> = None;
+            while let Some(response) = results.recv().await {
+                match response.response_type {
+                    ResponseType::FinalResult => {
+                        refinable_from = Some(finals.len());
+                        finals.push_str(&response.text);
+                        last_interim.clear();
+                    }
+                    ResponseType::RefinedResult => {
+                        if let Some(start) = refinable_from.take() {
+                            finals.truncate(start);
+                        }
+                        finals.push_str(&response.text);
+                        last_interim.clear();
+                    }
+                    ResponseType::InterimResult | ResponseType::VadEnd => {
+                        last_interim = response.text;
+                    }
+                    ResponseType::SessionTruncated => {
+                        tracing::warn!("Audio longer than max_session_secs was cut off");
+                    }
+                    ResponseType::Error => {
+                        return Err(AsrError::ServerRejected {
+                            code: response.error_code,
+                            message: response.error_msg,
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            finals.push_str(&last_interim);
+            Ok(finals)
+        };
+
+        // Allow for the paced upload plus time for the server to finish
+        let deadline = audio_duration.div_f32(speed) + Duration::from_secs(10);
+        match tokio::time::timeout(deadline, collect).await {
+            Ok(result) => result,
+            Err(_) => {
+                session.cancel();
+                Err(AsrError::Timeout(format!(
+                    "no SessionFinished within {}s while transcribing",
+                    deadline.as_secs()
+                )))
+            }
+        }
+    }
+
+    /// Run the handshake, retrying transient failures with jittered backoff
+    async fn connect_with_retry(
+        &self,
+        audio_rx: &mut mpsc::Receiver<Vec<u8>>,
+        buffered_audio: &mut Vec<Vec<u8>>,
+    ) -> AsrResult<Connection> {
+        let max_retries = self.config.read().unwrap().asr.handshake_retries;
+        let mut attempt = 0;
+
+        loop {
+            match buffer_audio_while(self.connect(), audio_rx, buffered_audio).await {
+                Ok(connection) => return Ok(connection),
+                Err(e) if e.is_transient() && attempt < max_retries => {
+                    attempt += 1;
+                    let backoff = RETRY_BASE_DELAY * 2u32.pow((attempt - 1).min(RETRY_MAX_DOUBLINGS));
+                    let jitter = rand::thread_rng().gen_range(0..=backoff.as_millis() as u64 / 2);
+                    let delay = backoff + Duration::from_millis(jitter);
+                    tracing::warn!(
+                        "ASR handshake failed ({}), retry {}/{} in {}ms",
+                        e,
+                        attempt,
+                        max_retries,
+                        delay.as_millis()
+                    );
+                    buffer_audio_while(tokio::time::sleep(delay), audio_rx, buffered_audio).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Run the handshake once, refreshing credentials if the server rejects them
+    async fn connect(&self) -> AsrResult<Connection> {
+        // Let the store swap in a fresh token if the cached one aged out
+        if let Some(store) = &self.credential_store {
+            match store.ensure_credentials().await {
+                Ok(credentials) => *self.credentials.write().unwrap() = credentials,
+                Err(e) => tracing::warn!("Cannot check credentials: {:#}", e),
+            }
+        }
+        let credentials = self.credentials.read().unwrap().clone();
+        let config = self.config.read().unwrap().clone();
+        match Self::handshake(&config, &credentials).await {
+            Err(e) if e.is_auth() && self.credential_store.is_some() => {
+                tracing::warn!("{}, refreshing credentials and retrying once", e);
+                let store = self.credential_store.as_ref().unwrap();
+                let refreshed = store
+                    .refresh_credentials()
+                    .await
+                    .map_err(|e| AsrError::AuthFailed(format!("credential refresh failed: {:#}", e)))?;
+                *self.credentials.write().unwrap() = refreshed.clone();
+                Self::handshake(&config, &refreshed).await
+            }
+            result => result,
+        }
+    }
+
+    /// Check that the server accepts `credentials` by starting a task and
+    /// closing the connection right away
+    pub async fn check_credentials(
+        config: &AppConfig,
+        credentials: &DeviceCredentials,
+    ) -> AsrResult<()> {
+        let mut connection = Self::open_task(config, credentials).await?;
+        let _ = connection.write.send(Message::Close(None)).await;
+        Ok(())
+    }
+
+    /// Connect and run the StartTask/StartSession handshake
+    async fn handshake(
+        config: &AppConfig,
+        credentials: &DeviceCredentials,
+    ) -> AsrResult<Connection> {
+        let mut connection = Self::open_task(config, credentials).await?;
+        connection.start_session(&credentials.device_id, config).await?;
+        Ok(connection)
+    }
+
+    /// Connect and start a task, stopping short of StartSession
+    async fn open_task(
+        config: &AppConfig,
+        credentials: &DeviceCredentials,
+    ) -> AsrResult<Connection> {
+        let endpoint = config.asr.websocket_endpoint().to_string();
+        let proxy_url = resolve_proxy_url(
+            config.asr.proxy_url.as_deref(),
+            config.asr.disable_system_proxy,
+        );
+        let target = reqwest::Url::parse(&endpoint).map_err(|e| {
+            AsrError::InvalidConfig(format!("invalid ASR WebSocket URL {}: {}", endpoint, e))
+        })?;
+        let host = target
+            .host_str()
+            .ok_or_else(|| {
+                AsrError::InvalidConfig(format!("ASR WebSocket URL has no host: {}", endpoint))
+            })?
+            .to_string();
+        let port = target.port_or_known_default().unwrap_or(443);
+        let host_header = match target.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.clone(),
+        };
+        let url = Self::ws_url(&endpoint, &credentials.device_id);
+        let request_id = Uuid::new_v4().to_string();
+        let token = credentials.token.clone();
+
+        // Build request with headers
+        let request = tokio_tungstenite::tungstenite::http::Request::builder()
+            .uri(&url)
+            .header("User-Agent", USER_AGENT)
+            .header("proto-version", "v2")
+            .header("x-custom-keepalive", "true")
+            .header("Host", host_header)
+            .header("Connection", "Upgrade")
+            .header("Upgrade", "websocket")
+            .header("Sec-WebSocket-Version", "13")
+            .header("Sec-WebSocket-Key", tokio_tungstenite::tungstenite::handshake::client::generate_key())
+            .body(())
+            .map_err(|e| AsrError::Handshake(e.to_string()))?;
+
+        tracing::info!("Connecting to ASR WebSocket: {}", url);
+        let connected = match proxy_url {
+            Some(proxy_url) => {
+                let stream = connect_via_proxy(&proxy_url, &host, port).await?;
+                client_async_tls(request, stream).await
+            }
+            None => connect_async(request).await,
+        };
+        let (ws_stream, _) = connected?;
+        tracing::info!("WebSocket connected successfully");
+        let (mut write, mut read) = ws_stream.split();
+
+        let dump = dump_dir(config.asr.debug_dump_dir.as_deref()).and_then(|dir| {
+            FrameDump::create(&dir, &request_id)
+                .map_err(|e| tracing::warn!("Cannot create ASR capture in {:?}: {}", dir, e))
+                .ok()
+                .map(Arc::new)
+        });
+
+        // Send StartTask
+        tracing::debug!("Sending StartTask (request_id: {})", &request_id[..8]);
+        let start_task_msg = build_start_task(&request_id, &token);
+        if let Some(dump) = &dump {
+            dump.outgoing(&start_task_msg);
+        }
+        write.send(Message::Binary(start_task_msg)).await?;
+
+        // Wait for TaskStarted response
+        let mut early_responses = Vec::new();
+        wait_for_response(
+            &mut read,
+            ResponseType::TaskStarted,
+            &mut early_responses,
+            dump.as_deref(),
+        )
+        .await?;
+        tracing::debug!("TaskStarted received");
+
+        Ok(Connection {
+            write,
+            read,
+            request_id,
+            token,
+            early_responses,
+            dump,
+        })
+    }
+
+    /// Start a session on the kept-open connection, if there is one
+    ///
+    /// Any failure drops the connection; the caller then connects afresh.
+    async fn resume_parked(&self) -> Option<Connection> {
+        let mut connection = self.parked.lock().unwrap().take()?;
+        let config = self.config.read().unwrap().clone();
+        if !config.asr.persistent_connection {
+            return None;
+        }
+        // The server may have closed it since it was last checked
+        if let Err(reason) = poll_parked(&mut connection) {
+            tracing::info!("Kept-open connection is gone ({}), reconnecting", reason);
+            return None;
+        }
+        let device_id = self.credentials.read().unwrap().device_id.clone();
+
+        tracing::info!("Reusing open ASR connection");
+        match connection.start_session(&device_id, &config).await {
+            Ok(()) => Some(connection),
+            Err(e) => {
+                tracing::warn!("Kept-open connection failed ({}), reconnecting", e);
+                None
+            }
+        }
+    }
+}
+
+impl Connection {
+    /// Send StartSession on a started task and wait for SessionStarted
+    async fn start_session(&mut self, device_id: &str, config: &AppConfig) -> AsrResult<()> {
+        tracing::debug!("Sending StartSession");
+        let mut session_config = SessionConfig::new(device_id);
+        session_config.enable_punctuation = config.asr.enable_punctuation;
+        session_config.enable_speech_rejection = config.asr.enable_speech_rejection;
+        session_config.set_hotwords(&config.asr.hotwords);
+        if !SUPPORTED_LANGUAGES.contains(&config.general.language.as_str()) {
+            tracing::warn!(
+                "Unsupported recognition language '{}', sending it anyway",
+                config.general.language
+            );
+        }
+        session_config.language = config.general.language.clone();
+        tracing::info!("Recognition language: {}", session_config.language);
+        let start_session_msg = build_start_session(&self.request_id, &self.token, &session_config);
+        if let Some(dump) = &self.dump {
+            dump.outgoing(&start_session_msg);
+        }
+        self.write.send(Message::Binary(start_session_msg)).await?;
+
+        // Wait for SessionStarted response
+        wait_for_response(
+            &mut self.read,
+            ResponseType::SessionStarted,
+            &mut self.early_responses,
+            self.dump.as_deref(),
+        )
+        .await?;
+        tracing::debug!("SessionStarted received");
+        Ok(())
+    }
+}
+
+/// Watch a kept-open connection until it is resumed or evicted
+///
+/// Whatever the server sends meanwhile is read every
+/// `PARKED_POLL_INTERVAL`, which also answers its pings. A close or error
+/// evicts the connection, as does sitting unused for `idle`.
+fn watch_parked(parked: Arc<Mutex<Option<Connection>>>, request_id: String, idle: Duration) {
+    tokio::spawn(async move {
+        let deadline = tokio::time::Instant::now() + idle;
+        loop {
+            let next_poll = tokio::time::Instant::now() + PARKED_POLL_INTERVAL;
+            tokio::time::sleep_until(next_poll.min(deadline)).await;
+            let evicted = {
+                let mut slot = parked.lock().unwrap();
+                // Resumed, or replaced by a later session's connection
+                let Some(connection) = slot.as_mut().filter(|c| c.request_id == request_id) else {
+                    return;
+                };
+                match poll_parked(connection) {
+                    Err(reason) => {
+                        tracing::info!("Dropping kept-open ASR connection: {}", reason);
+                        slot.take();
+                        return;
+                    }
+                    Ok(()) if tokio::time::Instant::now() >= deadline => slot.take(),
+                    Ok(()) => None,
+                }
+            };
+            if let Some(connection) = evicted {
+                tracing::info!("Closing ASR connection after {}s idle", idle.as_secs());
+                if let Ok(mut stream) = connection.write.reunite(connection.read) {
+                    let _ = stream.close(None).await;
+                }
+                return;
+            }
+        }
+    });
+}
+
+/// Read what the server sent to a parked connection, without waiting
+///
+/// Fails with the reason once the server has closed the connection or
+/// reported an error on it.
+fn poll_parked(connection: &mut Connection) -> Result<(), String> {
+    while let Some(message) = connection.read.next().now_or_never() {
+        let data = match message {
+            None => return Err("connection closed".to_string()),
+            Some(Err(e)) => return Err(e.to_string()),
+            Some(Ok(Message::Close(frame))) => {
+                return Err(format!("server closed the connection: {:?}", frame))
+            }
+            Some(Ok(Message::Binary(data))) => data,
+            Some(Ok(_)) => continue,
+        };
+        if let Some(dump) = &connection.dump {
+            dump.incoming(&data);
+        }
+        let response = parse_response(&data);
+        match response.response_type {
+            ResponseType::Heartbeat => {}
+            ResponseType::Error => return Err(format!("server error: {}", response.error_msg)),
+            other => tracing::debug!("Ignoring {:?} on the kept-open connection", other),
+        }
+    }
+    Ok(())
+}
+
+/// Drive `future` to completion while collecting audio frames into `buffer`
+async fn buffer_audio_while<F: Future>(
+    future: F,
+    audio_rx: &mut mpsc::Receiver<Vec<u8>>,
+    buffer: &mut Vec<Vec<u8>>,
+) -> F::Output {
+    tokio::pin!(future);
+    let mut audio_open = true;
+
+    loop {
+        tokio::select! {
+            output = &mut future => return output,
+            frame = audio_rx.recv(), if audio_open => match frame {
+                Some(frame) => buffer.push(frame),
+                None => audio_open = false,
+            },
+        }
+    }
+}
+
+/// Wait for a lifecycle response, skipping heartbeats
+///
+/// Recognition results that arrive before the expected response are kept in
+/// `early_responses` so they can be delivered once the session is running.
+async fn wait_for_response(
+    read: &mut WsRead,
+    expected: ResponseType,
+    early_responses: &mut Vec<AsrResponse>,
+    dump: Option<&FrameDump>,
+) -> AsrResult<()> {
+    let deadline = tokio::time::Instant::now() + HANDSHAKE_TIMEOUT;
+
+    loop {
+        let message = match tokio::time::timeout_at(deadline, read.next()).await {
+            Err(_) => {
+                return Err(AsrError::Timeout(format!(
+                    "timed out waiting for {:?} after {}s",
+                    expected,
+                    HANDSHAKE_TIMEOUT.as_secs()
+                )))
+            }
+            Ok(None) => {
+                return Err(AsrError::Network(format!(
+                    "connection closed while waiting for {:?}",
+                    expected
+                )))
+            }
+            Ok(Some(Err(e))) => return Err(e.into()),
+            Ok(Some(Ok(message))) => message,
+        };
+
+        let data = match message {
+            Message::Binary(data) => data,
+            Message::Close(frame) => {
+                return Err(AsrError::Network(format!(
+                    "server closed the connection while waiting for {:?}: {:?}",
+                    expected, frame
+                )))
+            }
+            _ => continue,
+        };
+
+        if let Some(dump) = dump {
+            dump.incoming(&data);
+        }
+        let response = parse_response(&data);
+        match response.response_type {
+            ref t if *t == expected => return Ok(()),
+            ResponseType::Heartbeat => continue,
+            ResponseType::Error => {
+                let known = response.error_code.and_then(describe_error_code);
+                if known.is_some_and(|k| k.category == ErrorCategory::InvalidToken)
+                    || is_auth_failure(&response.error_msg)
+                {
+                    return Err(AsrError::AuthFailed(response.error_msg));
+                }
+                return Err(AsrError::ServerRejected {
+                    code: response.error_code,
+                    message: format!("waiting for {:?}: {}", expected, response.error_msg),
+                });
+            }
+            ResponseType::InterimResult
+            | ResponseType::FinalResult
+            | ResponseType::RefinedResult
+            | ResponseType::VadStart
+            | ResponseType::VadEnd => {
+                early_responses.push(response);
+            }
+            ref other => {
+                tracing::debug!("Ignoring {:?} while waiting for {:?}", other, expected);
+            }
+        }
+    }
+}
+
+/// Check whether a server error message indicates rejected credentials
+fn is_auth_failure(message: &str) -> bool {
+    let message = message.to_lowercase();
+    ["auth", "token", "app_key", "unauthorized", "forbidden", "鉴权", "认证"]
+        .iter()
+        .any(|keyword| message.contains(keyword))
+}
+
+/// One `frame_ms` frame of silence, sent in place of DTX packets
+fn encoded_silence(frame_ms: u32) -> Option<Vec<u8>> {
+    let config = EncoderConfig {
+        frame_duration_ms: frame_ms,
+        ..EncoderConfig::default()
+    };
+    let mut encoder = OpusEncoder::with_config(SAMPLE_RATE, CHANNELS, &config)
+        .map_err(|e| tracing::warn!("Cannot encode silence for DTX frames: {:#}", e))
+        .ok()?;
+    let silence = vec![0u8; encoder.frame_size() * 2];
+    encoder
+        .encode(&silence)
+        .map_err(|e| tracing::warn!("Cannot encode silence for DTX frames: {:#}", e))
+        .ok()
+}
+
+/// Swap a DTX packet for `silence`, when set
+fn fill_dtx_frame(frame: Vec<u8>, silence: Option<&Vec<u8>>) -> Vec<u8> {
+    match silence {
+        Some(silence) if frame.len() <= DTX_FRAME_MAX => silence.clone(),
+        _ => frame,
     }
 }
 
@@ -195,3 +1172,175 @@ fn current_time_ms() -> u64 {
         .unwrap()
         .as_millis() as u64
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::OpusDecoder;
+
+    /// 20ms frames of a 300Hz tone
+    fn tone_frames(count: usize) -> Vec<Vec<i16>> {
+        let samples = SAMPLE_RATE as usize / 50;
+        (0..count)
+            .map(|n| {
+                (0..samples)
+                    .map(|i| {
+                        let t = (n * samples + i) as f64 / SAMPLE_RATE as f64;
+                        ((2.0 * std::f64::consts::PI * 300.0 * t).sin() * 8000.0) as i16
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn rms(samples: &[i16]) -> f64 {
+        (samples.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / samples.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn dtx_silence_is_replaced_and_speech_resumes() {
+        let config = EncoderConfig {
+            application: "voip".to_string(),
+            dtx: true,
+            ..EncoderConfig::default()
+        };
+        let mut encoder = OpusEncoder::with_config(SAMPLE_RATE, CHANNELS, &config).unwrap();
+        let mut decoder = OpusDecoder::new(SAMPLE_RATE, CHANNELS).unwrap();
+        let placeholder = encoded_silence(20).unwrap();
+        assert!(placeholder.len() > DTX_FRAME_MAX);
+
+        let speech = tone_frames(25);
+        let silence = vec![vec![0i16; encoder.frame_size()]; 50];
+        let mut dtx_packets = 0;
+        for (index, frame) in speech.iter().chain(&silence).chain(&speech).enumerate() {
+            let mut packet = Vec::new();
+            encoder.encode_i16(frame, &mut packet).unwrap();
+            if packet.len() <= DTX_FRAME_MAX {
+                dtx_packets += 1;
+            }
+            let sent = fill_dtx_frame(packet, Some(&placeholder));
+            assert!(sent.len() > DTX_FRAME_MAX, "frame {} went out as a DTX packet", index);
+
+            let decoded = decoder.decode(&sent).unwrap();
+            assert_eq!(decoded.len(), encoder.frame_size(), "frame {}", index);
+            // Judge speech once the decoder has caught up with the onset
+            if index >= 80 {
+                assert!(rms(&decoded) > 2000.0, "frame {} lost the speech", index);
+            }
+        }
+        assert!(dtx_packets > 0, "DTX never kicked in");
+    }
+
+    /// Frame states and timestamps of the TaskRequests for `frames`, sent
+    /// the way the sender task sends them
+    fn task_requests(frames: &[Vec<u8>]) -> Vec<(Vec<u8>, FrameState, u64)> {
+        use crate::asr::proto::AsrRequest;
+        use prost::Message as _;
+
+        let mut sequence = FrameSequence::default();
+        let mut outgoing = Vec::new();
+        let mut index = 0u64;
+        for frame in frames {
+            if let Some(ready) = sequence.push(frame.clone(), index) {
+                outgoing.push(ready);
+                index += 1;
+            }
+        }
+        outgoing.extend(sequence.finish(index));
+        outgoing
+            .into_iter()
+            .enumerate()
+            .map(|(i, (frame, state))| {
+                let bytes = build_task_request("request", frame, state, 1000 + i as u64 * 20);
+                let request = AsrRequest::decode(bytes.as_slice()).unwrap();
+                let payload: serde_json::Value = serde_json::from_str(&request.payload).unwrap();
+                (
+                    request.audio_data,
+                    FrameState::try_from(request.frame_state).unwrap(),
+                    payload["timestamp_ms"].as_u64().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn three_frames_go_first_middle_last() {
+        let frames = vec![vec![1; 40], vec![2; 40], vec![3; 40]];
+        assert_eq!(
+            task_requests(&frames),
+            vec![
+                (vec![1; 40], FrameState::First, 1000),
+                (vec![2; 40], FrameState::Middle, 1020),
+                (vec![3; 40], FrameState::Last, 1040),
+            ]
+        );
+    }
+
+    #[test]
+    fn one_frame_still_opens_with_first() {
+        let requests = task_requests(&[vec![7; 40]]);
+        let states: Vec<FrameState> = requests.iter().map(|(_, state, _)| *state).collect();
+        assert_eq!(states, vec![FrameState::First, FrameState::Last]);
+        assert_eq!(requests[0].0, vec![7; 40]);
+    }
+
+    #[test]
+    fn no_audio_sends_placeholders() {
+        let states: Vec<FrameState> = task_requests(&[]).iter().map(|(_, state, _)| *state).collect();
+        assert_eq!(states, vec![FrameState::First, FrameState::Last]);
+    }
+
+    #[test]
+    fn dtx_frames_pass_through_when_forwarded() {
+        assert_eq!(fill_dtx_frame(vec![0x08], None), vec![0x08]);
+        let silence = vec![1, 2, 3];
+        assert_eq!(fill_dtx_frame(vec![9; 40], Some(&silence)), vec![9; 40]);
+    }
+
+    /// A connection to a local WebSocket server, parked in a fresh slot,
+    /// and the server's end of it
+    async fn parked_connection() -> (Arc<Mutex<Option<Connection>>>, WebSocketStream<TcpStream>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            tokio_tungstenite::accept_async(socket).await.unwrap()
+        });
+        let (stream, _) = connect_async(url).await.unwrap();
+        let (write, read) = stream.split();
+        let parked = Arc::new(Mutex::new(Some(Connection {
+            write,
+            read,
+            request_id: "parked".to_string(),
+            token: String::new(),
+            early_responses: Vec::new(),
+            dump: None,
+        })));
+        (parked, server.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn parked_connection_answers_pings_and_is_evicted_on_close() {
+        let (parked, mut server) = parked_connection().await;
+        watch_parked(parked.clone(), "parked".to_string(), Duration::from_secs(60));
+
+        server.send(Message::Ping(b"alive?".to_vec())).await.unwrap();
+        let pong = tokio::time::timeout(Duration::from_secs(5), server.next()).await.unwrap();
+        assert!(matches!(pong, Some(Ok(Message::Pong(data))) if data == b"alive?"));
+        assert!(parked.lock().unwrap().is_some());
+
+        server.close(None).await.unwrap();
+        tokio::time::sleep(PARKED_POLL_INTERVAL * 3).await;
+        assert!(parked.lock().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn parked_connection_expires_when_idle() {
+        let (parked, mut server) = parked_connection().await;
+        watch_parked(parked.clone(), "parked".to_string(), Duration::from_millis(100));
+
+        let closing = tokio::time::timeout(Duration::from_secs(5), server.next()).await.unwrap();
+        assert!(matches!(closing, Some(Ok(Message::Close(_)))));
+        assert!(parked.lock().unwrap().is_none());
+    }
+}
@@ -41,7 +41,11 @@ pub const USER_AGENT: &str = "com.bytedance.android.doubaoime/100102018 (Linux;
 /// Audio configuration
 pub const SAMPLE_RATE: u32 = 16000;
 pub const CHANNELS: u16 = 1;
+/// Default `encoder.frame_duration_ms`, and the only one known to work with the server
 pub const FRAME_DURATION_MS: u32 = 20;
 
+/// Recognition languages accepted in StartSession
+pub const SUPPORTED_LANGUAGES: &[&str] = &["zh-CN", "en-US"];
+
 /// Service name for ASR
 pub const SERVICE_NAME: &str = "ASR";
@@ -2,15 +2,32 @@
 //!
 //! Implements the device registration flow to obtain device_id and ASR token.
 
-use anyhow::{anyhow, Result};
-use reqwest::Client;
+use anyhow::{Context, Result};
+use reqwest::{Client, Proxy, StatusCode};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
 use super::constants::*;
+use super::error::{AsrError, AsrResult};
+use super::proxy::{resolve_proxy_url, ProxyError};
+use crate::data::{atomic_file, dpapi, AppConfig, DeviceConfig};
+
+/// Attempts for each registration or token request
+const HTTP_ATTEMPTS: u32 = 4;
+
+/// Base delay between attempts (doubled on every retry)
+const HTTP_RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Stop retrying once a request has been failing for this long
+const HTTP_RETRY_DEADLINE: Duration = Duration::from_secs(30);
+
+/// Longest response body quoted in an error message, in characters
+const ERROR_BODY_LIMIT: usize = 300;
 
 /// Device credentials for ASR authentication
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,6 +38,94 @@ pub struct DeviceCredentials {
     pub openudid: String,
     pub clientudid: String,
     pub token: String,
+    /// When the token was fetched (Unix seconds, 0 if unknown)
+    #[serde(default)]
+    pub obtained_at: u64,
+    /// When the token stops working, if the server said so (Unix seconds)
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// Device profile picked at registration when `device.randomize` is set
+    #[serde(default)]
+    pub device_profile: Option<DeviceProfile>,
+    /// The `settings` object returned with the token, with secrets such as
+    /// the token itself redacted; kept for diagnostics and change detection
+    #[serde(default)]
+    pub server_settings: Option<serde_json::Value>,
+}
+
+/// Hardware details reported when registering a device
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceProfile {
+    pub model: String,
+    pub brand: String,
+    pub resolution: String,
+    pub dpi: String,
+    pub os_version: String,
+    pub os_api: String,
+    pub rom: String,
+}
+
+impl Default for DeviceProfile {
+    fn default() -> Self {
+        Self {
+            model: DEVICE_MODEL.to_string(),
+            brand: DEVICE_BRAND.to_string(),
+            resolution: RESOLUTION.to_string(),
+            dpi: DPI.to_string(),
+            os_version: OS_VERSION.to_string(),
+            os_api: OS_API.to_string(),
+            rom: ROM.to_string(),
+        }
+    }
+}
+
+/// Real devices to pick from when `device.randomize` is set
+/// (model, brand, resolution, dpi, os_version, os_api, rom)
+const DEVICE_PROFILES: &[[&str; 7]] = &[
+    ["Pixel 7 Pro", "google", "1080*2400", "420", "16", "34", "UP1A.231005.007"],
+    ["Pixel 8", "google", "1080*2400", "420", "14", "34", "AP2A.240805.005"],
+    ["SM-S9180", "samsung", "1080*2340", "480", "14", "34", "UP1A.231005.007"],
+    ["23127PN0CC", "Xiaomi", "1440*3200", "560", "14", "34", "UKQ1.230804.001"],
+    ["PJZ110", "OnePlus", "1440*3168", "560", "14", "34", "UKQ1.230924.001"],
+    ["V2309A", "vivo", "1260*2800", "480", "14", "34", "UP1A.231005.007"],
+];
+
+impl DeviceProfile {
+    /// Pick one of the built-in device profiles
+    pub fn random() -> Self {
+        use rand::seq::SliceRandom;
+        let [model, brand, resolution, dpi, os_version, os_api, rom] = DEVICE_PROFILES
+            .choose(&mut rand::thread_rng())
+            .copied()
+            .unwrap_or(DEVICE_PROFILES[0]);
+        Self {
+            model: model.to_string(),
+            brand: brand.to_string(),
+            resolution: resolution.to_string(),
+            dpi: dpi.to_string(),
+            os_version: os_version.to_string(),
+            os_api: os_api.to_string(),
+            rom: rom.to_string(),
+        }
+    }
+
+    /// Replace fields set explicitly in the `[device]` config section
+    fn with_overrides(mut self, config: &DeviceConfig) -> Self {
+        let fields = [
+            (&mut self.model, &config.model),
+            (&mut self.brand, &config.brand),
+            (&mut self.resolution, &config.resolution),
+            (&mut self.dpi, &config.dpi),
+            (&mut self.os_version, &config.os_version),
+            (&mut self.rom, &config.rom),
+        ];
+        for (field, value) in fields {
+            if let Some(value) = value.as_deref().map(str::trim).filter(|v| !v.is_empty()) {
+                *field = value.to_string();
+            }
+        }
+        self
+    }
 }
 
 impl DeviceCredentials {
@@ -33,26 +138,146 @@ impl DeviceCredentials {
             openudid: generate_openudid(),
             clientudid: Uuid::new_v4().to_string(),
             token: String::new(),
+            obtained_at: 0,
+            expires_at: None,
+            device_profile: None,
+            server_settings: None,
         }
     }
 
+    /// Look up a value in the last settings response by JSON pointer,
+    /// e.g. `/asr_config`; secrets read as `<redacted>`
+    pub fn server_setting(&self, pointer: &str) -> Option<&serde_json::Value> {
+        self.server_settings.as_ref()?.pointer(pointer)
+    }
+
     /// Check if credentials are complete
     pub fn is_complete(&self) -> bool {
         !self.device_id.is_empty() && !self.token.is_empty()
     }
 
-    /// Save credentials to file
-    pub fn save(&self, path: &PathBuf) -> Result<()> {
+    /// Check if the token is past its expiry or older than `max_age`
+    ///
+    /// Tokens of unknown age count as stale. A zero `max_age` only honors
+    /// `expires_at`.
+    pub fn is_token_stale(&self, max_age: Duration) -> bool {
+        let now = current_time_ms() / 1000;
+        if self.expires_at.is_some_and(|expires_at| now >= expires_at) {
+            return true;
+        }
+        !max_age.is_zero()
+            && (self.obtained_at == 0 || now.saturating_sub(self.obtained_at) >= max_age.as_secs())
+    }
+}
+
+/// Profile that single-device credential files are migrated to
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Named device identities kept in one credentials file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CredentialProfiles {
+    profiles: BTreeMap<String, DeviceCredentials>,
+}
+
+/// File contents as written by this or an older version
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredCredentials {
+    Single(Box<DeviceCredentials>),
+    Profiles(CredentialProfiles),
+}
+
+impl CredentialProfiles {
+    /// Credentials of the profile called `name`
+    pub fn get(&self, name: &str) -> Option<&DeviceCredentials> {
+        self.profiles.get(name)
+    }
+
+    /// Add or replace a profile
+    pub fn insert(&mut self, name: &str, creds: DeviceCredentials) {
+        self.profiles.insert(name.to_string(), creds);
+    }
+
+    /// Remove a profile, returning its credentials
+    pub fn remove(&mut self, name: &str) -> Option<DeviceCredentials> {
+        self.profiles.remove(name)
+    }
+
+    /// Profile names in alphabetical order
+    pub fn names(&self) -> Vec<String> {
+        self.profiles.keys().cloned().collect()
+    }
+
+    /// Save profiles to file, encrypted for the current user on Windows
+    pub fn save(&self, path: &Path) -> Result<()> {
         let json = serde_json::to_string_pretty(self)?;
-        std::fs::write(path, json)?;
-        Ok(())
+        atomic_file::write(path, &dpapi::protect(json.as_bytes())?)
+    }
+
+    /// Load profiles from file
+    ///
+    /// Plaintext files and single-device files from older versions are
+    /// re-saved in the current format, keeping the original as `.bak`. A
+    /// corrupt file is restored from a leftover `.tmp` or `.bak` copy when
+    /// one is readable.
+    pub fn load(path: &Path) -> Result<Self> {
+        let (profiles, outdated) = match std::fs::read(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| Self::decode(&bytes))
+        {
+            Ok(loaded) => loaded,
+            Err(e) => return Self::recover(path).ok_or(e),
+        };
+
+        if outdated {
+            let backup = path.with_extension("json.bak");
+            match std::fs::copy(path, &backup).map_err(anyhow::Error::from).and_then(|_| profiles.save(path)) {
+                Ok(()) => tracing::info!("Upgraded credentials file, original kept at {:?}", backup),
+                Err(e) => tracing::warn!("Failed to upgrade credentials file: {:#}", e),
+            }
+        }
+        Ok(profiles)
+    }
+
+    /// Parse file contents, reporting whether they need re-saving
+    fn decode(bytes: &[u8]) -> Result<(Self, bool)> {
+        let plaintext = bytes
+            .iter()
+            .find(|b| !b.is_ascii_whitespace())
+            .is_some_and(|&b| b == b'{');
+        let stored: StoredCredentials = if plaintext {
+            serde_json::from_slice(bytes).context("credentials file is not valid JSON")?
+        } else {
+            let json = dpapi::unprotect(bytes).context(
+                "credentials file cannot be decrypted (corrupt, or saved by another user)",
+            )?;
+            serde_json::from_slice(&json).context("decrypted credentials are not valid JSON")?
+        };
+
+        // Only Windows can encrypt, so plaintext is current elsewhere
+        let outdated = plaintext && cfg!(target_os = "windows");
+        Ok(match stored {
+            StoredCredentials::Single(creds) => {
+                let mut profiles = Self::default();
+                profiles.insert(DEFAULT_PROFILE, *creds);
+                (profiles, true)
+            }
+            StoredCredentials::Profiles(profiles) => (profiles, outdated),
+        })
     }
 
-    /// Load credentials from file
-    pub fn load(path: &PathBuf) -> Result<Self> {
-        let json = std::fs::read_to_string(path)?;
-        let creds: DeviceCredentials = serde_json::from_str(&json)?;
-        Ok(creds)
+    /// Load the leftover copy of a corrupt credentials file and restore it
+    fn recover(path: &Path) -> Option<Self> {
+        atomic_file::recovery_candidates(path).into_iter().find_map(|candidate| {
+            let bytes = std::fs::read(&candidate).ok()?;
+            let (profiles, _) = Self::decode(&bytes).ok()?;
+            tracing::warn!("{:?} is corrupt, recovered credentials from {:?}", path, candidate);
+            if let Err(e) = profiles.save(path) {
+                tracing::warn!("Failed to restore {:?}: {:#}", path, e);
+            }
+            Some(profiles)
+        })
     }
 }
 
@@ -105,19 +330,41 @@ struct DeviceRegisterHeader {
     region: String,
     tz_name: String,
     tz_offset: i32,
-    sim_region: String,
-    carrier_region: String,
     cpu_abi: String,
-    build_serial: String,
-    not_request_sender: i32,
-    sig_hash: String,
-    google_aid: String,
-    mc: String,
-    serial_number: String,
+    // Optional fields, left out with `privacy.minimal_register`; keep
+    // MINIMAL_OMITTED_FIELDS in sync
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sim_region: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    carrier_region: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    build_serial: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    not_request_sender: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sig_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    google_aid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mc: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    serial_number: Option<String>,
 }
 
+/// Header fields omitted by a minimal registration
+const MINIMAL_OMITTED_FIELDS: &[&str] = &[
+    "sim_region",
+    "carrier_region",
+    "build_serial",
+    "not_request_sender",
+    "sig_hash",
+    "google_aid",
+    "mc",
+    "serial_number",
+];
+
 impl DeviceRegisterHeader {
-    fn new(cdid: &str, openudid: &str, clientudid: &str) -> Self {
+    fn new(cdid: &str, openudid: &str, clientudid: &str, profile: &DeviceProfile) -> Self {
         Self {
             device_id: 0,
             install_id: 0,
@@ -131,35 +378,47 @@ impl DeviceRegisterHeader {
             package: PACKAGE.to_string(),
             device_platform: DEVICE_PLATFORM.to_string(),
             os: OS.to_string(),
-            os_api: OS_API.to_string(),
-            os_version: OS_VERSION.to_string(),
-            device_type: DEVICE_TYPE.to_string(),
-            device_brand: DEVICE_BRAND.to_string(),
-            device_model: DEVICE_MODEL.to_string(),
-            resolution: RESOLUTION.to_string(),
-            dpi: DPI.to_string(),
+            os_api: profile.os_api.clone(),
+            os_version: profile.os_version.clone(),
+            device_type: profile.model.clone(),
+            device_brand: profile.brand.clone(),
+            device_model: profile.model.clone(),
+            resolution: profile.resolution.clone(),
+            dpi: profile.dpi.clone(),
             language: LANGUAGE.to_string(),
             timezone: TIMEZONE,
             access: ACCESS.to_string(),
-            rom: ROM.to_string(),
-            rom_version: ROM_VERSION.to_string(),
+            rom: profile.rom.clone(),
+            rom_version: profile.rom.clone(),
             openudid: openudid.to_string(),
             clientudid: clientudid.to_string(),
             cdid: cdid.to_string(),
             region: "CN".to_string(),
             tz_name: "Asia/Shanghai".to_string(),
             tz_offset: 28800,
-            sim_region: "cn".to_string(),
-            carrier_region: "cn".to_string(),
             cpu_abi: "arm64-v8a".to_string(),
-            build_serial: "unknown".to_string(),
-            not_request_sender: 0,
-            sig_hash: String::new(),
-            google_aid: String::new(),
-            mc: String::new(),
-            serial_number: String::new(),
+            sim_region: Some("cn".to_string()),
+            carrier_region: Some("cn".to_string()),
+            build_serial: Some("unknown".to_string()),
+            not_request_sender: Some(0),
+            sig_hash: Some(String::new()),
+            google_aid: Some(String::new()),
+            mc: Some(String::new()),
+            serial_number: Some(String::new()),
         }
     }
+
+    /// Drop the fields the app sends empty or made-up values for
+    fn strip_optional(&mut self) {
+        self.sim_region = None;
+        self.carrier_region = None;
+        self.build_serial = None;
+        self.not_request_sender = None;
+        self.sig_hash = None;
+        self.google_aid = None;
+        self.mc = None;
+        self.serial_number = None;
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -189,7 +448,8 @@ struct SettingsResponse {
 
 #[derive(Debug, Deserialize)]
 struct SettingsData {
-    settings: Settings,
+    /// Kept whole; only `asr_config.app_key` is needed today
+    settings: serde_json::Value,
 }
 
 #[derive(Debug, Deserialize)]
@@ -202,11 +462,303 @@ struct AsrConfig {
     app_key: String,
 }
 
-/// Register a new device and get device_id
-pub async fn register_device(creds: &mut DeviceCredentials) -> Result<()> {
-    let client = Client::new();
+/// HTTP client shared by the device registration and settings requests
+#[derive(Clone)]
+pub struct DeviceApi {
+    client: Client,
+    proxy: Option<String>,
+    connect_timeout: Duration,
+    request_timeout: Duration,
+    minimal_register: bool,
+    register_url: String,
+    settings_url: String,
+    device: DeviceConfig,
+}
+
+impl DeviceApi {
+    /// Build the HTTP client from the proxy settings in config
+    ///
+    /// An explicit `asr.proxy_url` wins over `HTTPS_PROXY`; with
+    /// `asr.disable_system_proxy` the environment is ignored entirely.
+    pub fn from_config(config: &AppConfig) -> AsrResult<Self> {
+        let proxy = resolve_proxy_url(
+            config.asr.proxy_url.as_deref(),
+            config.asr.disable_system_proxy,
+        );
+
+        // Start from a clean slate so only the proxy chosen above applies
+        let connect_timeout = Duration::from_secs(config.network.connect_timeout_secs.max(1));
+        let request_timeout = Duration::from_secs(config.network.request_timeout_secs.max(1));
+        let mut builder = Client::builder()
+            .no_proxy()
+            .connect_timeout(connect_timeout)
+            .timeout(request_timeout);
+        if let Some(ref url) = proxy {
+            builder = builder.proxy(Proxy::all(url.as_str()).map_err(|e| {
+                AsrError::InvalidConfig(format!("invalid proxy URL {}: {}", url, e))
+            })?);
+            tracing::info!("Device registration will use proxy {}", url);
+        }
 
-    let header = DeviceRegisterHeader::new(&creds.cdid, &creds.openudid, &creds.clientudid);
+        Ok(Self {
+            client: builder.build()?,
+            proxy,
+            connect_timeout,
+            request_timeout,
+            minimal_register: config.privacy.minimal_register,
+            register_url: config.asr.register_endpoint().to_string(),
+            settings_url: config.asr.settings_endpoint().to_string(),
+            device: config.device.clone(),
+        })
+    }
+
+    /// Describe a transport error, telling proxy failures apart from upstream ones
+    fn request_error(&self, action: &str, e: reqwest::Error) -> AsrError {
+        if e.is_timeout() {
+            return AsrError::Timeout(if e.is_connect() {
+                format!(
+                    "{} timed out after {}s while connecting",
+                    action,
+                    self.connect_timeout.as_secs()
+                )
+            } else {
+                format!("{} timed out after {}s", action, self.request_timeout.as_secs())
+            });
+        }
+        match self.proxy {
+            Some(ref proxy) if e.is_connect() => AsrError::Network(format!(
+                "{} failed: cannot connect through proxy {}: {}",
+                action, proxy, e
+            )),
+            _ => match AsrError::from(e) {
+                AsrError::Network(msg) => AsrError::Network(format!("{} failed: {}", action, msg)),
+                AsrError::Timeout(msg) => AsrError::Timeout(format!("{} failed: {}", action, msg)),
+                other => other,
+            },
+        }
+    }
+
+    /// Describe a non-success HTTP status, quoting what the server said
+    fn status_error(&self, action: &str, status: StatusCode, body: &str) -> AsrError {
+        let message = match summarize_body(body) {
+            summary if summary.is_empty() => {
+                format!("{} failed: server responded {}", action, status)
+            }
+            summary => format!("{} failed: server responded {}: {}", action, status, summary),
+        };
+        match self.proxy {
+            Some(ref proxy) if status == StatusCode::PROXY_AUTHENTICATION_REQUIRED => {
+                AsrError::Proxy(ProxyError::AuthRequired(proxy.clone()))
+            }
+            _ if matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => {
+                AsrError::AuthFailed(message)
+            }
+            _ => AsrError::ServerRejected {
+                code: Some(status.as_u16() as i32),
+                message,
+            },
+        }
+    }
+
+    /// Read a response, turning a non-success status into an error
+    async fn read_response(&self, action: &str, response: reqwest::Response) -> AsrResult<String> {
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| self.request_error(action, e))?;
+        if !status.is_success() {
+            return Err(self.status_error(action, status, &body));
+        }
+        Ok(body)
+    }
+}
+
+/// Parse a JSON response body, quoting it if it has an unexpected shape
+fn decode_body<T: DeserializeOwned>(action: &str, body: &str) -> AsrResult<T> {
+    serde_json::from_str(body).map_err(|e| {
+        AsrError::ProtocolDecode(format!(
+            "{} returned an unexpected response ({}): {}",
+            action,
+            e,
+            summarize_body(body)
+        ))
+    })
+}
+
+/// Shorten a response body for logs and errors, hiding anything token-like
+fn summarize_body(body: &str) -> String {
+    let body = body.trim();
+    let redacted = match serde_json::from_str::<serde_json::Value>(body) {
+        Ok(mut value) => {
+            redact_json(&mut value);
+            value.to_string()
+        }
+        Err(_) => redact_text(body),
+    };
+    match redacted.char_indices().nth(ERROR_BODY_LIMIT) {
+        Some((end, _)) => format!("{}...", &redacted[..end]),
+        None => redacted,
+    }
+}
+
+/// Replace the values of secret-looking JSON fields
+fn redact_json(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let key = key.to_ascii_lowercase();
+                let secret = ["token", "key", "secret", "ticket", "session"]
+                    .iter()
+                    .any(|name| key.contains(name));
+                if secret && value.is_string() {
+                    *value = serde_json::Value::String("<redacted>".to_string());
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_json),
+        serde_json::Value::String(text) => *text = redact_text(text),
+        _ => {}
+    }
+}
+
+/// Hide long opaque runs of characters, which are usually tokens
+fn redact_text(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut run = String::new();
+    let flush = |run: &mut String, result: &mut String| {
+        if run.len() >= 24 {
+            result.push_str("<redacted>");
+        } else {
+            result.push_str(run);
+        }
+        run.clear();
+    };
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '+' | '/' | '=' | '.') {
+            run.push(c);
+        } else {
+            flush(&mut run, &mut result);
+            result.push(c);
+        }
+    }
+    flush(&mut run, &mut result);
+    result
+}
+
+/// Run a registration request, retrying transient failures with jittered backoff
+///
+/// Network errors, timeouts, 5xx and 429 responses are retried; other
+/// rejections fail immediately. The final error notes how many attempts ran.
+async fn with_retry<T, F, Fut>(action: &str, mut request: F) -> AsrResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = AsrResult<T>>,
+{
+    use rand::Rng;
+
+    let deadline = Instant::now() + HTTP_RETRY_DEADLINE;
+    let mut attempt = 1;
+    loop {
+        let e = match request().await {
+            Ok(value) => return Ok(value),
+            Err(e) => e,
+        };
+
+        let retryable = match &e {
+            AsrError::Network(_) | AsrError::Timeout(_) => true,
+            AsrError::ServerRejected { code, .. } => matches!(code, None | Some(429) | Some(500..)),
+            _ => false,
+        };
+        let backoff = HTTP_RETRY_BASE_DELAY * 2u32.pow(attempt - 1);
+        let jitter = rand::thread_rng().gen_range(0..=backoff.as_millis() as u64 / 2);
+        let delay = backoff + Duration::from_millis(jitter);
+        if !retryable || attempt >= HTTP_ATTEMPTS || Instant::now() + delay > deadline {
+            return Err(after_attempts(e, attempt));
+        }
+
+        tracing::warn!(
+            "{} failed ({}), attempt {}/{}, retrying in {}ms",
+            action,
+            e,
+            attempt,
+            HTTP_ATTEMPTS,
+            delay.as_millis()
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Note the number of attempts in an error message
+fn after_attempts(e: AsrError, attempts: u32) -> AsrError {
+    if attempts <= 1 {
+        return e;
+    }
+    map_message(e, |message| format!("{} (after {} attempts)", message, attempts))
+}
+
+/// Name the fields a minimal registration left out, and any of them the
+/// server's reply mentions, so they can be restored one at a time
+fn note_omitted_fields(e: AsrError) -> AsrError {
+    let mentioned: Vec<&str> = MINIMAL_OMITTED_FIELDS
+        .iter()
+        .copied()
+        .filter(|field| e.to_string().contains(field))
+        .collect();
+    map_message(e, |message| {
+        if mentioned.is_empty() {
+            format!(
+                "{} (privacy.minimal_register omitted: {})",
+                message,
+                MINIMAL_OMITTED_FIELDS.join(", ")
+            )
+        } else {
+            format!(
+                "{} (privacy.minimal_register omitted {}, which the server mentioned)",
+                message,
+                mentioned.join(", ")
+            )
+        }
+    })
+}
+
+/// Rewrite the message of an error that carries one
+fn map_message(e: AsrError, note: impl FnOnce(String) -> String) -> AsrError {
+    match e {
+        AsrError::Network(message) => AsrError::Network(note(message)),
+        AsrError::Timeout(message) => AsrError::Timeout(note(message)),
+        AsrError::AuthFailed(message) => AsrError::AuthFailed(note(message)),
+        AsrError::ProtocolDecode(message) => AsrError::ProtocolDecode(note(message)),
+        AsrError::ServerRejected { code, message } => AsrError::ServerRejected {
+            code,
+            message: note(message),
+        },
+        other => other,
+    }
+}
+
+/// Register a new device and get device_id
+pub async fn register_device(api: &DeviceApi, creds: &mut DeviceCredentials) -> AsrResult<()> {
+    // A randomized profile is stored with the credentials so it stays stable
+    if creds.device_profile.is_none() && api.device.randomize {
+        let profile = DeviceProfile::random();
+        tracing::info!("Registering as {} {}", profile.brand, profile.model);
+        creds.device_profile = Some(profile);
+    }
+    let profile = creds
+        .device_profile
+        .clone()
+        .unwrap_or_default()
+        .with_overrides(&api.device);
+
+    let mut header =
+        DeviceRegisterHeader::new(&creds.cdid, &creds.openudid, &creds.clientudid, &profile);
+    if api.minimal_register {
+        header.strip_optional();
+    }
     let body = DeviceRegisterBody {
         magic_tag: "ss_app_log".to_string(),
         header,
@@ -227,35 +779,43 @@ pub async fn register_device(creds: &mut DeviceCredentials) -> Result<()> {
     params.insert("version_name", VERSION_NAME.to_string());
     params.insert("manifest_version_code", VERSION_CODE.to_string());
     params.insert("update_version_code", VERSION_CODE.to_string());
-    params.insert("resolution", RESOLUTION.to_string());
-    params.insert("dpi", DPI.to_string());
-    params.insert("device_type", DEVICE_TYPE.to_string());
-    params.insert("device_brand", DEVICE_BRAND.to_string());
+    params.insert("resolution", profile.resolution.clone());
+    params.insert("dpi", profile.dpi.clone());
+    params.insert("device_type", profile.model.clone());
+    params.insert("device_brand", profile.brand.clone());
     params.insert("language", LANGUAGE.to_string());
-    params.insert("os_api", OS_API.to_string());
-    params.insert("os_version", OS_VERSION.to_string());
+    params.insert("os_api", profile.os_api.clone());
+    params.insert("os_version", profile.os_version.clone());
     params.insert("ac", "wifi".to_string());
 
-    let response = client
-        .post(REGISTER_URL)
-        .header("User-Agent", USER_AGENT)
-        .query(&params)
-        .json(&body)
-        .send()
-        .await?;
-
-    if !response.status().is_success() {
-        return Err(anyhow!(
-            "Device registration failed: {}",
-            response.status()
-        ));
-    }
-
-    let result: DeviceRegisterResponse = response.json().await?;
-
-    if result.device_id == 0 {
-        return Err(anyhow!("Device registration returned invalid device_id"));
-    }
+    let (params, body) = (&params, &body);
+    let result = with_retry("Device registration", || async move {
+        let response = api
+            .client
+            .post(&api.register_url)
+            .header("User-Agent", USER_AGENT)
+            .query(params)
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| api.request_error("Device registration", e))?;
+
+        let text = api.read_response("Device registration", response).await?;
+        let result: DeviceRegisterResponse = decode_body("Device registration", &text)?;
+
+        if result.device_id == 0 {
+            return Err(AsrError::ServerRejected {
+                code: None,
+                message: format!(
+                    "Device registration returned invalid device_id: {}",
+                    summarize_body(&text)
+                ),
+            });
+        }
+        Ok(result)
+    })
+    .await
+    .map_err(|e| if api.minimal_register { note_omitted_fields(e) } else { e })?;
 
     creds.device_id = result.device_id.to_string();
     creds.install_id = result.install_id.to_string();
@@ -265,9 +825,7 @@ pub async fn register_device(creds: &mut DeviceCredentials) -> Result<()> {
 }
 
 /// Get ASR token using device_id
-pub async fn get_asr_token(creds: &mut DeviceCredentials) -> Result<()> {
-    let client = Client::new();
-
+pub async fn get_asr_token(api: &DeviceApi, creds: &mut DeviceCredentials) -> AsrResult<()> {
     let mut params: HashMap<&str, String> = HashMap::new();
     params.insert("device_platform", DEVICE_PLATFORM.to_string());
     params.insert("os", OS.to_string());
@@ -285,21 +843,62 @@ pub async fn get_asr_token(creds: &mut DeviceCredentials) -> Result<()> {
     let body_str = "body=null";
     let x_ss_stub = format!("{:X}", md5::compute(body_str.as_bytes()));
 
-    let response = client
-        .post(SETTINGS_URL)
-        .header("User-Agent", USER_AGENT)
-        .header("x-ss-stub", x_ss_stub)
-        .query(&params)
-        .body(body_str)
-        .send()
-        .await?;
-
-    if !response.status().is_success() {
-        return Err(anyhow!("Failed to get ASR token: {}", response.status()));
+    let (params, x_ss_stub) = (&params, &x_ss_stub);
+    let result = with_retry("Getting ASR token", || async move {
+        let response = api
+            .client
+            .post(&api.settings_url)
+            .header("User-Agent", USER_AGENT)
+            .header("x-ss-stub", x_ss_stub.as_str())
+            .query(params)
+            .body(body_str)
+            .send()
+            .await
+            .map_err(|e| api.request_error("Getting ASR token", e))?;
+
+        let text = api.read_response("Getting ASR token", response).await?;
+        let result: SettingsResponse = decode_body("Getting ASR token", &text)?;
+        let settings: Settings =
+            serde_json::from_value(result.data.settings.clone()).map_err(|e| {
+                AsrError::ProtocolDecode(format!(
+                    "Getting ASR token returned unexpected settings ({}): {}",
+                    e,
+                    summarize_body(&text)
+                ))
+            })?;
+
+        // A blank key would be saved and fail every session afterwards; the
+        // server answers this way for devices it doesn't know
+        if settings.asr_config.app_key.trim().is_empty() {
+            return Err(AsrError::AuthFailed(format!(
+                "Getting ASR token returned an empty app_key, device not recognized: {}",
+                summarize_body(&text)
+            )));
+        }
+        Ok((settings, result.data.settings))
+    })
+    .await?;
+    let (settings, raw_settings) = result;
+    creds.token = settings.asr_config.app_key;
+    creds.obtained_at = current_time_ms() / 1000;
+    creds.expires_at = None;
+
+    // Surface server-side changes such as a new ASR host right away
+    let redacted = |mut settings: serde_json::Value| {
+        redact_json(&mut settings);
+        settings
+    };
+    let advertised = redacted(raw_settings);
+    match creds.server_settings.take().map(redacted) {
+        Some(previous) if previous.get("asr_config") != advertised.get("asr_config") => {
+            tracing::info!(
+                "Server ASR settings changed: {}",
+                advertised.get("asr_config").unwrap_or(&serde_json::Value::Null)
+            );
+        }
+        _ => tracing::debug!("Server settings: {}", summarize_body(&advertised.to_string())),
     }
-
-    let result: SettingsResponse = response.json().await?;
-    creds.token = result.data.settings.asr_config.app_key;
+    creds.server_settings = Some(advertised);
 
     tracing::info!("ASR token obtained successfully");
     Ok(())
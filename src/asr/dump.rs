@@ -0,0 +1,177 @@
+//! Protocol Capture
+//!
+//! Writes every binary frame of a session to disk so protocol problems can
+//! be reproduced offline.
+//!
+//! Each session produces `<timestamp>-<request>.bin`, a sequence of records
+//! (`direction: u8`, `length: u32 LE`, `payload`), and a matching
+//! `.jsonl` index with one line per frame. Tokens are blanked out of
+//! outgoing frames before they are written.
+
+use prost::Message;
+use serde_json::json;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use super::proto::{AsrRequest, AsrResponse as AsrResponseProto};
+
+/// Direction of a captured frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Outgoing,
+    Incoming,
+}
+
+impl Direction {
+    fn tag(self) -> u8 {
+        match self {
+            Direction::Outgoing => b'>',
+            Direction::Incoming => b'<',
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            b'>' => Some(Direction::Outgoing),
+            b'<' => Some(Direction::Incoming),
+            _ => None,
+        }
+    }
+}
+
+/// A frame read back from a capture file
+#[derive(Debug, Clone)]
+pub struct DumpRecord {
+    pub direction: Direction,
+    pub data: Vec<u8>,
+}
+
+/// Writer for one session's capture
+pub(crate) struct FrameDump {
+    state: Mutex<DumpFiles>,
+    started: Instant,
+}
+
+struct DumpFiles {
+    data: File,
+    index: File,
+    seq: u64,
+}
+
+impl FrameDump {
+    /// Create capture files for a new session in `dir`
+    pub(crate) fn create(dir: &Path, request_id: &str) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let stem = format!("{}-{}", timestamp, &request_id[..8.min(request_id.len())]);
+        let data_path = dir.join(format!("{}.bin", stem));
+        let index_path = dir.join(format!("{}.jsonl", stem));
+
+        tracing::info!("Capturing ASR frames to {:?}", data_path);
+        Ok(Self {
+            state: Mutex::new(DumpFiles {
+                data: File::create(data_path)?,
+                index: File::create(index_path)?,
+                seq: 0,
+            }),
+            started: Instant::now(),
+        })
+    }
+
+    /// Record a frame sent to the server
+    pub(crate) fn outgoing(&self, frame: &[u8]) {
+        // Blank the token so captures can be shared
+        let (data, entry) = match AsrRequest::decode(frame) {
+            Ok(mut request) => {
+                let entry = json!({
+                    "method": request.method_name,
+                    "frame_state": request.frame_state,
+                    "audio_bytes": request.audio_data.len(),
+                    "token": if request.token.is_empty() { "" } else { "<redacted>" },
+                });
+                request.token.clear();
+                (request.encode_to_vec(), entry)
+            }
+            Err(_) => (frame.to_vec(), json!({ "method": "<undecodable>" })),
+        };
+        self.write(Direction::Outgoing, &data, entry);
+    }
+
+    /// Record a frame received from the server
+    pub(crate) fn incoming(&self, frame: &[u8]) {
+        let entry = match AsrResponseProto::decode(frame) {
+            Ok(response) => json!({
+                "message_type": response.message_type,
+                "status_code": response.status_code,
+                "has_result": !response.result_json.is_empty(),
+            }),
+            Err(_) => json!({ "message_type": "<undecodable>" }),
+        };
+        self.write(Direction::Incoming, frame, entry);
+    }
+
+    fn write(&self, direction: Direction, data: &[u8], mut entry: serde_json::Value) {
+        let mut state = self.state.lock().unwrap();
+        state.seq += 1;
+        entry["seq"] = json!(state.seq);
+        entry["direction"] = json!(if direction == Direction::Outgoing { "out" } else { "in" });
+        entry["len"] = json!(data.len());
+        entry["elapsed_ms"] = json!(self.started.elapsed().as_millis() as u64);
+
+        let mut record = Vec::with_capacity(data.len() + 5);
+        record.push(direction.tag());
+        record.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        record.extend_from_slice(data);
+
+        let files = &mut *state;
+        let result = files
+            .data
+            .write_all(&record)
+            .and_then(|_| writeln!(files.index, "{}", entry));
+        if let Err(e) = result {
+            tracing::warn!("Failed to write ASR capture: {}", e);
+        }
+    }
+}
+
+/// Read all records from a capture file
+pub fn read_dump(path: &Path) -> io::Result<Vec<DumpRecord>> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+
+    let mut records = Vec::new();
+    let mut rest = bytes.as_slice();
+    while !rest.is_empty() {
+        if rest.len() < 5 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated record header"));
+        }
+        let direction = Direction::from_tag(rest[0])
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unknown direction tag"))?;
+        let len = u32::from_le_bytes([rest[1], rest[2], rest[3], rest[4]]) as usize;
+        rest = &rest[5..];
+        if rest.len() < len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated record"));
+        }
+        records.push(DumpRecord {
+            direction,
+            data: rest[..len].to_vec(),
+        });
+        rest = &rest[len..];
+    }
+
+    Ok(records)
+}
+
+/// Directory for captures, if enabled in the config
+pub(crate) fn dump_dir(configured: Option<&str>) -> Option<PathBuf> {
+    configured
+        .map(str::trim)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
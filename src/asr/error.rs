@@ -0,0 +1,145 @@
+//! ASR Error Types
+//!
+//! Typed errors returned by the ASR client and device registration, so
+//! callers can tell network problems from rejected credentials.
+
+use super::error_codes::{describe_error_code, ErrorCategory};
+use super::proxy::ProxyError;
+
+/// Errors produced by the ASR module
+#[derive(Debug, thiserror::Error)]
+pub enum AsrError {
+    /// The server could not be reached or the connection dropped
+    #[error("network error: {0}")]
+    Network(String),
+
+    /// The proxy refused or could not establish the tunnel
+    #[error(transparent)]
+    Proxy(#[from] ProxyError),
+
+    /// The server rejected our device credentials
+    #[error("authentication failed: {0}")]
+    AuthFailed(String),
+
+    /// The WebSocket upgrade or StartTask/StartSession exchange failed
+    #[error("handshake failed: {0}")]
+    Handshake(String),
+
+    /// A server message could not be decoded
+    #[error("protocol decode error: {0}")]
+    ProtocolDecode(String),
+
+    /// The server answered with an explicit failure
+    #[error("server rejected request{}: {message}", code.map(|c| format!(" (code {})", c)).unwrap_or_default())]
+    ServerRejected { code: Option<i32>, message: String },
+
+    /// The server did not answer in time
+    #[error("timed out: {0}")]
+    Timeout(String),
+
+    /// A configured URL or option is unusable
+    #[error("invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    /// Input audio could not be read or encoded
+    #[error("invalid audio: {0}")]
+    InvalidAudio(String),
+}
+
+/// Result type for the ASR module
+pub type AsrResult<T> = std::result::Result<T, AsrError>;
+
+impl AsrError {
+    /// Whether a fresh token might fix this error
+    pub fn is_auth(&self) -> bool {
+        matches!(self, AsrError::AuthFailed(_))
+    }
+
+    /// Whether retrying the same request later may succeed
+    ///
+    /// A server rejection only is when its code means the service is busy
+    /// or isn't one we know.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            AsrError::Network(_) | AsrError::Timeout(_) | AsrError::Handshake(_) => true,
+            AsrError::ServerRejected { code, .. } => match code.and_then(describe_error_code) {
+                Some(known) => known.category == ErrorCategory::ServerBusy,
+                None => true,
+            },
+            _ => false,
+        }
+    }
+
+    /// Short hint for the user describing what to check
+    pub fn user_hint(&self) -> &'static str {
+        match self {
+            AsrError::Network(_) | AsrError::Timeout(_) => "请检查网络连接",
+            AsrError::Proxy(_) => "请检查代理设置",
+            AsrError::AuthFailed(_) => "设备凭据无效，正在重新获取",
+            AsrError::InvalidConfig(_) => "请检查配置文件",
+            AsrError::InvalidAudio(_) => "请使用 16kHz 单声道 16 位 PCM 音频",
+            AsrError::Handshake(_) | AsrError::ProtocolDecode(_) | AsrError::ServerRejected { .. } => {
+                "语音服务暂时不可用，请稍后重试"
+            }
+        }
+    }
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for AsrError {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> Self {
+        use tokio_tungstenite::tungstenite::Error as WsError;
+
+        match e {
+            WsError::Http(ref response) if matches!(response.status().as_u16(), 401 | 403) => {
+                AsrError::AuthFailed(format!("HTTP {} on upgrade", response.status()))
+            }
+            WsError::Http(ref response) => {
+                AsrError::Handshake(format!("HTTP {} on upgrade", response.status()))
+            }
+            WsError::Io(_) | WsError::Tls(_) | WsError::ConnectionClosed | WsError::AlreadyClosed => {
+                AsrError::Network(e.to_string())
+            }
+            e => AsrError::Handshake(e.to_string()),
+        }
+    }
+}
+
+impl From<reqwest::Error> for AsrError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            AsrError::Timeout(e.to_string())
+        } else if e.is_decode() {
+            AsrError::ProtocolDecode(e.to_string())
+        } else {
+            AsrError::Network(e.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejected(code: Option<i32>) -> AsrError {
+        AsrError::ServerRejected {
+            code,
+            message: "rejected".to_string(),
+        }
+    }
+
+    #[test]
+    fn busy_or_unknown_rejections_are_retried() {
+        assert!(rejected(Some(3005)).is_transient());
+        assert!(rejected(Some(55000031)).is_transient());
+        assert!(rejected(Some(12345)).is_transient());
+        assert!(rejected(None).is_transient());
+    }
+
+    #[test]
+    fn permanent_rejections_are_not_retried() {
+        // Bad token, quota, bad request and unusable audio
+        for code in [401, 3003, 3001, 45000001, 3011] {
+            assert!(!rejected(Some(code)).is_transient(), "code {}", code);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Server Error Codes
+//!
+//! Known error codes returned in TaskFailed/SessionFailed messages, mapped
+//! to descriptions the user can act on.
+
+/// Broad class of a server error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// The token or device credentials were rejected
+    InvalidToken,
+    /// Request quota or concurrency limit reached
+    QuotaExceeded,
+    /// The audio format or content could not be processed
+    UnsupportedAudio,
+    /// The request itself was malformed
+    InvalidRequest,
+    /// The service is overloaded or failed internally
+    ServerBusy,
+}
+
+/// A known server error code
+#[derive(Debug, Clone, Copy)]
+pub struct KnownErrorCode {
+    pub code: i32,
+    pub category: ErrorCategory,
+    pub description: &'static str,
+}
+
+/// Error codes observed from the ASR service
+pub const KNOWN_ERROR_CODES: &[KnownErrorCode] = &[
+    KnownErrorCode {
+        code: 401,
+        category: ErrorCategory::InvalidToken,
+        description: "令牌无效 — 正在重新注册设备",
+    },
+    KnownErrorCode {
+        code: 403,
+        category: ErrorCategory::InvalidToken,
+        description: "令牌无权限 — 正在重新注册设备",
+    },
+    KnownErrorCode {
+        code: 3001,
+        category: ErrorCategory::InvalidRequest,
+        description: "请求参数无效, 请检查配置中的语言和热词",
+    },
+    KnownErrorCode {
+        code: 3003,
+        category: ErrorCategory::QuotaExceeded,
+        description: "请求过于频繁或超出配额, 请稍后重试",
+    },
+    KnownErrorCode {
+        code: 3005,
+        category: ErrorCategory::ServerBusy,
+        description: "语音服务繁忙, 请稍后重试",
+    },
+    KnownErrorCode {
+        code: 3010,
+        category: ErrorCategory::UnsupportedAudio,
+        description: "单次录音过长, 请分段输入",
+    },
+    KnownErrorCode {
+        code: 3011,
+        category: ErrorCategory::UnsupportedAudio,
+        description: "音频无效或格式不受支持, 请检查麦克风",
+    },
+    KnownErrorCode {
+        code: 45000001,
+        category: ErrorCategory::InvalidRequest,
+        description: "请求参数无效, 请检查配置中的语言和热词",
+    },
+    KnownErrorCode {
+        code: 45000151,
+        category: ErrorCategory::UnsupportedAudio,
+        description: "音频格式不受支持",
+    },
+    KnownErrorCode {
+        code: 55000031,
+        category: ErrorCategory::ServerBusy,
+        description: "语音服务繁忙, 请稍后重试",
+    },
+];
+
+/// Look up a server error code
+pub fn describe_error_code(code: i32) -> Option<&'static KnownErrorCode> {
+    KNOWN_ERROR_CODES.iter().find(|known| known.code == code)
+}
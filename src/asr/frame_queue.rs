@@ -0,0 +1,114 @@
+//! Outgoing Audio Queue
+//!
+//! Bounded buffer between the audio channel and the WebSocket writer.
+//! When the network stalls, the oldest frames are dropped instead of
+//! letting latency grow without limit.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+/// Bounded drop-oldest queue of encoded audio frames
+pub struct FrameQueue {
+    state: Mutex<QueueState>,
+    notify: Notify,
+    space: Notify,
+    capacity: usize,
+    dropped: AtomicU64,
+}
+
+struct QueueState {
+    frames: VecDeque<Vec<u8>>,
+    closed: bool,
+}
+
+impl FrameQueue {
+    /// Create a queue holding at most `capacity` frames
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            state: Mutex::new(QueueState {
+                frames: VecDeque::with_capacity(capacity),
+                closed: false,
+            }),
+            notify: Notify::new(),
+            space: Notify::new(),
+            capacity,
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Queue a frame, dropping the oldest one if the queue is full
+    ///
+    /// Frames pushed after `close` are discarded.
+    pub fn push(&self, frame: Vec<u8>) {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.closed {
+                return;
+            }
+            if state.frames.len() >= self.capacity {
+                state.frames.pop_front();
+                let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                if dropped == 1 || dropped % 50 == 0 {
+                    tracing::warn!(
+                        "WebSocket can't keep up, dropped {} audio frame(s) so far",
+                        dropped
+                    );
+                }
+            }
+            state.frames.push_back(frame);
+        }
+        self.notify.notify_one();
+    }
+
+    /// Queue a frame, waiting for room instead of dropping old frames
+    ///
+    /// Used for sources that can be slowed down, such as files.
+    pub async fn push_wait(&self, frame: Vec<u8>) {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.closed {
+                    return;
+                }
+                if state.frames.len() < self.capacity {
+                    state.frames.push_back(frame);
+                    break;
+                }
+            }
+            self.space.notified().await;
+        }
+        self.notify.notify_one();
+    }
+
+    /// Mark the end of the stream; `pop` returns `None` once drained
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.notify.notify_one();
+        self.space.notify_one();
+    }
+
+    /// Wait for the next frame
+    pub async fn pop(&self) -> Option<Vec<u8>> {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if let Some(frame) = state.frames.pop_front() {
+                    self.space.notify_one();
+                    return Some(frame);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    /// Number of frames dropped because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
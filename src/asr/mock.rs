@@ -0,0 +1,157 @@
+//! Mock ASR Backend
+//!
+//! Replays scripted responses without any network access, for exercising
+//! the start/stop/insert flow offline.
+
+use futures_util::future::BoxFuture;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+use super::backend::AsrBackend;
+use super::constants::FRAME_DURATION_MS;
+use super::error::AsrResult;
+use super::frame_queue::FrameQueue;
+use super::protocol::{AsrResponse, ResponseType};
+use super::session::{SessionEnd, SessionHandle};
+use super::stats::SessionMetrics;
+
+/// Frames buffered before the mock starts dropping audio
+const MOCK_QUEUE_DEPTH: usize = 250;
+
+/// One scripted response and the delay before it is delivered
+#[derive(Debug, Clone)]
+pub struct MockStep {
+    pub delay: Duration,
+    pub response: AsrResponse,
+}
+
+impl MockStep {
+    /// An interim result delivered after `delay`
+    pub fn interim(text: &str, delay: Duration) -> Self {
+        Self {
+            delay,
+            response: AsrResponse {
+                response_type: ResponseType::InterimResult,
+                text: text.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// A final result delivered after `delay`
+    pub fn final_result(text: &str, delay: Duration) -> Self {
+        Self {
+            delay,
+            response: AsrResponse {
+                response_type: ResponseType::FinalResult,
+                text: text.to_string(),
+                is_final: true,
+                vad_finished: true,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// A second-pass refinement of the preceding final, delivered after `delay`
+    pub fn refined_result(text: &str, delay: Duration) -> Self {
+        Self {
+            delay,
+            response: AsrResponse {
+                response_type: ResponseType::RefinedResult,
+                text: text.to_string(),
+                is_final: true,
+                vad_finished: true,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Backend that plays back the same script for every session
+///
+/// Audio frames are consumed and counted but otherwise ignored. The session
+/// finishes once the script has run and the audio channel is closed.
+pub struct MockBackend {
+    script: Mutex<Vec<MockStep>>,
+}
+
+impl MockBackend {
+    /// Create a backend replaying `script`
+    pub fn new(script: Vec<MockStep>) -> Self {
+        Self {
+            script: Mutex::new(script),
+        }
+    }
+
+    /// Replace the script used by subsequent sessions
+    pub fn set_script(&self, script: Vec<MockStep>) {
+        *self.script.lock().unwrap() = script;
+    }
+}
+
+impl AsrBackend for MockBackend {
+    fn start_session(
+        &self,
+        mut audio_rx: mpsc::Receiver<Vec<u8>>,
+    ) -> BoxFuture<'_, AsrResult<SessionHandle>> {
+        let script = self.script.lock().unwrap().clone();
+
+        Box::pin(async move {
+            let frame_queue = Arc::new(FrameQueue::new(MOCK_QUEUE_DEPTH));
+            let metrics = Arc::new(SessionMetrics::new(frame_queue.clone(), FRAME_DURATION_MS));
+            let (result_tx, result_rx) = mpsc::channel(100);
+            let (end_tx, end_rx) = oneshot::channel();
+            let (audio_done_tx, audio_done_rx) = oneshot::channel::<()>();
+
+            let forward_queue = frame_queue.clone();
+            tokio::spawn(async move {
+                while let Some(frame) = audio_rx.recv().await {
+                    forward_queue.push(frame);
+                }
+                forward_queue.close();
+            });
+
+            let sender_queue = frame_queue.clone();
+            let sender_metrics = metrics.clone();
+            let sender_task = tokio::spawn(async move {
+                while let Some(frame) = sender_queue.pop().await {
+                    sender_metrics.record_frame(frame.len(), false);
+                }
+                let _ = audio_done_tx.send(());
+            });
+
+            let reader_metrics = metrics.clone();
+            let reader_task = tokio::spawn(async move {
+                let mut last_final = None;
+                for step in script {
+                    tokio::time::sleep(step.delay).await;
+                    let is_final = step.response.is_final;
+                    reader_metrics.record_result(is_final);
+                    if is_final {
+                        last_final = Some(step.response.clone());
+                    }
+                    let _ = result_tx.send(step.response).await;
+                }
+
+                let _ = audio_done_rx.await;
+                let _ = result_tx
+                    .send(AsrResponse {
+                        response_type: ResponseType::SessionFinished,
+                        ..Default::default()
+                    })
+                    .await;
+                let _ = end_tx.send(SessionEnd::Finished(last_final));
+            });
+
+            Ok(SessionHandle::new(
+                result_rx,
+                frame_queue,
+                metrics,
+                end_rx,
+                sender_task,
+                reader_task,
+            ))
+        })
+    }
+}
@@ -2,15 +2,34 @@
 //!
 //! This module implements the Doubao ASR protocol for real-time speech recognition.
 
+mod backend;
 mod client;
 mod constants;
 mod device;
+mod dump;
+mod error;
+mod error_codes;
+mod frame_queue;
+pub mod mock;
 mod protocol;
+mod proxy;
+mod session;
+mod stats;
 
+pub use backend::AsrBackend;
 pub use client::AsrClient;
 pub use constants::*;
-pub use device::{DeviceCredentials, register_device, get_asr_token};
-pub use protocol::{AsrResponse, ResponseType};
+pub use error::{AsrError, AsrResult};
+pub use error_codes::{describe_error_code, ErrorCategory, KnownErrorCode, KNOWN_ERROR_CODES};
+pub use dump::{read_dump, Direction, DumpRecord};
+pub use device::{
+    CredentialProfiles, DeviceApi, DeviceCredentials, DeviceProfile, DEFAULT_PROFILE,
+    register_device, get_asr_token,
+};
+pub use protocol::{parse_response, AsrResponse, ResponseType, Utterance, WordInfo};
+pub use proxy::ProxyError;
+pub use session::{FramePacing, SessionHandle};
+pub use stats::{SessionMetrics, SessionStats};
 
 // Include the generated protobuf code
 pub mod proto {
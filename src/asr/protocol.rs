@@ -6,6 +6,7 @@ use prost::Message;
 use serde::Serialize;
 use serde_json::Value;
 
+use super::error_codes::describe_error_code;
 use super::proto::{AsrRequest, AsrResponse as AsrResponseProto, FrameState};
 
 /// Response types from ASR server
@@ -15,9 +16,15 @@ pub enum ResponseType {
     SessionStarted,
     SessionFinished,
     VadStart,
+    /// The server detected the end of speech on an interim result
+    VadEnd,
     InterimResult,
     FinalResult,
+    /// Second-pass (non-streaming) text replacing the preceding final result
+    RefinedResult,
     Heartbeat,
+    /// The client stopped sending audio because the session hit its length cap
+    SessionTruncated,
     Error,
     Unknown,
 }
@@ -32,9 +39,33 @@ pub struct AsrResponse {
     pub vad_finished: bool,
     pub packet_number: i32,
     pub error_msg: String,
+    /// Numeric error code extracted from a failure message
+    pub error_code: Option<i32>,
+    /// Per-word timing, empty when the server didn't send any
+    pub words: Vec<WordInfo>,
+    /// Individual segments in this message; `text` is their concatenation
+    pub utterances: Vec<Utterance>,
+    /// Recognition confidence (0.0-1.0) when the server reports one
+    pub confidence: Option<f32>,
     pub raw_json: Option<Value>,
 }
 
+/// One recognized segment of a response
+#[derive(Debug, Clone, PartialEq)]
+pub struct Utterance {
+    pub text: String,
+    pub is_interim: bool,
+    pub vad_finished: bool,
+}
+
+/// A recognized word with its position in the audio stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct WordInfo {
+    pub text: String,
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
 impl Default for AsrResponse {
     fn default() -> Self {
         Self {
@@ -45,6 +76,10 @@ impl Default for AsrResponse {
             vad_finished: false,
             packet_number: -1,
             error_msg: String::new(),
+            error_code: None,
+            words: Vec::new(),
+            utterances: Vec::new(),
+            confidence: None,
             raw_json: None,
         }
     }
@@ -56,9 +91,19 @@ pub struct SessionConfig {
     pub audio_info: AudioInfo,
     pub enable_punctuation: bool,
     pub enable_speech_rejection: bool,
+    pub language: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub corpus: Option<SessionCorpus>,
     pub extra: SessionExtra,
 }
 
+/// Recognition context, used to bias the recognizer towards hotwords
+#[derive(Debug, Serialize)]
+pub struct SessionCorpus {
+    /// JSON string of the form `{"hotwords":[{"word":"..."}]}`
+    pub context: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct AudioInfo {
     pub channel: u16,
@@ -86,6 +131,8 @@ impl SessionConfig {
             },
             enable_punctuation: true,
             enable_speech_rejection: false,
+            language: "zh-CN".to_string(),
+            corpus: None,
             extra: SessionExtra {
                 app_name: "com.android.chrome".to_string(),
                 cell_compress_rate: 8,
@@ -96,6 +143,24 @@ impl SessionConfig {
             },
         }
     }
+
+    /// Bias recognition towards the given words; empty entries are ignored
+    pub fn set_hotwords(&mut self, hotwords: &[String]) {
+        let words: Vec<Value> = hotwords
+            .iter()
+            .map(|w| w.trim())
+            .filter(|w| !w.is_empty())
+            .map(|w| serde_json::json!({ "word": w }))
+            .collect();
+
+        self.corpus = if words.is_empty() {
+            None
+        } else {
+            Some(SessionCorpus {
+                context: serde_json::json!({ "hotwords": words }).to_string(),
+            })
+        };
+    }
 }
 
 /// Build StartTask message
@@ -203,9 +268,11 @@ pub fn parse_response(data: &[u8]) -> AsrResponse {
             };
         }
         "TaskFailed" | "SessionFailed" => {
+            let (error_code, error_msg) = parse_error_status(pb.status_code, status_message);
             return AsrResponse {
                 response_type: ResponseType::Error,
-                error_msg: status_message.clone(),
+                error_msg,
+                error_code,
                 ..Default::default()
             };
         }
@@ -259,20 +326,40 @@ pub fn parse_response(data: &[u8]) -> AsrResponse {
 
     // Parse recognition results
     let results = results.unwrap();
-    let mut text = String::new();
+    let mut utterances = Vec::new();
     let mut is_interim = true;
     let mut vad_finished = false;
     let mut nonstream_result = false;
+    let mut words = Vec::new();
+    // The last score seen on a final result, and on an interim one; a
+    // trailing interim or unscored result doesn't replace a final's score
+    let mut final_confidence = None;
+    let mut interim_confidence = None;
 
     if let Some(results_array) = results.as_array() {
         for r in results_array {
+            let result_interim = r.get("is_interim").and_then(|v| v.as_bool()) != Some(false);
+            let result_vad_finished =
+                r.get("is_vad_finished").and_then(|v| v.as_bool()) == Some(true);
+
             if let Some(t) = r.get("text").and_then(|v| v.as_str()) {
-                text = t.to_string();
+                utterances.push(Utterance {
+                    text: t.to_string(),
+                    is_interim: result_interim,
+                    vad_finished: result_vad_finished,
+                });
+                words.extend(parse_words(r));
+                let score = parse_confidence(r);
+                if result_interim {
+                    interim_confidence = score.or(interim_confidence);
+                } else {
+                    final_confidence = score.or(final_confidence);
+                }
             }
-            if r.get("is_interim").and_then(|v| v.as_bool()) == Some(false) {
+            if !result_interim {
                 is_interim = false;
             }
-            if r.get("is_vad_finished").and_then(|v| v.as_bool()) == Some(true) {
+            if result_vad_finished {
                 vad_finished = true;
             }
             if r.get("extra")
@@ -285,13 +372,47 @@ pub fn parse_response(data: &[u8]) -> AsrResponse {
         }
     }
 
+    let text: String = utterances.iter().map(|u| u.text.as_str()).collect();
+    let confidence = if is_interim {
+        interim_confidence
+    } else {
+        final_confidence
+    };
+
     // Determine response type
-    if nonstream_result || (!is_interim && vad_finished) {
+    if nonstream_result {
+        AsrResponse {
+            response_type: ResponseType::RefinedResult,
+            text,
+            is_final: true,
+            vad_finished,
+            words,
+            utterances,
+            confidence,
+            raw_json: Some(json_data),
+            ..Default::default()
+        }
+    } else if !is_interim && vad_finished {
         AsrResponse {
             response_type: ResponseType::FinalResult,
             text,
             is_final: true,
             vad_finished,
+            words,
+            utterances,
+            confidence,
+            raw_json: Some(json_data),
+            ..Default::default()
+        }
+    } else if vad_finished {
+        AsrResponse {
+            response_type: ResponseType::VadEnd,
+            text,
+            is_final: false,
+            vad_finished,
+            words,
+            utterances,
+            confidence,
             raw_json: Some(json_data),
             ..Default::default()
         }
@@ -300,8 +421,348 @@ pub fn parse_response(data: &[u8]) -> AsrResponse {
             response_type: ResponseType::InterimResult,
             text,
             is_final: false,
+            words,
+            utterances,
+            confidence,
             raw_json: Some(json_data),
             ..Default::default()
         }
     }
 }
+
+/// Split a failure status into an error code and a readable message
+///
+/// The status message is sometimes JSON (`{"code":..,"message":..}`) and
+/// sometimes plain text with the code embedded; the protobuf status code is
+/// used when set, then a code found in the text.
+fn parse_error_status(status_code: i32, status_message: &str) -> (Option<i32>, String) {
+    if let Ok(json) = serde_json::from_str::<Value>(status_message) {
+        let code = ["code", "status_code", "error_code"]
+            .iter()
+            .find_map(|key| json.get(key))
+            .and_then(|v| v.as_i64().or_else(|| v.as_str().and_then(|s| s.parse().ok())))
+            .map(|c| c as i32);
+        let message = ["message", "msg", "error"]
+            .iter()
+            .find_map(|key| json.get(key).and_then(|v| v.as_str()))
+            .map(str::to_string)
+            .unwrap_or_else(|| status_message.to_string());
+        return (code.or((status_code != 0).then_some(status_code)), message);
+    }
+
+    let code = (status_code != 0)
+        .then_some(status_code)
+        .or_else(|| embedded_error_code(status_message));
+
+    (code, status_message.to_string())
+}
+
+/// Find an error code in free text
+///
+/// Only numbers labelled as one (`code 3001`, `错误码: 3001`, `[3001]`) or
+/// listed in the known codes count, so "timeout after 3000ms" has none.
+fn embedded_error_code(message: &str) -> Option<i32> {
+    let mut rest = message;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_digit()) {
+        let len = rest[start..]
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len() - start);
+        let (before, after) = (&rest[..start], &rest[start + len..]);
+        let digits = &rest[start..start + len];
+        rest = after;
+
+        let Some(code) = digits.parse::<i32>().ok().filter(|_| len >= 3) else {
+            continue;
+        };
+        let label = before
+            .trim_end_matches(|c: char| c.is_whitespace() || matches!(c, ':' | '：' | '=' | '#'))
+            .to_lowercase();
+        let labelled = label.ends_with("code")
+            || label.ends_with("错误码")
+            || (before.ends_with('[') && after.starts_with(']'));
+        if labelled || describe_error_code(code).is_some() {
+            return Some(code);
+        }
+    }
+    None
+}
+
+/// Extract the confidence score from a result entry
+///
+/// Looks for `confidence` (or `score`) on the result itself, then on its
+/// first alternative.
+fn parse_confidence(result: &Value) -> Option<f32> {
+    let score = |v: &Value| {
+        v.get("confidence")
+            .or_else(|| v.get("score"))
+            .and_then(|c| c.as_f64())
+    };
+
+    score(result)
+        .or_else(|| {
+            result
+                .get("alternatives")
+                .and_then(|a| a.get(0))
+                .and_then(score)
+        })
+        .map(|c| c as f32)
+}
+
+/// Extract word timing from a result entry
+///
+/// The server sends either `words` directly on the result or nested under
+/// `alternatives[0].words`; entries use `word` or `text` with
+/// `start_time`/`end_time` in milliseconds.
+fn parse_words(result: &Value) -> Vec<WordInfo> {
+    let words = result.get("words").or_else(|| {
+        result
+            .get("alternatives")
+            .and_then(|a| a.get(0))
+            .and_then(|a| a.get("words"))
+    });
+
+    let Some(words) = words.and_then(|w| w.as_array()) else {
+        return Vec::new();
+    };
+
+    words
+        .iter()
+        .filter_map(|w| {
+            let text = w
+                .get("word")
+                .or_else(|| w.get("text"))
+                .and_then(|v| v.as_str())?;
+            let start_ms = w.get("start_time").and_then(|v| v.as_u64())?;
+            let end_ms = w.get("end_time").and_then(|v| v.as_u64()).unwrap_or(start_ms);
+            Some(WordInfo {
+                text: text.to_string(),
+                start_ms,
+                end_ms,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The StartSession payload as JSON
+    fn start_session_payload(config: &SessionConfig) -> Value {
+        let request = AsrRequest::decode(build_start_session("request", "token", config).as_slice())
+            .unwrap();
+        assert_eq!(request.method_name, "StartSession");
+        serde_json::from_str(&request.payload).unwrap()
+    }
+
+    /// Decode a server message carrying `result_json`
+    fn parse_result(result_json: &str) -> AsrResponse {
+        let message = AsrResponseProto {
+            message_type: "TaskRequest".to_string(),
+            result_json: result_json.to_string(),
+            ..Default::default()
+        };
+        parse_response(&message.encode_to_vec())
+    }
+
+    fn word(text: &str, start_ms: u64, end_ms: u64) -> WordInfo {
+        WordInfo {
+            text: text.to_string(),
+            start_ms,
+            end_ms,
+        }
+    }
+
+    #[test]
+    fn words_on_the_result() {
+        let response = parse_result(
+            r#"{
+                "results": [{
+                    "text": "你好世界",
+                    "is_interim": false,
+                    "is_vad_finished": true,
+                    "words": [
+                        {"word": "你好", "start_time": 120, "end_time": 480},
+                        {"word": "世界", "start_time": 480, "end_time": 900}
+                    ]
+                }],
+                "extra": {"packet_number": 12}
+            }"#,
+        );
+        assert_eq!(response.response_type, ResponseType::FinalResult);
+        assert_eq!(response.words, vec![word("你好", 120, 480), word("世界", 480, 900)]);
+    }
+
+    #[test]
+    fn words_under_alternatives() {
+        let response = parse_result(
+            r#"{
+                "results": [{
+                    "text": "打开设置",
+                    "is_interim": true,
+                    "alternatives": [{
+                        "text": "打开设置",
+                        "confidence": 0.92,
+                        "words": [
+                            {"text": "打开", "start_time": 0, "end_time": 300},
+                            {"text": "设置", "start_time": 300}
+                        ]
+                    }]
+                }]
+            }"#,
+        );
+        assert_eq!(response.response_type, ResponseType::InterimResult);
+        // A missing end time is taken as the start
+        assert_eq!(response.words, vec![word("打开", 0, 300), word("设置", 300, 300)]);
+        assert_eq!(response.confidence, Some(0.92));
+    }
+
+    #[test]
+    fn no_words_without_timing() {
+        let response = parse_result(r#"{"results": [{"text": "好的", "is_interim": true}]}"#);
+        assert_eq!(response.text, "好的");
+        assert!(response.words.is_empty());
+    }
+
+    #[test]
+    fn several_results_become_utterances() {
+        // One message of a long dictation: two finished sentences and the
+        // start of the next
+        let response = parse_result(
+            r#"{
+                "results": [
+                    {"text": "今天下午三点开会，", "is_interim": false, "is_vad_finished": true,
+                     "words": [{"word": "今天", "start_time": 40, "end_time": 360}]},
+                    {"text": "请大家准时参加。", "is_interim": false, "is_vad_finished": true,
+                     "words": [{"word": "请", "start_time": 2100, "end_time": 2250}]},
+                    {"text": "会议室在", "is_interim": true, "is_vad_finished": false}
+                ],
+                "extra": {"packet_number": 231}
+            }"#,
+        );
+        assert_eq!(response.text, "今天下午三点开会，请大家准时参加。会议室在");
+        assert_eq!(
+            response.utterances,
+            vec![
+                Utterance {
+                    text: "今天下午三点开会，".to_string(),
+                    is_interim: false,
+                    vad_finished: true,
+                },
+                Utterance {
+                    text: "请大家准时参加。".to_string(),
+                    is_interim: false,
+                    vad_finished: true,
+                },
+                Utterance {
+                    text: "会议室在".to_string(),
+                    is_interim: true,
+                    vad_finished: false,
+                },
+            ]
+        );
+        assert_eq!(response.words, vec![word("今天", 40, 360), word("请", 2100, 2250)]);
+    }
+
+    #[test]
+    fn several_final_results_are_one_final() {
+        let response = parse_result(
+            r#"{"results": [
+                {"text": "第一句。", "is_interim": false, "is_vad_finished": true},
+                {"text": "第二句。", "is_interim": false, "is_vad_finished": true}
+            ]}"#,
+        );
+        assert_eq!(response.response_type, ResponseType::FinalResult);
+        assert_eq!(response.text, "第一句。第二句。");
+        assert_eq!(response.utterances.len(), 2);
+    }
+
+    #[test]
+    fn confidence_on_the_result() {
+        let response =
+            parse_result(r#"{"results": [{"text": "好的", "is_interim": false, "confidence": 0.87}]}"#);
+        assert_eq!(response.confidence, Some(0.87));
+    }
+
+    #[test]
+    fn score_on_the_result() {
+        let response =
+            parse_result(r#"{"results": [{"text": "好的", "is_interim": false, "score": 0.35}]}"#);
+        assert_eq!(response.confidence, Some(0.35));
+    }
+
+    #[test]
+    fn no_confidence_without_a_score() {
+        let response = parse_result(
+            r#"{"results": [{"text": "好的", "is_interim": false, "is_vad_finished": true}]}"#,
+        );
+        assert_eq!(response.response_type, ResponseType::FinalResult);
+        assert_eq!(response.confidence, None);
+    }
+
+    #[test]
+    fn confidence_of_several_results_is_the_last_final_score() {
+        // Neither the unscored final nor the trailing interim replaces it
+        let response = parse_result(
+            r#"{"results": [
+                {"text": "第一句。", "is_interim": false, "confidence": 0.2},
+                {"text": "第二句。", "is_interim": false, "score": 0.4},
+                {"text": "第三句。", "is_interim": false},
+                {"text": "第四", "is_interim": true, "confidence": 0.95}
+            ]}"#,
+        );
+        assert_eq!(response.confidence, Some(0.4));
+
+        let response = parse_result(
+            r#"{"results": [
+                {"text": "第一", "is_interim": true, "confidence": 0.6},
+                {"text": "第二", "is_interim": true}
+            ]}"#,
+        );
+        assert_eq!(response.confidence, Some(0.6));
+    }
+
+    #[test]
+    fn labelled_error_codes_are_found_in_text() {
+        assert_eq!(embedded_error_code("request failed, code: 45000002"), Some(45000002));
+        assert_eq!(embedded_error_code("Error code=1234 bad request"), Some(1234));
+        assert_eq!(embedded_error_code("识别失败，错误码：5678"), Some(5678));
+        assert_eq!(embedded_error_code("[4321] server error"), Some(4321));
+        // Known codes count even without a label
+        assert_eq!(embedded_error_code("quota exceeded (3003)"), Some(3003));
+    }
+
+    #[test]
+    fn other_numbers_are_not_error_codes() {
+        assert_eq!(embedded_error_code("timeout after 3000ms"), None);
+        assert_eq!(embedded_error_code("session 123456 closed at 12:30"), None);
+        assert_eq!(embedded_error_code("code: 12"), None);
+        assert_eq!(parse_error_status(0, "timeout after 3000ms").0, None);
+        assert_eq!(parse_error_status(55000031, "timeout after 3000ms").0, Some(55000031));
+    }
+
+    #[test]
+    fn start_session_payload_carries_hotwords() {
+        let mut config = SessionConfig::new("device");
+        config.set_hotwords(&["豆包".to_string(), " Rust ".to_string(), "  ".to_string()]);
+        let payload = start_session_payload(&config);
+
+        // The context is itself a JSON string
+        let context = payload["corpus"]["context"].as_str().unwrap();
+        let context: Value = serde_json::from_str(context).unwrap();
+        assert_eq!(
+            context,
+            serde_json::json!({ "hotwords": [{ "word": "豆包" }, { "word": "Rust" }] })
+        );
+    }
+
+    #[test]
+    fn no_hotwords_leaves_out_the_corpus() {
+        let mut config = SessionConfig::new("device");
+        config.set_hotwords(&["豆包".to_string()]);
+        config.set_hotwords(&[]);
+        let payload = start_session_payload(&config);
+        assert!(payload.get("corpus").is_none());
+        assert_eq!(payload["extra"]["did"], "device");
+    }
+}
@@ -0,0 +1,353 @@
+//! Proxy Tunneling
+//!
+//! Opens a TCP tunnel through an HTTP CONNECT or SOCKS5 proxy so the
+//! TLS/WebSocket handshake can run on top of it.
+
+use base64::Engine;
+use reqwest::Url;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Errors raised by the proxy itself, as opposed to the ASR server
+#[derive(Debug, thiserror::Error)]
+pub enum ProxyError {
+    #[error("invalid proxy URL '{0}'")]
+    InvalidUrl(String),
+    #[error("unsupported proxy scheme '{0}' (expected http, socks5 or socks5h)")]
+    UnsupportedScheme(String),
+    #[error("cannot reach proxy {proxy}: {source}")]
+    Unreachable {
+        proxy: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("proxy {0} requires authentication (407)")]
+    AuthRequired(String),
+    #[error("proxy {proxy} refused the tunnel: {reason}")]
+    Rejected { proxy: String, reason: String },
+    #[error("SOCKS5 {field} is {len} bytes, longer than the 255 the protocol allows")]
+    FieldTooLong { field: &'static str, len: usize },
+}
+
+/// Resolve the proxy to use: the configured URL wins, then `HTTPS_PROXY`/`ALL_PROXY`
+///
+/// With `disable_system_proxy` the environment is ignored entirely.
+pub fn resolve_proxy_url(configured: Option<&str>, disable_system_proxy: bool) -> Option<String> {
+    if let Some(url) = configured.map(str::trim).filter(|u| !u.is_empty()) {
+        return Some(url.to_string());
+    }
+    if disable_system_proxy {
+        return None;
+    }
+
+    ["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"]
+        .iter()
+        .filter_map(|key| std::env::var(key).ok())
+        .map(|value| value.trim().to_string())
+        .find(|value| !value.is_empty())
+}
+
+/// Open a TCP stream to `host:port` tunneled through the given proxy
+pub async fn connect_via_proxy(
+    proxy_url: &str,
+    host: &str,
+    port: u16,
+) -> Result<TcpStream, ProxyError> {
+    let url = Url::parse(proxy_url).map_err(|_| ProxyError::InvalidUrl(proxy_url.to_string()))?;
+    let proxy_host = url
+        .host_str()
+        .ok_or_else(|| ProxyError::InvalidUrl(proxy_url.to_string()))?;
+    let proxy_port = url.port_or_known_default().unwrap_or(match url.scheme() {
+        "socks5" | "socks5h" => 1080,
+        _ => 8080,
+    });
+    let proxy_addr = format!("{}:{}", proxy_host, proxy_port);
+
+    let unreachable = |source| ProxyError::Unreachable {
+        proxy: proxy_addr.clone(),
+        source,
+    };
+
+    let credentials = if url.username().is_empty() {
+        None
+    } else {
+        Some((
+            url.username().to_string(),
+            url.password().unwrap_or_default().to_string(),
+        ))
+    };
+
+    tracing::info!("Connecting through proxy {} ({})", proxy_addr, url.scheme());
+    let mut stream = TcpStream::connect(&proxy_addr).await.map_err(unreachable)?;
+
+    match url.scheme() {
+        "http" => {
+            http_connect(&mut stream, &proxy_addr, host, port, credentials)
+                .await
+                .map_err(|e| match e {
+                    TunnelError::Io(source) => unreachable(source),
+                    TunnelError::Proxy(e) => e,
+                })?;
+        }
+        "socks5" | "socks5h" => {
+            socks5_connect(&mut stream, &proxy_addr, host, port, credentials)
+                .await
+                .map_err(|e| match e {
+                    TunnelError::Io(source) => unreachable(source),
+                    TunnelError::Proxy(e) => e,
+                })?;
+        }
+        scheme => return Err(ProxyError::UnsupportedScheme(scheme.to_string())),
+    }
+
+    tracing::info!("Proxy tunnel to {}:{} established", host, port);
+    Ok(stream)
+}
+
+enum TunnelError {
+    Io(std::io::Error),
+    Proxy(ProxyError),
+}
+
+impl From<std::io::Error> for TunnelError {
+    fn from(e: std::io::Error) -> Self {
+        TunnelError::Io(e)
+    }
+}
+
+/// Run an HTTP CONNECT request on an open proxy connection
+async fn http_connect(
+    stream: &mut TcpStream,
+    proxy_addr: &str,
+    host: &str,
+    port: u16,
+    credentials: Option<(String, String)>,
+) -> Result<(), TunnelError> {
+    let mut request = format!(
+        "CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\nProxy-Connection: Keep-Alive\r\n"
+    );
+    if let Some((user, password)) = credentials {
+        let encoded =
+            base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", user, password));
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", encoded));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read the response head byte by byte so nothing past it is consumed
+    let mut head = Vec::with_capacity(256);
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() > 8192 {
+            return Err(TunnelError::Proxy(ProxyError::Rejected {
+                proxy: proxy_addr.to_string(),
+                reason: "oversized CONNECT response".to_string(),
+            }));
+        }
+        head.push(stream.read_u8().await?);
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let status_line = head.lines().next().unwrap_or_default().to_string();
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok());
+
+    match status {
+        Some(200..=299) => Ok(()),
+        Some(407) => Err(TunnelError::Proxy(ProxyError::AuthRequired(
+            proxy_addr.to_string(),
+        ))),
+        _ => Err(TunnelError::Proxy(ProxyError::Rejected {
+            proxy: proxy_addr.to_string(),
+            reason: status_line,
+        })),
+    }
+}
+
+/// Run the SOCKS5 greeting and CONNECT command on an open proxy connection
+async fn socks5_connect(
+    stream: &mut TcpStream,
+    proxy_addr: &str,
+    host: &str,
+    port: u16,
+    credentials: Option<(String, String)>,
+) -> Result<(), TunnelError> {
+    let rejected = |reason: &str| {
+        TunnelError::Proxy(ProxyError::Rejected {
+            proxy: proxy_addr.to_string(),
+            reason: reason.to_string(),
+        })
+    };
+
+    // SOCKS5 carries each of these behind a single length byte
+    let field_len = |field: &'static str, value: &str| {
+        u8::try_from(value.len()).map_err(|_| {
+            TunnelError::Proxy(ProxyError::FieldTooLong {
+                field,
+                len: value.len(),
+            })
+        })
+    };
+    let host_len = field_len("host name", host)?;
+
+    // Greeting: offer "no auth" and, if we have credentials, username/password
+    if credentials.is_some() {
+        stream.write_all(&[0x05, 0x02, 0x00, 0x02]).await?;
+    } else {
+        stream.write_all(&[0x05, 0x01, 0x00]).await?;
+    }
+
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != 0x05 {
+        return Err(rejected("not a SOCKS5 proxy"));
+    }
+
+    match (reply[1], credentials) {
+        (0x00, _) => {}
+        (0x02, Some((user, password))) => {
+            let mut auth = vec![0x01, field_len("user name", &user)?];
+            auth.extend_from_slice(user.as_bytes());
+            auth.push(field_len("password", &password)?);
+            auth.extend_from_slice(password.as_bytes());
+            stream.write_all(&auth).await?;
+
+            let mut auth_reply = [0u8; 2];
+            stream.read_exact(&mut auth_reply).await?;
+            if auth_reply[1] != 0x00 {
+                return Err(TunnelError::Proxy(ProxyError::AuthRequired(
+                    proxy_addr.to_string(),
+                )));
+            }
+        }
+        _ => {
+            return Err(TunnelError::Proxy(ProxyError::AuthRequired(
+                proxy_addr.to_string(),
+            )))
+        }
+    }
+
+    // CONNECT with a domain name so the proxy resolves the host
+    let mut request = vec![0x05, 0x01, 0x00, 0x03, host_len];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).await?;
+    if header[1] != 0x00 {
+        return Err(rejected(&format!("SOCKS5 reply code {}", header[1])));
+    }
+
+    // Skip the bound address in the reply
+    let addr_len = match header[3] {
+        0x01 => 4,
+        0x04 => 16,
+        0x03 => stream.read_u8().await? as usize,
+        _ => return Err(rejected("malformed SOCKS5 reply")),
+    };
+    let mut bound = vec![0u8; addr_len + 2];
+    stream.read_exact(&mut bound).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// Accept one connection on a local listener and answer its CONNECT with `reply`
+    async fn stub_proxy(reply: &'static str) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let task = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut head = Vec::new();
+            while !head.ends_with(b"\r\n\r\n") {
+                head.push(socket.read_u8().await.unwrap());
+            }
+            socket.write_all(reply.as_bytes()).await.unwrap();
+            // Echo whatever comes through the tunnel
+            let mut buf = [0u8; 64];
+            let n = socket.read(&mut buf).await.unwrap_or(0);
+            socket.write_all(&buf[..n]).await.unwrap();
+            String::from_utf8(head).unwrap()
+        });
+        (url, task)
+    }
+
+    #[tokio::test]
+    async fn http_connect_opens_a_tunnel() {
+        let (url, proxy) = stub_proxy("HTTP/1.1 200 Connection established\r\n\r\n").await;
+
+        let mut stream = connect_via_proxy(&url, "asr.example.com", 443)
+            .await
+            .unwrap();
+        stream.write_all(b"ping").await.unwrap();
+        let mut echoed = [0u8; 4];
+        stream.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"ping");
+
+        let head = proxy.await.unwrap();
+        assert!(head.starts_with("CONNECT asr.example.com:443 HTTP/1.1\r\n"));
+        assert!(!head.contains("Proxy-Authorization"));
+    }
+
+    #[tokio::test]
+    async fn http_connect_sends_credentials() {
+        let (url, proxy) = stub_proxy("HTTP/1.1 200 OK\r\n\r\n").await;
+        let url = url.replace("http://", "http://user:secret@");
+
+        let stream = connect_via_proxy(&url, "asr.example.com", 443)
+            .await
+            .unwrap();
+        drop(stream);
+
+        let head = proxy.await.unwrap();
+        let encoded = base64::engine::general_purpose::STANDARD.encode("user:secret");
+        assert!(head.contains(&format!("Proxy-Authorization: Basic {}\r\n", encoded)));
+    }
+
+    #[tokio::test]
+    async fn http_connect_reports_auth_required() {
+        let (url, _proxy) = stub_proxy("HTTP/1.1 407 Proxy Authentication Required\r\n\r\n").await;
+
+        let err = connect_via_proxy(&url, "asr.example.com", 443)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ProxyError::AuthRequired(_)), "{err}");
+    }
+
+    #[tokio::test]
+    async fn http_connect_reports_rejection() {
+        let (url, _proxy) = stub_proxy("HTTP/1.1 403 Forbidden\r\n\r\n").await;
+
+        let err = connect_via_proxy(&url, "asr.example.com", 443)
+            .await
+            .unwrap_err();
+        match err {
+            ProxyError::Rejected { reason, .. } => assert_eq!(reason, "HTTP/1.1 403 Forbidden"),
+            other => panic!("unexpected error: {other}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn socks5_refuses_an_overlong_host() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("socks5://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            // The host is checked before anything is sent, so just wait for the hang-up
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let _ = socket.read_u8().await;
+        });
+
+        let host = "a".repeat(300);
+        let err = connect_via_proxy(&url, &host, 443).await.unwrap_err();
+        assert!(
+            matches!(err, ProxyError::FieldTooLong { len: 300, .. }),
+            "{err}"
+        );
+    }
+}
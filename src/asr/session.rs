@@ -0,0 +1,134 @@
+//! ASR Session Handle
+//!
+//! Controls a running real-time session: results are read from the
+//! receiver, and `finish` ends the session deterministically.
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+use super::error::{AsrError, AsrResult};
+use super::frame_queue::FrameQueue;
+use super::protocol::AsrResponse;
+use super::stats::{SessionMetrics, SessionStats};
+
+/// How the sender task spaces out audio frames
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FramePacing {
+    /// Send frames as soon as they are queued (live capture is paced already)
+    #[default]
+    Immediate,
+    /// Send frames no faster than `speed` times real time
+    ///
+    /// The source is slowed down instead of dropping frames when it runs ahead.
+    RealTime { speed: f32 },
+}
+
+impl FramePacing {
+    /// Delay between consecutive `frame_ms` frames, if paced
+    pub(crate) fn frame_interval(self, frame_ms: u32) -> Option<Duration> {
+        match self {
+            FramePacing::Immediate => None,
+            FramePacing::RealTime { speed } => Some(Duration::from_secs_f32(
+                frame_ms as f32 / 1000.0 / speed,
+            )),
+        }
+    }
+}
+
+/// How a session ended, as observed by the response reader
+pub(crate) enum SessionEnd {
+    /// SessionFinished arrived; carries the last final result, if any
+    Finished(Option<AsrResponse>),
+    /// The server reported an error
+    Failed(AsrResponse),
+    /// The connection closed before SessionFinished
+    Disconnected,
+}
+
+/// Handle to a running real-time ASR session
+pub struct SessionHandle {
+    results: Option<mpsc::Receiver<AsrResponse>>,
+    frame_queue: Arc<FrameQueue>,
+    metrics: Arc<SessionMetrics>,
+    end_rx: Option<oneshot::Receiver<SessionEnd>>,
+    sender_task: JoinHandle<()>,
+    reader_task: JoinHandle<()>,
+}
+
+impl SessionHandle {
+    pub(crate) fn new(
+        results: mpsc::Receiver<AsrResponse>,
+        frame_queue: Arc<FrameQueue>,
+        metrics: Arc<SessionMetrics>,
+        end_rx: oneshot::Receiver<SessionEnd>,
+        sender_task: JoinHandle<()>,
+        reader_task: JoinHandle<()>,
+    ) -> Self {
+        Self {
+            results: Some(results),
+            frame_queue,
+            metrics,
+            end_rx: Some(end_rx),
+            sender_task,
+            reader_task,
+        }
+    }
+
+    /// Take the receiver for interim and final results
+    ///
+    /// The receiver closes once the session has ended.
+    pub fn take_results(&mut self) -> Option<mpsc::Receiver<AsrResponse>> {
+        self.results.take()
+    }
+
+    /// Current statistics for this session
+    pub fn stats(&self) -> SessionStats {
+        self.metrics.snapshot()
+    }
+
+    /// Shared counters, for reading statistics after the handle is gone
+    pub fn metrics(&self) -> Arc<SessionMetrics> {
+        self.metrics.clone()
+    }
+
+    /// Stop sending audio and wait for the server to finish the session
+    ///
+    /// Queued audio is flushed, the last frame is tagged Last and
+    /// FinishSession is sent. Resolves with the final result if one arrived,
+    /// `Ok(None)` if the server finished without recognizing any speech, or
+    /// a `Timeout` error if SessionFinished never came.
+    pub async fn finish(&mut self, timeout: Duration) -> AsrResult<Option<AsrResponse>> {
+        self.frame_queue.close();
+
+        let Some(end_rx) = self.end_rx.take() else {
+            return Err(AsrError::Handshake("session already finished".to_string()));
+        };
+
+        match tokio::time::timeout(timeout, end_rx).await {
+            Ok(Ok(SessionEnd::Finished(final_result))) => Ok(final_result),
+            Ok(Ok(SessionEnd::Failed(response))) => Err(AsrError::ServerRejected {
+                code: response.error_code,
+                message: response.error_msg,
+            }),
+            Ok(Ok(SessionEnd::Disconnected)) | Ok(Err(_)) => Err(AsrError::Network(
+                "connection closed before the session finished".to_string(),
+            )),
+            Err(_) => {
+                self.cancel();
+                Err(AsrError::Timeout(format!(
+                    "server did not finish the session within {}s",
+                    timeout.as_secs_f32()
+                )))
+            }
+        }
+    }
+
+    /// Abort the session without waiting for the server
+    pub fn cancel(&self) {
+        self.frame_queue.close();
+        self.sender_task.abort();
+        self.reader_task.abort();
+    }
+}
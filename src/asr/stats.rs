@@ -0,0 +1,187 @@
+//! Session Statistics
+//!
+//! Counters shared between the sender and reader tasks of a session, used
+//! to diagnose slow or lossy recognition.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use super::frame_queue::FrameQueue;
+
+/// Unacknowledged audio tolerated before warning
+const ACK_GAP_WARN_MS: u64 = 1000;
+
+/// Snapshot of a session's statistics
+#[derive(Debug, Clone, Default)]
+pub struct SessionStats {
+    /// Audio frames written to the socket
+    pub frames_sent: u64,
+    /// Compressed audio bytes written to the socket
+    pub bytes_sent: u64,
+    /// Frames dropped because the connection couldn't keep up
+    pub frames_dropped: u64,
+    /// Wall-clock time since the session started
+    pub duration: Duration,
+    /// Time from the first audio frame to the first result
+    pub first_result_latency: Option<Duration>,
+    /// Time from the Last frame to the final result that followed it
+    pub final_result_latency: Option<Duration>,
+    /// Highest `packet_number` acknowledged in a heartbeat
+    pub last_acked_packet: Option<u64>,
+    /// Largest observed gap between frames sent and frames acknowledged
+    pub max_ack_gap: u64,
+    /// Audio per frame, in ms
+    pub frame_duration_ms: u32,
+}
+
+impl SessionStats {
+    /// Compressed audio per second of audio, in bits per second; shows
+    /// what encoder options such as FEC cost
+    pub fn audio_bitrate_bps(&self) -> u64 {
+        let audio_ms = self.frames_sent * self.frame_duration_ms as u64;
+        if audio_ms == 0 {
+            return 0;
+        }
+        self.bytes_sent * 8 * 1000 / audio_ms
+    }
+}
+
+impl fmt::Display for SessionStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |d: Option<Duration>| {
+            d.map(|d| format!("{}ms", d.as_millis()))
+                .unwrap_or_else(|| "-".to_string())
+        };
+        write!(
+            f,
+            "{} frames ({} bytes, {:.1} kbps, {} dropped) in {:.1}s, first result after {}, final after {}, max ack gap {}",
+            self.frames_sent,
+            self.bytes_sent,
+            self.audio_bitrate_bps() as f64 / 1000.0,
+            self.frames_dropped,
+            self.duration.as_secs_f32(),
+            ms(self.first_result_latency),
+            ms(self.final_result_latency),
+            self.max_ack_gap
+        )
+    }
+}
+
+/// Live counters for a running session
+///
+/// The sender task records frames, the reader task records results; either
+/// side or the session owner can take a snapshot at any time.
+pub struct SessionMetrics {
+    started: Instant,
+    frames_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    first_frame_at: OnceLock<Instant>,
+    first_result_at: OnceLock<Instant>,
+    last_frame_at: OnceLock<Instant>,
+    final_after_last_at: OnceLock<Instant>,
+    acked_packet: AtomicI64,
+    max_ack_gap: AtomicU64,
+    gap_warned: AtomicBool,
+    frame_queue: Arc<FrameQueue>,
+    frame_duration_ms: u32,
+}
+
+impl SessionMetrics {
+    pub(crate) fn new(frame_queue: Arc<FrameQueue>, frame_duration_ms: u32) -> Self {
+        Self {
+            started: Instant::now(),
+            frames_sent: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
+            first_frame_at: OnceLock::new(),
+            first_result_at: OnceLock::new(),
+            last_frame_at: OnceLock::new(),
+            final_after_last_at: OnceLock::new(),
+            acked_packet: AtomicI64::new(-1),
+            max_ack_gap: AtomicU64::new(0),
+            gap_warned: AtomicBool::new(false),
+            frame_queue,
+            frame_duration_ms,
+        }
+    }
+
+    /// Record an audio frame written to the socket
+    pub(crate) fn record_frame(&self, bytes: usize, last: bool) {
+        let now = Instant::now();
+        let _ = self.first_frame_at.set(now);
+        if last {
+            let _ = self.last_frame_at.set(now);
+        }
+        self.frames_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Record a recognition result received from the server
+    pub(crate) fn record_result(&self, is_final: bool) {
+        let now = Instant::now();
+        let _ = self.first_result_at.set(now);
+        if is_final && self.last_frame_at.get().is_some() {
+            let _ = self.final_after_last_at.set(now);
+        }
+    }
+
+    /// Record a `packet_number` acknowledged by a server heartbeat
+    ///
+    /// Warns once when the server falls more than a second behind what we
+    /// sent, and again after it has caught up and falls behind anew.
+    pub(crate) fn record_ack(&self, packet_number: i32) {
+        if packet_number < 0 {
+            return;
+        }
+        let acked = self
+            .acked_packet
+            .fetch_max(packet_number as i64, Ordering::Relaxed)
+            .max(packet_number as i64) as u64;
+        let sent = self.frames_sent.load(Ordering::Relaxed);
+        let gap = sent.saturating_sub(acked);
+        self.max_ack_gap.fetch_max(gap, Ordering::Relaxed);
+
+        if gap * self.frame_duration_ms as u64 > ACK_GAP_WARN_MS {
+            if !self.gap_warned.swap(true, Ordering::Relaxed) {
+                tracing::warn!(
+                    "Server acknowledged packet {} but {} frames were sent ({} behind)",
+                    acked,
+                    sent,
+                    gap
+                );
+            }
+        } else {
+            self.gap_warned.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// Reset the send and acknowledgement counters for a retried session
+    ///
+    /// Frames replayed on the new session are recorded again.
+    pub(crate) fn restart(&self) {
+        self.frames_sent.store(0, Ordering::Relaxed);
+        self.bytes_sent.store(0, Ordering::Relaxed);
+        self.acked_packet.store(-1, Ordering::Relaxed);
+        self.gap_warned.store(false, Ordering::Relaxed);
+    }
+
+    /// Take a snapshot of the counters
+    pub fn snapshot(&self) -> SessionStats {
+        let between = |from: &OnceLock<Instant>, to: &OnceLock<Instant>| {
+            Some(to.get()?.saturating_duration_since(*from.get()?))
+        };
+
+        SessionStats {
+            frames_sent: self.frames_sent.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            frames_dropped: self.frame_queue.dropped(),
+            duration: self.started.elapsed(),
+            first_result_latency: between(&self.first_frame_at, &self.first_result_at),
+            final_result_latency: between(&self.last_frame_at, &self.final_after_last_at),
+            last_acked_packet: u64::try_from(self.acked_packet.load(Ordering::Relaxed)).ok(),
+            max_ack_gap: self.max_ack_gap.load(Ordering::Relaxed),
+            frame_duration_ms: self.frame_duration_ms,
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Automatic Gain Control
+//!
+//! Brings quiet and loud speakers to a similar level. The gain follows the
+//! frame RMS towards a target, dropping quickly (attack) and rising slowly
+//! (release). Frames near silence hold the gain so noise isn't pumped up.
+
+use crate::data::AudioConfig;
+
+/// Time constant for lowering the gain
+const ATTACK_MS: f32 = 50.0;
+/// Time constant for raising the gain
+const RELEASE_MS: f32 = 300.0;
+/// Frames quieter than this (dBFS) don't change the gain
+const NOISE_FLOOR_DB: f32 = -55.0;
+
+/// Streaming automatic gain control
+pub struct Agc {
+    target_db: f32,
+    max_gain_db: f32,
+    attack: f32,
+    release: f32,
+    gain_db: f32,
+}
+
+impl Agc {
+    /// AGC for frames of `frame_ms`, or None when `agc_enabled` is off
+    pub fn from_config(config: &AudioConfig, frame_ms: u32) -> Option<Self> {
+        if !config.agc_enabled {
+            return None;
+        }
+        let coefficient = |tau_ms: f32| 1.0 - (-(frame_ms as f32) / tau_ms).exp();
+        Some(Self {
+            target_db: config.agc_target_db,
+            max_gain_db: config.agc_max_gain_db.max(0.0),
+            attack: coefficient(ATTACK_MS),
+            release: coefficient(RELEASE_MS),
+            gain_db: 0.0,
+        })
+    }
+
+    /// Gain currently applied, in dB
+    pub fn gain_db(&self) -> f32 {
+        self.gain_db
+    }
+
+    /// Adjust the gain for one frame and apply it in place
+    pub fn process(&mut self, frame: &mut [i16]) {
+        if frame.is_empty() {
+            return;
+        }
+        let previous = self.gain_db;
+
+        let mean_square = frame.iter().map(|&s| (s as f64) * (s as f64)).sum::<f64>() / frame.len() as f64;
+        let level_db = (10.0 * (mean_square / (i16::MAX as f64 * i16::MAX as f64)).log10()) as f32;
+        if level_db > NOISE_FLOOR_DB {
+            let wanted = (self.target_db - level_db).clamp(-self.max_gain_db, self.max_gain_db);
+            let rate = if wanted < self.gain_db { self.attack } else { self.release };
+            self.gain_db += (wanted - self.gain_db) * rate;
+        }
+
+        // Ramp across the frame so gain changes don't click
+        let start = 10f32.powf(previous / 20.0);
+        let end = 10f32.powf(self.gain_db / 20.0);
+        let step = (end - start) / frame.len() as f32;
+        for (i, sample) in frame.iter_mut().enumerate() {
+            let gain = start + step * i as f32;
+            *sample = (*sample as f32 * gain)
+                .round()
+                .clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    const RATE: usize = 16_000;
+    const FRAME_MS: u32 = 20;
+
+    fn agc() -> Agc {
+        let config = AudioConfig {
+            agc_enabled: true,
+            ..AudioConfig::default()
+        };
+        Agc::from_config(&config, FRAME_MS).unwrap()
+    }
+
+    /// Level in dBFS (RMS) of `samples`
+    fn level_db(samples: &[i16]) -> f32 {
+        let mean_square = samples.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / samples.len() as f64;
+        (10.0 * (mean_square / (i16::MAX as f64).powi(2)).log10()) as f32
+    }
+
+    /// Run `seconds` of a 440 Hz sine at `level` dBFS through `agc` in
+    /// 20 ms frames, returning the last frame
+    fn settle(agc: &mut Agc, level: f32, seconds: usize) -> Vec<i16> {
+        let amplitude = i16::MAX as f32 * 10f32.powf(level / 20.0) * 2f32.sqrt();
+        let frame_len = RATE * FRAME_MS as usize / 1000;
+        let mut frame = Vec::new();
+        for index in 0..seconds * 1000 / FRAME_MS as usize {
+            frame = (0..frame_len)
+                .map(|i| {
+                    let t = (index * frame_len + i) as f32 / RATE as f32;
+                    (amplitude * (2.0 * PI * 440.0 * t).sin()) as i16
+                })
+                .collect();
+            agc.process(&mut frame);
+        }
+        frame
+    }
+
+    #[test]
+    fn quiet_speech_is_raised_to_the_target() {
+        let mut agc = agc();
+        let frame = settle(&mut agc, -30.0, 1);
+        let target = AudioConfig::default().agc_target_db;
+        assert!((level_db(&frame) - target).abs() < 1.0, "level {}", level_db(&frame));
+        assert!(agc.gain_db() > 9.0, "gain {}", agc.gain_db());
+    }
+
+    #[test]
+    fn loud_speech_is_lowered_to_the_target() {
+        let mut agc = agc();
+        let frame = settle(&mut agc, -6.0, 1);
+        let target = AudioConfig::default().agc_target_db;
+        assert!((level_db(&frame) - target).abs() < 1.0, "level {}", level_db(&frame));
+        assert!(agc.gain_db() < -13.0, "gain {}", agc.gain_db());
+    }
+
+    #[test]
+    fn gain_is_capped() {
+        let mut agc = agc();
+        settle(&mut agc, -50.0, 3);
+        assert!(agc.gain_db() <= AudioConfig::default().agc_max_gain_db);
+    }
+
+    #[test]
+    fn silence_holds_the_gain() {
+        let mut agc = agc();
+        settle(&mut agc, -30.0, 1);
+        let gain = agc.gain_db();
+        let mut silence = vec![0i16; 320];
+        for _ in 0..50 {
+            agc.process(&mut silence);
+        }
+        assert_eq!(agc.gain_db(), gain);
+    }
+}
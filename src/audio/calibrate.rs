@@ -0,0 +1,72 @@
+//! Noise Floor Calibration
+//!
+//! Statistics of a few seconds of room tone, used to pick the level below
+//! which audio counts as silence on this particular microphone.
+
+use std::fmt;
+
+/// Headroom between the loudest room-tone frames and the silence threshold
+const THRESHOLD_MARGIN_DB: f32 = 10.0;
+/// Range the recommended silence threshold is kept in
+const THRESHOLD_RANGE_DB: std::ops::RangeInclusive<f32> = -60.0..=-20.0;
+/// Level reported for digital silence
+const FLOOR_DB: f32 = -96.0;
+
+/// Levels of a quiet room, in dBFS
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoiseProfile {
+    /// Typical (median) frame RMS level
+    pub rms_floor: f32,
+    /// Loudest single sample
+    pub peak: f32,
+    /// Suggested `audio.silence_threshold_db`
+    pub recommended_threshold_db: f32,
+}
+
+impl NoiseProfile {
+    /// Profile of room tone given as 16 kHz mono frames
+    ///
+    /// Returns None if there is no audio at all.
+    pub(crate) fn from_frames<'a>(frames: impl Iterator<Item = &'a [i16]>) -> Option<Self> {
+        let mut levels = Vec::new();
+        let mut peak = 0u16;
+        for frame in frames.filter(|frame| !frame.is_empty()) {
+            let mean_square =
+                frame.iter().map(|&s| (s as f64) * (s as f64)).sum::<f64>() / frame.len() as f64;
+            levels.push(to_db(mean_square.sqrt()));
+            peak = peak.max(frame.iter().map(|s| s.unsigned_abs()).max().unwrap_or(0));
+        }
+        if levels.is_empty() {
+            return None;
+        }
+        levels.sort_by(f32::total_cmp);
+
+        // Frames above the 95th percentile are treated as stray noises
+        let loud = levels[(levels.len() - 1) * 95 / 100];
+        let recommended = (loud + THRESHOLD_MARGIN_DB)
+            .clamp(*THRESHOLD_RANGE_DB.start(), *THRESHOLD_RANGE_DB.end());
+        Some(Self {
+            rms_floor: levels[levels.len() / 2],
+            peak: to_db(peak as f64),
+            recommended_threshold_db: recommended.round(),
+        })
+    }
+}
+
+impl fmt::Display for NoiseProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "noise floor {:.1} dBFS, peak {:.1} dBFS, recommended silence threshold {:.0} dB",
+            self.rms_floor, self.peak, self.recommended_threshold_db
+        )
+    }
+}
+
+/// Amplitude in sample units to dBFS
+fn to_db(amplitude: f64) -> f32 {
+    if amplitude <= 0.0 {
+        return FLOOR_DB;
+    }
+    ((20.0 * (amplitude / i16::MAX as f64).log10()) as f32).max(FLOOR_DB)
+}
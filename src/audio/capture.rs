@@ -3,21 +3,180 @@
 use anyhow::{anyhow, Result};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::SampleFormat;
+use std::collections::VecDeque;
+use std::fmt;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 use std::sync::mpsc as std_mpsc;
 use tokio::sync::mpsc as tokio_mpsc;
+use tokio::sync::watch;
 
-use super::encoder::OpusEncoder;
+use super::agc::Agc;
+use super::calibrate::NoiseProfile;
+use super::encoder::{OpusDecoder, OpusEncoder};
+use super::filter::InputFilters;
+use super::resample::Resampler;
+use super::ring::SampleRing;
+use super::silence::SilenceDetector;
+use super::wav::WavDump;
+use crate::data::{AudioConfig, EncoderConfig};
 
 // Opus encoder always uses 16kHz mono
 const OPUS_SAMPLE_RATE: u32 = 16000;
 const OPUS_CHANNELS: u16 = 1;
-const FRAME_DURATION_MS: u32 = 20;
+/// Input streams hand audio over in chunks this long; Opus frames are
+/// `encoder.frame_duration_ms`
+const CHUNK_DURATION_MS: u32 = 20;
+
+/// Start of a recording checked for a muted microphone, in ms
+const MUTED_CHECK_MS: u32 = 1500;
+/// Largest sample magnitude still counted as digital silence
+const MUTED_EPSILON: u16 = 4;
+
+/// A stream's measured rate may be this far (fraction) from 16 kHz
+const RATE_TOLERANCE: f64 = 0.03;
+/// Streams shorter than this are too short to judge their rate
+const RATE_CHECK_MIN: Duration = Duration::from_secs(3);
+
+/// Sample rates probed against each device's supported ranges
+const COMMON_SAMPLE_RATES: &[u32] = &[8000, 11025, 16000, 22050, 32000, 44100, 48000, 96000];
+
+/// Live input state for meters and debugging
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InputLevel {
+    /// Level of the latest frame, 0.0 (silence) to 1.0 (full scale)
+    pub level: f32,
+    /// Gain the AGC currently applies, in dB (0 when it's off)
+    pub agc_gain_db: f32,
+}
+
+/// Counters for the current (or last) recording
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AudioCaptureStats {
+    /// Frames encoded and handed to the session
+    pub frames: u64,
+    /// Stream callbacks from the driver
+    pub callbacks: u64,
+    /// Samples the device delivered, counted at 16 kHz mono (after resampling)
+    pub samples: u64,
+    /// Input samples dropped because encoding fell behind the device
+    pub samples_dropped: u64,
+    /// Time the device delivered audio, from its first chunk until it was
+    /// closed or lost; only known once that happens
+    pub capture_time: Duration,
+}
+
+impl AudioCaptureStats {
+    /// Measured sample rate after resampling; should be close to 16 kHz
+    pub fn samples_per_sec(&self) -> f64 {
+        let secs = self.capture_time.as_secs_f64();
+        if secs <= 0.0 {
+            return 0.0;
+        }
+        self.samples as f64 / secs
+    }
+}
+
+impl fmt::Display for AudioCaptureStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} frames, {} callbacks, {} samples in {:.1}s ({:.0} Hz), {} input samples dropped",
+            self.frames,
+            self.callbacks,
+            self.samples,
+            self.capture_time.as_secs_f32(),
+            self.samples_per_sec(),
+            self.samples_dropped
+        )
+    }
+}
+
+/// Shared counters behind `AudioCaptureStats`
+#[derive(Default)]
+struct CaptureCounters {
+    frames: AtomicU64,
+    callbacks: AtomicU64,
+    samples: AtomicU64,
+    dropped: AtomicU64,
+    capture_us: AtomicU64,
+}
+
+impl CaptureCounters {
+    fn reset(&self) {
+        for counter in [&self.frames, &self.callbacks, &self.samples, &self.dropped, &self.capture_us] {
+            counter.store(0, Ordering::SeqCst);
+        }
+    }
+}
+
+/// An input device and what it can record
+#[derive(Debug, Clone)]
+pub struct AudioDeviceInfo {
+    /// Device name; repeated names get a " #2", " #3", ... suffix
+    pub name: String,
+    pub is_default: bool,
+    /// Supported sample rates in Hz, ascending
+    pub sample_rates: Vec<u32>,
+    /// Supported channel counts, ascending
+    pub channels: Vec<u16>,
+}
+
+/// Microphone trouble and silence during a recording
+#[derive(Debug, Clone)]
+pub enum AudioCaptureEvent {
+    /// The input stream failed, e.g. the device was unplugged; silence is
+    /// sent until `reopen` succeeds
+    StreamError { device: String, error: String },
+    /// Capture resumed on the named device
+    Reopened(String),
+    /// A `reopen` attempt found no usable device
+    ReopenFailed(String),
+    /// The user spoke and then stayed quiet for `silence_timeout_ms`
+    SilenceTimeout,
+    /// The start of the recording was digital silence; the microphone is
+    /// probably muted. Recording continues.
+    NoInput,
+}
 
 pub struct AudioCapture {
     is_recording: Arc<AtomicBool>,
+    /// Send silence instead of the microphone, keeping the session open
+    paused: Arc<AtomicBool>,
+    events: Mutex<Option<tokio_mpsc::UnboundedReceiver<AudioCaptureEvent>>>,
+    reopen_tx: Mutex<Option<std_mpsc::Sender<AudioConfig>>>,
+    /// Input level of the latest frame and the AGC gain
+    level: Arc<watch::Sender<InputLevel>>,
+    /// Microphone kept open between recordings for `audio.preroll_ms`
+    preroll: Mutex<Option<Preroll>>,
+    /// Statistics of the current recording
+    counters: Arc<CaptureCounters>,
+    /// Hands each recording to a test instead of opening a microphone
+    #[cfg(test)]
+    mock: Option<std_mpsc::Sender<MockRecording>>,
+}
+
+/// A recording a test drives in place of the capture thread
+#[cfg(test)]
+pub(crate) struct MockRecording {
+    /// Encoded frames for the session; dropping it ends the audio
+    pub frames: tokio_mpsc::Sender<Vec<u8>>,
+    pub events: tokio_mpsc::UnboundedSender<AudioCaptureEvent>,
+    /// Configs passed to `reopen`
+    pub reopen: std_mpsc::Receiver<AudioConfig>,
+    /// Cleared by `stop`
+    pub is_recording: Arc<AtomicBool>,
+}
+
+/// Idle listener keeping the last moments of audio before a recording
+struct Preroll {
+    /// Settings it was started with
+    config: AudioConfig,
+    running: Arc<AtomicBool>,
+    /// Latest 16 kHz mono samples, at most `preroll_ms` worth
+    ring: Arc<Mutex<VecDeque<i16>>>,
 }
 
 impl AudioCapture {
@@ -25,29 +184,227 @@ impl AudioCapture {
         let host = cpal::default_host();
         match host.default_input_device() {
             Some(device) => {
-                println!("[AudioCapture] Default device: {}", device.name().unwrap_or_default());
+                tracing::info!("Default input device: {}", device.name().unwrap_or_default());
             }
             None => {
-                println!("[AudioCapture] WARNING: No default input device found.");
+                tracing::warn!("No default input device found");
             }
         }
 
         Ok(Self {
             is_recording: Arc::new(AtomicBool::new(false)),
+            paused: Arc::new(AtomicBool::new(false)),
+            events: Mutex::new(None),
+            reopen_tx: Mutex::new(None),
+            level: Arc::new(watch::channel(InputLevel::default()).0),
+            preroll: Mutex::new(None),
+            counters: Arc::new(CaptureCounters::default()),
+            #[cfg(test)]
+            mock: None,
+        })
+    }
+
+    /// Capture whose recordings are handed to the returned receiver
+    #[cfg(test)]
+    pub(crate) fn mock() -> (Self, std_mpsc::Receiver<MockRecording>) {
+        let (tx, rx) = std_mpsc::channel();
+        let capture = Self {
+            is_recording: Arc::new(AtomicBool::new(false)),
+            paused: Arc::new(AtomicBool::new(false)),
+            events: Mutex::new(None),
+            reopen_tx: Mutex::new(None),
+            level: Arc::new(watch::channel(InputLevel::default()).0),
+            preroll: Mutex::new(None),
+            counters: Arc::new(CaptureCounters::default()),
+            mock: Some(tx),
+        };
+        (capture, rx)
+    }
+
+    /// List the input devices with their supported formats
+    ///
+    /// Runs on its own thread so COM can be set up for cpal without
+    /// touching the caller's apartment. Devices that vanish while being
+    /// queried are skipped.
+    pub fn list_devices() -> Result<Vec<AudioDeviceInfo>> {
+        thread::spawn(|| {
+            #[cfg(target_os = "windows")]
+            {
+                use windows::Win32::System::Com::{CoInitializeEx, COINIT_MULTITHREADED};
+                unsafe {
+                    let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+                }
+            }
+
+            let host = cpal::default_host();
+            let default_name = host.default_input_device().and_then(|d| d.name().ok());
+            let mut default_seen = false;
+            let mut devices = Vec::new();
+            for (name, device) in named_input_devices(&host)? {
+                let Ok(configs) = device.supported_input_configs() else {
+                    tracing::debug!("Input device '{}' went away while listing", name);
+                    continue;
+                };
+                let mut sample_rates = Vec::new();
+                let mut channels = Vec::new();
+                for config in configs {
+                    let (min, max) = (config.min_sample_rate().0, config.max_sample_rate().0);
+                    sample_rates.extend([min, max]);
+                    sample_rates.extend(
+                        COMMON_SAMPLE_RATES.iter().filter(|&&rate| rate >= min && rate <= max),
+                    );
+                    channels.push(config.channels());
+                }
+                sample_rates.sort_unstable();
+                sample_rates.dedup();
+                channels.sort_unstable();
+                channels.dedup();
+
+                // Only the first of several same-named devices can be told apart as default
+                let is_default = !default_seen
+                    && device.name().ok().is_some_and(|n| Some(n) == default_name);
+                default_seen |= is_default;
+                devices.push(AudioDeviceInfo {
+                    name,
+                    is_default,
+                    sample_rates,
+                    channels,
+                });
+            }
+            Ok(devices)
+        })
+        .join()
+        .map_err(|_| anyhow!("Enumerating input devices panicked"))?
+    }
+
+    /// Record `duration` of room tone from the configured device and
+    /// measure how loud it is
+    ///
+    /// The user should stay quiet meanwhile. Opens its own stream on its
+    /// own thread, like `list_devices`, so it works without a session.
+    /// Levels are taken after `gain_db`, before filters and AGC.
+    pub fn calibrate(config: &AudioConfig, duration: Duration) -> Result<NoiseProfile> {
+        let config = config.clone();
+        thread::spawn(move || {
+            #[cfg(target_os = "windows")]
+            {
+                use windows::Win32::System::Com::{CoInitializeEx, COINIT_MULTITHREADED};
+                unsafe {
+                    let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+                }
+            }
+
+            let (err_tx, err_rx) = std_mpsc::channel::<String>();
+            let sinks = StreamSinks {
+                is_recording: Arc::new(AtomicBool::new(true)),
+                errors: err_tx,
+                counters: Arc::new(CaptureCounters::default()),
+            };
+            let stream = open_stream(&config, &sinks)?;
+            let mut chunk = vec![0i16; stream.chunk_len];
+            let mut resampler = new_resampler(Some(&stream));
+            let gain = 10f32.powf(config.gain_db / 20.0);
+            let mut mono = Vec::new();
+            let mut samples = Vec::new();
+
+            let deadline = Instant::now() + duration;
+            while Instant::now() < deadline {
+                if let Ok(error) = err_rx.try_recv() {
+                    return Err(anyhow!("stream error on {}: {}", stream.name, error));
+                }
+                if !stream.ring.pop_exact(&mut chunk, Duration::from_millis(100)) {
+                    continue;
+                }
+                mono.clear();
+                downmix(&chunk, stream.channels, stream.input_channel, &mut mono);
+                if gain != 1.0 {
+                    apply_gain(&mut mono, gain);
+                }
+                resampler.process(&mono, &mut samples);
+            }
+
+            let frame_len = (OPUS_SAMPLE_RATE * CHUNK_DURATION_MS / 1000) as usize;
+            let profile = NoiseProfile::from_frames(samples.chunks_exact(frame_len))
+                .ok_or_else(|| anyhow!("No audio from {} while calibrating", stream.name))?;
+            tracing::info!("Calibrated {}: {}", stream.name, profile);
+            Ok(profile)
         })
+        .join()
+        .map_err(|_| anyhow!("Calibrating the microphone panicked"))?
+    }
+
+    /// Watch the input level (0.0 - 1.0) and AGC gain, updated every 20ms
+    /// while recording and reset to zero when capture stops
+    pub fn level(&self) -> watch::Receiver<InputLevel> {
+        self.level.subscribe()
+    }
+
+    /// Statistics of the current (or last) recording
+    pub fn stats(&self) -> AudioCaptureStats {
+        let counters = &self.counters;
+        AudioCaptureStats {
+            frames: counters.frames.load(Ordering::SeqCst),
+            callbacks: counters.callbacks.load(Ordering::SeqCst),
+            samples: counters.samples.load(Ordering::SeqCst),
+            samples_dropped: counters.dropped.load(Ordering::SeqCst),
+            capture_time: Duration::from_micros(counters.capture_us.load(Ordering::SeqCst)),
+        }
     }
 
     pub fn is_recording(&self) -> bool {
         self.is_recording.load(Ordering::SeqCst)
     }
 
-    pub fn start(&self) -> Result<tokio_mpsc::Receiver<Vec<u8>>> {
+    /// Start recording; with `detect_silence` a `SilenceTimeout` event is
+    /// sent once speech is followed by `silence_timeout_ms` of quiet
+    pub fn start(
+        &self,
+        config: &AudioConfig,
+        encoder: &EncoderConfig,
+        detect_silence: bool,
+    ) -> Result<tokio_mpsc::Receiver<Vec<u8>>> {
         if self.is_recording.swap(true, Ordering::SeqCst) {
             return Err(anyhow!("Already recording"));
         }
 
         let (tokio_tx, tokio_rx) = tokio_mpsc::channel::<Vec<u8>>(100);
         let is_recording = self.is_recording.clone();
+        let config = config.clone();
+        let encoder_config = encoder.clone();
+        let level = self.level.clone();
+        // Pauses in a meeting aren't the end of the dictation
+        let silence_detector = (detect_silence && !config.is_loopback())
+            .then(|| {
+                SilenceDetector::from_config(
+                    &config,
+                    std::time::Duration::from_millis(encoder.frame_duration_ms as u64),
+                )
+            })
+            .flatten();
+        let (events_tx, events_rx) = tokio_mpsc::unbounded_channel();
+        let (reopen_tx, reopen_rx) = std_mpsc::channel();
+        *self.events.lock().unwrap() = Some(events_rx);
+        self.paused.store(false, Ordering::SeqCst);
+        self.counters.reset();
+        let session = SessionLink {
+            events: events_tx,
+            reopen: reopen_rx,
+            paused: self.paused.clone(),
+            preroll: self.preroll.lock().unwrap().as_ref().map(|p| p.ring.clone()),
+            counters: self.counters.clone(),
+        };
+        *self.reopen_tx.lock().unwrap() = Some(reopen_tx);
+
+        #[cfg(test)]
+        if let Some(mock) = &self.mock {
+            let _ = mock.send(MockRecording {
+                frames: tokio_tx,
+                events: session.events,
+                reopen: session.reopen,
+                is_recording,
+            });
+            return Ok(tokio_rx);
+        }
 
         thread::spawn(move || {
             #[cfg(target_os = "windows")]
@@ -56,145 +413,485 @@ impl AudioCapture {
                 unsafe {
                     let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
                 }
-                println!("[AudioCapture] COM initialized");
+                tracing::debug!("COM initialized on the capture thread");
             }
 
-            println!("[AudioCapture] >>> Thread spawned <<<");
-            use std::io::Write;
-            let _ = std::io::stdout().flush();
+            tracing::debug!("Capture thread started");
             
             let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-                run_audio_capture(tokio_tx, is_recording.clone())
+                run_audio_capture(
+                    tokio_tx,
+                    is_recording.clone(),
+                    &config,
+                    &encoder_config,
+                    &level,
+                    silence_detector,
+                    session,
+                )
             }));
             
             match result {
                 Ok(Ok(_)) => {
-                    println!("[AudioCapture] Completed normally");
+                    tracing::debug!("Capture finished normally");
                 }
                 Ok(Err(e)) => {
-                    println!("[AudioCapture] ERROR: {}", e);
+                    tracing::error!("Audio capture failed: {:#}", e);
                 }
                 Err(panic_info) => {
-                    println!("[AudioCapture] PANIC: {:?}", panic_info);
+                    tracing::error!("Audio capture panicked: {:?}", panic_info);
                 }
             }
             
             is_recording.store(false, Ordering::SeqCst);
-            println!("[AudioCapture] Thread exiting");
-            let _ = std::io::stdout().flush();
+            level.send_replace(InputLevel::default());
+            tracing::debug!("Capture thread exiting");
         });
 
         tracing::info!("Audio capture started");
         Ok(tokio_rx)
     }
 
+    /// Take the events of the current recording (once per `start`)
+    pub fn take_event_receiver(&self) -> Option<tokio_mpsc::UnboundedReceiver<AudioCaptureEvent>> {
+        self.events.lock().unwrap().take()
+    }
+
+    /// Ask a recording whose stream failed to look for the device again,
+    /// preferring `config.device_name` and then the default device
+    pub fn reopen(&self, config: &AudioConfig) {
+        if let Some(tx) = self.reopen_tx.lock().unwrap().as_ref() {
+            let _ = tx.send(config.clone());
+        }
+    }
+
+    /// Keep the microphone open between recordings so the `preroll_ms`
+    /// before `start` are sent too
+    ///
+    /// Only restarts the listener when the relevant settings changed;
+    /// `preroll_ms = 0` closes it.
+    pub fn set_preroll(&self, config: &AudioConfig) {
+        let mut preroll = self.preroll.lock().unwrap();
+        if let Some(current) = preroll.as_ref() {
+            if current.config.preroll_ms == config.preroll_ms
+                && current.config.device_name == config.device_name
+                && current.config.input_channel == config.input_channel
+                && current.running.load(Ordering::SeqCst)
+            {
+                return;
+            }
+        }
+        if let Some(old) = preroll.take() {
+            old.running.store(false, Ordering::SeqCst);
+        }
+        if config.preroll_ms == 0 {
+            return;
+        }
+
+        let running = Arc::new(AtomicBool::new(true));
+        let ring = Arc::new(Mutex::new(VecDeque::new()));
+        let capacity = (OPUS_SAMPLE_RATE as u64 * config.preroll_ms / 1000) as usize;
+        tracing::info!("Keeping {}ms of audio before each recording", config.preroll_ms);
+        {
+            let config = config.clone();
+            let running = running.clone();
+            let ring = ring.clone();
+            thread::spawn(move || {
+                #[cfg(target_os = "windows")]
+                {
+                    use windows::Win32::System::Com::{CoInitializeEx, COINIT_MULTITHREADED};
+                    unsafe {
+                        let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+                    }
+                }
+                if let Err(e) = run_preroll(&config, &running, &ring, capacity) {
+                    tracing::warn!("Pre-roll listener stopped: {:#}", e);
+                }
+                running.store(false, Ordering::SeqCst);
+            });
+        }
+        *preroll = Some(Preroll {
+            config: config.clone(),
+            running,
+            ring,
+        });
+    }
+
+    /// Send silence instead of the microphone until `resume`
+    ///
+    /// Frames keep their timing, so the ASR session stays open and the
+    /// text recognized so far isn't finalized.
+    pub fn pause(&self) {
+        if self.is_recording() && !self.paused.swap(true, Ordering::SeqCst) {
+            tracing::info!("Audio capture paused");
+        }
+    }
+
+    /// Send the microphone again after `pause`
+    pub fn resume(&self) {
+        if self.paused.swap(false, Ordering::SeqCst) {
+            tracing::info!("Audio capture resumed");
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
     pub fn stop(&self) {
         self.is_recording.store(false, Ordering::SeqCst);
         tracing::info!("Audio capture stopped");
     }
 }
 
-fn run_audio_capture(
-    tokio_tx: tokio_mpsc::Sender<Vec<u8>>,
+/// Pick the input device whose name contains `name`, else the default one
+fn select_input_device(host: &cpal::Host, name: Option<&str>) -> Result<cpal::Device> {
+    if let Some(wanted) = name.map(str::trim).filter(|name| !name.is_empty()) {
+        let needle = wanted.to_lowercase();
+        let mut devices = named_input_devices(host)?;
+
+        // An exact name (as listed by `list_devices`) wins over a partial one
+        let exact = devices.iter().position(|(name, _)| name.to_lowercase() == needle);
+        let partial = || devices.iter().position(|(name, _)| name.to_lowercase().contains(&needle));
+        if let Some(index) = exact.or_else(partial) {
+            return Ok(devices.swap_remove(index).1);
+        }
+        let available: Vec<String> = devices.into_iter().map(|(name, _)| name).collect();
+        tracing::warn!(
+            "Microphone '{}' not found, using the default input device (available: {})",
+            wanted,
+            available.join(", ")
+        );
+    }
+
+    host.default_input_device()
+        .ok_or_else(|| anyhow!("No input device available"))
+}
+
+/// Input devices with names made unique by numbering repeats
+fn named_input_devices(host: &cpal::Host) -> Result<Vec<(String, cpal::Device)>> {
+    let mut seen: Vec<String> = Vec::new();
+    let mut devices = Vec::new();
+    for device in host.input_devices()? {
+        let Ok(name) = device.name() else {
+            continue;
+        };
+        let repeats = seen.iter().filter(|seen| **seen == name).count();
+        seen.push(name.clone());
+        let name = match repeats {
+            0 => name,
+            n => format!("{} #{}", name, n + 1),
+        };
+        devices.push((name, device));
+    }
+    Ok(devices)
+}
+
+/// Build an input stream that converts samples to i16 into `ring`
+///
+/// The callback only copies into the pre-allocated ring; it never
+/// allocates or waits. The first `discard` samples (interleaved, a whole
+/// number of frames) are thrown away.
+fn build_input_stream<T: cpal::SizedSample + 'static>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    sinks: StreamSinks,
+    ring: Arc<SampleRing>,
+    mut discard: usize,
+    convert: fn(T) -> i16,
+) -> Result<cpal::Stream> {
+    let StreamSinks {
+        is_recording,
+        errors: err_tx,
+        counters,
+    } = sinks;
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            if !is_recording.load(Ordering::SeqCst) {
+                return;
+            }
+            counters.callbacks.fetch_add(1, Ordering::Relaxed);
+            // Some drivers click or send garbage right after starting
+            let skipped = discard.min(data.len());
+            discard -= skipped;
+            let data = &data[skipped..];
+            if data.is_empty() {
+                return;
+            }
+            if !ring.push(data, convert) {
+                counters.dropped.fetch_add(data.len() as u64, Ordering::Relaxed);
+            }
+        },
+        move |err| {
+            tracing::debug!("Stream error callback: {}", err);
+            let _ = err_tx.send(err.to_string());
+        },
+        None,
+    )?;
+    Ok(stream)
+}
+
+/// Mix interleaved samples down to mono
+///
+/// Takes the 1-based `channel` alone when given, otherwise averages all
+/// channels. A trailing partial frame is ignored.
+fn downmix(interleaved: &[i16], channels: u16, channel: Option<u16>, mono: &mut Vec<i16>) {
+    let channels = channels.max(1) as usize;
+    if channels == 1 {
+        mono.extend_from_slice(interleaved);
+        return;
+    }
+    let frames = interleaved.chunks_exact(channels);
+    match channel {
+        Some(channel) => mono.extend(frames.map(|frame| frame[channel as usize - 1])),
+        None => mono.extend(frames.map(|frame| {
+            let sum: i32 = frame.iter().map(|&s| s as i32).sum();
+            (sum / channels as i32) as i16
+        })),
+    }
+}
+
+/// Scale samples by `gain` (linear), saturating instead of wrapping
+fn apply_gain(samples: &mut [i16], gain: f32) {
+    for sample in samples {
+        *sample = (*sample as f32 * gain)
+            .round()
+            .clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+    }
+}
+
+/// RMS level of `samples` on a -60..0 dBFS scale mapped to 0.0..1.0
+fn input_level(samples: &[i16]) -> f32 {
+    let sum_squares: f64 = samples
+        .iter()
+        .map(|&s| {
+            let s = s as f64 / 32768.0;
+            s * s
+        })
+        .sum();
+    let rms = (sum_squares / samples.len().max(1) as f64).sqrt();
+    if rms <= 0.0 {
+        return 0.0;
+    }
+    ((20.0 * rms.log10() + 60.0) / 60.0).clamp(0.0, 1.0) as f32
+}
+
+/// Convert a float sample in -1.0..=1.0 to i16, clipping overdriven input
+fn f32_to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * 32767.0) as i16
+}
+
+/// Convert an unsigned sample centered on 32768 to i16
+fn u16_to_i16(sample: u16) -> i16 {
+    (sample as i32 - 32768) as i16
+}
+
+/// Samples one stream delivered against the wall clock, to catch drivers
+/// that deliver fewer samples than their rate claims
+#[derive(Default)]
+struct RateMeter {
+    /// Arrival of the first chunk; later chunks are counted
+    since: Option<Instant>,
+    samples: u64,
+}
+
+impl RateMeter {
+    fn add(&mut self, samples: usize) {
+        if self.since.is_none() {
+            self.since = Some(Instant::now());
+        } else {
+            self.samples += samples as u64;
+        }
+    }
+
+    /// Add the stream's time to `counters`, warning if its rate was off
+    fn finish(self, device: &str, counters: &CaptureCounters) {
+        let Some(since) = self.since else {
+            return;
+        };
+        let elapsed = since.elapsed();
+        counters
+            .capture_us
+            .fetch_add(elapsed.as_micros() as u64, Ordering::SeqCst);
+        if elapsed < RATE_CHECK_MIN {
+            return;
+        }
+        let rate = self.samples as f64 / elapsed.as_secs_f64();
+        let deviation = rate / OPUS_SAMPLE_RATE as f64 - 1.0;
+        if deviation.abs() > RATE_TOLERANCE {
+            tracing::warn!(
+                "{} delivered {:.0} samples/s after resampling, expected {} ({:+.1}%); audio may be missing",
+                device,
+                rate,
+                OPUS_SAMPLE_RATE,
+                deviation * 100.0
+            );
+        }
+    }
+}
+
+/// Connection between a recording thread and its `AudioCapture`
+struct SessionLink {
+    events: tokio_mpsc::UnboundedSender<AudioCaptureEvent>,
+    reopen: std_mpsc::Receiver<AudioConfig>,
+    paused: Arc<AtomicBool>,
+    /// Pre-roll audio to send ahead of the microphone
+    preroll: Option<Arc<Mutex<VecDeque<i16>>>>,
+    counters: Arc<CaptureCounters>,
+}
+
+/// Where an input stream reports errors and what it delivered
+#[derive(Clone)]
+struct StreamSinks {
     is_recording: Arc<AtomicBool>,
-) -> Result<()> {
+    errors: std_mpsc::Sender<String>,
+    counters: Arc<CaptureCounters>,
+}
+
+/// Decodes every frame we send into the "as-sent" dump, next to the raw one
+struct SentDump {
+    decoder: OpusDecoder,
+    dump: WavDump,
+}
+
+impl SentDump {
+    fn create(raw: &WavDump) -> Result<Self> {
+        Ok(Self {
+            decoder: OpusDecoder::new(OPUS_SAMPLE_RATE, OPUS_CHANNELS)?,
+            dump: raw.companion("sent")?,
+        })
+    }
+
+    fn write(&mut self, packet: &[u8]) {
+        match self.decoder.decode(packet) {
+            Ok(samples) => self.dump.write(&samples),
+            Err(e) => tracing::warn!("Cannot decode a sent frame: {}", e),
+        }
+    }
+}
+
+/// An open input stream and the format it delivers
+struct OpenStream {
+    /// Capture stops when this is dropped
+    _stream: cpal::Stream,
+    name: String,
+    sample_rate: u32,
+    channels: u16,
+    /// Channel recorded alone (1-based), if configured and present
+    input_channel: Option<u16>,
+    /// Interleaved samples from the callback, drained by the opening thread
+    ring: Arc<SampleRing>,
+    /// Interleaved samples in one chunk
+    chunk_len: usize,
+}
+
+/// Pick the configured device and start streaming its samples into a ring
+///
+/// Must be called on the thread that reads the stream.
+fn open_stream(
+    audio_config: &AudioConfig,
+    sinks: &StreamSinks,
+) -> Result<OpenStream> {
     let host = cpal::default_host();
-    let device = host
-        .default_input_device()
-        .ok_or_else(|| anyhow!("No input device available"))?;
+    // WASAPI records what an output device plays when an input stream is
+    // built on it
+    let loopback = audio_config.is_loopback();
+    let device = if loopback {
+        host.default_output_device()
+            .ok_or_else(|| anyhow!("No output device available for loopback"))?
+    } else {
+        select_input_device(&host, audio_config.device_name.as_deref())?
+    };
+    let name = device.name().unwrap_or_default();
 
-    println!("[AudioCapture] Device: {}", device.name().unwrap_or_default());
+    if loopback {
+        tracing::info!("Recording system audio from: {}", name);
+    } else {
+        tracing::info!("Recording from microphone: {}", name);
+    }
 
     // Get the device's default config - USE THIS EXACTLY
-    let supported_config = device.default_input_config()?;
-    println!("[AudioCapture] Device config: {:?}", supported_config);
+    let supported_config = if loopback {
+        device.default_output_config()?
+    } else {
+        device.default_input_config()?
+    };
+    tracing::debug!("Device config: {:?}", supported_config);
 
     let native_sample_rate = supported_config.sample_rate().0;
     let native_channels = supported_config.channels();
     let sample_format = supported_config.sample_format();
     
-    println!("[AudioCapture] Native: {}Hz, {} channels, {:?}", 
-        native_sample_rate, native_channels, sample_format);
+    tracing::info!(
+        "Native format: {}Hz, {} channels, {:?}",
+        native_sample_rate,
+        native_channels,
+        sample_format
+    );
 
     // Use the device's EXACT config (don't override channels!)
     let config = supported_config.config();
-    println!("[AudioCapture] Using config: {:?}", config);
-
-    // Create Opus encoder (16kHz mono)
-    let mut encoder = match OpusEncoder::new(OPUS_SAMPLE_RATE, OPUS_CHANNELS) {
-        Ok(enc) => {
-            println!("[AudioCapture] Opus encoder created (16kHz mono)");
-            enc
-        }
-        Err(e) => {
-            println!("[AudioCapture] Opus encoder FAILED: {}", e);
-            return Err(e);
-        }
-    };
+    tracing::debug!("Using config: {:?}", config);
 
     // Calculate frame sizes
-    let samples_per_frame_native = (native_sample_rate * FRAME_DURATION_MS / 1000) as usize * native_channels as usize;
-    let samples_per_frame_opus = (OPUS_SAMPLE_RATE * FRAME_DURATION_MS / 1000) as usize; // mono
+    let samples_per_frame_native = (native_sample_rate * CHUNK_DURATION_MS / 1000) as usize * native_channels as usize;
+    tracing::debug!(
+        "Samples per frame: native={} ({}ch)",
+        samples_per_frame_native,
+        native_channels
+    );
 
-    println!("[AudioCapture] Samples/frame: native={} ({}ch), opus={} (mono)", 
-        samples_per_frame_native, native_channels, samples_per_frame_opus);
-
-    let (std_tx, std_rx) = std_mpsc::channel::<Vec<i16>>();
-
-    let is_recording_clone = is_recording.clone();
-    let frame_counter = Arc::new(AtomicU64::new(0));
-    let frame_counter_clone = frame_counter.clone();
-    let native_channels_clone = native_channels;
-
-    let err_fn = |err| {
-        println!("[AudioCapture] Stream error: {}", err);
-    };
+    // Interleaved frames are mixed down by the negotiated channel count
+    let channels = config.channels;
+    let input_channel = audio_config
+        .input_channel
+        .filter(|&channel| channel >= 1 && channel <= channels);
+    if let Some(channel) = audio_config.input_channel {
+        if input_channel.is_none() {
+            tracing::warn!(
+                "audio.input_channel = {} but the device has {} channel(s), averaging instead",
+                channel,
+                channels
+            );
+        }
+    }
 
+    // About a second of audio, so a stalled encoder doesn't lose any at once
+    let ring = Arc::new(SampleRing::new(native_sample_rate as usize * native_channels as usize));
+    let discard = (native_sample_rate as u64 * audio_config.discard_initial_ms / 1000) as usize
+        * native_channels as usize;
     let stream = match sample_format {
         SampleFormat::I16 => {
-            println!("[AudioCapture] Building I16 stream");
-            let mut buffer = Vec::<i16>::with_capacity(samples_per_frame_native * 2);
-            
-            device.build_input_stream(
+            tracing::debug!("Building I16 stream");
+            build_input_stream(
+                &device,
                 &config,
-                move |data: &[i16], _: &cpal::InputCallbackInfo| {
-                    if !is_recording_clone.load(Ordering::SeqCst) {
-                        return;
-                    }
-                    
-                    buffer.extend_from_slice(data);
-                    
-                    while buffer.len() >= samples_per_frame_native {
-                        let frame: Vec<i16> = buffer.drain(..samples_per_frame_native).collect();
-                        let _ = std_tx.send(frame);
-                    }
-                },
-                err_fn,
-                None,
+                sinks.clone(),
+                ring.clone(),
+                discard,
+                |s: i16| s,
+            )?
+        }
+        SampleFormat::U16 => {
+            tracing::debug!("Building U16 stream");
+            build_input_stream(
+                &device,
+                &config,
+                sinks.clone(),
+                ring.clone(),
+                discard,
+                u16_to_i16,
             )?
         }
         SampleFormat::F32 => {
-            println!("[AudioCapture] Building F32 stream");
-            let mut buffer = Vec::<i16>::with_capacity(samples_per_frame_native * 2);
-            
-            device.build_input_stream(
+            tracing::debug!("Building F32 stream");
+            build_input_stream(
+                &device,
                 &config,
-                move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                    if !is_recording_clone.load(Ordering::SeqCst) {
-                        return;
-                    }
-                    
-                    // Convert f32 to i16
-                    let samples: Vec<i16> = data.iter().map(|s| (*s * 32767.0) as i16).collect();
-                    buffer.extend_from_slice(&samples);
-                    
-                    while buffer.len() >= samples_per_frame_native {
-                        let frame: Vec<i16> = buffer.drain(..samples_per_frame_native).collect();
-                        let _ = std_tx.send(frame);
-                    }
-                },
-                err_fn,
-                None,
+                sinks.clone(),
+                ring.clone(),
+                discard,
+                f32_to_i16,
             )?
         }
         format => {
@@ -203,78 +900,413 @@ fn run_audio_capture(
     };
 
     stream.play()?;
-    println!("[AudioCapture] Stream playing!");
-    println!("[Mic] Recording started...");
+    tracing::debug!("Stream playing");
+
+    Ok(OpenStream {
+        _stream: stream,
+        name,
+        sample_rate: native_sample_rate,
+        channels,
+        input_channel,
+        ring,
+        chunk_len: samples_per_frame_native,
+    })
+}
+
+/// Resampler from the stream's rate to the encoder's
+fn new_resampler(stream: Option<&OpenStream>) -> Resampler {
+    let rate = stream.map_or(OPUS_SAMPLE_RATE, |stream| stream.sample_rate);
+    if rate != OPUS_SAMPLE_RATE {
+        tracing::info!("Resampling {}Hz -> {}Hz", rate, OPUS_SAMPLE_RATE);
+    }
+    Resampler::new(rate, OPUS_SAMPLE_RATE)
+}
+
+/// Fill `ring` with the latest 16 kHz mono audio until `running` clears
+fn run_preroll(
+    config: &AudioConfig,
+    running: &Arc<AtomicBool>,
+    ring: &Mutex<VecDeque<i16>>,
+    capacity: usize,
+) -> Result<()> {
+    let (err_tx, err_rx) = std_mpsc::channel::<String>();
+    let sinks = StreamSinks {
+        is_recording: running.clone(),
+        errors: err_tx,
+        // Idle audio isn't part of any recording's statistics
+        counters: Arc::new(CaptureCounters::default()),
+    };
+    let stream = open_stream(config, &sinks)?;
+    let mut frame = vec![0i16; stream.chunk_len];
+    let mut resampler = new_resampler(Some(&stream));
+    let mut mono = Vec::new();
+    let mut resampled = Vec::new();
+
+    while running.load(Ordering::SeqCst) {
+        if let Ok(error) = err_rx.try_recv() {
+            return Err(anyhow!("stream error on {}: {}", stream.name, error));
+        }
+        if !stream.ring.pop_exact(&mut frame, std::time::Duration::from_millis(100)) {
+            continue;
+        }
+        mono.clear();
+        downmix(&frame, stream.channels, stream.input_channel, &mut mono);
+        resampled.clear();
+        resampler.process(&mono, &mut resampled);
+
+        let mut ring = ring.lock().unwrap();
+        ring.extend(resampled.iter().copied());
+        let excess = ring.len().saturating_sub(capacity);
+        ring.drain(..excess);
+    }
+    Ok(())
+}
+
+fn run_audio_capture(
+    tokio_tx: tokio_mpsc::Sender<Vec<u8>>,
+    is_recording: Arc<AtomicBool>,
+    audio_config: &AudioConfig,
+    encoder_config: &EncoderConfig,
+    level: &Arc<watch::Sender<InputLevel>>,
+    mut silence_detector: Option<SilenceDetector>,
+    session: SessionLink,
+) -> Result<()> {
+    let SessionLink {
+        events,
+        reopen: reopen_rx,
+        paused,
+        preroll,
+        counters,
+    } = session;
+
+    // Create Opus encoder (16kHz mono)
+    let mut encoder = match OpusEncoder::with_config(OPUS_SAMPLE_RATE, OPUS_CHANNELS, encoder_config) {
+        Ok(enc) => {
+            tracing::debug!("Opus encoder created (16kHz mono)");
+            enc
+        }
+        Err(e) => {
+            tracing::error!("Cannot create the Opus encoder: {:#}", e);
+            return Err(e);
+        }
+    };
+
+    let frame_ms = encoder.frame_duration_ms();
+    let samples_per_frame_opus = encoder.frame_size(); // mono
+    // Progress is logged about once a second
+    let frames_per_sec = (1000 / frame_ms).max(1) as u64;
+    let muted_check_frames = MUTED_CHECK_MS / frame_ms;
+    let frame_secs = frame_ms as f32 / 1000.0;
+    let silence = vec![0i16; samples_per_frame_opus];
+    // Reused for every frame; only the encoded packet handed on is allocated
+    let mut opus_samples = Vec::<i16>::with_capacity(samples_per_frame_opus);
+    let mut packet = Vec::<u8>::new();
+
+    let (err_tx, err_rx) = std_mpsc::channel::<String>();
+
+    let frame_counter = &counters.frames;
+
+    let sinks = StreamSinks {
+        is_recording: is_recording.clone(),
+        errors: err_tx,
+        counters: counters.clone(),
+    };
+    let mut current = Some(open_stream(audio_config, &sinks)?);
+    tracing::info!("Recording started");
+
+    // Streaming resampler; output is encoded once a full frame is ready
+    let mut resampler = new_resampler(current.as_ref());
+    let mut rate = RateMeter::default();
+    let mut resampled = Vec::<i16>::with_capacity(samples_per_frame_opus * 2);
+    let mut mono_frame = Vec::<i16>::new();
+    let mut frame = Vec::<i16>::new();
+    let dump = audio_config
+        .dump_wav_dir
+        .as_deref()
+        .map(str::trim)
+        .filter(|dir| !dir.is_empty())
+        .and_then(|dir| {
+            WavDump::create(std::path::Path::new(dir), audio_config.dump_wav_keep)
+                .map_err(|e| tracing::warn!("Cannot save the recording in {:?}: {}", dir, e))
+                .ok()
+        });
+    let mut sent_dump = dump.as_ref().and_then(|dump| {
+        SentDump::create(dump)
+            .map_err(|e| tracing::warn!("Cannot save the sent audio: {}", e))
+            .ok()
+    });
+    let gain = 10f32.powf(audio_config.gain_db / 20.0);
+    if audio_config.gain_db != 0.0 {
+        tracing::info!("Input gain {:+.1} dB", audio_config.gain_db);
+    }
+    // Audio from just before the start goes out first, as ordinary frames
+    if let Some(ring) = &preroll {
+        let start = resampled.len();
+        resampled.extend(ring.lock().unwrap().drain(..));
+        if gain != 1.0 {
+            apply_gain(&mut resampled[start..], gain);
+        }
+        tracing::debug!("Pre-roll: {} samples", resampled.len() - start);
+    }
+    let mut filters = InputFilters::from_config(audio_config, OPUS_SAMPLE_RATE);
+    if filters.is_active() {
+        tracing::info!(
+            "Filters: high-pass {}, noise gate {}",
+            audio_config.high_pass, audio_config.noise_gate
+        );
+    }
+    let mut agc = Agc::from_config(audio_config, frame_ms);
+    // Quiet frames so far while checking for a muted microphone, once per
+    // session; not for system audio, which is silent between meeting turns
+    let mut muted_check = (!audio_config.is_loopback()).then_some(0u32);
 
     // Process frames: convert to mono 16kHz and encode
     while is_recording.load(Ordering::SeqCst) {
-        match std_rx.recv_timeout(std::time::Duration::from_millis(100)) {
-            Ok(frame) => {
-                // Step 1: Convert stereo to mono (if needed)
-                let mono_frame: Vec<i16> = if native_channels_clone > 1 {
-                    // Average channels
-                    frame.chunks(native_channels_clone as usize)
-                        .map(|chunk| {
-                            let sum: i32 = chunk.iter().map(|&s| s as i32).sum();
-                            (sum / native_channels_clone as i32) as i16
-                        })
-                        .collect()
-                } else {
-                    frame
-                };
-                
-                // Step 2: Resample to 16kHz (if needed)
-                let mono_samples_per_native_frame = samples_per_frame_native / native_channels_clone as usize;
-                let resampled: Vec<i16> = if mono_samples_per_native_frame != samples_per_frame_opus {
-                    let ratio = mono_samples_per_native_frame as f32 / samples_per_frame_opus as f32;
-                    (0..samples_per_frame_opus)
-                        .map(|i| {
-                            let src_idx = ((i as f32 * ratio) as usize).min(mono_frame.len() - 1);
-                            mono_frame[src_idx]
-                        })
-                        .collect()
-                } else {
-                    mono_frame
-                };
-                
-                // Step 3: Convert to bytes
-                let pcm_bytes: Vec<u8> = resampled.iter().flat_map(|s| s.to_le_bytes()).collect();
-                
-                // Step 4: Encode to Opus
-                match encoder.encode(&pcm_bytes) {
-                    Ok(opus_frame) => {
-                        let count = frame_counter_clone.fetch_add(1, Ordering::SeqCst);
-                        if count == 0 {
-                            println!("[Audio] First frame captured and encoded!");
-                        }
-                        if count > 0 && count % 50 == 0 {
-                            println!("[AudioCapture] Frames: {} ({:.1}s)", count, count as f32 * 0.02);
-                        }
-                        
-                        if tokio_tx.try_send(opus_frame).is_err() {
-                            println!("[AudioCapture] Channel full, dropping frame");
-                        }
+        // The device failed (e.g. unplugged): drop it and wait to be told to reopen
+        if let Ok(error) = err_rx.try_recv() {
+            if let Some(lost) = current.take() {
+                std::mem::take(&mut rate).finish(&lost.name, &counters);
+                tracing::warn!("Stream error on {}: {}", lost.name, error);
+                let device = lost.name.clone();
+                drop(lost);
+                resampled.clear();
+                level.send_modify(|level| level.level = 0.0);
+                let _ = events.send(AudioCaptureEvent::StreamError { device, error });
+            }
+            continue;
+        }
+
+        let Some(stream) = current.as_ref() else {
+            if let Ok(config) = reopen_rx.try_recv() {
+                match open_stream(&config, &sinks) {
+                    Ok(reopened) => {
+                        while err_rx.try_recv().is_ok() {}
+                        let _ = events.send(AudioCaptureEvent::Reopened(reopened.name.clone()));
+                        current = Some(reopened);
+                        resampler = new_resampler(current.as_ref());
+                        continue;
                     }
                     Err(e) => {
-                        if frame_counter_clone.load(Ordering::SeqCst) == 0 {
-                            println!("[AudioCapture] First encode error: {}", e);
-                        }
+                        let _ = events.send(AudioCaptureEvent::ReopenFailed(e.to_string()));
                     }
                 }
             }
-            Err(std_mpsc::RecvTimeoutError::Timeout) => {
-                // Normal timeout
+
+            // Keep the session fed with silence until the device is back
+            if encoder.encode_i16(&silence, &mut packet).is_ok() {
+                if let Some(sent_dump) = &mut sent_dump {
+                    sent_dump.write(&packet);
+                }
+                let _ = tokio_tx.try_send(packet.clone());
             }
-            Err(std_mpsc::RecvTimeoutError::Disconnected) => {
-                println!("[AudioCapture] Channel disconnected");
-                break;
+            if let Some(dump) = &dump {
+                dump.write(&silence);
             }
+            thread::sleep(std::time::Duration::from_millis(frame_ms as u64));
+            continue;
+        };
+
+        frame.resize(stream.chunk_len, 0);
+        if !stream.ring.pop_exact(&mut frame, std::time::Duration::from_millis(100)) {
+            continue;
+        }
+        let value = input_level(&frame);
+        level.send_modify(|level| level.level = value);
+
+        // Step 1: Convert stereo to mono (if needed)
+        mono_frame.clear();
+        downmix(&frame, stream.channels, stream.input_channel, &mut mono_frame);
+        if gain != 1.0 {
+            apply_gain(&mut mono_frame, gain);
+        }
+        
+        // Step 2: Resample to 16kHz (if needed)
+        let before = resampled.len();
+        resampler.process(&mono_frame, &mut resampled);
+        let produced = resampled.len() - before;
+        counters.samples.fetch_add(produced as u64, Ordering::SeqCst);
+        rate.add(produced);
+
+        while resampled.len() >= samples_per_frame_opus {
+            opus_samples.clear();
+            opus_samples.extend(resampled.drain(..samples_per_frame_opus));
+
+            // Paused: same frame timing, but silence
+            let is_paused = paused.load(Ordering::SeqCst);
+            if is_paused {
+                opus_samples.fill(0);
+            } else {
+                if let Some(quiet_frames) = muted_check {
+                    muted_check = if opus_samples.iter().any(|s| s.unsigned_abs() > MUTED_EPSILON) {
+                        None
+                    } else if quiet_frames + 1 >= muted_check_frames {
+                        tracing::warn!("Only digital silence so far, microphone muted?");
+                        let _ = events.send(AudioCaptureEvent::NoInput);
+                        None
+                    } else {
+                        Some(quiet_frames + 1)
+                    };
+                }
+
+                // Muting is judged on the raw input, the gate zeroes quiet frames
+                filters.process(&mut opus_samples);
+                if let Some(agc) = &mut agc {
+                    agc.process(&mut opus_samples);
+                    let gain_db = agc.gain_db();
+                    level.send_modify(|level| level.agc_gain_db = gain_db);
+                }
+            }
+
+            if let Some(dump) = &dump {
+                dump.write(&opus_samples);
+            }
+
+            if !is_paused && silence_detector.as_mut().is_some_and(|detector| detector.process(&opus_samples)) {
+                tracing::info!("Silence after speech, asking to stop");
+                let _ = events.send(AudioCaptureEvent::SilenceTimeout);
+            }
+
+            // Step 3: Encode to Opus
+            match encoder.encode_i16(&opus_samples, &mut packet) {
+                Ok(_) => {
+                    let count = frame_counter.fetch_add(1, Ordering::SeqCst);
+                    if count == 0 {
+                        tracing::debug!("First frame captured and encoded");
+                    }
+                    if count > 0 && count % frames_per_sec == 0 {
+                        tracing::trace!("Frames: {} ({:.1}s)", count, count as f32 * frame_secs);
+                    }
+                    if let Some(sent_dump) = &mut sent_dump {
+                        sent_dump.write(&packet);
+                    }
+                
+                    if tokio_tx.try_send(packet.clone()).is_err() {
+                        tracing::warn!("Frame channel full, dropping frame");
+                    }
+                }
+                Err(e) => {
+                    if frame_counter.load(Ordering::SeqCst) == 0 {
+                        tracing::warn!("Cannot encode the first frame: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    // Less than a frame is left over when recording stops; send it padded
+    if current.is_some() && !resampled.is_empty() && !paused.load(Ordering::SeqCst) {
+        filters.process(&mut resampled);
+        if let Some(agc) = &mut agc {
+            agc.process(&mut resampled);
+        }
+        if let Some(dump) = &dump {
+            dump.write(&resampled);
+        }
+        let pcm: Vec<u8> = resampled.iter().flat_map(|s| s.to_le_bytes()).collect();
+        match encoder.encode_final(&pcm) {
+            Ok(opus_frame) => {
+                frame_counter.fetch_add(1, Ordering::SeqCst);
+                if let Some(sent_dump) = &mut sent_dump {
+                    sent_dump.write(&opus_frame);
+                }
+                let _ = tokio_tx.try_send(opus_frame);
+            }
+            Err(e) => tracing::warn!("Cannot encode the final frame: {}", e),
         }
     }
 
+    if let Some(stream) = &current {
+        rate.finish(&stream.name, &counters);
+    }
+
     let total = frame_counter.load(Ordering::SeqCst);
-    println!("[AudioCapture] Total frames: {}", total);
-    println!("[Mic] Stopped. {} frames ({:.1}s)", total, total as f32 * 0.02);
+    tracing::info!("Recording stopped: {} frames ({:.1}s)", total, total as f32 * frame_secs);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples after pushing `data` through a ring with `convert`, as the
+    /// stream callback does
+    fn through_ring<T: Copy>(data: &[T], convert: fn(T) -> i16) -> Vec<i16> {
+        let ring = SampleRing::new(data.len());
+        assert!(ring.push(data, convert));
+        let mut out = vec![0; data.len()];
+        assert!(ring.pop_exact(&mut out, Duration::ZERO));
+        out
+    }
+
+    #[test]
+    fn i16_samples_pass_unchanged() {
+        let data = [0i16, 1, -1, i16::MAX, i16::MIN, 12_345];
+        assert_eq!(through_ring(&data, |s: i16| s), data);
+    }
+
+    #[test]
+    fn u16_samples_are_recentered() {
+        let data = [0u16, 1, 32_767, 32_768, 32_769, u16::MAX];
+        assert_eq!(
+            through_ring(&data, u16_to_i16),
+            [i16::MIN, -32_767, -1, 0, 1, i16::MAX]
+        );
+    }
+
+    #[test]
+    fn f32_samples_are_scaled_and_clipped() {
+        let data = [0.0f32, 1.0, -1.0, 0.5, -0.5, 2.0, -2.0];
+        assert_eq!(
+            through_ring(&data, f32_to_i16),
+            [0, 32_767, -32_767, 16_383, -16_383, 32_767, -32_767]
+        );
+    }
+
+    #[test]
+    fn stereo_is_averaged_to_mono() {
+        // Left and right differ in every frame
+        let stereo = [1_000i16, 3_000, -2_000, 2_000, 32_767, 32_767, -32_768, -32_768, 10, -30];
+        let mut mono = Vec::new();
+        downmix(&stereo, 2, None, &mut mono);
+        assert_eq!(mono, [2_000, 0, 32_767, -32_768, -10]);
+    }
+
+    #[test]
+    fn downmix_picks_one_channel() {
+        let stereo = [1i16, 2, 3, 4, 5, 6];
+        let mut mono = Vec::new();
+        downmix(&stereo, 2, Some(2), &mut mono);
+        assert_eq!(mono, [2, 4, 6]);
+    }
+
+    #[test]
+    fn downmix_drops_a_partial_frame() {
+        let three_channels = [3i16, 6, 9, 30, 60, 90, 1];
+        let mut mono = Vec::new();
+        downmix(&three_channels, 3, None, &mut mono);
+        assert_eq!(mono, [6, 60]);
+
+        let mut mono = Vec::new();
+        downmix(&[7, 8], 1, None, &mut mono);
+        assert_eq!(mono, [7, 8]);
+    }
+
+    #[test]
+    fn six_db_gain_doubles_amplitude() {
+        let gain = 10f32.powf(6.0 / 20.0);
+        let mut samples = [1_000i16, -1_000, 8_000, 0];
+        apply_gain(&mut samples, gain);
+        for (sample, doubled) in samples.iter().zip([2_000i16, -2_000, 16_000, 0]) {
+            // +6 dB is a factor of 1.995
+            assert!((sample - doubled).abs() <= doubled.abs() / 100, "{} vs {}", sample, doubled);
+        }
+    }
+
+    #[test]
+    fn gain_saturates_instead_of_wrapping() {
+        let mut samples = [20_000i16, -20_000, i16::MAX, i16::MIN];
+        apply_gain(&mut samples, 10f32.powf(20.0 / 20.0));
+        assert_eq!(samples, [i16::MAX, i16::MIN, i16::MAX, i16::MIN]);
+    }
+}
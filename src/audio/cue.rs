@@ -0,0 +1,128 @@
+//! Sound Cues
+//!
+//! Short tones played through the default output device so the user can
+//! hear that recording started, stopped or failed without looking at the
+//! floating button.
+
+use anyhow::{anyhow, Result};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample};
+use std::f32::consts::PI;
+use std::thread;
+use std::time::Duration;
+
+/// Peak amplitude of the tones (full scale is 1.0)
+const VOLUME: f32 = 0.2;
+/// Fade in and out, so tones don't click
+const FADE_MS: f32 = 5.0;
+/// Extra time the stream is kept open for the device to drain
+const DRAIN: Duration = Duration::from_millis(50);
+
+/// A session transition the user can hear
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cue {
+    /// Rising beep: recording started
+    Start,
+    /// Falling beep: recording stopped
+    Stop,
+    /// Low buzz: the session failed
+    Error,
+}
+
+impl Cue {
+    /// Tones as (frequency in Hz, duration in ms)
+    fn tones(self) -> &'static [(f32, u32)] {
+        match self {
+            Cue::Start => &[(660.0, 60), (880.0, 60)],
+            Cue::Stop => &[(880.0, 60), (660.0, 60)],
+            Cue::Error => &[(220.0, 90), (0.0, 40), (220.0, 90)],
+        }
+    }
+
+    /// Total length of the cue
+    pub fn duration(self) -> Duration {
+        Duration::from_millis(self.tones().iter().map(|&(_, ms)| ms as u64).sum())
+    }
+
+    /// Play the cue on its own thread and return at once
+    pub fn play(self) {
+        thread::spawn(move || self.play_and_wait());
+    }
+
+    /// Play the cue and return once it has finished
+    ///
+    /// Used before system audio is recorded, so the cue isn't captured.
+    pub fn play_and_wait(self) {
+        if let Err(e) = self.play_blocking() {
+            tracing::debug!("Cannot play {:?} cue: {:#}", self, e);
+        }
+    }
+
+    fn play_blocking(self) -> Result<()> {
+        #[cfg(target_os = "windows")]
+        {
+            use windows::Win32::System::Com::{CoInitializeEx, COINIT_MULTITHREADED};
+            unsafe {
+                let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+            }
+        }
+
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| anyhow!("no output device"))?;
+        let supported = device.default_output_config()?;
+        let config = supported.config();
+        let samples = self.render(config.sample_rate.0);
+        let channels = config.channels as usize;
+
+        let stream = match supported.sample_format() {
+            SampleFormat::F32 => build_output_stream::<f32>(&device, &config, samples, channels)?,
+            SampleFormat::I16 => build_output_stream::<i16>(&device, &config, samples, channels)?,
+            SampleFormat::U16 => build_output_stream::<u16>(&device, &config, samples, channels)?,
+            format => return Err(anyhow!("unsupported output format {:?}", format)),
+        };
+        stream.play()?;
+        thread::sleep(self.duration() + DRAIN);
+        Ok(())
+    }
+
+    /// Mono samples of the cue at `sample_rate`
+    fn render(self, sample_rate: u32) -> Vec<f32> {
+        let rate = sample_rate as f32;
+        let fade = (rate * FADE_MS / 1000.0).max(1.0);
+        let mut samples = Vec::new();
+        for &(frequency, ms) in self.tones() {
+            let len = (rate * ms as f32 / 1000.0) as usize;
+            samples.extend((0..len).map(|i| {
+                if frequency <= 0.0 {
+                    return 0.0;
+                }
+                let envelope = (i as f32 / fade).min((len - i) as f32 / fade).min(1.0);
+                (2.0 * PI * frequency * i as f32 / rate).sin() * VOLUME * envelope
+            }));
+        }
+        samples
+    }
+}
+
+/// Output stream playing mono `samples` on every channel, then silence
+fn build_output_stream<T: SizedSample + FromSample<f32>>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    samples: Vec<f32>,
+    channels: usize,
+) -> Result<cpal::Stream> {
+    let mut next = samples.into_iter();
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            for frame in data.chunks_mut(channels.max(1)) {
+                let value = T::from_sample(next.next().unwrap_or(0.0));
+                frame.fill(value);
+            }
+        },
+        |err| tracing::debug!("Cue stream error: {}", err),
+        None,
+    )?;
+    Ok(stream)
+}
@@ -2,44 +2,240 @@
 //!
 //! Encodes PCM audio data to Opus format.
 
-use anyhow::{anyhow, Result};
-use opus::{Application, Channels, Encoder};
+use std::ffi::CStr;
+use std::ptr::NonNull;
+
+use anyhow::{anyhow, bail, Result};
+use audiopus_sys as ffi;
+use opus::{Channels, Decoder};
+
+use crate::data::EncoderConfig;
+
+/// Bitrates libopus accepts
+const BITRATE_RANGE: std::ops::RangeInclusive<u32> = 500..=512_000;
+
+/// Largest Opus packet we ask for
+const MAX_PACKET_SIZE: usize = 4000;
+
+/// Longest frame an Opus packet can hold, in ms
+const MAX_FRAME_MS: usize = 120;
+
+/// Frame durations (ms) Opus can encode
+pub const FRAME_DURATIONS_MS: &[u32] = &[10, 20, 40, 60];
+
+/// Encoder complexity libopus accepts (10 is its default)
+const COMPLEXITY_RANGE: std::ops::RangeInclusive<u8> = 0..=10;
+
+/// Opus coding mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Application {
+    Voip,
+    Audio,
+    LowDelay,
+}
+
+impl Application {
+    fn code(self) -> i32 {
+        match self {
+            Application::Voip => ffi::OPUS_APPLICATION_VOIP,
+            Application::Audio => ffi::OPUS_APPLICATION_AUDIO,
+            Application::LowDelay => ffi::OPUS_APPLICATION_RESTRICTED_LOWDELAY,
+        }
+    }
+}
+
+/// libopus encoder state
+///
+/// Driven through the C API because the opus crate doesn't wrap
+/// OPUS_SET_COMPLEXITY or OPUS_SET_DTX.
+struct Encoder {
+    state: NonNull<ffi::OpusEncoder>,
+}
+
+// The state is only touched through `&mut self`
+unsafe impl Send for Encoder {}
+
+impl Encoder {
+    fn new(sample_rate: u32, channels: u16, application: Application) -> Result<Self> {
+        let mut error = ffi::OPUS_OK;
+        // SAFETY: libopus validates the arguments and reports through `error`
+        let state = unsafe {
+            ffi::opus_encoder_create(
+                sample_rate as i32,
+                channels as i32,
+                application.code(),
+                &mut error,
+            )
+        };
+        match NonNull::new(state) {
+            Some(state) if error == ffi::OPUS_OK => Ok(Self { state }),
+            _ => Err(anyhow!("Failed to create Opus encoder: {}", opus_error(error))),
+        }
+    }
+
+    /// Run a setter CTL such as OPUS_SET_BITRATE_REQUEST
+    fn set(&mut self, request: i32, value: i32) -> Result<(), String> {
+        // SAFETY: setter requests take a single opus_int32 argument
+        let result = unsafe { ffi::opus_encoder_ctl(self.state.as_ptr(), request, value) };
+        check(result)
+    }
+
+    /// Run a getter CTL such as OPUS_GET_BITRATE_REQUEST
+    fn get(&mut self, request: i32) -> Result<i32, String> {
+        let mut value = 0i32;
+        // SAFETY: getter requests write one opus_int32 through the pointer
+        let result =
+            unsafe { ffi::opus_encoder_ctl(self.state.as_ptr(), request, &mut value as *mut i32) };
+        check(result).map(|_| value)
+    }
+
+    fn reset_state(&mut self) -> Result<(), String> {
+        // SAFETY: OPUS_RESET_STATE takes no argument
+        let result = unsafe { ffi::opus_encoder_ctl(self.state.as_ptr(), ffi::OPUS_RESET_STATE) };
+        check(result)
+    }
+
+    fn encode(&mut self, samples: &[i16], out: &mut [u8]) -> Result<usize, String> {
+        // SAFETY: `samples` holds a whole frame (checked by the caller) and
+        // libopus writes at most `out.len()` bytes
+        let result = unsafe {
+            ffi::opus_encode(
+                self.state.as_ptr(),
+                samples.as_ptr(),
+                samples.len() as i32,
+                out.as_mut_ptr(),
+                out.len() as i32,
+            )
+        };
+        check(result).map(|_| result as usize)
+    }
+}
+
+impl Drop for Encoder {
+    fn drop(&mut self) {
+        // SAFETY: created by opus_encoder_create and destroyed only here
+        unsafe { ffi::opus_encoder_destroy(self.state.as_ptr()) }
+    }
+}
+
+/// Turn a negative libopus return code into its message
+fn check(result: i32) -> Result<(), String> {
+    if result < 0 {
+        Err(opus_error(result))
+    } else {
+        Ok(())
+    }
+}
+
+fn opus_error(code: i32) -> String {
+    // SAFETY: opus_strerror returns a static string for any code
+    let message = unsafe { CStr::from_ptr(ffi::opus_strerror(code)) };
+    format!("{} ({})", message.to_string_lossy(), code)
+}
 
 /// Opus encoder wrapper
 pub struct OpusEncoder {
     encoder: Encoder,
+    /// Settings re-applied by `reset`
+    config: EncoderConfig,
     sample_rate: u32,
     channels: u16,
     frame_size: usize,
 }
 
 impl OpusEncoder {
-    /// Create a new Opus encoder
+    /// Create a new Opus encoder with the default settings
     pub fn new(sample_rate: u32, channels: u16) -> Result<Self> {
-        let channels_enum = match channels {
-            1 => Channels::Mono,
-            2 => Channels::Stereo,
-            _ => return Err(anyhow!("Invalid channel count: {}", channels)),
-        };
+        Self::with_config(sample_rate, channels, &EncoderConfig::default())
+    }
 
-        let encoder = Encoder::new(sample_rate, channels_enum, Application::Audio)
-            .map_err(|e| anyhow!("Failed to create Opus encoder: {:?}", e))?;
+    /// Create a new Opus encoder with the `[encoder]` settings
+    pub fn with_config(sample_rate: u32, channels: u16, config: &EncoderConfig) -> Result<Self> {
+        if !(1..=2).contains(&channels) {
+            return Err(anyhow!("Invalid channel count: {}", channels));
+        }
+        let application = parse_application(&config.application)?;
+        if !FRAME_DURATIONS_MS.contains(&config.frame_duration_ms) {
+            bail!(
+                "encoder.frame_duration_ms = {} is not one of {:?}",
+                config.frame_duration_ms,
+                FRAME_DURATIONS_MS
+            );
+        }
+
+        let mut encoder = Encoder::new(sample_rate, channels, application)?;
+        apply_settings(&mut encoder, config)?;
+        let bitrate = match encoder.get(ffi::OPUS_GET_BITRATE_REQUEST) {
+            Ok(ffi::OPUS_BITRATE_MAX) => "max bitrate".to_string(),
+            Ok(bits) if bits > 0 => format!("{} bps", bits),
+            Ok(_) | Err(_) => "automatic bitrate".to_string(),
+        };
+        let rate_control = match (config.vbr, config.vbr_constraint) {
+            (false, _) => "CBR",
+            (true, true) => "constrained VBR",
+            (true, false) => "VBR",
+        };
+        let fec = if config.fec {
+            format!("FEC for {}% loss", config.expected_loss_pct)
+        } else {
+            "no FEC".to_string()
+        };
+        tracing::info!(
+            "Opus encoder: {} Hz, {} channel(s), {}ms frames, {:?}, {}, {}, complexity {}, {}{}",
+            sample_rate,
+            channels,
+            config.frame_duration_ms,
+            application,
+            bitrate,
+            rate_control,
+            config.complexity,
+            fec,
+            if config.dtx { ", DTX" } else { "" }
+        );
         
-        // Frame size for 20ms at the given sample rate
-        let frame_size = (sample_rate * 20 / 1000) as usize;
+        // Frame size for the configured duration at the given sample rate
+        let frame_size = (sample_rate * config.frame_duration_ms / 1000) as usize;
 
         Ok(Self {
             encoder,
+            config: config.clone(),
             sample_rate,
             channels,
             frame_size,
         })
     }
 
+    /// Clear the encoder's history (e.g. between sessions), keeping its settings
+    pub fn reset(&mut self) -> Result<()> {
+        self.encoder
+            .reset_state()
+            .map_err(|e| anyhow!("Failed to reset Opus encoder: {}", e))?;
+        apply_settings(&mut self.encoder, &self.config)
+    }
+
+    /// Send 1-2 byte "no data" packets instead of frames during silence
+    /// (libopus DTX)
+    pub fn set_dtx(&mut self, enabled: bool) -> Result<()> {
+        self.encoder
+            .set(ffi::OPUS_SET_DTX_REQUEST, enabled as i32)
+            .map_err(|e| anyhow!("Failed to set Opus DTX: {}", e))?;
+        self.config.dtx = enabled;
+        Ok(())
+    }
+
+    /// Trade encoding quality for CPU time (0-10; 10 is best)
+    pub fn set_complexity(&mut self, complexity: u8) -> Result<()> {
+        set_complexity(&mut self.encoder, complexity)?;
+        self.config.complexity = complexity;
+        Ok(())
+    }
+
     /// Encode PCM data to Opus
     ///
     /// Input: PCM data as bytes (16-bit samples, little-endian)
     /// Output: Opus-encoded frame
+    ///
+    /// Allocates per call; the capture loop uses `encode_i16` instead.
     pub fn encode(&mut self, pcm_data: &[u8]) -> Result<Vec<u8>> {
         // Convert bytes to i16 samples
         let samples: Vec<i16> = pcm_data
@@ -47,6 +243,34 @@ impl OpusEncoder {
             .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]))
             .collect();
 
+        let mut output = Vec::new();
+        self.encode_i16(&samples, &mut output)?;
+        Ok(output)
+    }
+
+    /// Encode the last, possibly short, frame of a recording
+    ///
+    /// Input shorter than a frame is padded with silence instead of being
+    /// rejected, so the end of the last word isn't lost.
+    pub fn encode_final(&mut self, pcm_data: &[u8]) -> Result<Vec<u8>> {
+        let mut samples: Vec<i16> = pcm_data
+            .chunks_exact(2)
+            .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]))
+            .collect();
+        let expected_samples = self.frame_size * self.channels as usize;
+        if samples.len() < expected_samples {
+            samples.resize(expected_samples, 0);
+        }
+
+        let mut output = Vec::new();
+        self.encode_i16(&samples, &mut output)?;
+        Ok(output)
+    }
+
+    /// Encode one frame of samples into `out`, reusing its allocation
+    ///
+    /// `out` is replaced by the encoded frame; its length is returned.
+    pub fn encode_i16(&mut self, samples: &[i16], out: &mut Vec<u8>) -> Result<usize> {
         // Ensure we have the right number of samples
         let expected_samples = self.frame_size * self.channels as usize;
         if samples.len() < expected_samples {
@@ -56,15 +280,20 @@ impl OpusEncoder {
                 expected_samples
             ));
         }
+        let samples = &samples[..expected_samples];
 
         // Encode to Opus
-        let mut output = vec![0u8; 4000]; // Max Opus frame size
-        let encoded_len = self.encoder
-            .encode(&samples[..expected_samples], &mut output)
-            .map_err(|e| anyhow!("Opus encode error: {:?}", e))?;
-        
-        output.truncate(encoded_len);
-        Ok(output)
+        out.resize(MAX_PACKET_SIZE, 0);
+        let encoded_len = match self.encoder.encode(samples, out) {
+            Ok(len) => len,
+            Err(e) => {
+                out.clear();
+                return Err(anyhow!("Opus encode error: {}", e));
+            }
+        };
+
+        out.truncate(encoded_len);
+        Ok(encoded_len)
     }
 
     /// Get the frame size in samples
@@ -72,6 +301,11 @@ impl OpusEncoder {
         self.frame_size
     }
 
+    /// Get the frame duration in ms
+    pub fn frame_duration_ms(&self) -> u32 {
+        self.config.frame_duration_ms
+    }
+
     /// Get the sample rate
     pub fn sample_rate(&self) -> u32 {
         self.sample_rate
@@ -82,3 +316,246 @@ impl OpusEncoder {
         self.channels
     }
 }
+
+/// Opus decoder wrapper
+///
+/// Turns the frames we send back into PCM, to hear what the server got.
+pub struct OpusDecoder {
+    decoder: Decoder,
+    channels: u16,
+    /// Samples per channel in the longest possible packet
+    max_frame_size: usize,
+}
+
+impl OpusDecoder {
+    /// Create a new Opus decoder
+    pub fn new(sample_rate: u32, channels: u16) -> Result<Self> {
+        let channels_enum = match channels {
+            1 => Channels::Mono,
+            2 => Channels::Stereo,
+            _ => return Err(anyhow!("Invalid channel count: {}", channels)),
+        };
+        let decoder = Decoder::new(sample_rate, channels_enum)
+            .map_err(|e| anyhow!("Failed to create Opus decoder: {:?}", e))?;
+
+        Ok(Self {
+            decoder,
+            channels,
+            max_frame_size: sample_rate as usize * MAX_FRAME_MS / 1000,
+        })
+    }
+
+    /// Decode one packet to interleaved samples
+    pub fn decode(&mut self, packet: &[u8]) -> Result<Vec<i16>> {
+        let mut output = vec![0i16; self.max_frame_size * self.channels as usize];
+        let decoded = self
+            .decoder
+            .decode(packet, &mut output, false)
+            .map_err(|e| anyhow!("Opus decode error: {:?}", e))?;
+        output.truncate(decoded * self.channels as usize);
+        Ok(output)
+    }
+}
+
+/// Apply bitrate and rate control from `[encoder]`
+fn apply_settings(encoder: &mut Encoder, config: &EncoderConfig) -> Result<()> {
+    if config.bitrate_bps != 0 {
+        if !BITRATE_RANGE.contains(&config.bitrate_bps) {
+            bail!(
+                "encoder.bitrate_bps = {} is out of range ({}..={}, or 0 for automatic)",
+                config.bitrate_bps,
+                BITRATE_RANGE.start(),
+                BITRATE_RANGE.end()
+            );
+        }
+        encoder
+            .set(ffi::OPUS_SET_BITRATE_REQUEST, config.bitrate_bps as i32)
+            .map_err(|e| anyhow!("Failed to set Opus bitrate {}: {}", config.bitrate_bps, e))?;
+    }
+    encoder
+        .set(ffi::OPUS_SET_VBR_REQUEST, config.vbr as i32)
+        .map_err(|e| anyhow!("Failed to set Opus VBR: {}", e))?;
+    encoder
+        .set(ffi::OPUS_SET_VBR_CONSTRAINT_REQUEST, config.vbr_constraint as i32)
+        .map_err(|e| anyhow!("Failed to set Opus VBR constraint: {}", e))?;
+    set_complexity(encoder, config.complexity)?;
+    encoder
+        .set(ffi::OPUS_SET_DTX_REQUEST, config.dtx as i32)
+        .map_err(|e| anyhow!("Failed to set Opus DTX: {}", e))?;
+
+    if config.expected_loss_pct > 100 {
+        bail!("encoder.expected_loss_pct = {} is above 100", config.expected_loss_pct);
+    }
+    encoder
+        .set(ffi::OPUS_SET_INBAND_FEC_REQUEST, config.fec as i32)
+        .map_err(|e| anyhow!("Failed to set Opus in-band FEC: {}", e))?;
+    encoder
+        .set(
+            ffi::OPUS_SET_PACKET_LOSS_PERC_REQUEST,
+            if config.fec { config.expected_loss_pct as i32 } else { 0 },
+        )
+        .map_err(|e| anyhow!("Failed to set Opus expected packet loss: {}", e))?;
+    Ok(())
+}
+
+fn set_complexity(encoder: &mut Encoder, complexity: u8) -> Result<()> {
+    if !COMPLEXITY_RANGE.contains(&complexity) {
+        bail!(
+            "encoder.complexity = {} is out of range ({}..={})",
+            complexity,
+            COMPLEXITY_RANGE.start(),
+            COMPLEXITY_RANGE.end()
+        );
+    }
+    encoder
+        .set(ffi::OPUS_SET_COMPLEXITY_REQUEST, complexity as i32)
+        .map_err(|e| anyhow!("Failed to set Opus complexity {}: {}", complexity, e))
+}
+
+/// Map `encoder.application` to the Opus mode
+fn parse_application(name: &str) -> Result<Application> {
+    match name.trim().to_lowercase().as_str() {
+        "voip" => Ok(Application::Voip),
+        "audio" => Ok(Application::Audio),
+        "lowdelay" | "low_delay" => Ok(Application::LowDelay),
+        other => bail!(
+            "encoder.application = \"{}\" is not one of \"voip\", \"audio\", \"lowdelay\"",
+            other
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16000;
+
+    /// `frames` 20ms frames of speech-like audio: a few harmonics of 220Hz
+    /// with a slow swell
+    fn voice(frames: usize) -> Vec<i16> {
+        let len = frames * (RATE as usize / 50);
+        (0..len)
+            .map(|i| {
+                let t = i as f64 / RATE as f64;
+                let envelope = 0.6 + 0.4 * (2.0 * std::f64::consts::PI * 3.0 * t).sin();
+                let wave: f64 = [1.0, 0.5, 0.3, 0.2]
+                    .iter()
+                    .enumerate()
+                    .map(|(k, a)| a * (2.0 * std::f64::consts::PI * 220.0 * (k + 1) as f64 * t).sin())
+                    .sum();
+                (wave * envelope * 6000.0) as i16
+            })
+            .collect()
+    }
+
+    fn encode_all(encoder: &mut OpusEncoder, samples: &[i16]) -> Vec<Vec<u8>> {
+        samples
+            .chunks_exact(encoder.frame_size())
+            .map(|frame| {
+                let mut packet = Vec::new();
+                encoder.encode_i16(frame, &mut packet).unwrap();
+                packet
+            })
+            .collect()
+    }
+
+    #[test]
+    fn complexity_levels_both_decode() {
+        let pcm = voice(50);
+        let mut sizes = Vec::new();
+        for complexity in [0, 10] {
+            let config = EncoderConfig {
+                complexity,
+                ..EncoderConfig::default()
+            };
+            let mut encoder = OpusEncoder::with_config(RATE, 1, &config).unwrap();
+            let mut decoder = OpusDecoder::new(RATE, 1).unwrap();
+            let packets = encode_all(&mut encoder, &pcm);
+            for packet in &packets {
+                assert!(!packet.is_empty());
+                assert_eq!(decoder.decode(packet).unwrap().len(), encoder.frame_size());
+            }
+            sizes.push(packets.iter().map(Vec::len).sum::<usize>());
+        }
+        assert_ne!(sizes[0], sizes[1], "complexity made no difference");
+    }
+
+    #[test]
+    fn round_trip_keeps_a_tone() {
+        let frame = RATE as usize / 50;
+        let tone: Vec<i16> = (0..frame * 50)
+            .map(|i| {
+                let t = i as f64 / RATE as f64;
+                ((2.0 * std::f64::consts::PI * 440.0 * t).sin() * 10000.0) as i16
+            })
+            .collect();
+        let mut encoder = OpusEncoder::new(RATE, 1).unwrap();
+        let mut decoder = OpusDecoder::new(RATE, 1).unwrap();
+        let mut decoded = Vec::new();
+        for packet in encode_all(&mut encoder, &tone) {
+            let samples = decoder.decode(&packet).unwrap();
+            assert_eq!(samples.len(), frame);
+            decoded.extend(samples);
+        }
+        assert_eq!(decoded.len(), tone.len());
+
+        // Skip the codec's start-up; compare level and pitch of the rest
+        let settled = &decoded[frame * 5..];
+        let rms = |s: &[i16]| {
+            (s.iter().map(|&x| (x as f64).powi(2)).sum::<f64>() / s.len() as f64).sqrt()
+        };
+        let expected = rms(&tone[frame * 5..]);
+        let actual = rms(settled);
+        assert!(
+            (actual - expected).abs() < expected * 0.1,
+            "level {:.0}, expected {:.0}",
+            actual,
+            expected
+        );
+        let crossings = settled
+            .windows(2)
+            .filter(|pair| (pair[0] < 0) != (pair[1] < 0))
+            .count();
+        let frequency = crossings as f64 / 2.0 / (settled.len() as f64 / RATE as f64);
+        assert!((frequency - 440.0).abs() < 5.0, "frequency {:.1}", frequency);
+    }
+
+    #[test]
+    fn complexity_out_of_range_is_rejected() {
+        let config = EncoderConfig {
+            complexity: 11,
+            ..EncoderConfig::default()
+        };
+        let error = OpusEncoder::with_config(RATE, 1, &config).err().unwrap();
+        assert!(error.to_string().contains("encoder.complexity"), "{}", error);
+    }
+
+    #[test]
+    fn fec_settings_reach_the_encoder_and_still_decode() {
+        let config = EncoderConfig {
+            fec: true,
+            expected_loss_pct: 20,
+            ..EncoderConfig::default()
+        };
+        let mut encoder = OpusEncoder::with_config(RATE, 1, &config).unwrap();
+        assert_eq!(encoder.encoder.get(ffi::OPUS_GET_INBAND_FEC_REQUEST), Ok(1));
+        assert_eq!(encoder.encoder.get(ffi::OPUS_GET_PACKET_LOSS_PERC_REQUEST), Ok(20));
+
+        let mut decoder = OpusDecoder::new(RATE, 1).unwrap();
+        for packet in encode_all(&mut encoder, &voice(50)) {
+            assert_eq!(decoder.decode(&packet).unwrap().len(), encoder.frame_size());
+        }
+    }
+
+    #[test]
+    fn loss_above_100_percent_is_rejected() {
+        let config = EncoderConfig {
+            fec: true,
+            expected_loss_pct: 101,
+            ..EncoderConfig::default()
+        };
+        let error = OpusEncoder::with_config(RATE, 1, &config).err().unwrap();
+        assert!(error.to_string().contains("encoder.expected_loss_pct"), "{}", error);
+    }
+}
@@ -0,0 +1,177 @@
+//! Input Filters
+//!
+//! Optional clean-up of the 16 kHz mono stream before encoding: a one-pole
+//! high-pass against rumble and DC offset, and a noise gate that mutes
+//! background noise between words.
+
+use std::f32::consts::PI;
+
+use crate::data::AudioConfig;
+
+/// One-pole high-pass filter
+pub struct HighPass {
+    alpha: f32,
+    last_input: f32,
+    last_output: f32,
+}
+
+impl HighPass {
+    /// Filter with a -3 dB point at `cutoff_hz`
+    pub fn new(cutoff_hz: f32, sample_rate: u32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff_hz.max(1.0));
+        let dt = 1.0 / sample_rate as f32;
+        Self {
+            alpha: rc / (rc + dt),
+            last_input: 0.0,
+            last_output: 0.0,
+        }
+    }
+
+    /// Filter `samples` in place
+    pub fn process(&mut self, samples: &mut [i16]) {
+        for sample in samples {
+            let input = *sample as f32;
+            let output = self.alpha * (self.last_output + input - self.last_input);
+            self.last_input = input;
+            self.last_output = output;
+            *sample = output.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        }
+    }
+}
+
+/// Mutes frames quieter than a threshold once they outlast the hold time
+pub struct NoiseGate {
+    /// Mean square below which a frame is noise
+    threshold: f64,
+    hold_samples: usize,
+    /// Samples since the last frame above the threshold
+    quiet_samples: usize,
+}
+
+impl NoiseGate {
+    /// Gate opening at `threshold_db` dBFS and closing `hold_ms` after the
+    /// level drops below it
+    pub fn new(threshold_db: f32, hold_ms: u64, sample_rate: u32) -> Self {
+        let full_scale = i16::MAX as f64;
+        Self {
+            threshold: full_scale * full_scale * 10f64.powf(threshold_db as f64 / 10.0),
+            hold_samples: (sample_rate as u64 * hold_ms / 1000) as usize,
+            // Closed until something is heard
+            quiet_samples: usize::MAX,
+        }
+    }
+
+    /// Gate one frame in place
+    pub fn process(&mut self, frame: &mut [i16]) {
+        if frame.is_empty() {
+            return;
+        }
+        let energy = frame.iter().map(|&s| (s as f64) * (s as f64)).sum::<f64>() / frame.len() as f64;
+        if energy >= self.threshold {
+            self.quiet_samples = 0;
+            return;
+        }
+        self.quiet_samples = self.quiet_samples.saturating_add(frame.len());
+        if self.quiet_samples > self.hold_samples {
+            frame.fill(0);
+        }
+    }
+}
+
+/// The filters enabled in `[audio]`
+pub struct InputFilters {
+    high_pass: Option<HighPass>,
+    noise_gate: Option<NoiseGate>,
+}
+
+impl InputFilters {
+    pub fn from_config(config: &AudioConfig, sample_rate: u32) -> Self {
+        Self {
+            high_pass: config
+                .high_pass
+                .then(|| HighPass::new(config.high_pass_hz, sample_rate)),
+            noise_gate: config.noise_gate.then(|| {
+                NoiseGate::new(config.noise_gate_threshold_db, config.noise_gate_hold_ms, sample_rate)
+            }),
+        }
+    }
+
+    /// Whether any filter is enabled
+    pub fn is_active(&self) -> bool {
+        self.high_pass.is_some() || self.noise_gate.is_some()
+    }
+
+    /// Run the enabled filters over one frame
+    pub fn process(&mut self, frame: &mut [i16]) {
+        if let Some(high_pass) = &mut self.high_pass {
+            high_pass.process(frame);
+        }
+        if let Some(noise_gate) = &mut self.noise_gate {
+            noise_gate.process(frame);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16_000;
+
+    /// A 1 kHz tone of `amplitude` on top of a DC `offset`, one second long
+    fn offset_tone(offset: f32, amplitude: f32) -> Vec<i16> {
+        (0..RATE)
+            .map(|i| (offset + amplitude * (2.0 * PI * 1_000.0 * i as f32 / RATE as f32).sin()) as i16)
+            .collect()
+    }
+
+    fn mean(samples: &[i16]) -> f64 {
+        samples.iter().map(|&s| s as f64).sum::<f64>() / samples.len() as f64
+    }
+
+    fn rms(samples: &[i16]) -> f64 {
+        (samples.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / samples.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn high_pass_removes_dc_and_keeps_the_tone() {
+        let mut samples = offset_tone(5_000.0, 3_000.0);
+        assert!(mean(&samples) > 4_900.0);
+
+        HighPass::new(90.0, RATE).process(&mut samples);
+        // Past the filter's settling time
+        let settled = &samples[RATE as usize / 4..];
+        assert!(mean(settled).abs() < 20.0, "DC left: {}", mean(settled));
+        let tone_rms = 3_000.0 / 2f64.sqrt();
+        assert!((rms(settled) - tone_rms).abs() < tone_rms * 0.05, "tone RMS {}", rms(settled));
+    }
+
+    #[test]
+    fn high_pass_works_across_frames() {
+        let mut whole = offset_tone(-4_000.0, 2_000.0);
+        let mut framed = whole.clone();
+        HighPass::new(90.0, RATE).process(&mut whole);
+        let mut filter = HighPass::new(90.0, RATE);
+        for frame in framed.chunks_mut(320) {
+            filter.process(frame);
+        }
+        assert_eq!(whole, framed);
+    }
+
+    #[test]
+    fn noise_gate_mutes_after_the_hold_time() {
+        let mut gate = NoiseGate::new(-50.0, 40, RATE);
+        let mut speech = vec![10_000i16; 320];
+        gate.process(&mut speech);
+        assert!(speech.iter().all(|&s| s == 10_000));
+
+        // 20 ms frames of quiet noise: the first two are within the hold
+        let mut frames = vec![vec![20i16; 320]; 3];
+        for frame in &mut frames {
+            gate.process(frame);
+        }
+        assert!(frames[0].iter().all(|&s| s == 20));
+        assert!(frames[1].iter().all(|&s| s == 20));
+        assert!(frames[2].iter().all(|&s| s == 0));
+    }
+}
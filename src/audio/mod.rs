@@ -1,7 +1,22 @@
 //! Audio capture and processing module
 
+mod agc;
+mod calibrate;
 mod capture;
+mod cue;
 mod encoder;
+mod filter;
+mod resample;
+mod ring;
+mod silence;
+mod wav;
 
-pub use capture::AudioCapture;
-pub use encoder::OpusEncoder;
+pub use agc::Agc;
+pub use calibrate::NoiseProfile;
+pub use capture::{AudioCapture, AudioCaptureEvent, AudioCaptureStats, AudioDeviceInfo, InputLevel};
+pub use cue::Cue;
+pub use encoder::{OpusDecoder, OpusEncoder, FRAME_DURATIONS_MS};
+pub use filter::{HighPass, InputFilters, NoiseGate};
+pub use resample::Resampler;
+pub use silence::SilenceDetector;
+pub use wav::read_pcm16_mono;
@@ -0,0 +1,215 @@
+//! Sample Rate Conversion
+//!
+//! Streams mono audio from the device rate to the rate the encoder wants.
+//! Input is low-pass filtered with a short windowed-sinc FIR when
+//! downsampling, then linearly interpolated. Positions are tracked as exact
+//! fractions of the input rate, so long sessions don't drift.
+
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+/// Taps of the anti-aliasing filter (odd, so it has a center tap)
+const FILTER_TAPS: usize = 31;
+
+/// Streaming mono resampler
+pub struct Resampler {
+    in_rate: u64,
+    out_rate: u64,
+    /// Low-pass taps, empty when not downsampling
+    filter: Vec<f32>,
+    /// Most recent input samples for the filter
+    history: VecDeque<f32>,
+    /// Filtered input not yet consumed; `pending[0]` is input sample `base`
+    pending: Vec<f32>,
+    base: u64,
+    /// Next output sample, in units of 1/out_rate input samples
+    position: u64,
+}
+
+impl Resampler {
+    /// Convert from `in_rate` to `out_rate` (both in Hz)
+    pub fn new(in_rate: u32, out_rate: u32) -> Self {
+        let filter = if out_rate < in_rate {
+            low_pass(out_rate as f64 / 2.0 * 0.9 / in_rate as f64)
+        } else {
+            Vec::new()
+        };
+        Self {
+            in_rate: in_rate as u64,
+            out_rate: out_rate as u64,
+            history: VecDeque::from(vec![0.0; filter.len()]),
+            filter,
+            pending: Vec::new(),
+            base: 0,
+            position: 0,
+        }
+    }
+
+    /// Whether the rates differ at all
+    pub fn is_passthrough(&self) -> bool {
+        self.in_rate == self.out_rate
+    }
+
+    /// Feed input samples, appending whatever output they complete
+    pub fn process(&mut self, input: &[i16], output: &mut Vec<i16>) {
+        if self.is_passthrough() {
+            output.extend_from_slice(input);
+            return;
+        }
+
+        for &sample in input {
+            let sample = sample as f32;
+            let filtered = if self.filter.is_empty() {
+                sample
+            } else {
+                self.history.pop_front();
+                self.history.push_back(sample);
+                self.history
+                    .iter()
+                    .zip(&self.filter)
+                    .map(|(x, h)| x * h)
+                    .sum()
+            };
+            self.pending.push(filtered);
+        }
+
+        // Output sample k sits at input position k * in_rate / out_rate
+        loop {
+            let index = self.position / self.out_rate;
+            let frac = (self.position % self.out_rate) as f32 / self.out_rate as f32;
+            let local = (index - self.base) as usize;
+            if local + 1 >= self.pending.len() {
+                break;
+            }
+            let (a, b) = (self.pending[local], self.pending[local + 1]);
+            let value = a + (b - a) * frac;
+            output.push(value.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16);
+            self.position += self.in_rate;
+        }
+
+        // Drop input that no later output needs
+        let consumed = (self.position / self.out_rate - self.base) as usize;
+        let consumed = consumed.min(self.pending.len());
+        self.pending.drain(..consumed);
+        self.base += consumed as u64;
+    }
+}
+
+/// Hann-windowed sinc low-pass with `cutoff` as a fraction of the sample rate
+fn low_pass(cutoff: f64) -> Vec<f32> {
+    let center = (FILTER_TAPS / 2) as f64;
+    let taps: Vec<f64> = (0..FILTER_TAPS)
+        .map(|i| {
+            let x = i as f64 - center;
+            let sinc = if x == 0.0 {
+                2.0 * cutoff
+            } else {
+                (2.0 * PI * cutoff * x).sin() / (PI * x)
+            };
+            let window = 0.5 - 0.5 * (2.0 * PI * i as f64 / (FILTER_TAPS - 1) as f64).cos();
+            sinc * window
+        })
+        .collect();
+    let gain: f64 = taps.iter().sum();
+    taps.iter().map(|tap| (tap / gain) as f32).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `seconds` of a sine at `freq` Hz sampled at `rate`
+    fn sine(freq: f64, rate: u32, seconds: f64, amplitude: f64) -> Vec<i16> {
+        let len = (rate as f64 * seconds) as usize;
+        (0..len)
+            .map(|i| (amplitude * (2.0 * PI * freq * i as f64 / rate as f64).sin()) as i16)
+            .collect()
+    }
+
+    /// Resample `input` fed in chunks of `chunk` samples
+    fn resample(input: &[i16], in_rate: u32, out_rate: u32, chunk: usize) -> Vec<i16> {
+        let mut resampler = Resampler::new(in_rate, out_rate);
+        let mut output = Vec::new();
+        for piece in input.chunks(chunk) {
+            resampler.process(piece, &mut output);
+        }
+        output
+    }
+
+    /// Frequency from the rising zero crossings, skipping the filter's start
+    fn frequency(samples: &[i16], rate: u32) -> f64 {
+        let samples = &samples[rate as usize / 10..];
+        let crossings: Vec<usize> = samples
+            .windows(2)
+            .enumerate()
+            .filter(|(_, pair)| pair[0] < 0 && pair[1] >= 0)
+            .map(|(i, _)| i)
+            .collect();
+        let periods = (crossings.len() - 1) as f64;
+        periods * rate as f64 / (crossings[crossings.len() - 1] - crossings[0]) as f64
+    }
+
+    #[test]
+    fn output_length_follows_the_ratio() {
+        for in_rate in [44_100, 48_000, 22_050, 8_000] {
+            let input = vec![0i16; in_rate as usize * 2];
+            let output = resample(&input, in_rate, 16_000, 441);
+            let expected = 32_000usize;
+            // Interpolating the last outputs needs one more input sample
+            let held_back = (16_000 / in_rate) as usize + 1;
+            assert!(
+                output.len() <= expected && expected - output.len() <= held_back,
+                "{} Hz: {} samples, expected {}",
+                in_rate,
+                output.len(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn long_sessions_do_not_drift() {
+        // Two minutes in 10 ms callbacks, where 44.1 kHz doesn't divide evenly
+        let mut resampler = Resampler::new(44_100, 16_000);
+        let chunk = vec![0i16; 441];
+        let mut output = Vec::new();
+        let mut produced = 0usize;
+        for _ in 0..12_000 {
+            resampler.process(&chunk, &mut output);
+            produced += output.len();
+            output.clear();
+        }
+        assert!(produced.abs_diff(16_000 * 120) <= 1, "{} samples", produced);
+    }
+
+    #[test]
+    fn sine_keeps_its_frequency() {
+        for in_rate in [44_100, 48_000] {
+            let input = sine(1_000.0, in_rate, 2.0, 10_000.0);
+            let output = resample(&input, in_rate, 16_000, 480);
+            let measured = frequency(&output, 16_000);
+            assert!((measured - 1_000.0).abs() < 1.0, "{} Hz input: {:.2} Hz", in_rate, measured);
+            // The tone is well below the cutoff and keeps its level
+            let peak = output[1_600..].iter().map(|s| s.unsigned_abs()).max().unwrap();
+            assert!((9_000..=10_500).contains(&peak), "{} Hz input: peak {}", in_rate, peak);
+        }
+    }
+
+    #[test]
+    fn tones_above_the_new_nyquist_are_filtered() {
+        let input = sine(12_000.0, 48_000, 1.0, 10_000.0);
+        let output = resample(&input, 48_000, 16_000, 480);
+        let peak = output[1_600..].iter().map(|s| s.unsigned_abs()).max().unwrap();
+        assert!(peak < 2_000, "peak {}", peak);
+    }
+
+    #[test]
+    fn same_rate_passes_through() {
+        let input = sine(440.0, 16_000, 0.1, 5_000.0);
+        let mut resampler = Resampler::new(16_000, 16_000);
+        assert!(resampler.is_passthrough());
+        let mut output = Vec::new();
+        resampler.process(&input, &mut output);
+        assert_eq!(output, input);
+    }
+}
@@ -0,0 +1,72 @@
+//! Sample Ring Buffer
+//!
+//! Fixed-size queue between a cpal callback and the thread that frames and
+//! encodes its audio. Pushing never allocates, locks or blocks: a callback
+//! whose samples don't fit is dropped whole.
+
+use std::sync::atomic::{AtomicI16, AtomicUsize, Ordering};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+/// Single-producer, single-consumer ring of i16 samples
+pub(crate) struct SampleRing {
+    slots: Box<[AtomicI16]>,
+    /// Samples ever written and read; positions are these modulo the capacity
+    written: AtomicUsize,
+    read: AtomicUsize,
+    /// Thread woken when samples arrive
+    consumer: Thread,
+}
+
+impl SampleRing {
+    /// Create a ring of `capacity` samples, drained by the calling thread
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            slots: (0..capacity.max(1)).map(|_| AtomicI16::new(0)).collect(),
+            written: AtomicUsize::new(0),
+            read: AtomicUsize::new(0),
+            consumer: thread::current(),
+        }
+    }
+
+    /// Append converted samples (producer side); false if they didn't fit
+    ///
+    /// All or nothing, so interleaved channels stay aligned.
+    pub(crate) fn push<T: Copy>(&self, data: &[T], convert: impl Fn(T) -> i16) -> bool {
+        let capacity = self.slots.len();
+        let written = self.written.load(Ordering::Relaxed);
+        let free = capacity - written.wrapping_sub(self.read.load(Ordering::Acquire));
+        if data.len() > free {
+            return false;
+        }
+        for (i, &sample) in data.iter().enumerate() {
+            self.slots[written.wrapping_add(i) % capacity].store(convert(sample), Ordering::Relaxed);
+        }
+        self.written.store(written.wrapping_add(data.len()), Ordering::Release);
+        self.consumer.unpark();
+        true
+    }
+
+    /// Fill `out` with the oldest samples, waiting up to `timeout` for
+    /// enough to arrive (consumer side); false if they didn't
+    pub(crate) fn pop_exact(&self, out: &mut [i16], timeout: Duration) -> bool {
+        let capacity = self.slots.len();
+        let deadline = Instant::now() + timeout;
+        loop {
+            let read = self.read.load(Ordering::Relaxed);
+            let available = self.written.load(Ordering::Acquire).wrapping_sub(read);
+            if available >= out.len() {
+                for (i, sample) in out.iter_mut().enumerate() {
+                    *sample = self.slots[read.wrapping_add(i) % capacity].load(Ordering::Relaxed);
+                }
+                self.read.store(read.wrapping_add(out.len()), Ordering::Release);
+                return true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            thread::park_timeout(deadline - now);
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Silence Detection
+//!
+//! Energy-based detector run on the frames sent to the encoder. It
+//! fires once the user has spoken and then stayed quiet for the configured
+//! time, so recording can stop without the hotkey.
+
+use std::time::Duration;
+
+use crate::data::AudioConfig;
+
+/// Frames above the threshold in a row that count as speech (clicks don't)
+const SPEECH_FRAMES: u32 = 3;
+
+/// Detects the end of an utterance from frame energy
+pub struct SilenceDetector {
+    /// Mean square below which a frame is quiet
+    threshold: f64,
+    timeout: Duration,
+    frame: Duration,
+    loud_frames: u32,
+    heard_speech: bool,
+    quiet: Duration,
+    fired: bool,
+}
+
+impl SilenceDetector {
+    /// Detector for `config`, or None when `silence_timeout_ms` is 0
+    pub fn from_config(config: &AudioConfig, frame: Duration) -> Option<Self> {
+        if config.silence_timeout_ms == 0 {
+            return None;
+        }
+        let full_scale = i16::MAX as f64;
+        Some(Self {
+            threshold: full_scale * full_scale * 10f64.powf(config.silence_threshold_db as f64 / 10.0),
+            timeout: Duration::from_millis(config.silence_timeout_ms),
+            frame,
+            loud_frames: 0,
+            heard_speech: false,
+            quiet: Duration::ZERO,
+            fired: false,
+        })
+    }
+
+    /// Feed one frame; true exactly once, when speech was followed by silence
+    pub fn process(&mut self, frame: &[i16]) -> bool {
+        if self.fired || frame.is_empty() {
+            return false;
+        }
+        let energy = frame.iter().map(|&s| (s as f64) * (s as f64)).sum::<f64>() / frame.len() as f64;
+
+        if energy >= self.threshold {
+            self.loud_frames += 1;
+            if self.loud_frames >= SPEECH_FRAMES {
+                self.heard_speech = true;
+                self.quiet = Duration::ZERO;
+            }
+            return false;
+        }
+
+        self.loud_frames = 0;
+        if !self.heard_speech {
+            return false;
+        }
+        self.quiet += self.frame;
+        if self.quiet >= self.timeout {
+            self.fired = true;
+        }
+        self.fired
+    }
+}
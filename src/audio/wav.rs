@@ -0,0 +1,228 @@
+//! WAV/PCM Files
+//!
+//! Reads recorded audio in the format the ASR pipeline expects, and writes
+//! what a recording sent as `session-<timestamp>.wav` for debugging.
+
+use anyhow::{anyhow, bail, Result};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Sample rate the recognizer expects
+const EXPECTED_SAMPLE_RATE: u32 = 16000;
+
+/// Read a 16 kHz mono 16-bit file as samples
+///
+/// `.wav` files must be 16-bit PCM, 16 kHz, mono; anything else is rejected
+/// with a description of what was found. Other extensions are treated as
+/// raw 16 kHz mono little-endian PCM.
+pub fn read_pcm16_mono(path: &Path) -> Result<Vec<i16>> {
+    let bytes = fs::read(path).map_err(|e| anyhow!("Cannot read {:?}: {}", path, e))?;
+
+    let is_wav = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("wav"));
+    let pcm = if is_wav { wav_data(&bytes)? } else { &bytes[..] };
+
+    Ok(pcm
+        .chunks_exact(2)
+        .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]))
+        .collect())
+}
+
+/// Prefix of dump files; only these are pruned
+const DUMP_PREFIX: &str = "session-";
+
+/// Size of the header written by `WavDump`
+const HEADER_LEN: u32 = 44;
+
+/// Writes one recording's 16 kHz mono PCM to a .wav file
+///
+/// Samples are handed to a writer thread, so `write` never waits on the
+/// disk. The RIFF header is completed when the dump is dropped.
+pub(crate) struct WavDump {
+    path: PathBuf,
+    samples: Sender<Vec<i16>>,
+}
+
+impl WavDump {
+    /// Start a new dump in `dir`, keeping at most `keep` dumps there
+    pub(crate) fn create(dir: &Path, keep: usize) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let path = dir.join(format!("{}{}.wav", DUMP_PREFIX, timestamp));
+        let dump = Self::open(path)?;
+        prune_dumps(dir, keep);
+        Ok(dump)
+    }
+
+    /// Start a second dump next to this one, named `session-<timestamp>-<tag>.wav`
+    ///
+    /// It is pruned together with this dump.
+    pub(crate) fn companion(&self, tag: &str) -> io::Result<Self> {
+        Self::open(companion_path(&self.path, tag))
+    }
+
+    fn open(path: PathBuf) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(&path)?);
+        write_header(&mut file, 0)?;
+
+        tracing::info!("Saving recording to {:?}", path);
+        let (samples, samples_rx) = channel::<Vec<i16>>();
+        let dump = Self { path: path.clone(), samples };
+        thread::spawn(move || {
+            let mut data_len = 0u32;
+            let mut result = Ok(());
+            for chunk in samples_rx {
+                let bytes: Vec<u8> = chunk.iter().flat_map(|s| s.to_le_bytes()).collect();
+                result = file.write_all(&bytes);
+                if result.is_err() {
+                    break;
+                }
+                data_len = data_len.saturating_add(bytes.len() as u32);
+            }
+            let result = result
+                .and_then(|_| file.seek(SeekFrom::Start(0)))
+                .and_then(|_| write_header(&mut file, data_len))
+                .and_then(|_| file.flush());
+            match result {
+                Ok(()) => tracing::info!("Saved {:?} ({} bytes of audio)", path, data_len),
+                Err(e) => tracing::warn!("Failed to write {:?}: {}", path, e),
+            }
+        });
+        Ok(dump)
+    }
+
+    /// Queue samples for the file
+    pub(crate) fn write(&self, samples: &[i16]) {
+        let _ = self.samples.send(samples.to_vec());
+    }
+}
+
+/// `session-<timestamp>.wav` -> `session-<timestamp>-<tag>.wav`
+fn companion_path(path: &Path, tag: &str) -> PathBuf {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    path.with_file_name(format!("{}-{}.wav", stem, tag))
+}
+
+/// Canonical 44-byte header for 16-bit PCM at the expected rate, mono
+fn write_header(out: &mut impl Write, data_len: u32) -> io::Result<()> {
+    let byte_rate = EXPECTED_SAMPLE_RATE * 2;
+    out.write_all(b"RIFF")?;
+    out.write_all(&(HEADER_LEN - 8 + data_len).to_le_bytes())?;
+    out.write_all(b"WAVEfmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?; // PCM
+    out.write_all(&1u16.to_le_bytes())?; // mono
+    out.write_all(&EXPECTED_SAMPLE_RATE.to_le_bytes())?;
+    out.write_all(&byte_rate.to_le_bytes())?;
+    out.write_all(&2u16.to_le_bytes())?; // block align
+    out.write_all(&16u16.to_le_bytes())?; // bits per sample
+    out.write_all(b"data")?;
+    out.write_all(&data_len.to_le_bytes())
+}
+
+/// Delete the oldest dumps in `dir` so at most `keep` remain
+fn prune_dumps(dir: &Path, keep: usize) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(DUMP_PREFIX) && name.ends_with(".wav"))
+        })
+        .collect();
+    // Only `session-<timestamp>.wav` counts; companions go with their dump
+    let mut dumps: Vec<&PathBuf> = files
+        .iter()
+        .filter(|path| {
+            path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_some_and(|stem| !stem[DUMP_PREFIX.len()..].contains('-'))
+        })
+        .collect();
+    if dumps.len() <= keep {
+        return;
+    }
+    // Timestamps have the same width, so names sort by age
+    dumps.sort();
+    for dump in &dumps[..dumps.len() - keep] {
+        let stem = dump.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+        let companion_prefix = format!("{}-", stem);
+        let companions = files.iter().filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(&companion_prefix))
+        });
+        for path in std::iter::once(*dump).chain(companions) {
+            if let Err(e) = fs::remove_file(path) {
+                tracing::warn!("Cannot remove old recording {:?}: {}", path, e);
+            }
+        }
+    }
+}
+
+/// Validate the RIFF header and return the `data` chunk
+fn wav_data(bytes: &[u8]) -> Result<&[u8]> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        bail!("Not a RIFF/WAVE file");
+    }
+
+    let mut format = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().unwrap()) as usize;
+        let body_start = offset + 8;
+        let body_end = (body_start + size).min(bytes.len());
+        let body = &bytes[body_start..body_end];
+
+        match id {
+            b"fmt " if body.len() >= 16 => {
+                let audio_format = u16::from_le_bytes([body[0], body[1]]);
+                let channels = u16::from_le_bytes([body[2], body[3]]);
+                let sample_rate = u32::from_le_bytes(body[4..8].try_into().unwrap());
+                let bits = u16::from_le_bytes([body[14], body[15]]);
+                format = Some((audio_format, channels, sample_rate, bits));
+            }
+            b"data" => {
+                let Some((audio_format, channels, sample_rate, bits)) = format else {
+                    bail!("WAV data chunk appears before fmt chunk");
+                };
+                // 0xFFFE is WAVE_FORMAT_EXTENSIBLE, which still carries PCM here
+                if !matches!(audio_format, 1 | 0xFFFE) || bits != 16 {
+                    bail!(
+                        "Unsupported WAV encoding (format {}, {} bits); expected 16-bit PCM",
+                        audio_format,
+                        bits
+                    );
+                }
+                if channels != 1 || sample_rate != EXPECTED_SAMPLE_RATE {
+                    bail!(
+                        "Unsupported WAV layout ({} Hz, {} channels); expected {} Hz mono",
+                        sample_rate,
+                        channels,
+                        EXPECTED_SAMPLE_RATE
+                    );
+                }
+                return Ok(body);
+            }
+            _ => {}
+        }
+
+        // Chunks are padded to an even size
+        offset = body_start + size + (size & 1);
+    }
+
+    bail!("WAV file has no data chunk")
+}
@@ -1,15 +1,16 @@
 //! Hotkey Manager
 //!
 //! Manages global hotkeys for triggering voice input.
-//! Supports combo keys (Ctrl+Shift+V) and double-tap of modifier keys (Ctrl).
+//! Supports combo keys (Ctrl+Shift+V), double-tap of modifier keys (Ctrl)
+//! and push-to-talk, recording while a key is held (F9).
 
 use anyhow::{anyhow, Result};
 use global_hotkey::{
     hotkey::{Code, HotKey, Modifiers},
-    GlobalHotKeyEvent, GlobalHotKeyManager,
+    GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState,
 };
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 
@@ -22,198 +23,579 @@ pub enum HotkeyMode {
     Combo,
     /// Double-tap mode (e.g., double-tap Ctrl)
     DoubleTap,
+    /// Push-to-talk mode (e.g., hold F9)
+    Hold,
 }
 
-/// Hotkey manager for global hotkey handling
-pub struct HotkeyManager {
-    _manager: Option<GlobalHotKeyManager>,
-    mode: HotkeyMode,
+/// What the recording hotkey asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotkeyEvent {
+    /// Start recording, or stop if it is running (combo and double-tap)
+    Toggle,
+    /// The hold key has been held down long enough, or the start key pressed
+    Start,
+    /// The hold key was released after a `Start`, or the stop key pressed
+    Stop,
+    /// Stop recording and throw away what wasn't typed yet
+    Cancel,
+}
+
+/// Callback run by a hotkey
+type HotkeyCallback = Arc<dyn Fn() + Send + Sync>;
+
+/// Callback run by the recording hotkeys
+type TriggerCallback = Arc<dyn Fn(HotkeyEvent) + Send + Sync>;
+
+/// A key binding from the config: a combo such as "Ctrl+Alt+S", or
+/// "double:<key>" for a key tapped twice, e.g. "double:Shift"
+#[derive(Debug, Clone)]
+enum Binding {
+    Combo(HotKey),
+    /// Lowercased key name
+    DoubleTap(String),
+    /// The "hold" mode key, lowercased; watched by the keyboard hook
+    Hold(String),
+}
+
+impl Binding {
+    fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        match spec.split_once(':') {
+            Some((kind, key)) if kind.trim().eq_ignore_ascii_case("double") => {
+                Self::double_tap(key)
+            }
+            _ => Ok(Binding::Combo(parse_combo_key(spec)?)),
+        }
+    }
+
+    fn double_tap(key: &str) -> Result<Self> {
+        let key = key_name(key);
+        if !is_modifier(&key) {
+            parse_key_code(&key)?;
+        }
+        Ok(Binding::DoubleTap(key))
+    }
+
+    /// Whether both react to the same keys
+    fn same_keys(&self, other: &Binding) -> bool {
+        match (self, other) {
+            (Binding::Combo(a), Binding::Combo(b)) => a.id() == b.id(),
+            (Binding::DoubleTap(a), Binding::DoubleTap(b))
+            | (Binding::Hold(a), Binding::Hold(b))
+            | (Binding::Hold(a), Binding::DoubleTap(b))
+            | (Binding::DoubleTap(a), Binding::Hold(b)) => a == b,
+            // Holding a key also presses it, firing a combo of just that key
+            (Binding::Hold(key), Binding::Combo(hotkey))
+            | (Binding::Combo(hotkey), Binding::Hold(key)) => {
+                hotkey.mods.is_empty() && parse_key_code(key).ok() == Some(hotkey.key)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Lowercase a key name, spelling Control as "ctrl"
+fn key_name(key: &str) -> String {
+    match key.trim().to_lowercase().as_str() {
+        "control" => "ctrl".to_string(),
+        key => key.to_string(),
+    }
+}
+
+/// Modifiers can only be watched by the keyboard hook
+fn is_modifier(key: &str) -> bool {
+    matches!(key, "ctrl" | "control" | "shift" | "alt")
+}
+
+/// Fail when two `[hotkey]` settings use the same keys, which would fire
+/// both or fail to register
+fn check_conflicts(bindings: &[(&str, Binding)]) -> Result<()> {
+    for (index, (name, binding)) in bindings.iter().enumerate() {
+        if let Some((earlier, _)) = bindings[..index]
+            .iter()
+            .find(|(_, earlier)| earlier.same_keys(binding))
+        {
+            return Err(anyhow!(
+                "hotkey.{} uses the same keys as hotkey.{}; give each its own binding in config.toml",
+                name,
+                earlier
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The `[hotkey]` settings parsed and checked, before anything is registered
+struct Plan {
+    /// The recording keys by setting name, with what each sends
+    triggers: Vec<(&'static str, Binding, HotkeyEvent)>,
+    undo: Option<HotKey>,
+    reinsert: Option<HotKey>,
+    /// How long the hold key must be down before recording starts
+    hold_threshold: Duration,
     double_tap_interval: Duration,
-    double_tap_key: String,
-    is_active: Arc<AtomicBool>,
 }
 
-impl HotkeyManager {
-    /// Create a new hotkey manager based on configuration
-    pub fn new(config: &HotkeyConfig) -> Result<Self> {
-        let mode = if config.mode == "combo" {
-            HotkeyMode::Combo
-        } else {
-            HotkeyMode::DoubleTap
+impl Plan {
+    /// Fails when a key name is unknown or two settings share the same keys
+    fn from_config(config: &HotkeyConfig) -> Result<Self> {
+        let mode = match config.mode.as_str() {
+            "combo" => HotkeyMode::Combo,
+            "hold" => HotkeyMode::Hold,
+            _ => HotkeyMode::DoubleTap,
         };
 
-        let manager = GlobalHotKeyManager::new()
-            .map_err(|e| anyhow!("Failed to create hotkey manager: {}", e))?;
-
-        // Register hotkey based on mode
+        let mut triggers = Vec::new();
         match mode {
             HotkeyMode::Combo => {
                 // Parse combo key (default: Ctrl+Shift+V)
-                let hotkey = parse_combo_key(&config.combo_key)?;
-                manager
-                    .register(hotkey)
-                    .map_err(|e| anyhow!("Failed to register hotkey: {}", e))?;
-                tracing::info!("Registered combo hotkey: {}", config.combo_key);
+                let binding = Binding::Combo(parse_combo_key(&config.combo_key)?);
+                triggers.push(("combo_key", binding, HotkeyEvent::Toggle));
             }
             HotkeyMode::DoubleTap => {
-                // For modifier keys like Ctrl, we use low-level keyboard hook
-                // For regular keys, we can use global_hotkey
-                let key_lower = config.double_tap_key.to_lowercase();
-                if key_lower == "ctrl" || key_lower == "shift" || key_lower == "alt" {
-                    // Will use Windows keyboard hook for modifier keys
-                    tracing::info!(
-                        "Double-tap modifier key: {} (using keyboard hook)",
-                        config.double_tap_key
-                    );
-                } else {
-                    // Regular key - can use global_hotkey
-                    let hotkey = HotKey::new(None, parse_key_code(&config.double_tap_key)?);
-                    manager
-                        .register(hotkey)
-                        .map_err(|e| anyhow!("Failed to register hotkey: {}", e))?;
-                    tracing::info!("Registered double-tap hotkey: {}", config.double_tap_key);
+                let binding = Binding::double_tap(&config.double_tap_key)?;
+                triggers.push(("double_tap_key", binding, HotkeyEvent::Toggle));
+            }
+            HotkeyMode::Hold => {
+                // Key down and up both matter, which only the keyboard hook sees
+                #[cfg(target_os = "windows")]
+                if key_vks(&config.hold_key).is_none() {
+                    return Err(anyhow!("Unknown hold key: {}", config.hold_key));
                 }
+                let binding = Binding::Hold(key_name(&config.hold_key));
+                triggers.push(("hold_key", binding, HotkeyEvent::Start));
+            }
+        }
+        let separate = [
+            ("start_key", &config.start_key, HotkeyEvent::Start),
+            ("stop_key", &config.stop_key, HotkeyEvent::Stop),
+            ("cancel_key", &config.cancel_key, HotkeyEvent::Cancel),
+        ];
+        for (name, spec, event) in separate {
+            if !spec.trim().is_empty() {
+                triggers.push((name, Binding::parse(spec)?, event));
             }
         }
 
+        let optional_combo = |key: &str| {
+            let key = key.trim();
+            (!key.is_empty()).then(|| parse_combo_key(key)).transpose()
+        };
+        let undo = optional_combo(&config.undo_key)?;
+        let reinsert = optional_combo(&config.reinsert_key)?;
+
+        let mut bindings: Vec<(&str, Binding)> = triggers
+            .iter()
+            .map(|(name, binding, _)| (*name, binding.clone()))
+            .collect();
+        bindings.extend(undo.map(|hotkey| ("undo_key", Binding::Combo(hotkey))));
+        bindings.extend(reinsert.map(|hotkey| ("reinsert_key", Binding::Combo(hotkey))));
+        check_conflicts(&bindings)?;
+
         Ok(Self {
-            _manager: Some(manager),
-            mode,
+            triggers,
+            undo,
+            reinsert,
+            hold_threshold: Duration::from_millis(config.hold_threshold_ms),
             double_tap_interval: Duration::from_millis(config.double_tap_interval),
-            double_tap_key: config.double_tap_key.clone(),
+        })
+    }
+}
+
+/// What the registered keys do, read by the receiver thread
+#[derive(Default)]
+struct Routes {
+    /// Registered combos and what each sends when pressed
+    combo_events: Vec<(u32, HotkeyEvent)>,
+    /// Registered keys and what each sends when pressed twice quickly
+    tap_events: Vec<(u32, HotkeyEvent)>,
+    double_tap_interval: Duration,
+    /// Id of the `undo_key` combo, if one is registered
+    undo_id: Option<u32>,
+    /// Id of the `reinsert_key` combo, if one is registered
+    reinsert_id: Option<u32>,
+}
+
+/// Keys only a keyboard hook sees
+#[derive(Clone, Default)]
+struct HookKeys {
+    /// Key held to record in "hold" mode, and for how long
+    hold: Option<(String, Duration)>,
+    /// Modifiers and what each sends when tapped twice
+    taps: Vec<(String, HotkeyEvent)>,
+    double_tap_interval: Duration,
+}
+
+/// Hotkey manager for global hotkey handling
+pub struct HotkeyManager {
+    manager: GlobalHotKeyManager,
+    /// Combos currently registered with `manager`
+    registered: Vec<HotKey>,
+    routes: Arc<Mutex<Routes>>,
+    hook_keys: HookKeys,
+    /// Threads running a keyboard hook, told to quit by posting WM_QUIT
+    hook_threads: Mutex<Vec<u32>>,
+    is_active: Arc<AtomicBool>,
+    trigger_callback: Arc<Mutex<Option<TriggerCallback>>>,
+    undo_callback: Arc<Mutex<Option<HotkeyCallback>>>,
+    reinsert_callback: Arc<Mutex<Option<HotkeyCallback>>>,
+}
+
+impl HotkeyManager {
+    /// Create a new hotkey manager based on configuration
+    ///
+    /// Fails when a key name is unknown, two settings share the same keys
+    /// or a combo is taken by another app.
+    pub fn new(config: &HotkeyConfig) -> Result<Self> {
+        let plan = Plan::from_config(config)?;
+        let manager = GlobalHotKeyManager::new()
+            .map_err(|e| anyhow!("Failed to create hotkey manager: {}", e))?;
+        let (registered, routes, hook_keys) = register(&manager, &plan)?;
+
+        Ok(Self {
+            manager,
+            registered,
+            routes: Arc::new(Mutex::new(routes)),
+            hook_keys,
+            hook_threads: Mutex::new(Vec::new()),
             is_active: Arc::new(AtomicBool::new(true)),
+            trigger_callback: Arc::new(Mutex::new(None)),
+            undo_callback: Arc::new(Mutex::new(None)),
+            reinsert_callback: Arc::new(Mutex::new(None)),
         })
     }
 
-    /// Set callback for when hotkey is triggered
-    pub fn on_trigger<F>(&self, callback: F)
+    /// Switch to the bindings in `config` without restarting
+    ///
+    /// Must run on the thread that created the manager, which owns the
+    /// registrations. On failure the previous bindings stay in place.
+    pub fn reconfigure(&mut self, config: &HotkeyConfig) -> Result<()> {
+        let plan = Plan::from_config(config)?;
+
+        // The new bindings may reuse some of the old combos
+        if let Err(e) = self.manager.unregister_all(&self.registered) {
+            tracing::warn!("Failed to unregister hotkeys: {}", e);
+        }
+        let (registered, routes, hook_keys) = match register(&self.manager, &plan) {
+            Ok(registered) => registered,
+            Err(e) => {
+                if let Err(e) = self.manager.register_all(&self.registered) {
+                    tracing::error!("Failed to restore the previous hotkeys: {}", e);
+                }
+                return Err(e);
+            }
+        };
+        self.registered = registered;
+        *self.routes.lock().unwrap() = routes;
+
+        self.stop_hooks();
+        self.hook_keys = hook_keys;
+        self.start_hooks();
+        tracing::info!("Hotkeys reconfigured");
+        Ok(())
+    }
+
+    /// Set callback for when the re-insert hotkey is pressed
+    pub fn on_reinsert<F>(&self, callback: F)
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        *self.reinsert_callback.lock().unwrap() = Some(Arc::new(callback));
+    }
+
+    /// Set callback for when the undo hotkey is pressed
+    pub fn on_undo<F>(&self, callback: F)
     where
         F: Fn() + Send + Sync + 'static,
     {
-        let mode = self.mode.clone();
-        let double_tap_interval = self.double_tap_interval;
-        let double_tap_key = self.double_tap_key.clone();
+        *self.undo_callback.lock().unwrap() = Some(Arc::new(callback));
+    }
+
+    /// Set callback for when a recording hotkey is triggered
+    ///
+    /// Combo and double-tap keys send `Toggle`; a hold key sends `Start`
+    /// once held for `hold_threshold_ms` and `Stop` when released. The
+    /// separate start, stop and cancel keys send their own event.
+    pub fn on_trigger<F>(&self, callback: F)
+    where
+        F: Fn(HotkeyEvent) + Send + Sync + 'static,
+    {
+        *self.trigger_callback.lock().unwrap() = Some(Arc::new(callback));
+        self.start_hooks();
+
         let is_active = self.is_active.clone();
-        let callback = Arc::new(callback);
+        let routes = self.routes.clone();
+        let trigger_callback = self.trigger_callback.clone();
+        let undo_callback = self.undo_callback.clone();
+        let reinsert_callback = self.reinsert_callback.clone();
 
-        // Check if we need to use keyboard hook for modifier keys
-        let key_lower = double_tap_key.to_lowercase();
-        let use_keyboard_hook =
-            mode == HotkeyMode::DoubleTap && (key_lower == "ctrl" || key_lower == "shift" || key_lower == "alt");
+        // Use global_hotkey receiver
+        thread::spawn(move || {
+            let receiver = GlobalHotKeyEvent::receiver();
+            // Last press of each double-tap key
+            let mut last_presses: Vec<(u32, Instant)> = Vec::new();
 
-        if use_keyboard_hook {
-            // Use Windows keyboard hook for modifier key double-tap
-            #[cfg(target_os = "windows")]
-            {
-                let callback_clone = callback.clone();
-                thread::spawn(move || {
-                    run_modifier_double_tap_hook(key_lower, double_tap_interval, is_active, callback_clone);
-                });
-            }
-            #[cfg(not(target_os = "windows"))]
-            {
-                tracing::warn!("Modifier key double-tap not supported on this platform");
-            }
-        } else {
-            // Use global_hotkey receiver
-            thread::spawn(move || {
-                let receiver = GlobalHotKeyEvent::receiver();
-                let mut last_press_time: Option<Instant> = None;
-
-                loop {
-                    if !is_active.load(Ordering::SeqCst) {
-                        thread::sleep(Duration::from_millis(100));
-                        continue;
-                    }
+            loop {
+                if !is_active.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_millis(100));
+                    continue;
+                }
 
-                    if let Ok(_event) = receiver.recv() {
-                        match mode {
-                            HotkeyMode::Combo => {
-                                callback();
-                            }
-                            HotkeyMode::DoubleTap => {
-                                let now = Instant::now();
-
-                                if let Some(last) = last_press_time {
-                                    let elapsed = now.duration_since(last);
-                                    if elapsed <= double_tap_interval {
-                                        callback();
-                                        last_press_time = None;
-                                        continue;
-                                    }
-                                }
-
-                                last_press_time = Some(now);
-                            }
-                        }
+                let Ok(event) = receiver.recv() else {
+                    continue;
+                };
+                // Releases are reported too
+                if !matches!(event.state, HotKeyState::Pressed) {
+                    continue;
+                }
+                let routes = routes.lock().unwrap();
+                let extra = if Some(event.id) == routes.undo_id {
+                    Some(&undo_callback)
+                } else if Some(event.id) == routes.reinsert_id {
+                    Some(&reinsert_callback)
+                } else {
+                    None
+                };
+                if let Some(extra) = extra {
+                    if let Some(extra) = extra.lock().unwrap().clone() {
+                        extra();
                     }
+                    continue;
                 }
-            });
-        }
+                let Some(callback) = trigger_callback.lock().unwrap().clone() else {
+                    continue;
+                };
+                if let Some(&(_, trigger)) = routes.combo_events.iter().find(|(id, _)| *id == event.id) {
+                    callback(trigger);
+                    continue;
+                }
+                let Some(&(id, trigger)) = routes.tap_events.iter().find(|(id, _)| *id == event.id) else {
+                    continue;
+                };
+                let now = Instant::now();
+                match last_presses.iter().position(|(tapped, _)| *tapped == id) {
+                    Some(index) if now.duration_since(last_presses[index].1) <= routes.double_tap_interval => {
+                        last_presses.remove(index);
+                        callback(trigger);
+                    }
+                    Some(index) => last_presses[index].1 = now,
+                    None => last_presses.push((id, now)),
+                }
+            }
+        });
     }
 
     /// Stop the hotkey manager
     pub fn stop(&self) {
         self.is_active.store(false, Ordering::SeqCst);
     }
+
+    /// Start keyboard hooks for the keys global_hotkey can't watch, once
+    /// there is a callback for them
+    fn start_hooks(&self) {
+        let Some(callback) = self.trigger_callback.lock().unwrap().clone() else {
+            return;
+        };
+        let HookKeys {
+            hold,
+            taps,
+            double_tap_interval,
+        } = self.hook_keys.clone();
+
+        #[cfg(target_os = "windows")]
+        {
+            let mut threads = self.hook_threads.lock().unwrap();
+            if let Some((key, threshold)) = hold {
+                let callback = callback.clone();
+                let is_active = self.is_active.clone();
+                threads.extend(spawn_hook(move |ready| {
+                    run_hold_hook(&key, threshold, is_active, callback, ready);
+                }));
+            }
+            // Use Windows keyboard hook for modifier key double-tap
+            if !taps.is_empty() {
+                let is_active = self.is_active.clone();
+                threads.extend(spawn_hook(move |ready| {
+                    run_modifier_double_tap_hook(taps, double_tap_interval, is_active, callback, ready);
+                }));
+            }
+        }
+        #[cfg(not(target_os = "windows"))]
+        {
+            if hold.is_some() {
+                tracing::warn!("Hold-to-talk not supported on this platform");
+            }
+            if !taps.is_empty() {
+                tracing::warn!("Modifier key double-tap not supported on this platform");
+            }
+            let _ = (callback, double_tap_interval);
+        }
+    }
+
+    /// Remove the keyboard hooks started by `start_hooks`
+    fn stop_hooks(&self) {
+        let threads: Vec<u32> = self.hook_threads.lock().unwrap().drain(..).collect();
+        #[cfg(target_os = "windows")]
+        for thread_id in threads {
+            use windows::Win32::Foundation::{LPARAM, WPARAM};
+            use windows::Win32::UI::WindowsAndMessaging::{PostThreadMessageW, WM_QUIT};
+
+            if let Err(e) = unsafe { PostThreadMessageW(thread_id, WM_QUIT, WPARAM(0), LPARAM(0)) } {
+                tracing::warn!("Failed to stop keyboard hook thread {}: {}", thread_id, e);
+            }
+        }
+        #[cfg(not(target_os = "windows"))]
+        let _ = threads;
+    }
+}
+
+/// Register the combos and regular double-tap keys of `plan`, returning
+/// them, where their events go and the keys left for keyboard hooks
+///
+/// Nothing stays registered when one fails, e.g. because another app took
+/// the combo.
+fn register(manager: &GlobalHotKeyManager, plan: &Plan) -> Result<(Vec<HotKey>, Routes, HookKeys)> {
+    let mut registered = Vec::new();
+    let result = register_into(manager, plan, &mut registered);
+    if result.is_err() {
+        if let Err(e) = manager.unregister_all(&registered) {
+            tracing::warn!("Failed to unregister hotkeys: {}", e);
+        }
+    }
+    result.map(|(routes, hook_keys)| (registered, routes, hook_keys))
+}
+
+/// `register`, adding each registered hotkey to `registered` as it goes
+fn register_into(
+    manager: &GlobalHotKeyManager,
+    plan: &Plan,
+    registered: &mut Vec<HotKey>,
+) -> Result<(Routes, HookKeys)> {
+    let mut add = |hotkey: HotKey, name: &str| -> Result<u32> {
+        manager
+            .register(hotkey)
+            .map_err(|e| anyhow!("Failed to register hotkey.{}: {}", name, e))?;
+        registered.push(hotkey);
+        Ok(hotkey.id())
+    };
+
+    let mut routes = Routes {
+        double_tap_interval: plan.double_tap_interval,
+        ..Routes::default()
+    };
+    let mut hook_keys = HookKeys {
+        double_tap_interval: plan.double_tap_interval,
+        ..HookKeys::default()
+    };
+    for (name, binding, event) in &plan.triggers {
+        match binding {
+            Binding::Combo(hotkey) => {
+                routes.combo_events.push((add(*hotkey, name)?, *event));
+                tracing::info!("Registered {} combo for {:?}", name, event);
+            }
+            // For modifier keys like Ctrl, we use low-level keyboard hook
+            Binding::DoubleTap(key) if is_modifier(key) => {
+                tracing::info!("Double-tap {} for {:?} (using keyboard hook)", key, event);
+                hook_keys.taps.push((key.clone(), *event));
+            }
+            // For regular keys, we can use global_hotkey
+            Binding::DoubleTap(key) => {
+                let hotkey = HotKey::new(None, parse_key_code(key)?);
+                routes.tap_events.push((add(hotkey, name)?, *event));
+                tracing::info!("Registered double-tap {} for {:?}", key, event);
+            }
+            Binding::Hold(key) => {
+                tracing::info!("Hold {} to record (using keyboard hook)", key);
+                hook_keys.hold = Some((key.clone(), plan.hold_threshold));
+            }
+        }
+    }
+    if let Some(hotkey) = plan.undo {
+        routes.undo_id = Some(add(hotkey, "undo_key")?);
+        tracing::info!("Registered undo hotkey");
+    }
+    if let Some(hotkey) = plan.reinsert {
+        routes.reinsert_id = Some(add(hotkey, "reinsert_key")?);
+        tracing::info!("Registered re-insert hotkey");
+    }
+    Ok((routes, hook_keys))
+}
+
+/// Run a keyboard hook on its own thread
+///
+/// `run` sends its thread id once the hook is installed; that id is
+/// returned for posting WM_QUIT to. None if the hook never got installed.
+#[cfg(target_os = "windows")]
+fn spawn_hook<F>(run: F) -> Option<u32>
+where
+    F: FnOnce(std::sync::mpsc::Sender<u32>) + Send + 'static,
+{
+    let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+    thread::spawn(move || run(ready_tx));
+    ready_rx.recv().ok()
 }
 
 /// Windows keyboard hook for modifier key double-tap detection
+///
+/// Each of `taps` is a modifier ("ctrl", "shift" or "alt") and the event
+/// sent when it is released twice within `interval`.
 #[cfg(target_os = "windows")]
-fn run_modifier_double_tap_hook<F>(
-    key: String,
+fn run_modifier_double_tap_hook(
+    taps: Vec<(String, HotkeyEvent)>,
     interval: Duration,
     is_active: Arc<AtomicBool>,
-    callback: Arc<F>,
-) where
-    F: Fn() + Send + Sync + 'static,
-{
+    callback: TriggerCallback,
+    ready: std::sync::mpsc::Sender<u32>,
+) {
     use std::cell::RefCell;
     use windows::Win32::Foundation::{LPARAM, LRESULT, WPARAM};
-    use windows::Win32::UI::Input::KeyboardAndMouse::{
-        VK_CONTROL, VK_LCONTROL, VK_RCONTROL, VK_LSHIFT, VK_RSHIFT, VK_LMENU, VK_RMENU,
-    };
     use windows::Win32::UI::WindowsAndMessaging::{
-        CallNextHookEx, DispatchMessageW, GetMessageW, SetWindowsHookExW, UnhookWindowsHookEx,
-        HHOOK, KBDLLHOOKSTRUCT, MSG, WH_KEYBOARD_LL, WM_KEYUP,
-        WM_SYSKEYUP,
+        CallNextHookEx, HHOOK, KBDLLHOOKSTRUCT, WM_KEYUP, WM_SYSKEYUP,
     };
 
-    // Determine which virtual keys to watch
-    let target_vks: Vec<u16> = match key.as_str() {
-        "ctrl" => vec![VK_CONTROL.0, VK_LCONTROL.0, VK_RCONTROL.0],
-        "shift" => vec![VK_LSHIFT.0, VK_RSHIFT.0],
-        "alt" => vec![VK_LMENU.0, VK_RMENU.0],
-        _ => vec![],
-    };
+    /// One watched modifier
+    struct Tap {
+        target_vks: Vec<u16>,
+        event: HotkeyEvent,
+        last_release: Option<Instant>,
+    }
 
-    if target_vks.is_empty() {
-        tracing::error!("Unknown modifier key: {}", key);
+    // Determine which virtual keys to watch
+    let mut watched = Vec::new();
+    for (key, event) in taps {
+        let Some(target_vks) = key_vks(&key) else {
+            tracing::error!("Unknown modifier key: {}", key);
+            continue;
+        };
+        tracing::info!("Starting keyboard hook for double-tap {} detection", key);
+        watched.push(Tap {
+            target_vks,
+            event,
+            last_release: None,
+        });
+    }
+    if watched.is_empty() {
         return;
     }
 
-    tracing::info!("Starting keyboard hook for double-tap {} detection", key);
-
     // Thread-local state for hook callback
     thread_local! {
-        static HOOK_STATE: RefCell<Option<HookState>> = RefCell::new(None);
+        static HOOK_STATE: RefCell<Option<HookState>> = const { RefCell::new(None) };
     }
 
     struct HookState {
-        target_vks: Vec<u16>,
+        taps: Vec<Tap>,
         interval: Duration,
-        last_release: Option<Instant>,
-        callback: Arc<dyn Fn() + Send + Sync>,
+        callback: TriggerCallback,
         is_active: Arc<AtomicBool>,
     }
 
     // Initialize thread-local state
     HOOK_STATE.with(|state| {
         *state.borrow_mut() = Some(HookState {
-            target_vks,
+            taps: watched,
             interval,
-            last_release: None,
-            callback: callback as Arc<dyn Fn() + Send + Sync>,
+            callback,
             is_active,
         });
     });
@@ -231,24 +613,26 @@ fn run_modifier_double_tap_hook<F>(
 
             HOOK_STATE.with(|state| {
                 if let Some(ref mut hook_state) = *state.borrow_mut() {
-                    if hook_state.is_active.load(Ordering::SeqCst)
-                        && hook_state.target_vks.contains(&vk_code)
-                        && is_key_up
-                    {
-                        let now = Instant::now();
-                        if let Some(last) = hook_state.last_release {
-                            let elapsed = now.duration_since(last);
-                            if elapsed <= hook_state.interval {
-                                // Double-tap detected!
-                                tracing::info!("Double-tap detected!");
-                                (hook_state.callback)();
-                                hook_state.last_release = None;
-                            } else {
-                                hook_state.last_release = Some(now);
-                            }
-                        } else {
-                            hook_state.last_release = Some(now);
+                    if !hook_state.is_active.load(Ordering::SeqCst) || !is_key_up {
+                        return;
+                    }
+                    let interval = hook_state.interval;
+                    let Some(tap) = hook_state
+                        .taps
+                        .iter_mut()
+                        .find(|tap| tap.target_vks.contains(&vk_code))
+                    else {
+                        return;
+                    };
+                    let now = Instant::now();
+                    match tap.last_release {
+                        Some(last) if now.duration_since(last) <= interval => {
+                            // Double-tap detected!
+                            tracing::info!("Double-tap detected!");
+                            tap.last_release = None;
+                            (hook_state.callback)(tap.event);
                         }
+                        _ => tap.last_release = Some(now),
                     }
                 }
             });
@@ -257,11 +641,32 @@ fn run_modifier_double_tap_hook<F>(
         CallNextHookEx(HHOOK::default(), code, wparam, lparam)
     }
 
-    // Install the hook
-    let hook = unsafe {
-        SetWindowsHookExW(WH_KEYBOARD_LL, Some(keyboard_hook_proc), None, 0)
+    run_keyboard_hook(keyboard_hook_proc, ready);
+}
+
+/// Install a low-level keyboard hook and pump messages for it on this
+/// thread until the thread's message loop ends
+///
+/// The thread id goes to `ready` once the hook is in; posting WM_QUIT to
+/// it removes the hook.
+#[cfg(target_os = "windows")]
+fn run_keyboard_hook(
+    hook_proc: unsafe extern "system" fn(
+        i32,
+        windows::Win32::Foundation::WPARAM,
+        windows::Win32::Foundation::LPARAM,
+    ) -> windows::Win32::Foundation::LRESULT,
+    ready: std::sync::mpsc::Sender<u32>,
+) {
+    use windows::Win32::System::Threading::GetCurrentThreadId;
+    use windows::Win32::UI::WindowsAndMessaging::{
+        DispatchMessageW, GetMessageW, PeekMessageW, SetWindowsHookExW, UnhookWindowsHookEx, MSG,
+        PM_NOREMOVE, WH_KEYBOARD_LL,
     };
 
+    // Install the hook
+    let hook = unsafe { SetWindowsHookExW(WH_KEYBOARD_LL, Some(hook_proc), None, 0) };
+
     match hook {
         Ok(h) => {
             tracing::info!("Keyboard hook installed successfully");
@@ -269,6 +674,9 @@ fn run_modifier_double_tap_hook<F>(
             // Message loop to keep hook alive
             let mut msg = MSG::default();
             unsafe {
+                // Creates the message queue, so WM_QUIT can be posted
+                let _ = PeekMessageW(&mut msg, None, 0, 0, PM_NOREMOVE);
+                let _ = ready.send(GetCurrentThreadId());
                 while GetMessageW(&mut msg, None, 0, 0).as_bool() {
                     DispatchMessageW(&msg);
                 }
@@ -284,6 +692,171 @@ fn run_modifier_double_tap_hook<F>(
     }
 }
 
+/// Windows keyboard hook for push-to-talk
+///
+/// `Start` goes out once the key has been held for `threshold` with no
+/// other key pressed, so quick taps and shortcuts using it don't record;
+/// `Stop` follows on release. A hold key that isn't a modifier is kept
+/// from the focused app.
+#[cfg(target_os = "windows")]
+fn run_hold_hook(
+    key: &str,
+    threshold: Duration,
+    is_active: Arc<AtomicBool>,
+    callback: TriggerCallback,
+    ready: std::sync::mpsc::Sender<u32>,
+) {
+    use std::cell::RefCell;
+    use windows::Win32::Foundation::{LPARAM, LRESULT, WPARAM};
+    use windows::Win32::UI::WindowsAndMessaging::{
+        CallNextHookEx, HHOOK, KBDLLHOOKSTRUCT, WM_KEYUP, WM_SYSKEYUP,
+    };
+
+    let Some(target_vks) = key_vks(key) else {
+        tracing::error!("Unknown hold key: {}", key);
+        return;
+    };
+    let swallow = !matches!(key.trim().to_lowercase().as_str(), "ctrl" | "control" | "shift" | "alt");
+
+    tracing::info!("Starting keyboard hook for hold-to-talk on {}", key);
+
+    /// The hold key's current press, shared with its timer thread
+    #[derive(Default)]
+    struct HoldPress {
+        /// Bumped by every press
+        id: u64,
+        /// Physically down; key repeat doesn't count as a new press
+        down: bool,
+        /// Down and not part of a shortcut
+        held: bool,
+        /// `Start` went out for this press
+        started: bool,
+    }
+
+    struct HookState {
+        target_vks: Vec<u16>,
+        threshold: Duration,
+        swallow: bool,
+        press: Arc<Mutex<HoldPress>>,
+        callback: TriggerCallback,
+        is_active: Arc<AtomicBool>,
+    }
+
+    thread_local! {
+        static HOLD_STATE: RefCell<Option<HookState>> = const { RefCell::new(None) };
+    }
+
+    HOLD_STATE.with(|state| {
+        *state.borrow_mut() = Some(HookState {
+            target_vks,
+            threshold,
+            swallow,
+            press: Arc::new(Mutex::new(HoldPress::default())),
+            callback,
+            is_active,
+        });
+    });
+
+    unsafe extern "system" fn hold_hook_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        if code >= 0 {
+            let kb_struct = &*(lparam.0 as *const KBDLLHOOKSTRUCT);
+            let vk_code = kb_struct.vkCode as u16;
+            let is_key_up = wparam.0 as u32 == WM_KEYUP || wparam.0 as u32 == WM_SYSKEYUP;
+
+            let swallowed = HOLD_STATE.with(|state| {
+                let state = state.borrow();
+                let Some(hook_state) = state.as_ref() else {
+                    return false;
+                };
+                if !hook_state.is_active.load(Ordering::SeqCst) {
+                    return false;
+                }
+                let mut press = hook_state.press.lock().unwrap();
+                if !hook_state.target_vks.contains(&vk_code) {
+                    // Another key while the hold key is down: a shortcut
+                    if !is_key_up && press.down && !press.started {
+                        press.held = false;
+                    }
+                    return false;
+                }
+
+                if is_key_up {
+                    press.down = false;
+                    press.held = false;
+                    // Under the lock, so Stop never overtakes Start
+                    if std::mem::take(&mut press.started) {
+                        (hook_state.callback)(HotkeyEvent::Stop);
+                    }
+                } else if !press.down {
+                    press.id += 1;
+                    press.down = true;
+                    press.held = true;
+                    let id = press.id;
+                    let shared = hook_state.press.clone();
+                    let callback = hook_state.callback.clone();
+                    let threshold = hook_state.threshold;
+                    thread::spawn(move || {
+                        thread::sleep(threshold);
+                        let mut press = shared.lock().unwrap();
+                        if press.held && press.id == id {
+                            press.started = true;
+                            tracing::info!("Hold key held, starting");
+                            callback(HotkeyEvent::Start);
+                        }
+                    });
+                }
+                hook_state.swallow
+            });
+            if swallowed {
+                return LRESULT(1);
+            }
+        }
+
+        CallNextHookEx(HHOOK::default(), code, wparam, lparam)
+    }
+
+    run_keyboard_hook(hold_hook_proc, ready);
+}
+
+/// Virtual key codes a key name comes as: "Ctrl", "Shift", "Alt",
+/// "F1" to "F24", a letter or digit, "Space", "CapsLock", "ScrollLock",
+/// "Pause" or "Insert"
+#[cfg(target_os = "windows")]
+fn key_vks(key: &str) -> Option<Vec<u16>> {
+    use windows::Win32::UI::Input::KeyboardAndMouse::{
+        VK_CAPITAL, VK_CONTROL, VK_F1, VK_INSERT, VK_LCONTROL, VK_LMENU, VK_LSHIFT, VK_PAUSE,
+        VK_RCONTROL, VK_RMENU, VK_RSHIFT, VK_SCROLL, VK_SPACE,
+    };
+
+    let key = key.trim().to_lowercase();
+    let vks = match key.as_str() {
+        "ctrl" | "control" => vec![VK_CONTROL.0, VK_LCONTROL.0, VK_RCONTROL.0],
+        "shift" => vec![VK_LSHIFT.0, VK_RSHIFT.0],
+        "alt" => vec![VK_LMENU.0, VK_RMENU.0],
+        "space" => vec![VK_SPACE.0],
+        "capslock" => vec![VK_CAPITAL.0],
+        "scrolllock" => vec![VK_SCROLL.0],
+        "pause" => vec![VK_PAUSE.0],
+        "insert" => vec![VK_INSERT.0],
+        _ => {
+            let function = key
+                .strip_prefix('f')
+                .and_then(|number| number.parse::<u16>().ok())
+                .filter(|number| (1..=24).contains(number));
+            let mut chars = key.chars();
+            match (function, chars.next(), chars.next()) {
+                (Some(number), _, _) => vec![VK_F1.0 + number - 1],
+                // Letters and digits are their own key codes
+                (None, Some(c), None) if c.is_ascii_alphanumeric() => {
+                    vec![c.to_ascii_uppercase() as u16]
+                }
+                _ => return None,
+            }
+        }
+    };
+    Some(vks)
+}
+
 /// Parse a combo key string like "Ctrl+Shift+V"
 fn parse_combo_key(key_str: &str) -> Result<HotKey> {
     let parts: Vec<&str> = key_str.split('+').map(|s| s.trim()).collect();
@@ -367,3 +940,116 @@ fn parse_key_code(key: &str) -> Result<Code> {
 
     Ok(code)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn combo(binding: &Binding) -> HotKey {
+        match binding {
+            Binding::Combo(hotkey) => *hotkey,
+            other => panic!("expected a combo, got {:?}", other),
+        }
+    }
+
+    fn plan_error(config: &HotkeyConfig) -> String {
+        match Plan::from_config(config) {
+            Ok(_) => panic!("expected the plan to be refused"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn combo_specs_parse() {
+        let hotkey = combo(&Binding::parse(" Ctrl + Alt + S ").unwrap());
+        assert_eq!(hotkey.mods, Modifiers::CONTROL | Modifiers::ALT);
+        assert_eq!(hotkey.key, Code::KeyS);
+
+        let hotkey = combo(&Binding::parse("F9").unwrap());
+        assert!(hotkey.mods.is_empty());
+        assert_eq!(hotkey.key, Code::F9);
+    }
+
+    #[test]
+    fn double_tap_specs_parse() {
+        assert!(matches!(
+            Binding::parse("double:Shift").unwrap(),
+            Binding::DoubleTap(key) if key == "shift"
+        ));
+        assert!(matches!(
+            Binding::parse("Double: Control").unwrap(),
+            Binding::DoubleTap(key) if key == "ctrl"
+        ));
+        assert!(matches!(
+            Binding::parse("double:f8").unwrap(),
+            Binding::DoubleTap(key) if key == "f8"
+        ));
+    }
+
+    #[test]
+    fn bad_specs_are_refused() {
+        assert!(Binding::parse("Ctrl+Shift").is_err());
+        assert!(Binding::parse("Ctrl+Nope").is_err());
+        assert!(Binding::parse("double:Nope").is_err());
+        assert!(Binding::parse("").is_err());
+    }
+
+    #[test]
+    fn same_combo_in_another_order_conflicts() {
+        let bindings = [
+            ("start_key", Binding::parse("Ctrl+Alt+S").unwrap()),
+            ("stop_key", Binding::parse("alt+ctrl+s").unwrap()),
+        ];
+        let error = check_conflicts(&bindings).unwrap_err().to_string();
+        assert!(error.contains("hotkey.stop_key"), "{}", error);
+        assert!(error.contains("hotkey.start_key"), "{}", error);
+    }
+
+    #[test]
+    fn distinct_bindings_pass() {
+        let bindings = [
+            ("combo_key", Binding::parse("Ctrl+Shift+V").unwrap()),
+            ("start_key", Binding::parse("Ctrl+Shift+S").unwrap()),
+            ("stop_key", Binding::parse("double:Shift").unwrap()),
+            ("cancel_key", Binding::parse("double:Ctrl").unwrap()),
+        ];
+        assert!(check_conflicts(&bindings).is_ok());
+    }
+
+    #[test]
+    fn default_settings_plan() {
+        let plan = Plan::from_config(&HotkeyConfig::default()).unwrap();
+        assert_eq!(plan.triggers.len(), 1);
+    }
+
+    #[test]
+    fn hold_key_conflicts_with_the_same_combo() {
+        let config = HotkeyConfig {
+            mode: "hold".to_string(),
+            hold_key: "F9".to_string(),
+            start_key: "F9".to_string(),
+            ..HotkeyConfig::default()
+        };
+        let error = plan_error(&config);
+        assert!(error.contains("hotkey.hold_key"), "{}", error);
+
+        // With a modifier the combo is a different chord
+        let config = HotkeyConfig {
+            start_key: "Ctrl+F9".to_string(),
+            ..config
+        };
+        assert!(Plan::from_config(&config).is_ok());
+    }
+
+    #[test]
+    fn hold_key_conflicts_with_a_double_tap() {
+        let config = HotkeyConfig {
+            mode: "hold".to_string(),
+            hold_key: "Control".to_string(),
+            cancel_key: "double:Ctrl".to_string(),
+            ..HotkeyConfig::default()
+        };
+        let error = plan_error(&config);
+        assert!(error.contains("hotkey.cancel_key"), "{}", error);
+    }
+}
@@ -4,8 +4,18 @@
 
 mod hotkey_manager;
 mod text_inserter;
+mod text_processing;
+#[cfg(target_os = "windows")]
+mod uia_inserter;
 mod voice_controller;
 
-pub use hotkey_manager::HotkeyManager;
-pub use text_inserter::TextInserter;
-pub use voice_controller::VoiceController;
+pub use hotkey_manager::{HotkeyEvent, HotkeyManager};
+pub use text_inserter::{
+    ElevatedTarget, InsertAborted, InsertTarget, NoEditableFocus, NoKeyForChar, TextInserter,
+    TooLongToType,
+};
+pub use text_processing::{
+    normalize_numbers, normalize_punctuation, space_cjk_latin, Fillers, PunctuationStyle,
+    Replacements, VoiceCommand, VoiceCommands,
+};
+pub use voice_controller::{VoiceController, VoiceControllerEvent};
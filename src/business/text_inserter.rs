@@ -1,58 +1,562 @@
 //! Text Inserter using Windows SendInput API
 //!
-//! Inserts text into the currently focused window using keyboard simulation.
+//! Inserts text into the currently focused window using keyboard simulation,
+//! or by pasting it through the clipboard for long text and apps that drop
+//! simulated characters.
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use std::mem::size_of;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use windows::core::PWSTR;
+use windows::Win32::Foundation::{
+    CloseHandle, GetLastError, GlobalFree, E_ACCESSDENIED, HANDLE, HGLOBAL, HWND,
+};
+use windows::Win32::Security::{GetTokenInformation, TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY};
+use windows::Win32::System::DataExchange::{
+    CloseClipboard, CountClipboardFormats, EmptyClipboard, GetClipboardData,
+    GetClipboardSequenceNumber, IsClipboardFormatAvailable, OpenClipboard, SetClipboardData,
+};
+use windows::Win32::System::Memory::{
+    GlobalAlloc, GlobalLock, GlobalSize, GlobalUnlock, GMEM_MOVEABLE,
+};
+use windows::Win32::System::Ole::CF_UNICODETEXT;
+use windows::Win32::System::Threading::{
+    AttachThreadInput, GetCurrentProcess, GetCurrentThreadId, OpenProcess, OpenProcessToken,
+    QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+};
 use windows::Win32::UI::Input::KeyboardAndMouse::{
-    SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBDINPUT, KEYEVENTF_KEYUP, KEYEVENTF_UNICODE,
-    VIRTUAL_KEY, VK_BACK,
+    GetAsyncKeyState, GetKeyboardLayout, SendInput, SetFocus, VkKeyScanExW, INPUT, INPUT_0,
+    INPUT_KEYBOARD, KEYBDINPUT, KEYEVENTF_KEYUP, KEYEVENTF_UNICODE, VIRTUAL_KEY, VK_BACK,
+    VK_CONTROL, VK_ESCAPE, VK_MENU, VK_RETURN, VK_SHIFT, VK_V,
 };
+use windows::Win32::UI::TextServices::HKL;
+use windows::Win32::UI::WindowsAndMessaging::{
+    GetForegroundWindow, GetGUIThreadInfo, GetWindowThreadProcessId, IsIconic, IsWindow,
+    SetForegroundWindow, ShowWindow, GUITHREADINFO, GUI_CARETBLINKING, SW_RESTORE,
+};
+
+use crate::data::TextInsertConfig;
+
+/// Attempts to open a clipboard another program holds before giving up
+const CLIPBOARD_OPEN_ATTEMPTS: u32 = 10;
+/// Wait between those attempts
+const CLIPBOARD_RETRY_DELAY: Duration = Duration::from_millis(20);
+/// Time for a window brought to the front to take the keyboard focus
+const ACTIVATE_DELAY: Duration = Duration::from_millis(50);
+/// How long elevation and text field checks of the focus are reused, so
+/// interim updates don't query tokens and UI Automation every time
+const FOCUS_CHECK_TTL: Duration = Duration::from_secs(3);
+
+/// The focused window runs as administrator and we don't, so Windows
+/// (UIPI) would silently discard simulated input
+#[derive(Debug, thiserror::Error)]
+#[error("the target window is running as administrator")]
+pub struct ElevatedTarget;
+
+/// Nothing that takes text has the keyboard focus (the desktop, a game),
+/// where typed characters would act as shortcuts
+#[derive(Debug, thiserror::Error)]
+#[error("no text field has the keyboard focus")]
+pub struct NoEditableFocus;
+
+/// Text longer than `max_inject_chars` went to the clipboard instead of
+/// being typed, which would tie up the keyboard for a long time
+#[derive(Debug, thiserror::Error)]
+#[error("text of {units} UTF-16 units is over the typing limit of {limit}")]
+pub struct TooLongToType {
+    pub units: usize,
+    pub limit: usize,
+}
+
+/// The user pressed Esc while text was being typed
+#[derive(Debug, thiserror::Error)]
+#[error("typing stopped with Esc after {done} of {count} characters")]
+pub struct InsertAborted {
+    pub done: usize,
+    pub count: usize,
+}
+
+/// `text_insert.mode = "vk"` met a character that isn't ASCII or has no
+/// key on the keyboard layout, and put the text on the clipboard instead
+#[derive(Debug, thiserror::Error)]
+#[error("{character:?} cannot be typed with the keys of the current keyboard layout")]
+pub struct NoKeyForChar {
+    pub character: char,
+}
+
+/// How text reaches the focused window (`text_insert.mode`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InsertMode {
+    /// One simulated key press per character
+    SendInput,
+    /// Put the text on the clipboard and press Ctrl+V
+    Clipboard,
+    /// Clipboard for text of at least `clipboard_threshold` characters
+    Auto,
+    /// Set the focused element's value through UI Automation, typing when
+    /// it doesn't support that
+    Uia,
+    /// Only put text on the clipboard; no key is ever simulated
+    ClipboardOnly,
+    /// Press the keyboard layout's keys for ASCII text, for apps that
+    /// ignore simulated Unicode characters
+    Vk,
+}
+
+impl InsertMode {
+    fn parse(mode: &str) -> Self {
+        match mode.to_ascii_lowercase().as_str() {
+            "sendinput" => InsertMode::SendInput,
+            "clipboard" => InsertMode::Clipboard,
+            "auto" => InsertMode::Auto,
+            "uia" => InsertMode::Uia,
+            "clipboard-only" => InsertMode::ClipboardOnly,
+            "vk" => InsertMode::Vk,
+            _ => {
+                tracing::warn!("Unknown text_insert.mode {:?}, using \"sendinput\"", mode);
+                InsertMode::SendInput
+            }
+        }
+    }
+}
+
+/// What a line break in typed text becomes (`text_insert.newline`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NewlineKey {
+    /// Press Enter
+    Enter,
+    /// Press Shift+Enter, a line break that doesn't send chat messages
+    ShiftEnter,
+    /// Type the line feed character itself
+    Literal,
+}
+
+impl NewlineKey {
+    fn parse(newline: &str) -> Self {
+        match newline.to_ascii_lowercase().as_str() {
+            "enter" => NewlineKey::Enter,
+            "shift-enter" => NewlineKey::ShiftEnter,
+            "literal" => NewlineKey::Literal,
+            _ => {
+                tracing::warn!("Unknown text_insert.newline {:?}, using \"enter\"", newline);
+                NewlineKey::Enter
+            }
+        }
+    }
+}
+
+/// Insertion settings, replaced when the config is reloaded
+struct Settings {
+    mode: InsertMode,
+    /// Log instead of sending anything
+    dry_run: bool,
+    newline: NewlineKey,
+    clipboard_threshold: usize,
+    require_editable: bool,
+    /// Lowercased executable names taken as editable without checking
+    editable_apps: Vec<String>,
+    restore_delay: Duration,
+    chunk_chars: usize,
+    chunk_delay: Duration,
+    /// Longest text typed, in UTF-16 units; 0 for no limit
+    max_inject_chars: usize,
+}
+
+impl Settings {
+    fn from_config(config: &TextInsertConfig) -> Self {
+        Self {
+            mode: InsertMode::parse(&config.mode),
+            dry_run: config.dry_run,
+            newline: NewlineKey::parse(&config.newline),
+            clipboard_threshold: config.clipboard_threshold,
+            require_editable: config.require_editable,
+            editable_apps: config
+                .editable_apps
+                .iter()
+                .map(|app| app.trim().to_lowercase())
+                .collect(),
+            restore_delay: Duration::from_millis(config.restore_delay_ms),
+            chunk_chars: config.chunk_chars,
+            chunk_delay: Duration::from_millis(config.chunk_delay_ms),
+            max_inject_chars: config.max_inject_chars,
+        }
+    }
+}
+
+/// Clipboard contents from before a paste
+enum SavedClipboard {
+    Text(String),
+    Empty,
+    /// Images, files and the like, which are not restored
+    Other,
+}
+
+/// The user's clipboard while our pasted text sits on it
+#[derive(Default)]
+struct PendingRestore {
+    /// Set by the first of a run of pastes, taken by the restore
+    saved: Option<SavedClipboard>,
+    /// Clipboard sequence number right after our last paste; a different
+    /// one means someone copied since, and nothing is restored
+    sequence: u32,
+    /// Bumped by every paste, so only the restore scheduled last runs
+    generation: u64,
+}
+
+/// Result of checking a window's elevation
+#[derive(Clone, Copy)]
+struct ElevationCheck {
+    window: isize,
+    elevated: bool,
+    at: Instant,
+}
+
+/// Result of checking whether the focus takes text
+#[derive(Clone, Copy)]
+struct EditableCheck {
+    target: InsertTarget,
+    editable: bool,
+    at: Instant,
+}
 
 /// Text inserter service using Windows SendInput API
-pub struct TextInserter;
+pub struct TextInserter {
+    settings: Mutex<Settings>,
+    pending: Arc<Mutex<PendingRestore>>,
+    elevation: Mutex<Option<ElevationCheck>>,
+    editable: Mutex<Option<EditableCheck>>,
+}
 
 impl TextInserter {
     /// Create a new text inserter
     pub fn new() -> Self {
-        Self
+        Self::with_config(&TextInsertConfig::default())
+    }
+
+    /// Create a text inserter using the `[text_insert]` settings
+    pub fn with_config(config: &TextInsertConfig) -> Self {
+        Self {
+            settings: Mutex::new(Settings::from_config(config)),
+            pending: Arc::new(Mutex::new(PendingRestore::default())),
+            elevation: Mutex::new(None),
+            editable: Mutex::new(None),
+        }
+    }
+
+    /// Apply changed `[text_insert]` settings
+    pub fn update_config(&self, config: &TextInsertConfig) {
+        *self.settings.lock().unwrap() = Settings::from_config(config);
     }
 
     /// Insert text into the currently focused window
+    ///
+    /// Falls back to typing when the clipboard can't be used. Fails with
+    /// `ElevatedTarget` when the window runs as administrator and we don't,
+    /// or `NoEditableFocus` when nothing focused takes text (unless
+    /// `require_editable` is off), leaving the text on the clipboard. With
+    /// `dry_run` it only logs what would be typed where.
     pub fn insert(&self, text: &str) -> Result<()> {
         if text.is_empty() {
             return Ok(());
         }
+        if self.dry_run(|| format!("type {:?}", text)) {
+            return Ok(());
+        }
+        if self.settings.lock().unwrap().mode == InsertMode::ClipboardOnly {
+            return set_clipboard_text(text);
+        }
+        if let Err(e) = self.check_target().and_then(|()| self.check_editable()) {
+            if let Err(copy_error) = set_clipboard_text(text) {
+                tracing::error!("Failed to copy to clipboard: {:#}", copy_error);
+            }
+            return Err(e);
+        }
 
-        let mut inputs: Vec<INPUT> = Vec::new();
+        let chars: Vec<char> = text.chars().collect();
+        let (mode, paste, restore_delay, newline, max_inject_chars) = {
+            let settings = self.settings.lock().unwrap();
+            let paste = match settings.mode {
+                InsertMode::SendInput | InsertMode::Uia | InsertMode::ClipboardOnly | InsertMode::Vk => {
+                    false
+                }
+                InsertMode::Clipboard => true,
+                InsertMode::Auto => chars.len() >= settings.clipboard_threshold,
+            };
+            let mode = settings.mode;
+            (mode, paste, settings.restore_delay, settings.newline, settings.max_inject_chars)
+        };
+        #[cfg(target_os = "windows")]
+        if mode == InsertMode::Uia {
+            match super::uia_inserter::insert(text) {
+                Ok(true) => return Ok(()),
+                Ok(false) => tracing::debug!("UI Automation can't set the text, typing it"),
+                Err(e) => tracing::warn!("UI Automation insertion failed, typing the text: {:#}", e),
+            }
+        }
+        if paste {
+            match self.paste(text, restore_delay) {
+                Ok(()) => return Ok(()),
+                Err(e) => tracing::warn!("Cannot paste, typing the text instead: {:#}", e),
+            }
+        }
 
-        for ch in text.encode_utf16() {
-            // Key down
-            inputs.push(self.create_unicode_input(ch, true));
-            // Key up
-            inputs.push(self.create_unicode_input(ch, false));
+        // Typing time grows with the UTF-16 units sent
+        let units = text.encode_utf16().count();
+        if max_inject_chars > 0 && units > max_inject_chars {
+            set_clipboard_text(text)?;
+            return Err(TooLongToType {
+                units,
+                limit: max_inject_chars,
+            }
+            .into());
         }
 
-        self.send_inputs(&inputs)?;
+        // Looked up before anything is typed, so unknown characters don't
+        // leave half the text behind
+        let strokes = if mode == InsertMode::Vk {
+            let layout = foreground_layout();
+            match key_strokes(&chars, |c| unsafe { VkKeyScanExW(c as u16, layout) }) {
+                Ok(strokes) => Some(strokes),
+                Err(e) => {
+                    set_clipboard_text(text)?;
+                    return Err(e.into());
+                }
+            }
+        } else {
+            None
+        };
+
+        self.send_in_chunks(chars.len(), |index, inputs| {
+            // A typed line feed isn't a new line everywhere; Enter is
+            let literal = newline == NewlineKey::Literal && strokes.is_none();
+            if chars[index] == '\n' && !literal {
+                let shift = newline == NewlineKey::ShiftEnter;
+                if shift {
+                    inputs.push(self.create_key_input(VK_SHIFT, true));
+                }
+                inputs.push(self.create_key_input(VK_RETURN, true));
+                inputs.push(self.create_key_input(VK_RETURN, false));
+                if shift {
+                    inputs.push(self.create_key_input(VK_SHIFT, false));
+                }
+                return;
+            }
+            if let Some(stroke) = strokes.as_ref().and_then(|strokes| strokes[index]) {
+                let modifiers = stroke.modifiers();
+                for &modifier in &modifiers {
+                    inputs.push(self.create_key_input(modifier, true));
+                }
+                inputs.push(self.create_key_input(stroke.vk, true));
+                inputs.push(self.create_key_input(stroke.vk, false));
+                for &modifier in modifiers.iter().rev() {
+                    inputs.push(self.create_key_input(modifier, false));
+                }
+                return;
+            }
+            let mut units = [0u16; 2];
+            for &unit in chars[index].encode_utf16(&mut units).iter() {
+                // Key down
+                inputs.push(self.create_unicode_input(unit, true));
+                // Key up
+                inputs.push(self.create_unicode_input(unit, false));
+            }
+        })
+    }
+
+    /// Turn `prev`, typed just before the caret, into `next`
+    ///
+    /// Only the part after their common prefix is erased and retyped, so
+    /// growing interim results don't flicker. Backspaces are counted in
+    /// UTF-16 code units, which is what edit controls delete one at a time.
+    pub fn update_interim(&self, prev: &str, next: &str) -> Result<()> {
+        let kept = common_prefix_len(prev, next);
+        let to_delete = prev[kept..].encode_utf16().count();
+        let to_append = &next[kept..];
+
+        if to_delete > 0 {
+            self.delete_chars(to_delete)?;
+        }
+        if !to_append.is_empty() {
+            self.insert(to_append)?;
+        }
+
+        tracing::debug!(
+            "Updated text incrementally: '{}' -> '{}' (deleted {} units, appended '{}')",
+            prev,
+            next,
+            to_delete,
+            to_append
+        );
         Ok(())
     }
 
+    /// Type whatever `next` adds beyond what it shares with `prev`, never
+    /// deleting anything
+    pub fn append_missing(&self, prev: &str, next: &str) -> Result<()> {
+        self.insert(&next[common_prefix_len(prev, next)..])
+    }
+
     /// Delete specified number of characters (simulate backspace)
     pub fn delete_chars(&self, count: usize) -> Result<()> {
         if count == 0 {
             return Ok(());
         }
+        if self.dry_run(|| format!("press Backspace {} times", count)) {
+            return Ok(());
+        }
+        self.check_target()?;
 
-        let mut inputs: Vec<INPUT> = Vec::new();
-
-        for _ in 0..count {
+        self.send_in_chunks(count, |_, inputs| {
             // Backspace key down
             inputs.push(self.create_key_input(VK_BACK, true));
             // Backspace key up
             inputs.push(self.create_key_input(VK_BACK, false));
+        })
+    }
+
+    /// Press and release a key that isn't a character, e.g. Enter or Tab
+    pub fn press_key(&self, key: VIRTUAL_KEY) -> Result<()> {
+        if self.dry_run(|| format!("press {:?}", key)) {
+            return Ok(());
+        }
+        self.check_target()?;
+        let inputs = [self.create_key_input(key, true), self.create_key_input(key, false)];
+        self.send_inputs(&inputs)
+            .map_err(|failure| anyhow!("SendInput failed to press {:?}{}", key, failure.reason()))
+    }
+
+    /// Put text on the clipboard without typing it
+    pub fn copy_to_clipboard(&self, text: &str) -> Result<()> {
+        set_clipboard_text(text)
+    }
+
+    /// In dry-run mode, log the `action` that would have been taken, with
+    /// the insertion mode and the window, and return true
+    fn dry_run(&self, action: impl FnOnce() -> String) -> bool {
+        let mode = {
+            let settings = self.settings.lock().unwrap();
+            if !settings.dry_run {
+                return false;
+            }
+            settings.mode
+        };
+        let app = InsertTarget::current().and_then(|target| target.process_name());
+        tracing::info!(
+            "Dry run: would {} ({:?} mode) in {}",
+            action(),
+            mode,
+            app.as_deref().unwrap_or("no window")
+        );
+        true
+    }
+
+    /// Fail with `ElevatedTarget` if input to the foreground window would
+    /// be discarded, or at all in clipboard-only mode; the answer is cached
+    /// per window for a few seconds
+    fn check_target(&self) -> Result<()> {
+        if self.settings.lock().unwrap().mode == InsertMode::ClipboardOnly {
+            anyhow::bail!("keys are never sent with text_insert.mode = \"clipboard-only\"");
+        }
+        let window = unsafe { GetForegroundWindow() };
+        if window.0 == 0 {
+            return Ok(());
         }
+        let mut cache = self.elevation.lock().unwrap();
+        let elevated = match *cache {
+            Some(check) if check.window == window.0 && check.at.elapsed() < FOCUS_CHECK_TTL => {
+                check.elevated
+            }
+            _ => {
+                let elevated = window_elevated(window);
+                *cache = Some(ElevationCheck {
+                    window: window.0,
+                    elevated,
+                    at: Instant::now(),
+                });
+                elevated
+            }
+        };
+        if elevated {
+            tracing::warn!("Foreground window runs as administrator, not sending input");
+            return Err(ElevatedTarget.into());
+        }
+        Ok(())
+    }
 
-        self.send_inputs(&inputs)?;
+    /// Fail with `NoEditableFocus` unless the focused control takes text;
+    /// the answer is cached per control for a few seconds
+    fn check_editable(&self) -> Result<()> {
+        let editable_apps = {
+            let settings = self.settings.lock().unwrap();
+            if !settings.require_editable {
+                return Ok(());
+            }
+            settings.editable_apps.clone()
+        };
+        let Some(target) = InsertTarget::current() else {
+            return Err(NoEditableFocus.into());
+        };
+        let mut cache = self.editable.lock().unwrap();
+        let editable = match *cache {
+            Some(check) if check.target == target && check.at.elapsed() < FOCUS_CHECK_TTL => {
+                check.editable
+            }
+            _ => {
+                let editable = focus_editable(&target, &editable_apps);
+                *cache = Some(EditableCheck {
+                    target,
+                    editable,
+                    at: Instant::now(),
+                });
+                editable
+            }
+        };
+        if !editable {
+            tracing::warn!("No text field has the focus, not sending input");
+            return Err(NoEditableFocus.into());
+        }
+        Ok(())
+    }
+
+    /// Paste `text` with Ctrl+V and put the user's clipboard back after
+    /// `restore_delay`
+    fn paste(&self, text: &str, restore_delay: Duration) -> Result<()> {
+        let generation = {
+            let mut pending = self.pending.lock().unwrap();
+            // Later pastes of a run keep what the first one saved
+            let fresh = pending.saved.is_none();
+            if fresh {
+                pending.saved = Some(read_clipboard()?);
+            }
+            if let Err(e) = set_clipboard_text(text) {
+                if fresh {
+                    pending.saved = None;
+                }
+                return Err(e);
+            }
+            pending.sequence = unsafe { GetClipboardSequenceNumber() };
+            pending.generation += 1;
+            pending.generation
+        };
+
+        let keys = [
+            self.create_key_input(VK_CONTROL, true),
+            self.create_key_input(VK_V, true),
+            self.create_key_input(VK_V, false),
+            self.create_key_input(VK_CONTROL, false),
+        ];
+        if let Err(failure) = self.send_inputs(&keys) {
+            return Err(anyhow!("SendInput failed to press Ctrl+V{}", failure.reason()));
+        }
+
+        // The target app reads the clipboard when it handles the keys
+        let pending = self.pending.clone();
+        thread::spawn(move || {
+            thread::sleep(restore_delay);
+            restore_clipboard(&pending, generation);
+        });
         Ok(())
     }
 
@@ -96,23 +600,113 @@ impl TextInserter {
         }
     }
 
-    /// Send inputs using Windows SendInput API
-    fn send_inputs(&self, inputs: &[INPUT]) -> Result<()> {
-        if inputs.is_empty() {
+    /// Send the key presses for `count` characters, `chunk_chars` of them
+    /// per SendInput call with `chunk_delay` in between
+    ///
+    /// Apps with small input queues reorder or lose characters from one
+    /// huge batch. `to_inputs` adds the inputs for the character at an index.
+    /// Pressing Esc stops before the next chunk with `InsertAborted`.
+    fn send_in_chunks(
+        &self,
+        count: usize,
+        to_inputs: impl Fn(usize, &mut Vec<INPUT>),
+    ) -> Result<()> {
+        let (chunk_chars, chunk_delay) = {
+            let settings = self.settings.lock().unwrap();
+            (settings.chunk_chars.max(1), settings.chunk_delay)
+        };
+
+        let mut inputs: Vec<INPUT> = Vec::new();
+        // Number of inputs up to the end of each character in the chunk
+        let mut ends = Vec::with_capacity(chunk_chars);
+        // Clears the "pressed since the last call" bit of an earlier Esc
+        esc_pressed();
+        for start in (0..count).step_by(chunk_chars) {
+            if start > 0 && !chunk_delay.is_zero() {
+                thread::sleep(chunk_delay);
+            }
+            if start > 0 && esc_pressed() {
+                tracing::info!("Esc pressed, stopping after {} of {} characters", start, count);
+                return Err(InsertAborted { done: start, count }.into());
+            }
+            inputs.clear();
+            ends.clear();
+            for index in start..(start + chunk_chars).min(count) {
+                to_inputs(index, &mut inputs);
+                ends.push(inputs.len());
+            }
+
+            if let Err(failure) = self.send_inputs(&inputs) {
+                let done = start + ends.iter().filter(|&&end| end <= failure.sent).count();
+                return Err(anyhow!(
+                    "SendInput stopped after {} of {} characters{}",
+                    done,
+                    count,
+                    failure.reason()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Send inputs using Windows SendInput API, retrying the rest once if
+    /// it stops short
+    fn send_inputs(&self, inputs: &[INPUT]) -> std::result::Result<(), SendFailure> {
+        let mut sent = 0;
+        let mut blocked = false;
+        let mut os_error = None;
+        for attempt in 0..2 {
+            if sent == inputs.len() {
+                return Ok(());
+            }
+            if attempt > 0 {
+                tracing::warn!(
+                    "SendInput sent {} of {} inputs, retrying the rest",
+                    sent,
+                    inputs.len()
+                );
+            }
+            let count = unsafe { SendInput(&inputs[sent..], size_of::<INPUT>() as i32) } as usize;
+            // Nothing at all goes in when UIPI blocks the input, often
+            // without an error code
+            if count == 0 {
+                blocked = true;
+                os_error = unsafe { GetLastError() }.err();
+            }
+            sent += count;
+        }
+        if sent == inputs.len() {
             return Ok(());
         }
+        Err(SendFailure {
+            sent,
+            blocked,
+            os_error,
+        })
+    }
+}
 
-        let sent = unsafe { SendInput(inputs, size_of::<INPUT>() as i32) };
+/// SendInput gave up part way
+struct SendFailure {
+    /// Inputs that went in
+    sent: usize,
+    /// A call sent nothing at all
+    blocked: bool,
+    /// What GetLastError said about that
+    os_error: Option<windows::core::Error>,
+}
 
-        if sent != inputs.len() as u32 {
-            tracing::warn!(
-                "SendInput sent {} of {} inputs",
-                sent,
-                inputs.len()
-            );
+impl SendFailure {
+    /// Explanation appended to the error message
+    fn reason(&self) -> String {
+        if !self.blocked {
+            return String::new();
         }
-
-        Ok(())
+        let error = self
+            .os_error
+            .as_ref()
+            .map_or_else(|| "input was blocked".to_string(), ToString::to_string);
+        format!(": {} (the target window may be running as administrator)", error)
     }
 }
 
@@ -121,3 +715,484 @@ impl Default for TextInserter {
         Self::new()
     }
 }
+
+/// A key and the modifiers that type a character on a keyboard layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct KeyStroke {
+    vk: VIRTUAL_KEY,
+    shift: bool,
+    ctrl: bool,
+    alt: bool,
+}
+
+impl KeyStroke {
+    /// Decode a VkKeyScanExW result: the key in the low byte, Shift, Ctrl
+    /// and Alt as bits 1, 2 and 4 of the high byte, and -1 for no key
+    fn from_key_scan(scan: i16) -> Option<Self> {
+        let [vk, state] = scan.to_le_bytes();
+        if vk == 0xFF && state == 0xFF {
+            return None;
+        }
+        Some(Self {
+            vk: VIRTUAL_KEY(vk as u16),
+            shift: state & 1 != 0,
+            ctrl: state & 2 != 0,
+            alt: state & 4 != 0,
+        })
+    }
+
+    /// Modifier keys held around the key, in the order they go down
+    fn modifiers(&self) -> Vec<VIRTUAL_KEY> {
+        [(self.ctrl, VK_CONTROL), (self.alt, VK_MENU), (self.shift, VK_SHIFT)]
+            .into_iter()
+            .filter(|&(held, _)| held)
+            .map(|(_, key)| key)
+            .collect()
+    }
+}
+
+/// Keys for each of `chars`, looked up with `key_scan` (VkKeyScanExW on
+/// the target's layout); line feeds get none, they are pressed as Enter
+fn key_strokes(
+    chars: &[char],
+    key_scan: impl Fn(char) -> i16,
+) -> Result<Vec<Option<KeyStroke>>, NoKeyForChar> {
+    chars
+        .iter()
+        .map(|&character| {
+            if character == '\n' {
+                return Ok(None);
+            }
+            if !character.is_ascii() {
+                return Err(NoKeyForChar { character });
+            }
+            match KeyStroke::from_key_scan(key_scan(character)) {
+                Some(stroke) => Ok(Some(stroke)),
+                None => Err(NoKeyForChar { character }),
+            }
+        })
+        .collect()
+}
+
+/// Keyboard layout of the foreground window's thread, which decides what
+/// a key types there
+fn foreground_layout() -> HKL {
+    unsafe {
+        let thread = GetWindowThreadProcessId(GetForegroundWindow(), None);
+        GetKeyboardLayout(thread)
+    }
+}
+
+/// Whether Esc is down or was pressed since the last call
+fn esc_pressed() -> bool {
+    unsafe { GetAsyncKeyState(VK_ESCAPE.0 as i32) != 0 }
+}
+
+/// Byte length of the longest common prefix, on a character boundary
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.char_indices()
+        .zip(b.chars())
+        .find(|((_, x), y)| x != y)
+        .map_or_else(|| a.len().min(b.len()), |((index, _), _)| index)
+}
+
+/// The window and control with keyboard focus, where typed text goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InsertTarget {
+    window: isize,
+    focus: isize,
+}
+
+impl InsertTarget {
+    /// The current foreground window and its focused control, if any
+    pub fn current() -> Option<Self> {
+        unsafe {
+            let window = GetForegroundWindow();
+            if window.0 == 0 {
+                return None;
+            }
+            let thread = GetWindowThreadProcessId(window, None);
+            let mut info = GUITHREADINFO {
+                cbSize: size_of::<GUITHREADINFO>() as u32,
+                ..Default::default()
+            };
+            let focus = match GetGUIThreadInfo(thread, &mut info) {
+                Ok(()) => info.hwndFocus.0,
+                Err(_) => 0,
+            };
+            Some(Self {
+                window: window.0,
+                focus,
+            })
+        }
+    }
+
+    /// Executable name of the window's process, e.g. "notepad.exe"
+    pub fn process_name(&self) -> Option<String> {
+        unsafe {
+            let mut pid = 0;
+            GetWindowThreadProcessId(HWND(self.window), Some(&mut pid));
+            if pid == 0 {
+                return None;
+            }
+            let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+            let mut buffer = [0u16; 260];
+            let mut length = buffer.len() as u32;
+            let result = QueryFullProcessImageNameW(
+                process,
+                PROCESS_NAME_WIN32,
+                PWSTR(buffer.as_mut_ptr()),
+                &mut length,
+            );
+            let _ = CloseHandle(process);
+            result.ok()?;
+            let path = String::from_utf16_lossy(&buffer[..length as usize]);
+            path.rsplit('\\').next().map(str::to_string)
+        }
+    }
+
+    /// Bring this window back to the front and focus its control
+    ///
+    /// Windows only lets the foreground thread hand over the focus, so our
+    /// input is attached to it and to the target's thread while switching.
+    pub fn activate(&self) -> Result<()> {
+        if Self::current() == Some(*self) {
+            return Ok(());
+        }
+        let window = HWND(self.window);
+        unsafe {
+            if !IsWindow(window).as_bool() {
+                anyhow::bail!("the window dictation started in was closed");
+            }
+            tracing::info!("Focus moved while dictating, switching back to the original window");
+
+            let ours = GetCurrentThreadId();
+            let foreground = GetWindowThreadProcessId(GetForegroundWindow(), None);
+            let target = GetWindowThreadProcessId(window, None);
+            let attached: Vec<u32> = [foreground, target]
+                .into_iter()
+                .filter(|&thread| thread != 0 && thread != ours)
+                .filter(|&thread| AttachThreadInput(ours, thread, true).as_bool())
+                .collect();
+
+            if IsIconic(window).as_bool() {
+                ShowWindow(window, SW_RESTORE);
+            }
+            SetForegroundWindow(window);
+            if self.focus != 0 {
+                SetFocus(HWND(self.focus));
+            }
+
+            for thread in attached {
+                AttachThreadInput(ours, thread, false);
+            }
+        }
+
+        thread::sleep(ACTIVATE_DELAY);
+        match Self::current() {
+            Some(current) if current.window == self.window => Ok(()),
+            _ => Err(anyhow!("the window dictation started in could not be brought back")),
+        }
+    }
+}
+
+/// Whether the focused control takes text: it shows a caret, UI Automation
+/// sees an editable element, or its app is in `editable_apps`
+fn focus_editable(target: &InsertTarget, editable_apps: &[String]) -> bool {
+    if let Some(name) = target.process_name() {
+        if editable_apps.contains(&name.to_lowercase()) {
+            return true;
+        }
+    }
+    unsafe {
+        let thread = GetWindowThreadProcessId(HWND(target.window), None);
+        let mut info = GUITHREADINFO {
+            cbSize: size_of::<GUITHREADINFO>() as u32,
+            ..Default::default()
+        };
+        if GetGUIThreadInfo(thread, &mut info).is_ok()
+            && (info.hwndCaret.0 != 0 || info.flags.contains(GUI_CARETBLINKING))
+        {
+            return true;
+        }
+    }
+    // Browsers and other custom-drawn apps have no Win32 caret
+    #[cfg(target_os = "windows")]
+    let editable = super::uia_inserter::focused_editable().unwrap_or_else(|e| {
+        tracing::debug!("Cannot ask UI Automation about the focus: {}", e);
+        true
+    });
+    #[cfg(not(target_os = "windows"))]
+    let editable = true;
+    editable
+}
+
+/// Whether `window` belongs to an elevated process while we aren't elevated
+fn window_elevated(window: HWND) -> bool {
+    static SELF_ELEVATED: OnceLock<bool> = OnceLock::new();
+    let self_elevated = *SELF_ELEVATED.get_or_init(|| {
+        process_elevated(unsafe { GetCurrentProcess() }).unwrap_or_else(|e| {
+            tracing::warn!("Cannot tell whether we run as administrator: {}", e);
+            false
+        })
+    });
+    if self_elevated {
+        return false;
+    }
+
+    unsafe {
+        let mut pid = 0;
+        GetWindowThreadProcessId(window, Some(&mut pid));
+        if pid == 0 {
+            return false;
+        }
+        let process = match OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) {
+            Ok(process) => process,
+            Err(e) => {
+                tracing::debug!("Cannot open process {}: {}", pid, e);
+                return false;
+            }
+        };
+        let elevated = process_elevated(process);
+        let _ = CloseHandle(process);
+        match elevated {
+            Ok(elevated) => elevated,
+            // Tokens of elevated processes can't be opened from a normal one
+            Err(e) if e.code() == E_ACCESSDENIED => true,
+            Err(e) => {
+                tracing::debug!("Cannot query process {}: {}", pid, e);
+                false
+            }
+        }
+    }
+}
+
+/// Whether a process token is elevated
+fn process_elevated(process: HANDLE) -> windows::core::Result<bool> {
+    unsafe {
+        let mut token = HANDLE::default();
+        OpenProcessToken(process, TOKEN_QUERY, &mut token)?;
+        let mut elevation = TOKEN_ELEVATION::default();
+        let mut length = 0;
+        let result = GetTokenInformation(
+            token,
+            TokenElevation,
+            Some(&mut elevation as *mut _ as *mut _),
+            size_of::<TOKEN_ELEVATION>() as u32,
+            &mut length,
+        );
+        let _ = CloseHandle(token);
+        result.map(|()| elevation.TokenIsElevated != 0)
+    }
+}
+
+/// Open the clipboard, retrying briefly while another program has it open
+///
+/// Never waits indefinitely: a program that keeps the clipboard open makes
+/// this fail after `CLIPBOARD_OPEN_ATTEMPTS`.
+unsafe fn open_clipboard() -> Result<()> {
+    let mut attempt = 1;
+    loop {
+        match OpenClipboard(None) {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt >= CLIPBOARD_OPEN_ATTEMPTS => {
+                return Err(anyhow!("clipboard is in use by another program: {}", e));
+            }
+            Err(_) => {
+                attempt += 1;
+                thread::sleep(CLIPBOARD_RETRY_DELAY);
+            }
+        }
+    }
+}
+
+/// Replace the clipboard contents with `text`
+fn set_clipboard_text(text: &str) -> Result<()> {
+    let wide: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+    let bytes = wide.len() * size_of::<u16>();
+
+    unsafe {
+        open_clipboard()?;
+
+        let result = (|| -> Result<()> {
+            EmptyClipboard()?;
+
+            let hmem = GlobalAlloc(GMEM_MOVEABLE, bytes)?;
+            let ptr = GlobalLock(hmem) as *mut u16;
+            if ptr.is_null() {
+                let _ = GlobalFree(hmem);
+                anyhow::bail!("GlobalLock failed");
+            }
+            std::ptr::copy_nonoverlapping(wide.as_ptr(), ptr, wide.len());
+            // GlobalUnlock reports an "error" once the lock count reaches zero
+            let _ = GlobalUnlock(hmem);
+
+            // The clipboard owns the memory once SetClipboardData succeeds
+            if let Err(e) = SetClipboardData(CF_UNICODETEXT.0 as u32, HANDLE(hmem.0 as isize)) {
+                let _ = GlobalFree(hmem);
+                return Err(e.into());
+            }
+            Ok(())
+        })();
+
+        let _ = CloseClipboard();
+        result
+    }
+}
+
+/// Empty the clipboard
+fn clear_clipboard() -> Result<()> {
+    unsafe {
+        open_clipboard()?;
+        let result = EmptyClipboard().map_err(Into::into);
+        let _ = CloseClipboard();
+        result
+    }
+}
+
+/// Snapshot the clipboard so it can be put back after a paste
+fn read_clipboard() -> Result<SavedClipboard> {
+    unsafe {
+        open_clipboard()?;
+
+        let saved = (|| {
+            if CountClipboardFormats() == 0 {
+                return SavedClipboard::Empty;
+            }
+            if IsClipboardFormatAvailable(CF_UNICODETEXT.0 as u32).is_err() {
+                return SavedClipboard::Other;
+            }
+            let Ok(handle) = GetClipboardData(CF_UNICODETEXT.0 as u32) else {
+                return SavedClipboard::Other;
+            };
+            let hmem = HGLOBAL(handle.0 as *mut _);
+            let ptr = GlobalLock(hmem) as *const u16;
+            if ptr.is_null() {
+                return SavedClipboard::Other;
+            }
+            let units = std::slice::from_raw_parts(ptr, GlobalSize(hmem) / size_of::<u16>());
+            let end = units.iter().position(|&unit| unit == 0).unwrap_or(units.len());
+            let text = String::from_utf16_lossy(&units[..end]);
+            let _ = GlobalUnlock(hmem);
+            SavedClipboard::Text(text)
+        })();
+
+        let _ = CloseClipboard();
+        Ok(saved)
+    }
+}
+
+/// Put back what the clipboard held before a run of pastes, unless another
+/// paste came since or someone else changed the clipboard
+fn restore_clipboard(pending: &Mutex<PendingRestore>, generation: u64) {
+    let mut pending = pending.lock().unwrap();
+    if pending.generation != generation {
+        return;
+    }
+    let Some(saved) = pending.saved.take() else {
+        return;
+    };
+    if unsafe { GetClipboardSequenceNumber() } != pending.sequence {
+        tracing::debug!("Clipboard changed since the paste, not restoring it");
+        return;
+    }
+
+    let restored = match saved {
+        SavedClipboard::Text(text) => set_clipboard_text(&text),
+        SavedClipboard::Empty => clear_clipboard(),
+        SavedClipboard::Other => {
+            tracing::info!("Clipboard held non-text data before the paste, not restoring it");
+            Ok(())
+        }
+    };
+    if let Err(e) = restored {
+        tracing::warn!("Cannot restore the clipboard: {:#}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// VkKeyScanExW on the US layout, for the characters the tests type
+    fn us_layout(character: char) -> i16 {
+        const SHIFT: i16 = 0x100;
+        match character {
+            'a'..='z' => character.to_ascii_uppercase() as i16,
+            'A'..='Z' => character as i16 | SHIFT,
+            '0'..='9' | ' ' => character as i16,
+            '!' => '1' as i16 | SHIFT,
+            '@' => '2' as i16 | SHIFT,
+            ';' => 0xBA,
+            ':' => 0xBA | SHIFT,
+            '/' => 0xBF,
+            '?' => 0xBF | SHIFT,
+            '-' => 0xBD,
+            '_' => 0xBD | SHIFT,
+            _ => -1,
+        }
+    }
+
+    fn stroke(vk: u16, shift: bool) -> Option<KeyStroke> {
+        Some(KeyStroke {
+            vk: VIRTUAL_KEY(vk),
+            shift,
+            ctrl: false,
+            alt: false,
+        })
+    }
+
+    #[test]
+    fn no_key_is_none() {
+        assert_eq!(KeyStroke::from_key_scan(-1), None);
+        assert_eq!(KeyStroke::from_key_scan(i16::from_le_bytes([0xFF, 0xFF])), None);
+    }
+
+    #[test]
+    fn shift_bit_is_decoded() {
+        assert_eq!(KeyStroke::from_key_scan(0x0041), stroke(0x41, false));
+        assert_eq!(KeyStroke::from_key_scan(0x0141), stroke(0x41, true));
+        assert_eq!(KeyStroke::from_key_scan(0x0141).unwrap().modifiers(), vec![VK_SHIFT]);
+    }
+
+    #[test]
+    fn altgr_is_ctrl_and_alt() {
+        // '@' on a German layout: AltGr+Q
+        let at = KeyStroke::from_key_scan(0x0651).unwrap();
+        assert_eq!(at.vk, VIRTUAL_KEY(0x51));
+        assert!(at.ctrl && at.alt && !at.shift);
+        assert_eq!(at.modifiers(), vec![VK_CONTROL, VK_MENU]);
+        // '{' with Shift held as well
+        let brace = KeyStroke::from_key_scan(0x0737).unwrap();
+        assert!(brace.ctrl && brace.alt && brace.shift);
+        assert_eq!(brace.modifiers(), vec![VK_CONTROL, VK_MENU, VK_SHIFT]);
+    }
+
+    #[test]
+    fn us_layout_maps_chars_to_keys() {
+        let chars: Vec<char> = "aZ 1!:?_\n".chars().collect();
+        let strokes = key_strokes(&chars, us_layout).unwrap();
+        assert_eq!(
+            strokes,
+            vec![
+                stroke(0x41, false),
+                stroke(0x5A, true),
+                stroke(0x20, false),
+                stroke(0x31, false),
+                stroke(0x31, true),
+                stroke(0xBA, true),
+                stroke(0xBF, true),
+                stroke(0xBD, true),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn unmapped_or_non_ascii_chars_are_refused() {
+        let error = key_strokes(&['o', 'k', '~'], us_layout).unwrap_err();
+        assert_eq!(error.character, '~');
+        let error = key_strokes(&['é'], |_| panic!("non-ASCII must not be looked up")).unwrap_err();
+        assert_eq!(error.character, 'é');
+    }
+}
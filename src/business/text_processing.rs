@@ -0,0 +1,808 @@
+//! Text Processing
+//!
+//! Clean-ups applied to recognized text before it is typed.
+
+use anyhow::{anyhow, Result};
+use regex::{NoExpand, Regex};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::data::ReplaceRule;
+
+/// Filler words to drop from recognized text (`text_insert.fillers`)
+#[derive(Debug, Clone, Default)]
+pub struct Fillers {
+    /// Lowercased, so "Um" matches "um"
+    words: Vec<String>,
+}
+
+impl Fillers {
+    pub fn new(words: &[String]) -> Self {
+        Self {
+            words: words
+                .iter()
+                .map(|word| word.trim().to_lowercase())
+                .filter(|word| !word.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Remove fillers that stand alone
+    ///
+    /// A filler only goes when it is a whole run of text between
+    /// punctuation, spaces or the ends of the text ("嗯，" but not the
+    /// "那个" of "那个人"), repeats like "嗯嗯" included. The punctuation
+    /// after it goes with it, or the one before it when nothing but
+    /// fillers follows. Text made only of fillers becomes empty.
+    pub fn remove(&self, text: &str) -> String {
+        if self.words.is_empty() {
+            return text.to_string();
+        }
+
+        // Alternating runs of word characters and of separators
+        let mut runs: Vec<(bool, String)> = Vec::new();
+        for c in text.chars() {
+            let word = !is_separator(c);
+            match runs.last_mut() {
+                Some((last_word, run)) if *last_word == word => run.push(c),
+                _ => runs.push((word, c.to_string())),
+            }
+        }
+        let filler: Vec<bool> = runs
+            .iter()
+            .map(|(word, run)| *word && self.is_filler(run))
+            .collect();
+        if !filler.contains(&true) {
+            return text.to_string();
+        }
+        if runs.iter().zip(&filler).all(|((word, _), &filler)| !word || filler) {
+            return String::new();
+        }
+
+        let mut dropped = vec![false; runs.len()];
+        for index in (0..runs.len()).filter(|&index| filler[index]) {
+            dropped[index] = true;
+            let words_follow = runs[index + 1..]
+                .iter()
+                .zip(&filler[index + 1..])
+                .any(|((word, _), &filler)| *word && !filler);
+            if words_follow {
+                if index + 1 < runs.len() {
+                    dropped[index + 1] = true;
+                }
+            } else if index > 0 {
+                dropped[index - 1] = true;
+            }
+        }
+        runs.into_iter()
+            .zip(dropped)
+            .filter(|(_, dropped)| !dropped)
+            .map(|((_, run), _)| run)
+            .collect()
+    }
+
+    /// Whether a run is a filler, or one repeated
+    fn is_filler(&self, run: &str) -> bool {
+        let run = run.to_lowercase();
+        self.words.iter().any(|word| {
+            run.len() % word.len() == 0 && run == word.repeat(run.len() / word.len())
+        })
+    }
+}
+
+/// Punctuation or space, which bounds a standalone filler
+fn is_separator(c: char) -> bool {
+    c.is_whitespace()
+        || c.is_ascii_punctuation()
+        || matches!(
+            c,
+            '，' | '。' | '、' | '：' | '；' | '？' | '！' | '…' | '—' | '～' | '“' | '”' | '‘' | '’'
+                | '（' | '）' | '【' | '】' | '《' | '》' | '「' | '」'
+        )
+}
+
+/// The user's find/replace rules (`[[text_insert.replace]]`), compiled
+#[derive(Debug, Clone, Default)]
+pub struct Replacements {
+    rules: Vec<CompiledRule>,
+}
+
+#[derive(Debug, Clone)]
+struct CompiledRule {
+    pattern: Regex,
+    replacement: String,
+    /// `$1` and `${name}` refer to groups (regex rules only)
+    expand: bool,
+}
+
+impl Replacements {
+    /// Compile the rules; fails on the first invalid regex, naming it
+    pub fn compile(rules: &[ReplaceRule]) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .filter(|rule| !rule.pattern.is_empty())
+            .map(|rule| {
+                let source = if rule.regex {
+                    rule.pattern.clone()
+                } else {
+                    regex::escape(&rule.pattern)
+                };
+                let pattern = Regex::new(&source).map_err(|e| {
+                    anyhow!("replace pattern {:?} is not a valid regex: {}", rule.pattern, e)
+                })?;
+                Ok(CompiledRule {
+                    pattern,
+                    replacement: rule.replacement.clone(),
+                    expand: rule.regex,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { rules })
+    }
+
+    /// Apply every rule in order, each to the previous one's output
+    pub fn apply(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for rule in &self.rules {
+            let replaced = if rule.expand {
+                rule.pattern.replace_all(&text, rule.replacement.as_str())
+            } else {
+                rule.pattern.replace_all(&text, NoExpand(&rule.replacement))
+            };
+            text = replaced.into_owned();
+        }
+        text
+    }
+}
+
+/// Punctuation wanted in typed text (`text_insert.punctuation`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PunctuationStyle {
+    /// Keep what the recognizer returned
+    AsIs,
+    /// ASCII punctuation, for code, terminals and English documents
+    Halfwidth,
+    /// Chinese full-width punctuation
+    Fullwidth,
+}
+
+impl PunctuationStyle {
+    /// Parse a config value: "as-is", "halfwidth" or "fullwidth"
+    pub fn parse(style: &str) -> Self {
+        match style.to_ascii_lowercase().as_str() {
+            "as-is" => PunctuationStyle::AsIs,
+            "halfwidth" => PunctuationStyle::Halfwidth,
+            "fullwidth" => PunctuationStyle::Fullwidth,
+            _ => {
+                tracing::warn!("Unknown text_insert.punctuation {:?}, using \"as-is\"", style);
+                PunctuationStyle::AsIs
+            }
+        }
+    }
+}
+
+/// Convert the punctuation in `text` to `style`
+///
+/// Covers commas, periods, colons, semicolons, question and exclamation
+/// marks, parentheses, brackets and quotes. Half-width marks get a space
+/// before a following word; full-width ones absorb the spaces after them.
+/// Quotes are paired: ASCII quotes alternate between opening and closing.
+/// Periods, commas and colons inside numbers, words and URLs are kept.
+pub fn normalize_punctuation(text: &str, style: PunctuationStyle) -> String {
+    match style {
+        PunctuationStyle::AsIs => text.to_string(),
+        PunctuationStyle::Halfwidth => to_halfwidth(text),
+        PunctuationStyle::Fullwidth => to_fullwidth(text),
+    }
+}
+
+/// Put a space between Chinese, Japanese or Korean characters and Latin
+/// letters or digits next to them (`text_insert.cjk_spacing`)
+///
+/// "我在用VSCode写Rust代码" becomes "我在用 VSCode 写 Rust 代码" and "3个"
+/// "3 个"; "5G" stays together. Only directly adjacent characters are
+/// spaced, so existing spaces and punctuation are left as they are.
+/// Full-width letters and digits are meant to sit in CJK text and count
+/// as neither side.
+pub fn space_cjk_latin(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + text.len() / 4);
+    let mut prev: Option<char> = None;
+    for c in text.chars() {
+        if let Some(p) = prev {
+            if (is_cjk(p) && c.is_ascii_alphanumeric()) || (p.is_ascii_alphanumeric() && is_cjk(c)) {
+                out.push(' ');
+            }
+        }
+        out.push(c);
+        prev = Some(c);
+    }
+    out
+}
+
+/// Han characters, kana and Hangul syllables
+fn is_cjk(c: char) -> bool {
+    matches!(
+        c,
+        '\u{3040}'..='\u{30FF}'
+            | '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{AC00}'..='\u{D7AF}'
+            | '\u{F900}'..='\u{FAFF}'
+            | '\u{20000}'..='\u{2FA1F}'
+    )
+}
+
+fn to_halfwidth(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    for (i, &c) in chars.iter().enumerate() {
+        let mapped = match c {
+            '，' | '、' => ',',
+            '。' => '.',
+            '：' => ':',
+            '；' => ';',
+            '？' => '?',
+            '！' => '!',
+            '（' => '(',
+            '）' => ')',
+            '【' => '[',
+            '】' => ']',
+            '“' | '”' | '「' | '」' | '＂' => '"',
+            '‘' | '’' | '『' | '』' | '＇' => '\'',
+            _ => {
+                out.push(c);
+                continue;
+            }
+        };
+        out.push(mapped);
+        // Full-width marks include their spacing, ASCII ones don't
+        let next_is_word = chars.get(i + 1).is_some_and(|next| next.is_alphanumeric());
+        if matches!(mapped, ',' | '.' | ':' | ';' | '?' | '!') && next_is_word {
+            out.push(' ');
+        }
+    }
+    out
+}
+
+fn to_fullwidth(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len() * 2);
+    // Quotes alternate between opening and closing
+    let mut double_open = false;
+    let mut single_open = false;
+    let mut skip_spaces = false;
+    for (i, &c) in chars.iter().enumerate() {
+        if skip_spaces && c == ' ' {
+            continue;
+        }
+        skip_spaces = false;
+
+        let prev = i.checked_sub(1).map(|p| chars[p]);
+        let next = chars.get(i + 1).copied();
+        let between_digits =
+            prev.is_some_and(|p| p.is_ascii_digit()) && next.is_some_and(|n| n.is_ascii_digit());
+        let inside_word =
+            prev.is_some_and(|p| p.is_alphanumeric()) && next.is_some_and(|n| n.is_alphanumeric());
+        let mapped = match c {
+            // 1,000 and 3.14 and example.com and ...
+            ',' if !between_digits => '，',
+            '.' if !inside_word && prev != Some('.') && next != Some('.') => '。',
+            // 10:30 and https://
+            ':' if !between_digits && next != Some('/') => '：',
+            ';' => '；',
+            '?' => '？',
+            '!' => '！',
+            '(' => '（',
+            ')' => '）',
+            '"' => {
+                double_open = !double_open;
+                if double_open {
+                    '“'
+                } else {
+                    '”'
+                }
+            }
+            // Apostrophes (don't, it's) are left alone
+            '\'' if !inside_word => {
+                single_open = !single_open;
+                if single_open {
+                    '‘'
+                } else {
+                    '’'
+                }
+            }
+            _ => {
+                out.push(c);
+                continue;
+            }
+        };
+        out.push(mapped);
+        skip_spaces = matches!(mapped, '，' | '。' | '：' | '；' | '？' | '！');
+    }
+    out
+}
+
+/// Convert Chinese numerals to Arabic digits (`text_insert.normalize_numbers`)
+///
+/// Handles 十/百/千/万/亿 structures (一千二百三十四 -> 1234, 两万 -> 20000,
+/// 一千二 -> 1200, 一百零五 -> 105) and digit-by-digit readings of three
+/// or more digits (幺三五 -> 135, 二零二四 -> 2024). Lone numerals, two-digit
+/// readings like 七八 (seven or eight) and anything that isn't a well-formed
+/// number (万一, 三三两两, 一五一十) are left alone, as is any text inside
+/// an `exclusions` phrase.
+pub fn normalize_numbers(text: &str, exclusions: &[String]) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut protected = vec![false; chars.len()];
+    for phrase in exclusions {
+        let phrase: Vec<char> = phrase.chars().collect();
+        if phrase.is_empty() || phrase.len() > chars.len() {
+            continue;
+        }
+        for start in 0..=chars.len() - phrase.len() {
+            if chars[start..start + phrase.len()] == phrase[..] {
+                protected[start..start + phrase.len()].fill(true);
+            }
+        }
+    }
+    let is_numeral = |i: usize| {
+        !protected[i] && (numeral_digit(chars[i]).is_some() || numeral_unit(chars[i]).is_some())
+    };
+
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        if !is_numeral(i) {
+            out.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < chars.len() && is_numeral(i) {
+            i += 1;
+        }
+        let run = &chars[start..i];
+        match convert_numeral_run(run) {
+            Some(digits) => out.push_str(&digits),
+            None => out.extend(run),
+        }
+    }
+    out
+}
+
+/// Value of a numeral digit; 两 only counts before a unit
+fn numeral_digit(c: char) -> Option<u64> {
+    match c {
+        '零' | '〇' => Some(0),
+        '一' | '幺' => Some(1),
+        '二' | '两' => Some(2),
+        '三' => Some(3),
+        '四' => Some(4),
+        '五' => Some(5),
+        '六' => Some(6),
+        '七' => Some(7),
+        '八' => Some(8),
+        '九' => Some(9),
+        _ => None,
+    }
+}
+
+fn numeral_unit(c: char) -> Option<u64> {
+    match c {
+        '十' => Some(10),
+        '百' => Some(100),
+        '千' => Some(1_000),
+        '万' => Some(10_000),
+        '亿' => Some(100_000_000),
+        _ => None,
+    }
+}
+
+/// Arabic digits for a run of numeral characters, if it is a number
+fn convert_numeral_run(run: &[char]) -> Option<String> {
+    if run.len() < 2 {
+        return None;
+    }
+    if run.iter().all(|&c| numeral_unit(c).is_none()) {
+        // Read digit by digit; 两 isn't used that way
+        if run.len() < 3 || run.contains(&'两') {
+            return None;
+        }
+        return run
+            .iter()
+            .map(|&c| numeral_digit(c).map(|d| char::from(b'0' + d as u8)))
+            .collect();
+    }
+    parse_numeral(run).map(|value| value.to_string())
+}
+
+/// Value of a 十/百/千/万/亿 structured number
+fn parse_numeral(run: &[char]) -> Option<u64> {
+    // Completed 亿 part, 万 part and the part below 万
+    let mut yi = 0u64;
+    let mut wan = 0u64;
+    let mut section = 0u64;
+    let mut digit: Option<u64> = None;
+    // Smallest of 十/百/千 in the current section, which must descend
+    let mut smallest = u64::MAX;
+    // Last unit seen, for shortened forms like 一千二 (1200)
+    let mut last_unit = 1u64;
+    let mut after_zero = false;
+
+    for (index, &c) in run.iter().enumerate() {
+        if let Some(d) = numeral_digit(c) {
+            let next = run.get(index + 1).copied();
+            if c == '两' && !next.is_some_and(|n| matches!(n, '百' | '千' | '万' | '亿')) {
+                return None;
+            }
+            if digit.is_some() {
+                return None;
+            }
+            if d == 0 {
+                after_zero = true;
+            } else {
+                digit = Some(d);
+            }
+            continue;
+        }
+        let unit = numeral_unit(c)?;
+        match unit {
+            10 | 100 | 1_000 => {
+                if unit >= smallest {
+                    return None;
+                }
+                // 十五 and 一千零十, but not 百 or 千 on their own
+                let n = match digit.take() {
+                    Some(d) => d,
+                    None if unit == 10 && (section == 0 || after_zero) => 1,
+                    None => return None,
+                };
+                section += n * unit;
+                smallest = unit;
+            }
+            10_000 => {
+                section += digit.take().unwrap_or(0);
+                if section == 0 || wan != 0 {
+                    return None;
+                }
+                wan = section * unit;
+                section = 0;
+                smallest = u64::MAX;
+            }
+            _ => {
+                let value = wan + section + digit.take().unwrap_or(0);
+                if value == 0 || yi != 0 {
+                    return None;
+                }
+                yi = value * unit;
+                wan = 0;
+                section = 0;
+                smallest = u64::MAX;
+            }
+        }
+        last_unit = unit;
+        after_zero = false;
+    }
+
+    if let Some(d) = digit {
+        // 一千二 is 1200 and 三万五 35000, but 一千零二 is 1002
+        if last_unit >= 100 && !after_zero {
+            section += d * last_unit / 10;
+        } else {
+            section += d;
+        }
+    }
+    Some(yi + wan + section)
+}
+
+/// What a spoken command does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceCommand {
+    /// Press Enter
+    Enter,
+    /// Press Tab
+    Tab,
+    /// Erase the result typed last
+    DeleteLast,
+    /// Stop dictating
+    Stop,
+}
+
+impl VoiceCommand {
+    /// Parse a config action: "enter", "tab", "delete_last" or "stop"
+    pub fn parse(action: &str) -> Option<Self> {
+        match action.to_ascii_lowercase().as_str() {
+            "enter" => Some(VoiceCommand::Enter),
+            "tab" => Some(VoiceCommand::Tab),
+            "delete_last" => Some(VoiceCommand::DeleteLast),
+            "stop" => Some(VoiceCommand::Stop),
+            _ => None,
+        }
+    }
+}
+
+/// Phrases that run a command instead of being typed (`text_insert.commands`)
+#[derive(Debug, Clone, Default)]
+pub struct VoiceCommands {
+    /// Keyed by the phrase as `command_key` normalizes it
+    table: HashMap<String, VoiceCommand>,
+}
+
+impl VoiceCommands {
+    /// Build the table from phrase -> action pairs, skipping unknown actions
+    pub fn from_config(commands: &BTreeMap<String, String>) -> Self {
+        let mut table = HashMap::new();
+        for (phrase, action) in commands {
+            let key = command_key(phrase);
+            match VoiceCommand::parse(action) {
+                Some(command) if !key.is_empty() => {
+                    table.insert(key, command);
+                }
+                _ => tracing::warn!(
+                    "Ignoring text_insert.commands entry {:?} = {:?}",
+                    phrase,
+                    action
+                ),
+            }
+        }
+        Self { table }
+    }
+
+    /// The command a whole utterance names, if it is nothing but a command
+    /// phrase (surrounding punctuation and spaces aside)
+    pub fn lookup(&self, text: &str) -> Option<VoiceCommand> {
+        self.table.get(&command_key(text)).copied()
+    }
+}
+
+/// A phrase without surrounding punctuation and spaces, lowercased
+fn command_key(text: &str) -> String {
+    text.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fillers() -> Fillers {
+        Fillers::new(&["嗯".to_string(), "那个".to_string(), "就是说".to_string(), "Um".to_string()])
+    }
+
+    #[test]
+    fn fillers_standing_alone_are_removed() {
+        let cases = [
+            ("嗯，我们明天开会", "我们明天开会"),
+            ("嗯嗯，好的", "好的"),
+            ("嗯，那个，就是说，明天开会", "明天开会"),
+            // Hesitation before a pause
+            ("那个，我觉得可以", "我觉得可以"),
+            ("我觉得，那个，可以", "我觉得，可以"),
+            // Trailing filler takes the punctuation before it
+            ("好的，嗯。", "好的。"),
+            ("um, let's go", "let's go"),
+            ("嗯", ""),
+            ("嗯。那个。", ""),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(fillers().remove(text), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn demonstrative_that_is_kept() {
+        let cases = [
+            "那个人是谁",
+            "那个人，我见过",
+            "我喜欢那个，不喜欢这个",
+            "就要那个。",
+            "嗯哼",
+            "说就是说",
+        ];
+        for text in cases {
+            assert_eq!(fillers().remove(text), text, "{}", text);
+        }
+    }
+
+    #[test]
+    fn no_fillers_configured() {
+        assert_eq!(Fillers::default().remove("嗯，好的"), "嗯，好的");
+    }
+
+    fn rule(pattern: &str, replacement: &str, regex: bool) -> ReplaceRule {
+        ReplaceRule {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            regex,
+        }
+    }
+
+    #[test]
+    fn replacements_apply_in_order() {
+        // The second rule sees the first one's output
+        let rules = [rule("张三", "张三丰", false), rule("张三丰", "Zhang Sanfeng", false)];
+        let replacements = Replacements::compile(&rules).unwrap();
+        assert_eq!(replacements.apply("张三来了"), "Zhang Sanfeng来了");
+
+        // Matches within one rule don't overlap
+        let replacements = Replacements::compile(&[rule("aa", "b", false)]).unwrap();
+        assert_eq!(replacements.apply("aaa"), "ba");
+    }
+
+    #[test]
+    fn replacements_with_cjk_patterns() {
+        let rules = [rule("艾特", "@", false), rule("点com", ".com", false)];
+        let replacements = Replacements::compile(&rules).unwrap();
+        assert_eq!(replacements.apply("me艾特example点com"), "me@example.com");
+
+        let replacements = Replacements::compile(&[rule("豆包|抖包", "Doubao", true)]).unwrap();
+        assert_eq!(replacements.apply("打开抖包和豆包"), "打开Doubao和Doubao");
+    }
+
+    #[test]
+    fn replacements_with_capture_groups() {
+        let replacements = Replacements::compile(&[rule(r"(\d+)点(\d+)", "$1.$2", true)]).unwrap();
+        assert_eq!(replacements.apply("版本3点14"), "版本3.14");
+
+        let rules = [rule(r"(?P<user>[a-z]+) at (?P<host>[a-z]+)", "${user}@${host}", true)];
+        let replacements = Replacements::compile(&rules).unwrap();
+        assert_eq!(replacements.apply("mail bob at example"), "mail bob@example");
+    }
+
+    #[test]
+    fn literal_replacements_are_not_expanded() {
+        let rules = [rule("a.b", "$1", false)];
+        let replacements = Replacements::compile(&rules).unwrap();
+        assert_eq!(replacements.apply("axb a.b"), "axb $1");
+    }
+
+    #[test]
+    fn invalid_regex_names_the_pattern() {
+        let error = Replacements::compile(&[rule("(unclosed", "", true)]).unwrap_err();
+        assert!(error.to_string().contains("(unclosed"), "{}", error);
+        // Empty patterns are skipped rather than matching everywhere
+        let replacements = Replacements::compile(&[rule("", "x", false)]).unwrap();
+        assert_eq!(replacements.apply("abc"), "abc");
+    }
+
+    #[test]
+    fn punctuation_to_halfwidth() {
+        let cases = [
+            ("你好，world。", "你好, world."),
+            ("打开config.toml：设置port为8080；然后重启！", "打开config.toml: 设置port为8080; 然后重启!"),
+            ("真的吗？是的！", "真的吗? 是的!"),
+            ("他说“hello”，我说「好」", "他说\"hello\", 我说\"好\""),
+            ("（注意）【重要】", "(注意)[重要]"),
+            ("苹果、香蕉", "苹果, 香蕉"),
+            ("plain ASCII, untouched.", "plain ASCII, untouched."),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(normalize_punctuation(text, PunctuationStyle::Halfwidth), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn punctuation_to_fullwidth() {
+        let cases = [
+            ("Hello, 世界.", "Hello，世界。"),
+            ("真的吗? 是的!", "真的吗？是的！"),
+            // Numbers, versions, times and URLs keep their marks
+            ("版本1.0, 价格1,000元", "版本1.0，价格1,000元"),
+            ("时间10:30, 网址https://example.com.", "时间10:30，网址https://example.com。"),
+            ("等等...", "等等..."),
+            // Quotes are paired, apostrophes left alone
+            ("他说\"你好\"然后说\"再见\"", "他说“你好”然后说“再见”"),
+            ("don't 'quote'", "don't ‘quote’"),
+            ("(注意): 小心", "（注意）：小心"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(normalize_punctuation(text, PunctuationStyle::Fullwidth), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn punctuation_as_is() {
+        let text = "你好, world。";
+        assert_eq!(normalize_punctuation(text, PunctuationStyle::AsIs), text);
+        assert_eq!(PunctuationStyle::parse("HalfWidth"), PunctuationStyle::Halfwidth);
+        assert_eq!(PunctuationStyle::parse("unknown"), PunctuationStyle::AsIs);
+    }
+
+    #[test]
+    fn cjk_latin_spacing() {
+        let cases = [
+            ("我在用VSCode写Rust代码", "我在用 VSCode 写 Rust 代码"),
+            ("买了3个苹果", "买了 3 个苹果"),
+            ("5G网络", "5G 网络"),
+            ("支持5G", "支持 5G"),
+            ("これはRustです", "これは Rust です"),
+            ("한국어Korean", "한국어 Korean"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(space_cjk_latin(text), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn cjk_latin_spacing_leaves_existing_boundaries() {
+        let cases = [
+            "我在用 VSCode 写代码",
+            "你好，World",
+            "（Rust）语言",
+            "版本 v1.2 已发布",
+            // Full-width letters and digits belong to the CJK text
+            "用ＡＢＣ写",
+            "第１名",
+            "5G",
+            "",
+        ];
+        for text in cases {
+            assert_eq!(space_cjk_latin(text), text, "{}", text);
+        }
+    }
+
+    #[test]
+    fn numbers_with_units() {
+        let cases = [
+            ("一千二百三十四", "1234"),
+            ("一千零五", "1005"),
+            ("一千零一十", "1010"),
+            ("一百零五", "105"),
+            ("两千", "2000"),
+            ("两万", "20000"),
+            ("二百", "200"),
+            ("十五", "15"),
+            ("二十", "20"),
+            ("一千二", "1200"),
+            ("三万五", "35000"),
+            ("一亿二", "120000000"),
+            ("十万零三", "100003"),
+            ("我要一千零五个", "我要1005个"),
+            ("他二十四岁", "他24岁"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(normalize_numbers(text, &[]), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn numbers_read_digit_by_digit() {
+        let cases = [
+            ("幺三五", "135"),
+            ("二零二四", "2024"),
+            ("零零七", "007"),
+            ("电话幺三八零零", "电话13800"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(normalize_numbers(text, &[]), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn numbers_left_alone() {
+        let cases = [
+            // Idioms and words that aren't quantities
+            "千万",
+            "万一",
+            "三三两两",
+            "一五一十",
+            // Lone numerals and two-digit readings (seven or eight)
+            "一",
+            "两个",
+            "七八个",
+            // 两 only counts before 百, 千, 万 or 亿
+            "两十",
+            "两三",
+        ];
+        for text in cases {
+            assert_eq!(normalize_numbers(text, &[]), text, "{}", text);
+        }
+    }
+
+    #[test]
+    fn numbers_in_exclusions_are_kept() {
+        let exclusions = ["二十四节气".to_string()];
+        assert_eq!(normalize_numbers("二十四节气", &exclusions), "二十四节气");
+        assert_eq!(
+            normalize_numbers("二十四节气有二十四个", &exclusions),
+            "二十四节气有24个"
+        );
+    }
+}
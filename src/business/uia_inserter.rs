@@ -0,0 +1,141 @@
+//! UI Automation text insertion
+//!
+//! Writes text into the focused element through UI Automation instead of
+//! simulated key presses, for apps that swallow KEYEVENTF_UNICODE input
+//! (`text_insert.mode = "uia"`).
+
+use anyhow::Result;
+use windows::core::BSTR;
+use windows::Win32::System::Com::{
+    CoCreateInstance, CoInitializeEx, CoUninitialize, CLSCTX_INPROC_SERVER, COINIT_MULTITHREADED,
+};
+use windows::Win32::UI::Accessibility::{
+    CUIAutomation, IUIAutomation, IUIAutomationTextPattern, IUIAutomationValuePattern,
+    TextPatternRangeEndpoint_End, TextPatternRangeEndpoint_Start, TextUnit_Character,
+    UIA_DocumentControlTypeId, UIA_EditControlTypeId, UIA_TextPatternId, UIA_ValuePatternId,
+};
+
+/// COM initialized on the current thread while this lives
+struct ComGuard {
+    initialized: bool,
+}
+
+impl ComGuard {
+    fn new() -> Self {
+        // Succeeds (S_FALSE) on threads that already joined the MTA, which
+        // still needs balancing; fails on STA threads, where UI Automation
+        // works as well and nothing is to be undone
+        let initialized = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) }.is_ok();
+        Self { initialized }
+    }
+}
+
+impl Drop for ComGuard {
+    fn drop(&mut self) {
+        if self.initialized {
+            unsafe { CoUninitialize() };
+        }
+    }
+}
+
+/// Insert `text` at the caret of the focused element
+///
+/// Needs an editable value pattern; the text pattern, when there is one,
+/// tells where the caret or selection is, otherwise the text is appended.
+/// Returns `Ok(false)` when the element can't take text this way, so the
+/// caller can type it instead.
+pub(crate) fn insert(text: &str) -> Result<bool> {
+    let _com = ComGuard::new();
+    unsafe {
+        let automation: IUIAutomation =
+            CoCreateInstance(&CUIAutomation, None, CLSCTX_INPROC_SERVER)?;
+        let element = automation.GetFocusedElement()?;
+        let Ok(value) = element.GetCurrentPatternAs::<IUIAutomationValuePattern>(UIA_ValuePatternId)
+        else {
+            tracing::debug!("Focused element has no value pattern");
+            return Ok(false);
+        };
+        if value.CurrentIsReadOnly()?.as_bool() {
+            tracing::debug!("Focused element is read-only");
+            return Ok(false);
+        }
+
+        let current: Vec<char> = value.CurrentValue()?.to_string().chars().collect();
+        let text_pattern = element
+            .GetCurrentPatternAs::<IUIAutomationTextPattern>(UIA_TextPatternId)
+            .ok();
+        let (start, end) = text_pattern
+            .as_ref()
+            .and_then(|pattern| selection_offsets(pattern).ok())
+            .filter(|&(start, end)| start <= end && end <= current.len())
+            .unwrap_or((current.len(), current.len()));
+
+        let mut updated: String = current[..start].iter().collect();
+        updated.push_str(text);
+        updated.extend(&current[end..]);
+        value.SetValue(&BSTR::from(updated))?;
+
+        // Setting the value moves the caret to either end; put it after
+        // the inserted text
+        if let Some(pattern) = &text_pattern {
+            let caret = start + text.chars().count();
+            if let Err(e) = place_caret(pattern, caret) {
+                tracing::debug!("Cannot move the caret after UIA insertion: {}", e);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Whether the focused element takes text: an edit or document control,
+/// or one with a writable value
+pub(crate) fn focused_editable() -> Result<bool> {
+    let _com = ComGuard::new();
+    unsafe {
+        let automation: IUIAutomation =
+            CoCreateInstance(&CUIAutomation, None, CLSCTX_INPROC_SERVER)?;
+        let element = automation.GetFocusedElement()?;
+        let control_type = element.CurrentControlType()?;
+        if control_type == UIA_EditControlTypeId || control_type == UIA_DocumentControlTypeId {
+            return Ok(true);
+        }
+        match element.GetCurrentPatternAs::<IUIAutomationValuePattern>(UIA_ValuePatternId) {
+            Ok(value) => Ok(!value.CurrentIsReadOnly()?.as_bool()),
+            Err(_) => Ok(false),
+        }
+    }
+}
+
+/// Character offsets of the first selection (the caret when empty)
+unsafe fn selection_offsets(pattern: &IUIAutomationTextPattern) -> windows::core::Result<(usize, usize)> {
+    let selection = pattern.GetSelection()?;
+    if selection.Length()? < 1 {
+        return Err(windows::core::Error::OK);
+    }
+    let selected = selection.GetElement(0)?;
+    let document = pattern.DocumentRange()?;
+
+    // From the start of the document to each end of the selection
+    let before = document.Clone()?;
+    before.MoveEndpointByRange(
+        TextPatternRangeEndpoint_End,
+        &selected,
+        TextPatternRangeEndpoint_Start,
+    )?;
+    let start = before.GetText(-1)?.to_string().chars().count();
+    let length = selected.GetText(-1)?.to_string().chars().count();
+    Ok((start, start + length))
+}
+
+/// Put the caret `offset` characters into the document
+unsafe fn place_caret(pattern: &IUIAutomationTextPattern, offset: usize) -> windows::core::Result<()> {
+    let range = pattern.DocumentRange()?;
+    // Collapse to the start, then walk forward
+    range.MoveEndpointByRange(
+        TextPatternRangeEndpoint_End,
+        &range,
+        TextPatternRangeEndpoint_Start,
+    )?;
+    range.Move(TextUnit_Character, offset as i32)?;
+    range.Select()
+}
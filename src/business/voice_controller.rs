@@ -2,44 +2,294 @@
 //!
 //! Coordinates voice input between audio capture, ASR, and text insertion.
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use crate::asr::{AsrClient, ResponseType};
-use crate::audio::AudioCapture;
-use crate::business::TextInserter;
+use crate::asr::{
+    describe_error_code, AsrBackend, AsrError, ErrorCategory, ResponseType, SessionHandle,
+};
+use crate::audio::{AudioCapture, AudioCaptureEvent, Cue, InputLevel, NoiseProfile};
+use crate::business::{
+    normalize_numbers, normalize_punctuation, space_cjk_latin, ElevatedTarget, Fillers,
+    InsertAborted, InsertTarget, NoEditableFocus, NoKeyForChar, PunctuationStyle, Replacements,
+    TextInserter, TooLongToType, VoiceCommand, VoiceCommands,
+};
+use crate::data::{AppConfig, AudioConfig, CredentialStore, History, HistoryEntry};
+use crate::ui::notify;
+use windows::Win32::UI::Input::KeyboardAndMouse::{VK_RETURN, VK_TAB};
+
+/// How long to wait for SessionFinished after the user stops
+const FINISH_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Give up on a lost microphone after this long and finish the session
+const AUDIO_RECOVERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Delay between attempts to reopen a lost microphone
+const AUDIO_REOPEN_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Events from the voice controller
+#[derive(Debug, Clone)]
+pub enum VoiceControllerEvent {
+    /// A session ended on its own; `truncated` is set when it hit the length cap
+    SessionEnded { truncated: bool },
+    /// The startup credential check finished
+    CredentialsChecked { valid: bool },
+    /// The microphone stopped delivering audio; recovery is under way
+    AudioInterrupted,
+    /// The microphone is back and recording continues
+    AudioRecovered,
+    /// The user went quiet after speaking; recording should stop as if the
+    /// hotkey had been pressed
+    SilenceDetected,
+    /// The microphone delivers only silence, probably muted; recording continues
+    NoInput,
+    /// The user said a stop command; recording should stop as if the
+    /// hotkey had been pressed
+    StopRequested,
+}
 
 /// Voice input controller
 pub struct VoiceController {
-    asr_client: Arc<AsrClient>,
+    asr_backend: Arc<dyn AsrBackend>,
     audio_capture: Arc<AudioCapture>,
     text_inserter: Arc<TextInserter>,
+    config: AppConfig,
     is_recording: Arc<AtomicBool>,
-    stop_signal: Arc<AtomicBool>,
+    session: Option<SessionHandle>,
+    credential_store: Option<Arc<CredentialStore>>,
+    event_tx: Sender<VoiceControllerEvent>,
+    event_rx: Option<Receiver<VoiceControllerEvent>>,
+    /// The text typed last ended with whitespace, so the next result
+    /// shouldn't start with more
+    after_separator: Arc<AtomicBool>,
+    /// Results typed last, newest at the back, for undoing them
+    undo_stack: Arc<Mutex<VecDeque<Inserted>>>,
+    /// Recognized results, unless `general.history_enabled` is off
+    history: Option<Arc<History>>,
+    /// Set by `cancel` so the current session types nothing more
+    cancelled: Arc<AtomicBool>,
+    /// Reads the settings again at the start of each session
+    load_config: fn() -> Result<AppConfig>,
+}
+
+/// Results kept for undoing
+const UNDO_DEPTH: usize = 10;
+
+/// Text typed for one result, separator included, and where it went
+struct Inserted {
+    text: String,
+    target: Option<InsertTarget>,
 }
 
 impl VoiceController {
     /// Create a new voice controller
     pub fn new(
-        asr_client: Arc<AsrClient>,
+        asr_backend: Arc<dyn AsrBackend>,
         audio_capture: Arc<AudioCapture>,
         text_inserter: Arc<TextInserter>,
+        config: &AppConfig,
     ) -> Self {
+        let (event_tx, event_rx) = channel();
+        audio_capture.set_preroll(&config.audio);
         Self {
-            asr_client,
+            asr_backend,
             audio_capture,
             text_inserter,
+            config: config.clone(),
             is_recording: Arc::new(AtomicBool::new(false)),
-            stop_signal: Arc::new(AtomicBool::new(false)),
+            session: None,
+            credential_store: None,
+            event_tx,
+            event_rx: Some(event_rx),
+            after_separator: Arc::new(AtomicBool::new(false)),
+            undo_stack: Arc::new(Mutex::new(VecDeque::new())),
+            history: open_history(config),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            load_config: AppConfig::load_or_default,
         }
     }
 
+    /// Read settings from `load_config` instead of config.toml
+    #[cfg(test)]
+    pub(crate) fn with_config_loader(mut self, load_config: fn() -> Result<AppConfig>) -> Self {
+        self.load_config = load_config;
+        self
+    }
+
+    /// Invalidate the cached token in `store` when the server rejects it
+    pub fn with_credential_store(mut self, store: Arc<CredentialStore>) -> Self {
+        self.credential_store = Some(store);
+        self
+    }
+
+    /// Live microphone level and AGC gain, zero while not recording
+    pub fn input_level(&self) -> tokio::sync::watch::Receiver<InputLevel> {
+        self.audio_capture.level()
+    }
+
+    /// Check the credentials in the background, reporting `CredentialsChecked`
+    pub fn validate_credentials(&self) {
+        let Some(store) = self.credential_store.clone() else {
+            return;
+        };
+        let config = self.config.clone();
+        let event_tx = self.event_tx.clone();
+        tokio::spawn(async move {
+            let valid = match store.validate(&config).await {
+                Ok(()) => true,
+                Err(e) => {
+                    tracing::warn!("Credential check failed: {:#}", e);
+                    false
+                }
+            };
+            let _ = event_tx.send(VoiceControllerEvent::CredentialsChecked { valid });
+        });
+    }
+
+    /// Take the event receiver (can only be called once)
+    pub fn take_event_receiver(&mut self) -> Option<Receiver<VoiceControllerEvent>> {
+        self.event_rx.take()
+    }
+
     /// Check if currently recording
     pub fn is_recording(&self) -> bool {
         self.is_recording.load(Ordering::SeqCst)
     }
 
+    /// Pause or resume a recording, returning whether it is now paused
+    ///
+    /// While paused the session is kept open with silence, so nothing is
+    /// finalized.
+    pub fn toggle_pause(&self) -> bool {
+        if !self.is_recording() {
+            return false;
+        }
+        if self.audio_capture.is_paused() {
+            self.audio_capture.resume();
+            false
+        } else {
+            self.audio_capture.pause();
+            true
+        }
+    }
+
+    /// Record from the named input device (`None` for the default) and save
+    /// the choice
+    ///
+    /// Takes effect with the next recording; a pre-roll stream switches
+    /// right away.
+    pub fn set_microphone(&mut self, device_name: Option<String>) -> Result<()> {
+        let mut config = AppConfig::load_or_default()?;
+        config.audio.device_name = device_name.clone();
+        config.save()?;
+        self.config.audio.device_name = device_name;
+        if !self.is_recording() {
+            self.audio_capture.set_preroll(&self.config.audio);
+        }
+        Ok(())
+    }
+
+    /// Record `duration` of room tone from the configured microphone and
+    /// measure its noise floor
+    ///
+    /// Refused while recording, which would hear the user speaking.
+    pub async fn calibrate_microphone(&mut self, duration: Duration) -> Result<NoiseProfile> {
+        if self.is_recording() {
+            return Err(anyhow!("cannot calibrate while recording"));
+        }
+        let config = self.config.audio.clone();
+        tokio::task::spawn_blocking(move || AudioCapture::calibrate(&config, duration)).await?
+    }
+
+    /// Turn dry-run mode on or off and save the choice
+    ///
+    /// Takes effect with the next recording.
+    pub fn set_dry_run(&mut self, dry_run: bool) -> Result<()> {
+        let mut config = AppConfig::load_or_default()?;
+        config.text_insert.dry_run = dry_run;
+        config.save()?;
+        self.config.text_insert.dry_run = dry_run;
+        if !self.is_recording() {
+            self.text_inserter.update_config(&self.config.text_insert);
+        }
+        Ok(())
+    }
+
+    /// Save a new `audio.silence_threshold_db`, e.g. from calibration
+    pub fn set_silence_threshold(&mut self, threshold_db: f32) -> Result<()> {
+        let mut config = AppConfig::load_or_default()?;
+        config.audio.silence_threshold_db = threshold_db;
+        config.save()?;
+        self.config.audio.silence_threshold_db = threshold_db;
+        Ok(())
+    }
+
+    /// Erase the result typed last, walking further back on each call
+    ///
+    /// Only erases while the focus is still where the result was typed;
+    /// `bring_back` first returns there, for callers such as the tray menu
+    /// that take the focus themselves. Returns whether anything was erased.
+    pub fn undo_last(&self, bring_back: bool) -> Result<bool> {
+        undo_inserted(
+            &self.text_inserter,
+            &self.undo_stack,
+            &self.after_separator,
+            bring_back,
+        )
+    }
+
+    /// Up to `count` recognized results, newest first
+    pub fn last_results(&self, count: usize) -> Vec<HistoryEntry> {
+        match &self.history {
+            Some(history) => history.recent(count),
+            None => Vec::new(),
+        }
+    }
+
+    /// Type the history entry `index` places back from the newest again,
+    /// where the focus is now
+    ///
+    /// Refused while recording, whose results would get mixed in.
+    pub fn reinsert(&self, index: usize) -> Result<()> {
+        if self.is_recording() {
+            return Err(anyhow!("cannot re-insert while recording"));
+        }
+        let entry = self
+            .history
+            .as_ref()
+            .and_then(|history| history.get(index))
+            .ok_or_else(|| anyhow!("no history entry {}", index))?;
+        let lead = self.after_separator.load(Ordering::SeqCst);
+        let typed = Trailing::parse(&self.config.text_insert.trailing).apply(&entry.text, lead);
+        tracing::info!("Re-inserting history entry {}", index);
+        match self.text_inserter.insert(&typed) {
+            Ok(()) => {
+                remember_inserted(&self.undo_stack, &typed, false);
+                self.after_separator
+                    .store(ends_with_separator(&typed), Ordering::SeqCst);
+            }
+            Err(e) => paste_manually(&self.text_inserter, &entry.text, &e),
+        }
+        Ok(())
+    }
+
+    /// Put `text`, e.g. from the history, on the clipboard
+    pub fn copy_to_clipboard(&self, text: &str) -> Result<()> {
+        self.text_inserter.copy_to_clipboard(text)
+    }
+
+    /// Forget all recognized results, on disk too
+    pub fn clear_history(&self) -> Result<()> {
+        match &self.history {
+            Some(history) => history.clear(),
+            None => Ok(()),
+        }
+    }
+
     /// Toggle voice input on/off
     pub async fn toggle(&mut self) -> Result<()> {
         if self.is_recording() {
@@ -57,102 +307,440 @@ impl VoiceController {
 
         tracing::info!("Starting voice input...");
         self.is_recording.store(true, Ordering::SeqCst);
-        self.stop_signal.store(false, Ordering::SeqCst);
+        // Before anything here could take the focus
+        let start_target = InsertTarget::current();
+
+        // Pick up config.toml edits made since the last session
+        match (self.load_config)() {
+            Ok(config) => self.config = config,
+            Err(e) => tracing::warn!("Failed to reload config, keeping previous settings: {}", e),
+        }
+        self.asr_backend.update_config(&self.config);
+        self.text_inserter.update_config(&self.config.text_insert);
+        self.audio_capture.set_preroll(&self.config.audio);
+        if self.config.general.history_enabled != self.history.is_some() {
+            self.history = open_history(&self.config);
+        }
+
+        let cues = self.config.ui.sound_feedback;
+        if cues {
+            if self.config.audio.is_loopback() {
+                // System audio would record the cue; let it finish first
+                let _ = tokio::task::spawn_blocking(|| Cue::Start.play_and_wait()).await;
+            } else {
+                Cue::Start.play();
+            }
+        }
 
         // Start audio capture
         tracing::debug!("Starting audio capture...");
-        let audio_rx = self.audio_capture.start()?;
+        let audio_rx = self
+            .audio_capture
+            .start(&self.config.audio, &self.config.encoder, self.config.asr.vad_enabled)?;
         tracing::info!("Audio capture started, frames will be sent to ASR");
+        if let Some(audio_events) = self.audio_capture.take_event_receiver() {
+            tokio::spawn(watch_audio(
+                audio_events,
+                self.audio_capture.clone(),
+                self.config.audio.clone(),
+                self.event_tx.clone(),
+                cues,
+            ));
+        }
 
         // Start ASR
         tracing::debug!("Connecting to ASR server...");
-        let mut result_rx = self.asr_client.start_realtime(audio_rx).await?;
+        let mut session = match self.asr_backend.start_session(audio_rx).await {
+            Ok(session) => session,
+            Err(e) => {
+                tracing::error!("Failed to start ASR session: {} ({})", e, e.user_hint());
+                if e.is_auth() {
+                    if let Some(store) = &self.credential_store {
+                        store.invalidate_token();
+                    }
+                }
+                self.audio_capture.stop();
+                self.is_recording.store(false, Ordering::SeqCst);
+                if cues {
+                    Cue::Error.play();
+                }
+                return Err(e.into());
+            }
+        };
         tracing::info!("ASR connection established");
+        if let Some(store) = &self.credential_store {
+            store.confirm_token();
+        }
+        let mut result_rx = session
+            .take_results()
+            .expect("results are taken once per session");
+        let metrics = session.metrics();
+        self.session = Some(session);
 
         // Clone for the task
         let text_inserter = self.text_inserter.clone();
         let is_recording = self.is_recording.clone();
-        let stop_signal = self.stop_signal.clone();
+        self.cancelled = Arc::new(AtomicBool::new(false));
+        let cancelled = self.cancelled.clone();
         let audio_capture = self.audio_capture.clone();
+        let min_confidence = self.config.asr.min_confidence;
+        let to_clipboard = self.config.audio.is_loopback();
+        let clipboard_only = self.config.text_insert.is_clipboard_only();
+        let dry_run = self.config.text_insert.dry_run;
+        let clipboard_append = self.config.text_insert.clipboard_append;
+        let live_typing = self.config.text_insert.live_typing;
+        let trailing = Trailing::parse(&self.config.text_insert.trailing);
+        let punctuation = PunctuationStyle::parse(&self.config.text_insert.punctuation);
+        // Checked when the config was loaded
+        let replacements = Replacements::compile(&self.config.text_insert.replace)
+            .unwrap_or_else(|e| {
+                tracing::warn!("Ignoring text_insert.replace: {:#}", e);
+                Replacements::default()
+            });
+        let fillers = if self.config.text_insert.remove_fillers {
+            Fillers::new(&self.config.text_insert.fillers)
+        } else {
+            Fillers::default()
+        };
+        let cjk_spacing = self.config.text_insert.cjk_spacing;
+        let number_exclusions = self
+            .config
+            .text_insert
+            .normalize_numbers
+            .then(|| self.config.text_insert.number_exclusions.clone());
+        // Recognized text without fillers, with digits, the user's
+        // replacements, punctuation and spacing
+        let processed = move |text: &str| {
+            let mut text = fillers.remove(text);
+            if let Some(exclusions) = &number_exclusions {
+                text = normalize_numbers(&text, exclusions);
+            }
+            let text = normalize_punctuation(&replacements.apply(&text), punctuation);
+            if cjk_spacing {
+                space_cjk_latin(&text)
+            } else {
+                text
+            }
+        };
+        // ... as typed, after or not after a separator
+        let as_typed = {
+            let processed = processed.clone();
+            move |text: &str, lead: bool| trailing.apply(&processed(text), lead)
+        };
+        let pinned_target = match TargetMode::parse(&self.config.text_insert.target) {
+            TargetMode::FocusAtStart => start_target,
+            TargetMode::FocusAtInsert => None,
+        };
+        let after_separator = self.after_separator.clone();
+        let undo_stack = self.undo_stack.clone();
+        let history = self.history.clone();
+        let commands = VoiceCommands::from_config(&self.config.text_insert.commands);
+        let event_tx = self.event_tx.clone();
+        let credential_store = self.credential_store.clone();
 
         // Spawn result processing task
         tokio::spawn(async move {
             let mut last_text = String::new();
+            // Streaming final a refinement may still replace: Some(typed
+            // text), or Some("") when it went to the clipboard instead
+            let mut refinable: Option<String> = None;
+            // Whether the current utterance follows a separator, and whether
+            // the refinable final did
+            let mut lead = after_separator.load(Ordering::SeqCst);
+            let mut refinable_lead = lead;
             let mut response_count = 0u32;
+            let mut truncated = false;
+            let mut failed = false;
+            // Loopback: the whole transcript, kept on the clipboard
+            let mut transcript = String::new();
+            let mut refinable_start: Option<usize> = None;
+            let mut guard = TypingGuard::pinned(pinned_target);
+            // Results not typed are announced once per session, not per result
+            let mut notified = false;
 
             tracing::info!("ASR result processing task started");
 
-            loop {
-                // Check stop signal
-                if stop_signal.load(Ordering::SeqCst) {
-                    tracing::info!("Voice input stopped by user (processed {} responses)", response_count);
+            // Runs until the session ends; finals that arrive after the
+            // user stops are still typed
+            while let Some(response) = result_rx.recv().await {
+                if cancelled.load(Ordering::SeqCst) {
                     break;
                 }
-
-                // Use timeout to periodically check stop signal
-                match tokio::time::timeout(
-                    std::time::Duration::from_millis(100),
-                    result_rx.recv()
-                ).await {
-                    Ok(Some(response)) => {
-                        response_count += 1;
-                        match response.response_type {
-                            ResponseType::InterimResult => {
-                                tracing::debug!("[INTERIM #{}] {}", response_count, response.text);
-                                println!("📝 [识别中] {}", response.text);
-                                if !response.text.is_empty() {
-                                    if let Err(e) = update_text(&text_inserter, &last_text, &response.text) {
-                                        tracing::error!("Failed to update text: {}", e);
+                response_count += 1;
+                match response.response_type {
+                    // Meeting text is too long to type; collect it instead
+                    ResponseType::InterimResult | ResponseType::VadEnd if to_clipboard => {
+                        println!("📝 [识别中] {}", response.text);
+                    }
+                    ResponseType::FinalResult | ResponseType::RefinedResult if to_clipboard => {
+                        let amends = response.response_type == ResponseType::RefinedResult
+                            && refinable_start.is_some();
+                        remember_result(history.as_deref(), &response.text, amends);
+                        if response.response_type == ResponseType::FinalResult {
+                            refinable_start = Some(transcript.len());
+                        } else if let Some(start) = refinable_start.take() {
+                            transcript.truncate(start);
+                        }
+                        println!("✅ [确认] {}", response.text);
+                        transcript.push_str(&response.text);
+                        if let Err(e) = text_inserter.copy_to_clipboard(&transcript) {
+                            tracing::error!("Failed to copy transcript to clipboard: {}", e);
+                        }
+                    }
+                    // Results are only shown; the inserter logs instead of typing
+                    ResponseType::InterimResult | ResponseType::VadEnd if dry_run => {
+                        println!("📝 [识别中] {}", response.text);
+                    }
+                    ResponseType::FinalResult | ResponseType::RefinedResult if dry_run => {
+                        println!("✅ [确认] {}", response.text);
+                        let text = processed(&response.text);
+                        let is_final = response.response_type == ResponseType::FinalResult;
+                        let previous = if is_final { None } else { refinable.take() };
+                        remember_result(history.as_deref(), &text, previous.is_some());
+                        if is_final {
+                            refinable = Some(text.clone());
+                        }
+                        if text.is_empty() || previous.as_deref() == Some(text.as_str()) {
+                            continue;
+                        }
+                        if let Err(e) = text_inserter.insert(&as_typed(&response.text, lead)) {
+                            tracing::error!("Dry run failed: {:#}", e);
+                        }
+                        if !notified {
+                            notify("预览模式, 未输入", &preview(&text));
+                            notified = true;
+                        }
+                    }
+                    // Nothing is typed; results only go to the clipboard
+                    ResponseType::InterimResult | ResponseType::VadEnd if clipboard_only => {
+                        println!("📝 [识别中] {}", response.text);
+                    }
+                    ResponseType::FinalResult | ResponseType::RefinedResult if clipboard_only => {
+                        println!("✅ [确认] {}", response.text);
+                        let text = processed(&response.text);
+                        let is_final = response.response_type == ResponseType::FinalResult;
+                        remember_result(history.as_deref(), &text, !is_final && refinable_start.is_some());
+                        if is_final {
+                            refinable_start = Some(transcript.len());
+                        } else if let Some(start) = refinable_start.take() {
+                            transcript.truncate(start);
+                        }
+                        if !clipboard_append {
+                            transcript.clear();
+                        } else if !transcript.is_empty() && !text.is_empty() {
+                            transcript.push('\n');
+                        }
+                        transcript.push_str(&text);
+                        if text.is_empty() {
+                            continue;
+                        }
+                        match text_inserter.copy_to_clipboard(&transcript) {
+                            Ok(()) if !notified => {
+                                notify("已复制到剪贴板", &preview(&text));
+                                notified = true;
+                            }
+                            Ok(()) => {}
+                            Err(e) => tracing::error!("Failed to copy to clipboard: {}", e),
+                        }
+                    }
+                    ResponseType::InterimResult | ResponseType::VadEnd => {
+                        if response.response_type == ResponseType::VadEnd {
+                            tracing::info!("[VAD END #{}] speech ended", response_count);
+                        }
+                        tracing::debug!("[INTERIM #{}] {}", response_count, response.text);
+                        println!("📝 [识别中] {}", response.text);
+                        // Interims are only typed while they can be corrected in place
+                        if live_typing && !response.text.is_empty() && guard.can_correct() {
+                            let text = processed(&response.text);
+                            let shown = strip_lead(&text, lead);
+                            if let Err(e) = update_text(&text_inserter, &mut guard, &last_text, shown) {
+                                tracing::error!("Failed to update text: {}", e);
+                            }
+                            last_text = shown.to_string();
+                        }
+                    }
+                    ResponseType::FinalResult => {
+                        tracing::info!("[FINAL #{}] {}", response_count, response.text);
+                        println!("✅ [确认] {}", response.text);
+                        let uncertain = response
+                            .confidence
+                            .is_some_and(|c| c < min_confidence);
+                        if uncertain && !response.text.is_empty() {
+                            tracing::warn!(
+                                "Low confidence {:.2} < {:.2}, copying to clipboard instead",
+                                response.confidence.unwrap_or_default(),
+                                min_confidence
+                            );
+                            // Remove the interim text we already typed
+                            if let Err(e) = update_text(&text_inserter, &mut guard, &last_text, "") {
+                                tracing::error!("Failed to remove interim text: {}", e);
+                            }
+                            remember_result(history.as_deref(), &processed(&response.text), false);
+                            match text_inserter.copy_to_clipboard(&response.text) {
+                                Ok(()) => notify(
+                                    "识别结果不确定",
+                                    &format!("已复制到剪贴板, 请确认后粘贴:\n{}", response.text),
+                                ),
+                                Err(e) => tracing::error!("Failed to copy to clipboard: {}", e),
+                            }
+                            last_text = String::new();
+                            refinable = Some(String::new());
+                        } else if let Some(command) = commands.lookup(&response.text) {
+                            // The command phrase's interim text goes away
+                            if let Err(e) = update_text(&text_inserter, &mut guard, &last_text, "") {
+                                tracing::error!("Failed to remove interim text: {}", e);
+                            }
+                            last_text = String::new();
+                            run_command(command, &text_inserter, &undo_stack, &after_separator, &event_tx);
+                            lead = after_separator.load(Ordering::SeqCst);
+                            // A refinement of the command phrase isn't typed either
+                            refinable = Some(String::new());
+                        } else if !response.text.is_empty() {
+                            // Only what is typed gets replacements, separators
+                            // and converted punctuation; logs and the clipboard
+                            // fallback keep the recognized text
+                            let typed = as_typed(&response.text, lead);
+                            remember_result(history.as_deref(), &processed(&response.text), false);
+                            match update_text(&text_inserter, &mut guard, &last_text, &typed) {
+                                Ok(()) => remember_inserted(&undo_stack, &typed, false),
+                                Err(e) => paste_manually(&text_inserter, &response.text, &e),
+                            }
+                            // 清空 last_text，这样新的语句不会删除已确认的文字
+                            last_text = String::new();
+                            refinable_lead = lead;
+                            lead = ends_with_separator(&typed);
+                            after_separator.store(lead, Ordering::SeqCst);
+                            refinable = Some(typed);
+                        } else {
+                            refinable = None;
+                        }
+                    }
+                    ResponseType::RefinedResult => {
+                        tracing::info!("[REFINED #{}] {}", response_count, response.text);
+                        match refinable.take() {
+                            // The streaming final went to the clipboard; leave it there
+                            Some(typed) if typed.is_empty() => {}
+                            // Correct the typed final, keeping any interim typed after it
+                            Some(typed) => {
+                                remember_result(history.as_deref(), &processed(&response.text), true);
+                                let refined = as_typed(&response.text, refinable_lead);
+                                if typed != refined && guard.can_correct() {
+                                    println!("✏️ [修正] {} -> {}", typed, refined);
+                                    let old = format!("{}{}", typed, last_text);
+                                    let new = format!("{}{}", refined, last_text);
+                                    match update_text(&text_inserter, &mut guard, &old, &new) {
+                                        Ok(()) => remember_inserted(&undo_stack, &refined, true),
+                                        Err(e) => paste_manually(&text_inserter, &response.text, &e),
+                                    }
+                                    if last_text.is_empty() {
+                                        lead = ends_with_separator(&refined);
+                                        after_separator.store(lead, Ordering::SeqCst);
                                     }
-                                    last_text = response.text.clone();
                                 }
                             }
-                            ResponseType::FinalResult => {
-                                tracing::info!("[FINAL #{}] {}", response_count, response.text);
+                            // No streaming final preceded it; treat it as one
+                            None => {
                                 println!("✅ [确认] {}", response.text);
-                                if !response.text.is_empty() {
-                                    if let Err(e) = update_text(&text_inserter, &last_text, &response.text) {
-                                        tracing::error!("Failed to update text: {}", e);
+                                if let Some(command) = commands.lookup(&response.text) {
+                                    if let Err(e) = update_text(&text_inserter, &mut guard, &last_text, "") {
+                                        tracing::error!("Failed to remove interim text: {}", e);
                                     }
-                                    // 清空 last_text，这样新的语句不会删除已确认的文字
                                     last_text = String::new();
+                                    run_command(command, &text_inserter, &undo_stack, &after_separator, &event_tx);
+                                    lead = after_separator.load(Ordering::SeqCst);
+                                    continue;
                                 }
-                            }
-                            ResponseType::SessionFinished => {
-                                tracing::info!("ASR session finished (total {} responses)", response_count);
-                                println!("🏁 [会话结束]");
-                                break;
-                            }
-                            ResponseType::Error => {
-                                tracing::error!("ASR error: {}", response.error_msg);
-                                println!("❌ [错误] {}", response.error_msg);
-                                break;
-                            }
-                            _ => {
-                                tracing::trace!("Other response type: {:?}", response.response_type);
+                                let typed = as_typed(&response.text, lead);
+                                remember_result(history.as_deref(), &processed(&response.text), false);
+                                match update_text(&text_inserter, &mut guard, &last_text, &typed) {
+                                    Ok(()) => remember_inserted(&undo_stack, &typed, false),
+                                    Err(e) => paste_manually(&text_inserter, &response.text, &e),
+                                }
+                                last_text = String::new();
+                                lead = ends_with_separator(&typed);
+                                after_separator.store(lead, Ordering::SeqCst);
                             }
                         }
                     }
-                    Ok(None) => {
-                        // Channel closed
-                        tracing::warn!("ASR result channel closed unexpectedly");
+                    ResponseType::SessionTruncated => {
+                        tracing::warn!("Recording hit the session length limit, finishing");
+                        println!("⏱️ [已达到最长录音时长]");
+                        truncated = true;
+                        audio_capture.stop();
+                        is_recording.store(false, Ordering::SeqCst);
+                        if cues {
+                            Cue::Stop.play();
+                        }
+                        notify("已达到最长录音时长", "录音已自动结束, 已识别的文字会继续输入");
+                    }
+                    ResponseType::SessionFinished => {
+                        tracing::info!("ASR session finished (total {} responses)", response_count);
+                        println!("🏁 [会话结束]");
+                        break;
+                    }
+                    ResponseType::Error => {
+                        let known = response.error_code.and_then(describe_error_code);
+                        if known.is_some_and(|k| k.category == ErrorCategory::InvalidToken) {
+                            if let Some(store) = &credential_store {
+                                store.invalidate_token();
+                            }
+                        }
+                        tracing::error!(
+                            "ASR error (code {:?}): {}",
+                            response.error_code,
+                            response.error_msg
+                        );
+                        println!("❌ [错误] {}", response.error_msg);
+                        match known {
+                            Some(known) => notify("语音识别失败", known.description),
+                            None => notify("语音识别失败", &response.error_msg),
+                        }
+                        failed = true;
                         break;
                     }
-                    Err(_) => {
-                        // Timeout, continue loop to check stop signal
-                        continue;
+                    _ => {
+                        tracing::trace!("Other response type: {:?}", response.response_type);
                     }
                 }
             }
 
+            tracing::info!("ASR result processing finished ({} responses)", response_count);
+            // Live-typed text of the utterance that was cut off
+            if cancelled.load(Ordering::SeqCst) && !last_text.is_empty() {
+                if let Err(e) = update_text(&text_inserter, &mut guard, &last_text, "") {
+                    tracing::error!("Failed to remove interim text: {}", e);
+                }
+            }
+            tracing::info!("Session stats: {}", metrics.snapshot());
+            tracing::info!("Capture stats: {}", audio_capture.stats());
+
+            if to_clipboard && !transcript.is_empty() {
+                notify("转写完成", "转写文字已复制到剪贴板");
+            }
+
             // Cleanup
             audio_capture.stop();
             is_recording.store(false, Ordering::SeqCst);
+            if cues && failed {
+                Cue::Error.play();
+            }
+            let _ = event_tx.send(VoiceControllerEvent::SessionEnded { truncated });
         });
 
         Ok(())
     }
 
+    /// Throw away the stored device and register a new one
+    pub async fn reset_device(&mut self) -> Result<()> {
+        let store = self
+            .credential_store
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("no credential store configured"))?;
+        let creds = store.reset_and_register().await?;
+        tracing::info!("Registered new device: {}", creds.device_id);
+        Ok(())
+    }
+
     /// Stop voice input
     pub async fn stop(&mut self) -> Result<()> {
         if !self.is_recording() {
@@ -161,52 +749,526 @@ impl VoiceController {
 
         tracing::info!("Stopping voice input...");
 
-        // Signal stop
-        self.stop_signal.store(true, Ordering::SeqCst);
         self.audio_capture.stop();
+        // Played after capture stopped, so system audio recording misses it
+        if self.config.ui.sound_feedback {
+            Cue::Stop.play();
+        }
+
+        // Flush the remaining audio and wait for the server's last word
+        if let Some(mut session) = self.session.take() {
+            match session.finish(FINISH_TIMEOUT).await {
+                Ok(Some(final_result)) => {
+                    tracing::info!("Session finished with final result: {}", final_result.text);
+                }
+                Ok(None) => {
+                    tracing::info!("Session finished, no speech detected");
+                    println!("🔇 [未检测到语音]");
+                }
+                Err(AsrError::Timeout(e)) => {
+                    tracing::warn!("Server never answered FinishSession: {}", e);
+                    println!("⌛ [服务器未响应]");
+                }
+                Err(e) => {
+                    tracing::warn!("Session ended abnormally: {}", e);
+                }
+            }
+        }
 
-        // Wait a bit for the task to finish
-        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
-        
         self.is_recording.store(false, Ordering::SeqCst);
 
         Ok(())
     }
+
+    /// Stop voice input without waiting for the rest of the results
+    ///
+    /// Nothing more is typed; text already typed for finished sentences
+    /// stays, live-typed text of the current one is erased. After a stop
+    /// it still drops the results the last session hasn't delivered yet.
+    pub fn cancel(&mut self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        if !self.is_recording() {
+            return;
+        }
+
+        tracing::info!("Cancelling voice input...");
+        self.audio_capture.stop();
+        if self.config.ui.sound_feedback {
+            Cue::Stop.play();
+        }
+        if let Some(session) = self.session.take() {
+            session.cancel();
+        }
+        tracing::info!("Voice input cancelled");
+
+        self.is_recording.store(false, Ordering::SeqCst);
+    }
 }
 
-/// Update text in the focused window using incremental updates
+/// Follow microphone trouble and silence during a recording
 ///
-/// Uses prefix matching to minimize deletions and insertions:
-/// 1. Find the common prefix between old and new text
-/// 2. Only delete characters beyond the common prefix
-/// 3. Only append the new suffix
-/// 
-/// This significantly reduces visual flickering compared to full replacement.
-fn update_text(text_inserter: &TextInserter, old_text: &str, new_text: &str) -> Result<()> {
-    // 找到公共前缀长度（无需删除和重新输入的部分）
-    let common_prefix_len = old_text
-        .chars()
-        .zip(new_text.chars())
-        .take_while(|(a, b)| a == b)
-        .count();
-    
-    // 计算需要删除的字符数 = 旧文本超出公共前缀的部分
-    let chars_to_delete = old_text.chars().count() - common_prefix_len;
-    
-    // 需要追加的文本 = 新文本超出公共前缀的部分
-    let text_to_append: String = new_text.chars().skip(common_prefix_len).collect();
-    
-    // 执行增量更新
-    if chars_to_delete > 0 {
-        text_inserter.delete_chars(chars_to_delete)?;
-    }
-    if !text_to_append.is_empty() {
-        text_inserter.insert(&text_to_append)?;
-    }
-    
-    tracing::debug!(
-        "Updated text incrementally: '{}' -> '{}' (kept {} chars, deleted {}, appended '{}')",
-        old_text, new_text, common_prefix_len, chars_to_delete, text_to_append
-    );
+/// A failed stream is reopened (configured device first, then the default)
+/// while silence keeps the session alive; if the device stays gone the
+/// session is finished with what was recognized so far.
+async fn watch_audio(
+    mut events: tokio::sync::mpsc::UnboundedReceiver<AudioCaptureEvent>,
+    audio_capture: Arc<AudioCapture>,
+    config: AudioConfig,
+    event_tx: Sender<VoiceControllerEvent>,
+    cues: bool,
+) {
+    let mut deadline = None;
+    // Device and error of the latest failure, for the notification
+    let mut failure = None;
+    while let Some(event) = events.recv().await {
+        match event {
+            AudioCaptureEvent::StreamError { device, error } => {
+                tracing::warn!("Microphone {} stopped ({}), trying to reopen it", device, error);
+                failure = Some(format!("{}: {}", device, error));
+                let _ = event_tx.send(VoiceControllerEvent::AudioInterrupted);
+                deadline = Some(tokio::time::Instant::now() + AUDIO_RECOVERY_TIMEOUT);
+                audio_capture.reopen(&config);
+            }
+            AudioCaptureEvent::ReopenFailed(e) => {
+                if deadline.is_some_and(|deadline| tokio::time::Instant::now() < deadline) {
+                    tracing::debug!("Microphone not back yet: {}", e);
+                    tokio::time::sleep(AUDIO_REOPEN_INTERVAL).await;
+                    audio_capture.reopen(&config);
+                } else {
+                    tracing::error!("Microphone did not come back ({}), finishing the session", e);
+                    let message = match &failure {
+                        Some(failure) => format!("{}\n录音已结束, 已识别的文字会继续输入", failure),
+                        None => "录音已结束, 已识别的文字会继续输入".to_string(),
+                    };
+                    notify("麦克风已断开", &message);
+                    audio_capture.stop();
+                    if cues {
+                        Cue::Error.play();
+                    }
+                }
+            }
+            AudioCaptureEvent::Reopened(name) => {
+                tracing::info!("Microphone recovered: {}", name);
+                deadline = None;
+                failure = None;
+                let _ = event_tx.send(VoiceControllerEvent::AudioRecovered);
+            }
+            AudioCaptureEvent::NoInput => {
+                tracing::warn!("No microphone input at the start of the recording");
+                notify("未检测到麦克风输入", "未检测到麦克风输入，请检查是否静音");
+                let _ = event_tx.send(VoiceControllerEvent::NoInput);
+            }
+            AudioCaptureEvent::SilenceTimeout => {
+                tracing::info!("Silence after speech, stopping automatically");
+                let _ = event_tx.send(VoiceControllerEvent::SilenceDetected);
+            }
+        }
+    }
+}
+
+/// The start of a result for a notification
+fn preview(text: &str) -> String {
+    const PREVIEW_CHARS: usize = 40;
+    match text.char_indices().nth(PREVIEW_CHARS) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Carry out a spoken command
+fn run_command(
+    command: VoiceCommand,
+    text_inserter: &TextInserter,
+    undo_stack: &Mutex<VecDeque<Inserted>>,
+    after_separator: &AtomicBool,
+    event_tx: &Sender<VoiceControllerEvent>,
+) {
+    tracing::info!("Voice command: {:?}", command);
+    println!("🎙️ [命令] {:?}", command);
+    let result = match command {
+        VoiceCommand::Enter | VoiceCommand::Tab => {
+            let key = if command == VoiceCommand::Enter { VK_RETURN } else { VK_TAB };
+            // The caret moved past the typed results; they can't be erased anymore
+            undo_stack.lock().unwrap().clear();
+            after_separator.store(true, Ordering::SeqCst);
+            text_inserter.press_key(key)
+        }
+        VoiceCommand::DeleteLast => {
+            undo_inserted(text_inserter, undo_stack, after_separator, false).map(|_| ())
+        }
+        VoiceCommand::Stop => {
+            let _ = event_tx.send(VoiceControllerEvent::StopRequested);
+            Ok(())
+        }
+    };
+    if let Err(e) = result {
+        tracing::error!("Voice command {:?} failed: {:#}", command, e);
+    }
+}
+
+/// The dictation history, if `general.history_enabled`
+fn open_history(config: &AppConfig) -> Option<Arc<History>> {
+    config.general.history_enabled.then(|| {
+        Arc::new(History::open(
+            AppConfig::history_path(),
+            config.general.history_size.max(1),
+        ))
+    })
+}
+
+/// Keep a recognized result in the history; a refinement `amends` the
+/// result it corrects
+fn remember_result(history: Option<&History>, text: &str, amends: bool) {
+    let Some(history) = history else {
+        return;
+    };
+    if text.is_empty() {
+        return;
+    }
+    let saved = if amends {
+        history.amend_last(text)
+    } else {
+        let app = InsertTarget::current().and_then(|target| target.process_name());
+        history.record(text, app)
+    };
+    if let Err(e) = saved {
+        tracing::warn!("Failed to save to history: {:#}", e);
+    }
+}
+
+/// Note the text just typed for a result, for undoing it; a refinement
+/// `replaces` the result it corrected
+fn remember_inserted(undo_stack: &Mutex<VecDeque<Inserted>>, typed: &str, replaces: bool) {
+    let mut undo_stack = undo_stack.lock().unwrap();
+    if replaces {
+        undo_stack.pop_back();
+    }
+    if typed.is_empty() {
+        return;
+    }
+    if undo_stack.len() == UNDO_DEPTH {
+        undo_stack.pop_front();
+    }
+    undo_stack.push_back(Inserted {
+        text: typed.to_string(),
+        target: InsertTarget::current(),
+    });
+}
+
+/// Erase the newest remembered result with backspaces, if the focus is
+/// still where it was typed (or, with `bring_back`, can be moved there)
+///
+/// Returns whether there was anything to erase. Fails, keeping the result,
+/// when the focus moved.
+fn undo_inserted(
+    text_inserter: &TextInserter,
+    undo_stack: &Mutex<VecDeque<Inserted>>,
+    after_separator: &AtomicBool,
+    bring_back: bool,
+) -> Result<bool> {
+    let mut undo_stack = undo_stack.lock().unwrap();
+    let Some(inserted) = undo_stack.back() else {
+        tracing::info!("No result to undo");
+        return Ok(false);
+    };
+    if let (true, Some(target)) = (bring_back, inserted.target) {
+        target.activate()?;
+    }
+    if inserted.target.is_none() || inserted.target != InsertTarget::current() {
+        tracing::warn!("Focus moved since the result was typed, not erasing it");
+        notify("无法撤销上一句", "输入位置已改变, 请手动删除");
+        return Err(anyhow!("focus moved since the result was typed"));
+    }
+
+    let units = inserted.text.encode_utf16().count();
+    tracing::info!("Undoing the last result ({} UTF-16 units)", units);
+    text_inserter.delete_chars(units)?;
+    undo_stack.pop_back();
+    // What is before the caret now is the result before it, if any
+    let lead = undo_stack
+        .back()
+        .is_some_and(|previous| ends_with_separator(&previous.text));
+    after_separator.store(lead, Ordering::SeqCst);
+    Ok(true)
+}
+
+/// Which window text goes to (`text_insert.target`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TargetMode {
+    /// The window focused when recording started, brought back if needed
+    FocusAtStart,
+    /// Whatever is focused when the text arrives
+    FocusAtInsert,
+}
+
+impl TargetMode {
+    fn parse(target: &str) -> Self {
+        match target.to_ascii_lowercase().as_str() {
+            "focus-at-start" => TargetMode::FocusAtStart,
+            "focus-at-insert" => TargetMode::FocusAtInsert,
+            _ => {
+                tracing::warn!(
+                    "Unknown text_insert.target {:?}, using \"focus-at-insert\"",
+                    target
+                );
+                TargetMode::FocusAtInsert
+            }
+        }
+    }
+}
+
+/// What is typed after each final result (`text_insert.trailing`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Trailing {
+    None,
+    Space,
+    Newline,
+    /// A space after text ending in a Latin letter or digit, so English
+    /// sentences don't run together while Chinese gets nothing
+    Smart,
+}
+
+impl Trailing {
+    fn parse(trailing: &str) -> Self {
+        match trailing.to_ascii_lowercase().as_str() {
+            "none" => Trailing::None,
+            "space" => Trailing::Space,
+            "newline" => Trailing::Newline,
+            "smart" => Trailing::Smart,
+            _ => {
+                tracing::warn!("Unknown text_insert.trailing {:?}, using \"none\"", trailing);
+                Trailing::None
+            }
+        }
+    }
+
+    /// `text` as typed: without leading whitespace if it follows a
+    /// separator, and with the separator added unless it already ends in one
+    fn apply(self, text: &str, after_separator: bool) -> String {
+        let text = strip_lead(text, after_separator);
+        if text.is_empty() || ends_with_separator(text) {
+            return text.to_string();
+        }
+        let separator = match self {
+            Trailing::None => "",
+            Trailing::Space => " ",
+            Trailing::Newline => "\n",
+            Trailing::Smart => match text.chars().last() {
+                Some(c) if is_latin_alphanumeric(c) => " ",
+                _ => "",
+            },
+        };
+        format!("{}{}", text, separator)
+    }
+}
+
+/// `text` without leading whitespace when it follows a separator
+fn strip_lead(text: &str, after_separator: bool) -> &str {
+    if after_separator {
+        text.trim_start()
+    } else {
+        text
+    }
+}
+
+/// Whether text typed after `text` counts as following a separator
+fn ends_with_separator(text: &str) -> bool {
+    text.ends_with(char::is_whitespace)
+}
+
+/// ASCII letters and digits, plus accented Latin letters
+fn is_latin_alphanumeric(c: char) -> bool {
+    c.is_ascii_alphanumeric() || (c.is_alphabetic() && ('\u{00C0}'..='\u{024F}').contains(&c))
+}
+
+/// Put a result that couldn't be typed on the clipboard and ask the user
+/// to paste it themselves
+///
+/// Typing fails outright when the target window runs as administrator.
+fn paste_manually(text_inserter: &TextInserter, text: &str, error: &anyhow::Error) {
+    tracing::error!("Failed to type text, copying it to the clipboard: {:#}", error);
+    // The inserter copied only the part it was asked to type
+    match text_inserter.copy_to_clipboard(text) {
+        Ok(()) if error.downcast_ref::<ElevatedTarget>().is_some() => {
+            notify("无法输入文字", "目标窗口以管理员权限运行，文本已复制到剪贴板")
+        }
+        Ok(()) if error.downcast_ref::<NoEditableFocus>().is_some() => {
+            notify("无法输入文字", "当前没有可输入文字的位置，文本已复制到剪贴板")
+        }
+        Ok(()) if error.downcast_ref::<TooLongToType>().is_some() => {
+            notify("文字较长", "已复制到剪贴板, 请手动粘贴 (Ctrl+V)")
+        }
+        Ok(()) if error.downcast_ref::<NoKeyForChar>().is_some() => {
+            notify("无法输入文字", "按键输入方式只能输入英文字符，文本已复制到剪贴板")
+        }
+        Ok(()) if error.downcast_ref::<InsertAborted>().is_some() => {
+            notify("已停止输入", "完整文字已复制到剪贴板")
+        }
+        Ok(()) => notify(
+            "无法输入文字",
+            &format!(
+                "已复制到剪贴板, 请手动粘贴 (Ctrl+V)\n目标程序以管理员身份运行时, 需同样以管理员身份运行本程序\n\n{:#}",
+                error
+            ),
+        ),
+        Err(e) => tracing::error!("Failed to copy to clipboard: {}", e),
+    }
+}
+
+/// Where a session's text is typed, to notice the user moving away
+///
+/// Typed text is corrected by backspacing, which would eat the user's own
+/// text once they click into another window or control. A pinned target
+/// is instead brought back before each insertion.
+#[derive(Default)]
+struct TypingGuard {
+    /// Window and control of the first text typed, or the pinned one
+    target: Option<InsertTarget>,
+    /// Focus moved since; text is only appended from then on
+    moved: bool,
+    /// `target` was focused when recording started
+    pinned: bool,
+}
+
+impl TypingGuard {
+    /// Keep typing into `target` (`text_insert.target = "focus-at-start"`)
+    fn pinned(target: Option<InsertTarget>) -> Self {
+        Self {
+            target,
+            moved: false,
+            pinned: target.is_some(),
+        }
+    }
+
+    /// Whether text typed so far can still be corrected in place
+    fn can_correct(&mut self) -> bool {
+        if !self.moved && !self.pinned {
+            if let Some(target) = self.target {
+                if InsertTarget::current() != Some(target) {
+                    tracing::warn!("Focus moved while dictating, only appending text from now on");
+                    self.moved = true;
+                }
+            }
+        }
+        !self.moved
+    }
+
+    /// Bring back the pinned target if the user moved away; fails when it
+    /// was closed or won't come back, so no text goes elsewhere
+    fn restore(&mut self) -> Result<()> {
+        match self.target {
+            Some(target) if self.pinned => {
+                let restored = target.activate();
+                if restored.is_err() {
+                    self.moved = true;
+                }
+                restored
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Note that text was typed into the focused control
+    fn typed(&mut self) {
+        if self.target.is_none() {
+            self.target = InsertTarget::current();
+        }
+    }
+}
+
+/// Turn `old_text`, typed before the caret, into `new_text`: corrected in
+/// place while the focus stays put, otherwise only the new end is typed
+fn update_text(
+    text_inserter: &TextInserter,
+    guard: &mut TypingGuard,
+    old_text: &str,
+    new_text: &str,
+) -> Result<()> {
+    if old_text == new_text {
+        return Ok(());
+    }
+    guard.restore()?;
+    if guard.can_correct() {
+        text_inserter.update_interim(old_text, new_text)?;
+    } else {
+        text_inserter.append_missing(old_text, new_text)?;
+    }
+    if !new_text.is_empty() {
+        guard.typed();
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asr::mock::MockBackend;
+    use std::time::Instant;
+
+    /// Settings without sounds or history, which would touch the machine
+    fn quiet_config() -> AppConfig {
+        let mut config = AppConfig::default();
+        config.ui.sound_feedback = false;
+        config.general.history_enabled = false;
+        config
+    }
+
+    /// Wait for `done`, giving up after a few seconds
+    async fn wait_for(what: &str, mut done: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done() {
+            assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn lost_microphone_returns_to_idle() {
+        let (capture, recordings) = AudioCapture::mock();
+        let mut controller = VoiceController::new(
+            Arc::new(MockBackend::new(Vec::new())),
+            Arc::new(capture),
+            Arc::new(TextInserter::new()),
+            &quiet_config(),
+        )
+        // `start` would otherwise read the user's config.toml
+        .with_config_loader(|| Ok(quiet_config()));
+        let events = controller.take_event_receiver().unwrap();
+        controller.start().await.unwrap();
+        assert!(controller.is_recording());
+
+        let recording = recordings.recv_timeout(Duration::from_secs(1)).unwrap();
+        recording
+            .events
+            .send(AudioCaptureEvent::StreamError {
+                device: "USB Microphone".to_string(),
+                error: "The device is no longer available".to_string(),
+            })
+            .unwrap();
+        // The device never comes back
+        wait_for("the capture to stop", || {
+            if recording.reopen.try_recv().is_ok() {
+                let failed = AudioCaptureEvent::ReopenFailed("no input device".to_string());
+                recording.events.send(failed).unwrap();
+            }
+            !recording.is_recording.load(Ordering::SeqCst)
+        })
+        .await;
+        // As the capture thread does once it sees the stop
+        drop(recording.frames);
+
+        let mut received = Vec::new();
+        wait_for("the session to end", || {
+            received.extend(events.try_iter());
+            received
+                .iter()
+                .any(|event| matches!(event, VoiceControllerEvent::SessionEnded { .. }))
+        })
+        .await;
+        assert!(matches!(received[0], VoiceControllerEvent::AudioInterrupted));
+        assert!(!received
+            .iter()
+            .any(|event| matches!(event, VoiceControllerEvent::AudioRecovered)));
+        assert!(!controller.is_recording());
+    }
+}
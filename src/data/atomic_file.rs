@@ -0,0 +1,128 @@
+//! Atomic File Writes
+//!
+//! Saves go to a temporary file next to the target, are flushed to disk and
+//! then renamed over it, so a crash leaves either the old or the new file.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Replace `path` with `contents` without ever leaving a partial file
+pub(crate) fn write(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let tmp = sibling(path, "tmp");
+    let mut file = fs::File::create(&tmp).with_context(|| format!("cannot create {:?}", tmp))?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    // The temporary file stays behind as a recovery copy if the rename fails
+    fs::rename(&tmp, path).with_context(|| format!("cannot replace {:?}", path))?;
+    Ok(())
+}
+
+/// Leftover copies to recover from when `path` is corrupt, most recent first
+pub(crate) fn recovery_candidates(path: &Path) -> Vec<PathBuf> {
+    ["tmp", "bak"]
+        .iter()
+        .map(|suffix| sibling(path, suffix))
+        .filter(|candidate| candidate.is_file())
+        .collect()
+}
+
+/// `path` with an extra extension, e.g. `credentials.json.tmp`
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asr::{CredentialProfiles, DeviceCredentials, DEFAULT_PROFILE};
+    use crate::data::AppConfig;
+
+    /// Empty directory of its own for each test
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("atomic-file-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Cut `path` short, as a crash in the middle of a plain write would
+    fn truncate(path: &Path) {
+        let contents = fs::read(path).unwrap();
+        fs::write(path, &contents[..contents.len() / 2]).unwrap();
+    }
+
+    #[test]
+    fn write_replaces_the_file_and_leaves_no_copy() {
+        let dir = scratch_dir("write");
+        let path = dir.join("settings.toml");
+        write(&path, b"old").unwrap();
+        write(&path, b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert!(recovery_candidates(&path).is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn truncated_config_loads_from_the_backup() {
+        let dir = scratch_dir("config");
+        let path = dir.join("config.toml");
+        let mut config = AppConfig::default();
+        config.asr.max_session_secs = 42;
+        let contents = toml::to_string_pretty(&config).unwrap();
+        fs::write(&path, &contents).unwrap();
+        fs::write(sibling(&path, "bak"), &contents).unwrap();
+        truncate(&path);
+
+        let loaded = AppConfig::load_from(&path).unwrap();
+        assert_eq!(loaded.asr.max_session_secs, 42);
+        // The good copy is put back in place
+        assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn truncated_credentials_load_from_the_backup() {
+        let dir = scratch_dir("credentials");
+        let path = dir.join("credentials.json");
+        let mut creds = DeviceCredentials::new_generated();
+        creds.device_id = "7391".to_string();
+        creds.token = "secret".to_string();
+        let mut profiles = CredentialProfiles::default();
+        profiles.insert(DEFAULT_PROFILE, creds);
+        profiles.save(&path).unwrap();
+        fs::copy(&path, sibling(&path, "bak")).unwrap();
+        truncate(&path);
+
+        let loaded = CredentialProfiles::load(&path).unwrap();
+        let creds = loaded.get(DEFAULT_PROFILE).unwrap();
+        assert_eq!(creds.device_id, "7391");
+        assert_eq!(creds.token, "secret");
+        assert!(CredentialProfiles::load(&path).is_ok(), "the corrupt file was not restored");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_file_without_a_copy_is_an_error() {
+        let dir = scratch_dir("no-copy");
+        let path = dir.join("config.toml");
+        fs::write(&path, "[asr\nmax_session").unwrap();
+        assert!(AppConfig::load_from(&path).is_err());
+
+        // The error names the section holding the bad value
+        fs::write(&path, "[general]\nlanguage = \"zh-CN\"\n\n[hotkey]\ncombo_key = 5\n").unwrap();
+        let error = format!("{:#}", AppConfig::load_from(&path).unwrap_err());
+        assert!(error.starts_with("in the [hotkey] section"), "{}", error);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
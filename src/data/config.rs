@@ -2,10 +2,25 @@
 //!
 //! Handles loading and saving application configuration.
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
+
+use super::atomic_file;
+use crate::asr::{DEFAULT_PROFILE, REGISTER_URL, SETTINGS_URL, WEBSOCKET_URL};
+use crate::audio::FRAME_DURATIONS_MS;
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Folder under %APPDATA% holding per-user data
+const APP_DIR_NAME: &str = "doubao-ime-win";
+
+/// File beside the executable that keeps data next to it
+const PORTABLE_FLAG_FILE: &str = "portable.flag";
+
+/// Set by `--portable`
+static PORTABLE: AtomicBool = AtomicBool::new(false);
 
 /// Application configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,6 +33,20 @@ pub struct AppConfig {
     pub floating_button: FloatingButtonConfig,
     #[serde(default)]
     pub asr: AsrConfig,
+    #[serde(default)]
+    pub audio: AudioConfig,
+    #[serde(default)]
+    pub device: DeviceConfig,
+    #[serde(default)]
+    pub encoder: EncoderConfig,
+    #[serde(default)]
+    pub network: NetworkConfig,
+    #[serde(default)]
+    pub privacy: PrivacyConfig,
+    #[serde(default)]
+    pub ui: UiConfig,
+    #[serde(default)]
+    pub text_insert: TextInsertConfig,
 }
 
 impl Default for AppConfig {
@@ -27,27 +56,77 @@ impl Default for AppConfig {
             hotkey: HotkeyConfig::default(),
             floating_button: FloatingButtonConfig::default(),
             asr: AsrConfig::default(),
+            audio: AudioConfig::default(),
+            device: DeviceConfig::default(),
+            encoder: EncoderConfig::default(),
+            network: NetworkConfig::default(),
+            privacy: PrivacyConfig::default(),
+            ui: UiConfig::default(),
+            text_insert: TextInsertConfig::default(),
         }
     }
 }
 
 impl AppConfig {
+    /// Keep config and credentials beside the executable (`--portable`)
+    pub fn set_portable(portable: bool) {
+        PORTABLE.store(portable, Ordering::SeqCst);
+    }
+
+    /// Whether data is kept beside the executable
+    pub fn is_portable() -> bool {
+        PORTABLE.load(Ordering::SeqCst) || exe_dir().join(PORTABLE_FLAG_FILE).exists()
+    }
+
+    /// Directory holding config.toml and credentials.json
+    ///
+    /// `%APPDATA%\doubao-ime-win` normally, the executable's directory in
+    /// portable mode or when no per-user directory is available.
+    pub fn data_dir() -> PathBuf {
+        if Self::is_portable() {
+            return exe_dir();
+        }
+        user_data_dir()
+            .map(|dir| dir.join(APP_DIR_NAME))
+            .unwrap_or_else(exe_dir)
+    }
+
     /// Get the config file path
     pub fn config_path() -> PathBuf {
-        let exe_dir = std::env::current_exe()
-            .ok()
-            .and_then(|p| p.parent().map(|p| p.to_path_buf()))
-            .unwrap_or_else(|| PathBuf::from("."));
-        exe_dir.join("config.toml")
+        Self::data_file("config.toml")
     }
 
     /// Get the credentials file path
     pub fn credentials_path() -> PathBuf {
-        let exe_dir = std::env::current_exe()
-            .ok()
-            .and_then(|p| p.parent().map(|p| p.to_path_buf()))
-            .unwrap_or_else(|| PathBuf::from("."));
-        exe_dir.join("credentials.json")
+        Self::data_file("credentials.json")
+    }
+
+    /// Get the dictation history file path
+    pub fn history_path() -> PathBuf {
+        Self::data_dir().join("history.jsonl")
+    }
+
+    /// Resolve a data file, moving it over from beside the executable on first use
+    fn data_file(name: &str) -> PathBuf {
+        let dir = Self::data_dir();
+        let path = dir.join(name);
+        let legacy = exe_dir().join(name);
+        if path != legacy && !path.exists() && legacy.exists() {
+            let moved = fs::create_dir_all(&dir).and_then(|_| {
+                fs::rename(&legacy, &path).or_else(|_| {
+                    fs::copy(&legacy, &path)?;
+                    fs::remove_file(&legacy)
+                })
+            });
+            match moved {
+                Ok(()) => tracing::info!("Moved {:?} to {:?}", legacy, path),
+                Err(e) => {
+                    tracing::warn!("Cannot move {:?} to {:?}, using it in place: {}", legacy, path, e);
+                    return legacy;
+                }
+            }
+        }
+        path
     }
 
     /// Load configuration from file or create default
@@ -55,9 +134,7 @@ impl AppConfig {
         let path = Self::config_path();
 
         if path.exists() {
-            let content = fs::read_to_string(&path)?;
-            let config: AppConfig = toml::from_str(&content)?;
-            Ok(config)
+            Self::load_from(&path)
         } else {
             let config = AppConfig::default();
             config.save()?;
@@ -65,13 +142,139 @@ impl AppConfig {
         }
     }
 
+    /// Load configuration from `path`, recovering a corrupt file from its
+    /// leftover copy
+    pub(crate) fn load_from(path: &Path) -> Result<Self> {
+        let parsed = fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| {
+                toml::from_str::<AppConfig>(&content).map_err(|e| {
+                    let section = e.span().and_then(|span| section_at(&content, span.start));
+                    match section {
+                        Some(section) => {
+                            anyhow::Error::from(e).context(format!("in the [{}] section", section))
+                        }
+                        None => e.into(),
+                    }
+                })
+            });
+        match parsed {
+            Ok(config) => {
+                config.check()?;
+                Ok(config.validated())
+            }
+            Err(e) => Self::recover(path).map(Self::validated).ok_or(e),
+        }
+    }
+
     /// Save configuration to file
     pub fn save(&self) -> Result<()> {
-        let path = Self::config_path();
         let content = toml::to_string_pretty(self)?;
-        fs::write(&path, content)?;
+        atomic_file::write(&Self::config_path(), content.as_bytes())
+    }
+
+    /// Reject settings that can't be fixed up, naming the culprit
+    fn check(&self) -> Result<()> {
+        // Sessions and pacing divide by the frame length
+        if !FRAME_DURATIONS_MS.contains(&self.encoder.frame_duration_ms) {
+            return Err(anyhow!(
+                "encoder.frame_duration_ms = {} is not one of {:?}",
+                self.encoder.frame_duration_ms,
+                FRAME_DURATIONS_MS
+            ));
+        }
+        for rule in &self.text_insert.replace {
+            if rule.pattern.is_empty() {
+                return Err(anyhow!("text_insert.replace has a rule with an empty pattern"));
+            }
+            if rule.regex {
+                if let Err(e) = regex::Regex::new(&rule.pattern) {
+                    return Err(anyhow!(
+                        "text_insert.replace pattern {:?} is not a valid regex: {}",
+                        rule.pattern,
+                        e
+                    ));
+                }
+            }
+        }
         Ok(())
     }
+
+    /// Bring out-of-range values back into range, warning about each
+    fn validated(mut self) -> Self {
+        let gain = self.audio.gain_db;
+        if !GAIN_DB_RANGE.contains(&gain) {
+            let clamped = if gain.is_nan() {
+                0.0
+            } else {
+                gain.clamp(*GAIN_DB_RANGE.start(), *GAIN_DB_RANGE.end())
+            };
+            tracing::warn!("audio.gain_db = {} is outside -20..=20 dB, using {}", gain, clamped);
+            self.audio.gain_db = clamped;
+        }
+        if self.audio.preroll_ms > MAX_PREROLL_MS {
+            tracing::warn!(
+                "audio.preroll_ms = {} is above {}, using {}",
+                self.audio.preroll_ms,
+                MAX_PREROLL_MS,
+                MAX_PREROLL_MS
+            );
+            self.audio.preroll_ms = MAX_PREROLL_MS;
+        }
+        if self.audio.discard_initial_ms > MAX_DISCARD_INITIAL_MS {
+            tracing::warn!(
+                "audio.discard_initial_ms = {} is above {}, using {}",
+                self.audio.discard_initial_ms,
+                MAX_DISCARD_INITIAL_MS,
+                MAX_DISCARD_INITIAL_MS
+            );
+            self.audio.discard_initial_ms = MAX_DISCARD_INITIAL_MS;
+        }
+        self
+    }
+
+    /// Load the leftover copy of a corrupt config file and restore it
+    fn recover(path: &Path) -> Option<Self> {
+        atomic_file::recovery_candidates(path).into_iter().find_map(|candidate| {
+            let content = fs::read_to_string(&candidate).ok()?;
+            let config: AppConfig = toml::from_str(&content).ok()?;
+            tracing::warn!("{:?} is corrupt, recovered settings from {:?}", path, candidate);
+            if let Err(e) = atomic_file::write(path, content.as_bytes()) {
+                tracing::warn!("Failed to restore {:?}: {:#}", path, e);
+            }
+            Some(config)
+        })
+    }
+}
+
+/// The `[section]` of a TOML document that byte `offset` falls in
+fn section_at(content: &str, offset: usize) -> Option<String> {
+    content
+        .get(..offset)?
+        .lines()
+        .rev()
+        .find_map(|line| line.trim().strip_prefix('['))
+        .and_then(|header| header.trim_start_matches('[').split(']').next())
+        .map(|section| section.trim().to_string())
+}
+
+/// Directory containing the executable
+fn exe_dir() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(|p| p.to_path_buf()))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Per-user application data directory (%APPDATA%, or XDG config elsewhere)
+fn user_data_dir() -> Option<PathBuf> {
+    #[cfg(target_os = "windows")]
+    let dir = std::env::var_os("APPDATA").map(PathBuf::from);
+    #[cfg(not(target_os = "windows"))]
+    let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    dir.filter(|dir| dir.is_absolute())
 }
 
 /// General configuration
@@ -81,23 +284,39 @@ pub struct GeneralConfig {
     pub auto_start: bool,
     #[serde(default = "default_language")]
     pub language: String,
+    /// Keep recognized results in history.jsonl for finding them again
+    #[serde(default = "default_history_enabled")]
+    pub history_enabled: bool,
+    /// Results kept in the history
+    #[serde(default = "default_history_size")]
+    pub history_size: usize,
 }
 
 fn default_language() -> String {
     "zh-CN".to_string()
 }
 
+fn default_history_enabled() -> bool {
+    true
+}
+
+fn default_history_size() -> usize {
+    100
+}
+
 impl Default for GeneralConfig {
     fn default() -> Self {
         Self {
             auto_start: false,
             language: default_language(),
+            history_enabled: default_history_enabled(),
+            history_size: default_history_size(),
         }
     }
 }
 
 /// Hotkey configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct HotkeyConfig {
     #[serde(default = "default_hotkey_mode")]
     pub mode: String,
@@ -107,6 +326,29 @@ pub struct HotkeyConfig {
     pub double_tap_key: String,
     #[serde(default = "default_double_tap_interval")]
     pub double_tap_interval: u64,
+    /// Key held down to record in "hold" mode, e.g. "F9" or "Ctrl"
+    #[serde(default = "default_hold_key")]
+    pub hold_key: String,
+    /// How long the hold key must be down before recording starts, so
+    /// taps and shortcuts using it don't
+    #[serde(default = "default_hold_threshold_ms")]
+    pub hold_threshold_ms: u64,
+    /// Combo that erases the last typed result, e.g. "Ctrl+Alt+Z"; empty
+    /// for none
+    #[serde(default)]
+    pub undo_key: String,
+    /// Combo that types the newest history entry again; empty for none
+    #[serde(default)]
+    pub reinsert_key: String,
+    /// Extra keys that only start, only stop, or stop recording without
+    /// typing anything: a combo like "Ctrl+Alt+S" or a double tap like
+    /// "double:Shift"; empty for none
+    #[serde(default)]
+    pub start_key: String,
+    #[serde(default)]
+    pub stop_key: String,
+    #[serde(default)]
+    pub cancel_key: String,
 }
 
 fn default_hotkey_mode() -> String {
@@ -125,6 +367,14 @@ fn default_double_tap_interval() -> u64 {
     300
 }
 
+fn default_hold_key() -> String {
+    "F9".to_string()
+}
+
+fn default_hold_threshold_ms() -> u64 {
+    200
+}
+
 impl Default for HotkeyConfig {
     fn default() -> Self {
         Self {
@@ -132,6 +382,13 @@ impl Default for HotkeyConfig {
             combo_key: default_combo_key(),
             double_tap_key: default_double_tap_key(),
             double_tap_interval: default_double_tap_interval(),
+            hold_key: default_hold_key(),
+            hold_threshold_ms: default_hold_threshold_ms(),
+            undo_key: String::new(),
+            reinsert_key: String::new(),
+            start_key: String::new(),
+            stop_key: String::new(),
+            cancel_key: String::new(),
         }
     }
 }
@@ -170,10 +427,645 @@ impl Default for FloatingButtonConfig {
 pub struct AsrConfig {
     #[serde(default = "default_true")]
     pub vad_enabled: bool,
+    /// Let the server add punctuation to the transcript
+    #[serde(default = "default_true")]
+    pub enable_punctuation: bool,
+    /// Let the server discard audio it classifies as non-speech
+    #[serde(default)]
+    pub enable_speech_rejection: bool,
+    /// Words the recognizer should prefer (names, product terms, ...)
+    #[serde(default)]
+    pub hotwords: Vec<String>,
+    /// Final results below this confidence go to the clipboard instead of
+    /// being typed (0.0 disables the check)
+    #[serde(default)]
+    pub min_confidence: f32,
+    /// Proxy for the ASR connection (http:// or socks5://), falls back to HTTPS_PROXY
+    #[serde(default)]
+    pub proxy_url: Option<String>,
+    /// Ignore proxy environment variables and only use `proxy_url`
+    #[serde(default)]
+    pub disable_system_proxy: bool,
+    /// Override for the ASR WebSocket endpoint (e.g. a local mock server)
+    #[serde(default)]
+    pub websocket_url: Option<String>,
+    /// Override for the device registration endpoint
+    #[serde(default)]
+    pub register_url: Option<String>,
+    /// Override for the settings (token) endpoint
+    #[serde(default)]
+    pub settings_url: Option<String>,
+    /// Existing device_id to use instead of registering one (needs `token`)
+    #[serde(default)]
+    pub device_id: Option<String>,
+    /// ASR app_key to go with `device_id`
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Device identity to use from credentials.json
+    #[serde(default = "default_credential_profile")]
+    pub credential_profile: String,
+    /// Fetch a new ASR token once the cached one is this many hours old
+    /// (0 = only when the server rejects it)
+    #[serde(default = "default_token_max_age_hours")]
+    pub token_max_age_hours: u64,
+    /// How many times to retry a failed connect/StartTask/StartSession
+    #[serde(default = "default_handshake_retries")]
+    pub handshake_retries: u32,
+    /// Maximum number of audio frames queued for sending before the
+    /// oldest ones are dropped (one frame each)
+    #[serde(default = "default_send_queue_depth")]
+    pub send_queue_depth: usize,
+    /// Finish the session automatically after this many seconds (0 = no limit)
+    #[serde(default = "default_max_session_secs")]
+    pub max_session_secs: u64,
+    /// Deliver at most this many interim results per second, keeping only
+    /// the newest (0 = deliver every interim)
+    #[serde(default = "default_max_interims_per_sec")]
+    pub max_interims_per_sec: u32,
+    /// Keep the WebSocket open between recordings so only StartSession runs
+    /// per utterance
+    #[serde(default)]
+    pub persistent_connection: bool,
+    /// Close a kept-open connection after this many idle seconds
+    #[serde(default = "default_connection_idle_secs")]
+    pub connection_idle_secs: u64,
+    /// What to send for 1-2 byte DTX packets: "silence" sends an encoded
+    /// silent frame instead, "forward" sends them as they are (not yet
+    /// confirmed to be accepted by the server)
+    #[serde(default = "default_dtx_frames")]
+    pub dtx_frames: String,
+    /// Write every WebSocket frame of each session here for debugging
+    #[serde(default)]
+    pub debug_dump_dir: Option<String>,
+}
+
+fn default_dtx_frames() -> String {
+    "silence".to_string()
+}
+
+fn default_credential_profile() -> String {
+    DEFAULT_PROFILE.to_string()
+}
+
+fn default_token_max_age_hours() -> u64 {
+    24
+}
+
+fn default_handshake_retries() -> u32 {
+    2
+}
+
+fn default_send_queue_depth() -> usize {
+    250
+}
+
+fn default_max_session_secs() -> u64 {
+    120
+}
+
+fn default_max_interims_per_sec() -> u32 {
+    10
+}
+
+fn default_connection_idle_secs() -> u64 {
+    300
+}
+
+impl AsrConfig {
+    /// ASR WebSocket endpoint, falling back to the built-in default
+    pub fn websocket_endpoint(&self) -> &str {
+        self.websocket_url.as_deref().unwrap_or(WEBSOCKET_URL)
+    }
+
+    /// Device registration endpoint, falling back to the built-in default
+    pub fn register_endpoint(&self) -> &str {
+        self.register_url.as_deref().unwrap_or(REGISTER_URL)
+    }
+
+    /// Settings endpoint, falling back to the built-in default
+    pub fn settings_endpoint(&self) -> &str {
+        self.settings_url.as_deref().unwrap_or(SETTINGS_URL)
+    }
 }
 
 impl Default for AsrConfig {
     fn default() -> Self {
-        Self { vad_enabled: true }
+        Self {
+            vad_enabled: true,
+            enable_punctuation: true,
+            enable_speech_rejection: false,
+            hotwords: Vec::new(),
+            min_confidence: 0.0,
+            proxy_url: None,
+            disable_system_proxy: false,
+            websocket_url: None,
+            register_url: None,
+            settings_url: None,
+            device_id: None,
+            token: None,
+            credential_profile: default_credential_profile(),
+            token_max_age_hours: default_token_max_age_hours(),
+            handshake_retries: default_handshake_retries(),
+            send_queue_depth: default_send_queue_depth(),
+            max_session_secs: default_max_session_secs(),
+            max_interims_per_sec: default_max_interims_per_sec(),
+            persistent_connection: false,
+            connection_idle_secs: default_connection_idle_secs(),
+            dtx_frames: default_dtx_frames(),
+            debug_dump_dir: None,
+        }
+    }
+}
+
+/// Audio input configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioConfig {
+    /// What to record: "microphone", or "loopback" for what the default
+    /// speakers play (meetings); loopback text goes to the clipboard
+    #[serde(default = "default_audio_source")]
+    pub source: String,
+    /// Microphone to record from, matched as a case-insensitive substring
+    /// of the device name (default input device when unset)
+    #[serde(default)]
+    pub device_name: Option<String>,
+    /// Record only this channel (1-based) of a multi-channel device
+    /// instead of averaging all of them
+    #[serde(default)]
+    pub input_channel: Option<u16>,
+    /// Stop recording after this much quiet following speech (0 disables;
+    /// only used while `asr.vad_enabled` is on)
+    #[serde(default = "default_silence_timeout_ms")]
+    pub silence_timeout_ms: u64,
+    /// Frame energy (dBFS) below which audio counts as quiet
+    #[serde(default = "default_silence_threshold_db")]
+    pub silence_threshold_db: f32,
+    /// Software gain applied to the microphone, -20 to +20 dB
+    #[serde(default)]
+    pub gain_db: f32,
+    /// High-pass the input to remove rumble (air conditioning, desk bumps)
+    #[serde(default)]
+    pub high_pass: bool,
+    /// Cutoff of the high-pass filter
+    #[serde(default = "default_high_pass_hz")]
+    pub high_pass_hz: f32,
+    /// Mute background noise between words
+    #[serde(default)]
+    pub noise_gate: bool,
+    /// Frame level (dBFS) below which the gate closes
+    #[serde(default = "default_noise_gate_threshold_db")]
+    pub noise_gate_threshold_db: f32,
+    /// How long the gate stays open after the level drops
+    #[serde(default = "default_noise_gate_hold_ms")]
+    pub noise_gate_hold_ms: u64,
+    /// Keep the microphone open and send this much audio from before the
+    /// hotkey (0 disables, at most 1000)
+    #[serde(default)]
+    pub preroll_ms: u64,
+    /// Audio thrown away each time the device starts, to skip the click
+    /// some drivers emit (at most 500)
+    #[serde(default = "default_discard_initial_ms")]
+    pub discard_initial_ms: u64,
+    /// Even out quiet and loud speakers (applied after `gain_db`)
+    #[serde(default)]
+    pub agc_enabled: bool,
+    /// Level (dBFS RMS) the AGC aims for
+    #[serde(default = "default_agc_target_db")]
+    pub agc_target_db: f32,
+    /// Most gain the AGC may apply, in dB
+    #[serde(default = "default_agc_max_gain_db")]
+    pub agc_max_gain_db: f32,
+    /// Save what each recording captured (16 kHz mono) as .wav files here,
+    /// each with a `-sent.wav` decoded from the Opus frames actually sent
+    #[serde(default)]
+    pub dump_wav_dir: Option<String>,
+    /// Number of .wav dumps kept, older ones are deleted
+    #[serde(default = "default_dump_wav_keep")]
+    pub dump_wav_keep: usize,
+}
+
+fn default_audio_source() -> String {
+    "microphone".to_string()
+}
+
+impl AudioConfig {
+    /// Whether system audio is recorded instead of a microphone
+    pub fn is_loopback(&self) -> bool {
+        self.source.eq_ignore_ascii_case("loopback")
+    }
+}
+
+fn default_silence_timeout_ms() -> u64 {
+    2000
+}
+
+fn default_silence_threshold_db() -> f32 {
+    -40.0
+}
+
+fn default_high_pass_hz() -> f32 {
+    90.0
+}
+
+fn default_noise_gate_threshold_db() -> f32 {
+    -50.0
+}
+
+fn default_noise_gate_hold_ms() -> u64 {
+    200
+}
+
+fn default_discard_initial_ms() -> u64 {
+    30
+}
+
+fn default_agc_target_db() -> f32 {
+    -20.0
+}
+
+fn default_agc_max_gain_db() -> f32 {
+    20.0
+}
+
+fn default_dump_wav_keep() -> usize {
+    20
+}
+
+/// Supported range of `audio.gain_db`
+const GAIN_DB_RANGE: std::ops::RangeInclusive<f32> = -20.0..=20.0;
+
+/// Longest supported `audio.preroll_ms`
+const MAX_PREROLL_MS: u64 = 1000;
+
+/// Longest supported `audio.discard_initial_ms`
+const MAX_DISCARD_INITIAL_MS: u64 = 500;
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            source: default_audio_source(),
+            device_name: None,
+            input_channel: None,
+            silence_timeout_ms: default_silence_timeout_ms(),
+            silence_threshold_db: default_silence_threshold_db(),
+            gain_db: 0.0,
+            high_pass: false,
+            high_pass_hz: default_high_pass_hz(),
+            noise_gate: false,
+            noise_gate_threshold_db: default_noise_gate_threshold_db(),
+            noise_gate_hold_ms: default_noise_gate_hold_ms(),
+            preroll_ms: 0,
+            discard_initial_ms: default_discard_initial_ms(),
+            agc_enabled: false,
+            agc_target_db: default_agc_target_db(),
+            agc_max_gain_db: default_agc_max_gain_db(),
+            dump_wav_dir: None,
+            dump_wav_keep: default_dump_wav_keep(),
+        }
+    }
+}
+
+/// Opus encoder settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncoderConfig {
+    /// Target bitrate in bits per second (0 lets Opus choose)
+    #[serde(default)]
+    pub bitrate_bps: u32,
+    /// Opus mode: "voip" (tuned for speech), "audio" or "lowdelay"
+    #[serde(default = "default_encoder_application")]
+    pub application: String,
+    /// Variable bitrate; off gives constant-size frames
+    #[serde(default = "default_true")]
+    pub vbr: bool,
+    /// Keep VBR frames close to the target bitrate
+    #[serde(default = "default_true")]
+    pub vbr_constraint: bool,
+    /// Encoder effort, 0-10; lower uses less CPU at some cost in quality
+    #[serde(default = "default_encoder_complexity")]
+    pub complexity: u8,
+    /// Send 1-2 byte "no data" packets during silence (discontinuous transmission)
+    #[serde(default)]
+    pub dtx: bool,
+    /// Add in-band forward error correction (only helps if the server's
+    /// decoder uses it; costs bitrate)
+    #[serde(default)]
+    pub fec: bool,
+    /// Packet loss (percent) FEC is tuned for
+    #[serde(default = "default_expected_loss_pct")]
+    pub expected_loss_pct: u8,
+    /// Audio per Opus frame in ms: 10, 20, 40 or 60; longer frames mean
+    /// fewer messages but more latency
+    #[serde(default = "default_frame_duration_ms")]
+    pub frame_duration_ms: u32,
+}
+
+fn default_expected_loss_pct() -> u8 {
+    10
+}
+
+fn default_frame_duration_ms() -> u32 {
+    20
+}
+
+fn default_encoder_application() -> String {
+    "audio".to_string()
+}
+
+fn default_encoder_complexity() -> u8 {
+    10
+}
+
+impl Default for EncoderConfig {
+    fn default() -> Self {
+        Self {
+            bitrate_bps: 0,
+            application: default_encoder_application(),
+            vbr: true,
+            vbr_constraint: true,
+            complexity: default_encoder_complexity(),
+            dtx: false,
+            fec: false,
+            expected_loss_pct: default_expected_loss_pct(),
+            frame_duration_ms: default_frame_duration_ms(),
+        }
+    }
+}
+
+/// Device fingerprint sent when registering
+///
+/// Unset fields keep the built-in values.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeviceConfig {
+    /// Pick a real device profile at random on first registration; the
+    /// choice is stored with the credentials
+    #[serde(default)]
+    pub randomize: bool,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub brand: Option<String>,
+    /// Screen size as "width*height"
+    #[serde(default)]
+    pub resolution: Option<String>,
+    #[serde(default)]
+    pub dpi: Option<String>,
+    /// Android version, e.g. "14"
+    #[serde(default)]
+    pub os_version: Option<String>,
+    /// Build ID, e.g. "UP1A.231005.007"
+    #[serde(default)]
+    pub rom: Option<String>,
+}
+
+/// HTTP settings for device registration and token requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    /// Give up connecting to the server after this many seconds
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// Give up on a whole request after this many seconds
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    5
+}
+
+fn default_request_timeout_secs() -> u64 {
+    15
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: default_connect_timeout_secs(),
+            request_timeout_secs: default_request_timeout_secs(),
+        }
+    }
+}
+
+/// What the app reveals about this machine
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PrivacyConfig {
+    /// Leave the optional fields (serial numbers, MAC, advertising ID and
+    /// the like) out of the device registration request
+    #[serde(default)]
+    pub minimal_register: bool,
+}
+
+/// Feedback while recording
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UiConfig {
+    /// Beep when recording starts and stops, buzz when it fails
+    #[serde(default)]
+    pub sound_feedback: bool,
+}
+
+/// How recognized text is put into the focused window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextInsertConfig {
+    /// "sendinput" types each character, "clipboard" pastes with Ctrl+V
+    /// and restores the clipboard afterwards, "auto" pastes text of at
+    /// least `clipboard_threshold` characters, "uia" sets the focused
+    /// control's value through UI Automation, "clipboard-only" never types
+    /// and leaves each result on the clipboard, "vk" presses the keyboard
+    /// layout's keys and only takes ASCII text
+    #[serde(default = "default_text_insert_mode")]
+    pub mode: String,
+    /// In "clipboard-only" mode, add each result to the session's earlier
+    /// ones on a new line instead of replacing them
+    #[serde(default)]
+    pub clipboard_append: bool,
+    /// Only log and preview results instead of typing them, e.g. while
+    /// trying out replacement rules
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Length (characters) from which "auto" pastes
+    #[serde(default = "default_clipboard_threshold")]
+    pub clipboard_threshold: usize,
+    /// How long after pasting the previous clipboard contents are put back
+    #[serde(default = "default_restore_delay_ms")]
+    pub restore_delay_ms: u64,
+    /// Characters typed per SendInput call
+    #[serde(default = "default_chunk_chars")]
+    pub chunk_chars: usize,
+    /// Pause between those calls, so slow apps keep up
+    #[serde(default = "default_chunk_delay_ms")]
+    pub chunk_delay_ms: u64,
+    /// Longer text (in UTF-16 units) goes to the clipboard instead of
+    /// being typed; 0 for no limit
+    #[serde(default = "default_max_inject_chars")]
+    pub max_inject_chars: usize,
+    /// Copy text to the clipboard instead of typing it when no text field
+    /// has the focus, so it can't trigger shortcuts in games and the like
+    #[serde(default = "default_true")]
+    pub require_editable: bool,
+    /// Executables (e.g. "WindowsTerminal.exe") that take text although
+    /// no text field can be detected in them
+    #[serde(default = "default_editable_apps")]
+    pub editable_apps: Vec<String>,
+    /// Window text goes to: "focus-at-insert" (whatever is focused then)
+    /// or "focus-at-start" (the one focused when recording started)
+    #[serde(default = "default_target")]
+    pub target: String,
+    /// Type interim results as they arrive and correct them in place;
+    /// otherwise only final results are typed
+    #[serde(default)]
+    pub live_typing: bool,
+    /// Typed after each final result: "none", "space", "newline", or
+    /// "smart" for a space after text ending in a Latin letter or digit
+    #[serde(default = "default_trailing")]
+    pub trailing: String,
+    /// How line breaks are typed: "enter", "shift-enter" (chat apps that
+    /// send on Enter) or "literal" (a line feed character)
+    #[serde(default = "default_newline")]
+    pub newline: String,
+    /// Punctuation of typed text: "as-is", "halfwidth" (ASCII, for code
+    /// and terminals) or "fullwidth" (Chinese)
+    #[serde(default = "default_punctuation")]
+    pub punctuation: String,
+    /// Space between Chinese and English words or digits ("用 Rust 写")
+    #[serde(default)]
+    pub cjk_spacing: bool,
+    /// Drop filler words standing on their own before anything else
+    #[serde(default)]
+    pub remove_fillers: bool,
+    /// Filler words removed with `remove_fillers`
+    #[serde(default = "default_fillers")]
+    pub fillers: Vec<String>,
+    /// Write Chinese numerals as Arabic digits (一千二百 -> 1200)
+    #[serde(default)]
+    pub normalize_numbers: bool,
+    /// Phrases whose numerals stay as they are, e.g. idioms
+    #[serde(default = "default_number_exclusions")]
+    pub number_exclusions: Vec<String>,
+    /// Find/replace rules applied in order to each result before it is typed
+    #[serde(default)]
+    pub replace: Vec<ReplaceRule>,
+    /// Phrases that run an action ("enter", "tab", "delete_last" or "stop")
+    /// instead of being typed, when they are the whole utterance
+    #[serde(default = "default_commands")]
+    pub commands: BTreeMap<String, String>,
+}
+
+/// One `[[text_insert.replace]]` rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplaceRule {
+    /// Text to find, or a regular expression with `regex = true`
+    pub pattern: String,
+    /// Replacement; with `regex = true`, `$1` or `${name}` insert groups
+    #[serde(default)]
+    pub replacement: String,
+    #[serde(default)]
+    pub regex: bool,
+}
+
+fn default_text_insert_mode() -> String {
+    "sendinput".to_string()
+}
+
+fn default_editable_apps() -> Vec<String> {
+    ["WindowsTerminal.exe", "conhost.exe", "mintty.exe", "alacritty.exe", "wezterm-gui.exe"]
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+fn default_target() -> String {
+    "focus-at-insert".to_string()
+}
+
+fn default_newline() -> String {
+    "enter".to_string()
+}
+
+fn default_trailing() -> String {
+    "none".to_string()
+}
+
+fn default_commands() -> BTreeMap<String, String> {
+    [
+        ("换行", "enter"),
+        ("回车", "enter"),
+        ("缩进", "tab"),
+        ("删除上一句", "delete_last"),
+        ("停止听写", "stop"),
+    ]
+    .into_iter()
+    .map(|(phrase, action)| (phrase.to_string(), action.to_string()))
+    .collect()
+}
+
+fn default_punctuation() -> String {
+    "as-is".to_string()
+}
+
+fn default_number_exclusions() -> Vec<String> {
+    ["十万八千里", "一千零一夜", "七七八八", "三十六计"]
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+fn default_fillers() -> Vec<String> {
+    ["嗯", "呃", "额", "那个", "就是说"]
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+fn default_clipboard_threshold() -> usize {
+    200
+}
+
+fn default_restore_delay_ms() -> u64 {
+    500
+}
+
+fn default_chunk_chars() -> usize {
+    32
+}
+
+fn default_chunk_delay_ms() -> u64 {
+    5
+}
+
+fn default_max_inject_chars() -> usize {
+    1000
+}
+
+impl TextInsertConfig {
+    /// Whether results only go to the clipboard and nothing is typed
+    pub fn is_clipboard_only(&self) -> bool {
+        self.mode.eq_ignore_ascii_case("clipboard-only")
+    }
+}
+
+impl Default for TextInsertConfig {
+    fn default() -> Self {
+        Self {
+            mode: default_text_insert_mode(),
+            clipboard_append: false,
+            dry_run: false,
+            clipboard_threshold: default_clipboard_threshold(),
+            restore_delay_ms: default_restore_delay_ms(),
+            chunk_chars: default_chunk_chars(),
+            chunk_delay_ms: default_chunk_delay_ms(),
+            max_inject_chars: default_max_inject_chars(),
+            require_editable: true,
+            editable_apps: default_editable_apps(),
+            target: default_target(),
+            live_typing: false,
+            trailing: default_trailing(),
+            newline: default_newline(),
+            punctuation: default_punctuation(),
+            cjk_spacing: false,
+            remove_fillers: false,
+            fillers: default_fillers(),
+            normalize_numbers: false,
+            number_exclusions: default_number_exclusions(),
+            replace: Vec::new(),
+            commands: default_commands(),
+        }
     }
 }
@@ -1,61 +1,353 @@
 //! Credential Store
 //!
-//! Manages device credentials with optional encryption.
+//! Manages device credentials with optional encryption. Several named
+//! device identities can be kept side by side; one of them is active.
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
 
-use crate::asr::{get_asr_token, register_device, DeviceCredentials};
+use crate::asr::{
+    get_asr_token, register_device, AsrClient, AsrError, CredentialProfiles, DeviceApi,
+    DeviceCredentials,
+};
 use crate::data::AppConfig;
 
+/// Consecutive auth failures after which the device is registered again
+const MAX_AUTH_FAILURES: u32 = 2;
+
 /// Credential store for managing device credentials
 pub struct CredentialStore {
     credentials_path: PathBuf,
-    credentials: Option<DeviceCredentials>,
+    profiles: Mutex<CredentialProfiles>,
+    /// Name of the profile sessions use
+    active: Mutex<String>,
+    api: DeviceApi,
+    token_max_age: Duration,
+    /// Set when the server rejected the cached token
+    token_invalid: AtomicBool,
+    /// Sessions rejected for auth in a row
+    auth_failures: AtomicU32,
+    /// Credentials given in config; registration is skipped entirely
+    manual: Option<DeviceCredentials>,
 }
 
 impl CredentialStore {
     /// Create a new credential store
-    pub fn new(_config: &AppConfig) -> Result<Self> {
+    pub fn new(config: &AppConfig) -> Result<Self> {
         let credentials_path = AppConfig::credentials_path();
 
         // Try to load existing credentials
-        let credentials = if credentials_path.exists() {
-            DeviceCredentials::load(&credentials_path).ok()
+        let profiles = if credentials_path.exists() {
+            match CredentialProfiles::load(&credentials_path) {
+                Ok(profiles) => profiles,
+                Err(e) => {
+                    tracing::warn!("Cannot load credentials, registering again: {:#}", e);
+                    CredentialProfiles::default()
+                }
+            }
         } else {
-            None
+            CredentialProfiles::default()
         };
 
+        let manual = Self::manual_credentials(config);
+        if manual.is_some() {
+            tracing::info!("Using device_id and token from config, device registration disabled");
+        }
+
+        let active = config.asr.credential_profile.clone();
+        if profiles.get(&active).is_none() {
+            tracing::info!("Credential profile '{}' not registered yet", active);
+        }
+
         Ok(Self {
             credentials_path,
-            credentials,
+            profiles: Mutex::new(profiles),
+            active: Mutex::new(active),
+            api: DeviceApi::from_config(config)?,
+            token_max_age: Duration::from_secs(config.asr.token_max_age_hours * 3600),
+            token_invalid: AtomicBool::new(false),
+            auth_failures: AtomicU32::new(0),
+            manual,
         })
     }
 
+    /// Credentials from `asr.device_id` and `asr.token`, if both are set
+    fn manual_credentials(config: &AppConfig) -> Option<DeviceCredentials> {
+        let non_empty = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+        let device_id = non_empty(&config.asr.device_id);
+        let token = non_empty(&config.asr.token);
+        if device_id.is_some() != token.is_some() {
+            tracing::warn!("asr.device_id and asr.token must be set together, ignoring them");
+        }
+        let mut creds = DeviceCredentials::new_generated();
+        creds.device_id = device_id?;
+        creds.token = token?;
+        Some(creds)
+    }
+
+    /// Error for when the server rejects credentials from config
+    fn manual_rejected() -> anyhow::Error {
+        anyhow::anyhow!(AsrError::AuthFailed(
+            "manual credentials rejected; check asr.device_id and asr.token in config.toml"
+                .to_string()
+        ))
+    }
+
     /// Ensure we have valid credentials
+    ///
+    /// A token that is too old or was invalidated is fetched again for the
+    /// same device; if that fails the cached one is still returned.
     pub async fn ensure_credentials(&self) -> Result<DeviceCredentials> {
-        // Check if we have existing complete credentials
-        if let Some(ref creds) = self.credentials {
-            if creds.is_complete() {
-                tracing::info!("Using cached credentials");
-                return Ok(creds.clone());
+        if let Some(creds) = &self.manual {
+            if self.token_invalid.load(Ordering::SeqCst) {
+                return Err(Self::manual_rejected());
+            }
+            return Ok(creds.clone());
+        }
+
+        // A fresh token didn't help; the device itself was probably revoked
+        if self.auth_failures.load(Ordering::SeqCst) >= MAX_AUTH_FAILURES {
+            return self.reset_and_register().await;
+        }
+
+        // Keep a registered device and only fetch a new token for it
+        if let Some(creds) = self.cached().filter(|c| !c.device_id.is_empty()) {
+            let invalid = self.token_invalid.load(Ordering::SeqCst);
+            if creds.is_complete() && !invalid && !creds.is_token_stale(self.token_max_age) {
+                tracing::debug!("Using cached credentials");
+                return Ok(creds);
+            }
+
+            tracing::info!(
+                "ASR token {}, fetching a new one...",
+                if creds.token.is_empty() {
+                    "is missing"
+                } else if invalid {
+                    "was rejected"
+                } else {
+                    "is stale"
+                }
+            );
+            match self.refresh_token().await {
+                Ok(creds) => return Ok(creds),
+                Err(e) if is_unknown_device(&e) => {
+                    tracing::warn!("Device no longer recognized, registering again: {:#}", e);
+                }
+                Err(e) if creds.is_complete() => {
+                    tracing::warn!("Token refresh failed, using cached token: {:#}", e);
+                    return Ok(creds);
+                }
+                Err(e) => return Err(e),
             }
         }
 
-        // Need to register device
-        tracing::info!("Registering new device...");
+        self.register_new_device().await
+    }
+
+    /// Fetch a new ASR token for the active device, keeping its identity
+    pub async fn refresh_token(&self) -> Result<DeviceCredentials> {
+        if self.manual.is_some() {
+            return Err(Self::manual_rejected());
+        }
+        let mut creds = self
+            .cached()
+            .filter(|c| !c.device_id.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("no registered device to fetch a token for"))?;
+
+        tracing::info!("Refreshing ASR token for device_id={}", creds.device_id);
+        get_asr_token(&self.api, &mut creds).await?;
+        self.store(&creds)?;
+        Ok(creds)
+    }
+
+    /// Check the credentials against the ASR server before the first recording
+    ///
+    /// Only starts a task and disconnects. A rejected token is fetched again
+    /// so the first dictation doesn't run into it.
+    pub async fn validate(&self, config: &AppConfig) -> Result<()> {
+        let creds = self.ensure_credentials().await?;
+        match AsrClient::check_credentials(config, &creds).await {
+            Ok(()) => {
+                tracing::info!("Credentials verified");
+                self.confirm_token();
+                Ok(())
+            }
+            Err(e) if e.is_auth() => {
+                tracing::warn!("Credentials rejected at startup ({}), refreshing", e);
+                self.refresh_credentials().await?;
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Explain a failed `ensure_credentials` in terms the user can act on
+    pub fn describe_error(e: &anyhow::Error) -> String {
+        match e.downcast_ref::<AsrError>() {
+            Some(asr_error) => format!(
+                "设备注册失败: {}\n开始录音时会自动重试。\n\n{}",
+                asr_error.user_hint(),
+                asr_error
+            ),
+            None => format!("设备注册失败: {:#}\n开始录音时会自动重试。", e),
+        }
+    }
+
+    /// Mark the cached token as rejected so the next call refreshes it
+    ///
+    /// After repeated rejections the next call registers a new device.
+    pub fn invalidate_token(&self) {
+        let failures = self.auth_failures.fetch_add(1, Ordering::SeqCst) + 1;
+        tracing::info!("ASR token invalidated ({} auth failure(s) in a row)", failures);
+        self.token_invalid.store(true, Ordering::SeqCst);
+    }
+
+    /// Record that the server accepted the current credentials
+    pub fn confirm_token(&self) {
+        self.auth_failures.store(0, Ordering::SeqCst);
+        self.token_invalid.store(false, Ordering::SeqCst);
+    }
+
+    /// Names of the stored credential profiles
+    pub fn list_profiles(&self) -> Vec<String> {
+        self.profiles.lock().unwrap().names()
+    }
+
+    /// Name of the profile in use
+    pub fn active_profile(&self) -> String {
+        self.active.lock().unwrap().clone()
+    }
+
+    /// Register a new device under `name` without switching to it
+    pub async fn create_profile(&self, name: &str) -> Result<DeviceCredentials> {
+        let name = name.trim();
+        if name.is_empty() {
+            bail!("credential profile name is empty");
+        }
+        if self.manual.is_some() {
+            bail!("device registration is disabled while asr.device_id and asr.token are set");
+        }
+        if self.profiles.lock().unwrap().get(name).is_some() {
+            bail!("credential profile '{}' already exists", name);
+        }
+        self.register_profile(name).await
+    }
+
+    /// Switch sessions to the profile `name`
+    ///
+    /// A profile that doesn't exist yet is registered by the next
+    /// `ensure_credentials`.
+    pub fn use_profile(&self, name: &str) {
+        let name = name.trim().to_string();
+        tracing::info!("Using credential profile '{}'", name);
+        *self.active.lock().unwrap() = name;
+        self.token_invalid.store(false, Ordering::SeqCst);
+        self.auth_failures.store(0, Ordering::SeqCst);
+    }
+
+    /// Discard the active profile's device and register a new one
+    ///
+    /// The file as it was is kept as `credentials.json.old`. The old device
+    /// stays in use until the new one is registered, so a failed
+    /// registration leaves the profile working.
+    pub async fn reset_and_register(&self) -> Result<DeviceCredentials> {
+        if self.manual.is_some() {
+            bail!("device registration is disabled while asr.device_id and asr.token are set");
+        }
+        match self.cached() {
+            Some(old) => tracing::warn!(
+                "Discarding device_id={} install_id={} and registering a new device",
+                old.device_id,
+                old.install_id
+            ),
+            None => tracing::warn!("Registering a new device"),
+        }
+
+        if self.credentials_path.exists() {
+            let old_path = self.credentials_path.with_extension("json.old");
+            if let Err(e) = std::fs::copy(&self.credentials_path, &old_path) {
+                tracing::warn!("Cannot keep a copy of the old credentials: {}", e);
+            }
+        }
+
+        let creds = self.register_new_device().await?;
+        self.auth_failures.store(0, Ordering::SeqCst);
+        Ok(creds)
+    }
+
+    /// Refresh credentials after the ASR server rejected them
+    ///
+    /// Re-fetches the ASR token for the current device first; the device is
+    /// only registered from scratch if the server no longer knows it.
+    pub async fn refresh_credentials(&self) -> Result<DeviceCredentials> {
+        if self.manual.is_some() {
+            return Err(Self::manual_rejected());
+        }
+        if self.cached().is_some_and(|c| !c.device_id.is_empty()) {
+            match self.refresh_token().await {
+                Ok(creds) => return Ok(creds),
+                Err(e) if is_unknown_device(&e) => {
+                    tracing::warn!("Device no longer recognized, registering new device: {:#}", e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.register_new_device().await
+    }
+
+    /// Register a new device for the active profile
+    async fn register_new_device(&self) -> Result<DeviceCredentials> {
+        self.register_profile(&self.active_profile()).await
+    }
+
+    /// Register a new device and fetch its ASR token
+    async fn register_profile(&self, name: &str) -> Result<DeviceCredentials> {
+        tracing::info!("Registering new device for profile '{}'...", name);
         let mut creds = DeviceCredentials::new_generated();
 
         // Register device to get device_id
-        register_device(&mut creds).await?;
+        register_device(&self.api, &mut creds).await?;
 
         // Get ASR token
-        get_asr_token(&mut creds).await?;
-
-        // Save credentials
-        creds.save(&self.credentials_path)?;
-        tracing::info!("Credentials saved to {:?}", self.credentials_path);
+        get_asr_token(&self.api, &mut creds).await?;
 
+        self.store_profile(name, &creds)?;
         Ok(creds)
     }
+
+    /// Save credentials of the active profile
+    fn store(&self, creds: &DeviceCredentials) -> Result<()> {
+        self.store_profile(&self.active_profile(), creds)
+    }
+
+    /// Save credentials of profile `name` to disk and update the in-memory copy
+    fn store_profile(&self, name: &str, creds: &DeviceCredentials) -> Result<()> {
+        let mut profiles = self.profiles.lock().unwrap();
+        profiles.insert(name, creds.clone());
+        profiles.save(&self.credentials_path)?;
+        tracing::info!("Credentials for profile '{}' saved to {:?}", name, self.credentials_path);
+        if name == self.active_profile() {
+            self.token_invalid.store(false, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+
+    fn cached(&self) -> Option<DeviceCredentials> {
+        self.profiles.lock().unwrap().get(&self.active_profile()).cloned()
+    }
+}
+
+/// Whether the settings endpoint refused to issue a token for the device
+fn is_unknown_device(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<AsrError>().is_some_and(AsrError::is_auth)
 }
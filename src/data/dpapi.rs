@@ -0,0 +1,82 @@
+//! Data Protection
+//!
+//! Encrypts secrets at rest with Windows DPAPI, keyed to the current user.
+//! Other platforms have no equivalent here and store data as-is.
+
+use anyhow::Result;
+
+/// Encrypt `data` for the current user
+#[cfg(target_os = "windows")]
+pub(crate) fn protect(data: &[u8]) -> Result<Vec<u8>> {
+    use windows::core::w;
+    use windows::Win32::Security::Cryptography::{
+        CryptProtectData, CRYPTPROTECT_UI_FORBIDDEN, CRYPT_INTEGER_BLOB,
+    };
+
+    let input = CRYPT_INTEGER_BLOB {
+        cbData: data.len() as u32,
+        pbData: data.as_ptr() as *mut u8,
+    };
+    let mut output = CRYPT_INTEGER_BLOB::default();
+    unsafe {
+        CryptProtectData(
+            &input,
+            w!("Doubao Voice Input credentials"),
+            None,
+            None,
+            None,
+            CRYPTPROTECT_UI_FORBIDDEN,
+            &mut output,
+        )?;
+        Ok(take_blob(output))
+    }
+}
+
+/// Decrypt data produced by `protect` for the current user
+#[cfg(target_os = "windows")]
+pub(crate) fn unprotect(data: &[u8]) -> Result<Vec<u8>> {
+    use windows::Win32::Security::Cryptography::{
+        CryptUnprotectData, CRYPTPROTECT_UI_FORBIDDEN, CRYPT_INTEGER_BLOB,
+    };
+
+    let input = CRYPT_INTEGER_BLOB {
+        cbData: data.len() as u32,
+        pbData: data.as_ptr() as *mut u8,
+    };
+    let mut output = CRYPT_INTEGER_BLOB::default();
+    unsafe {
+        CryptUnprotectData(
+            &input,
+            None,
+            None,
+            None,
+            None,
+            CRYPTPROTECT_UI_FORBIDDEN,
+            &mut output,
+        )?;
+        Ok(take_blob(output))
+    }
+}
+
+/// Copy a DPAPI output blob and release its buffer
+#[cfg(target_os = "windows")]
+unsafe fn take_blob(blob: windows::Win32::Security::Cryptography::CRYPT_INTEGER_BLOB) -> Vec<u8> {
+    use windows::Win32::Foundation::{LocalFree, HLOCAL};
+
+    let bytes = std::slice::from_raw_parts(blob.pbData, blob.cbData as usize).to_vec();
+    let _ = LocalFree(HLOCAL(blob.pbData as *mut _));
+    bytes
+}
+
+/// Store data unencrypted; DPAPI is only available on Windows
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn protect(data: &[u8]) -> Result<Vec<u8>> {
+    tracing::warn!("Credential encryption is only supported on Windows, saving in plaintext");
+    Ok(data.to_vec())
+}
+
+/// Counterpart of the plaintext `protect`
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn unprotect(data: &[u8]) -> Result<Vec<u8>> {
+    Ok(data.to_vec())
+}
@@ -0,0 +1,167 @@
+//! Dictation History
+//!
+//! Keeps recognized results in memory and appends them to a JSONL file, so
+//! text typed into the wrong place can be found again. A refinement is
+//! written as another line with the same time as the result it corrects;
+//! loading keeps the last line for each time.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::data::atomic_file;
+
+/// The file is rewritten with only the kept entries once it grows past this
+const MAX_FILE_BYTES: u64 = 256 * 1024;
+
+/// One recognized result
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Milliseconds since the Unix epoch, unique within the history
+    pub time: u64,
+    pub text: String,
+    /// Process the text was meant for, e.g. "notepad.exe"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+}
+
+/// The newest results, mirrored to a size-capped file
+pub struct History {
+    path: PathBuf,
+    capacity: usize,
+    entries: Mutex<VecDeque<HistoryEntry>>,
+}
+
+impl History {
+    /// Open the history at `path`, keeping up to `capacity` entries
+    ///
+    /// A missing or unreadable file starts an empty history; lines that
+    /// don't parse are skipped.
+    pub fn open(path: PathBuf, capacity: usize) -> Self {
+        let mut entries = VecDeque::new();
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                let mut skipped = 0;
+                for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+                    let Ok(entry) = serde_json::from_str::<HistoryEntry>(line) else {
+                        skipped += 1;
+                        continue;
+                    };
+                    // A refinement replaces the line it follows
+                    if entries.back().is_some_and(|last: &HistoryEntry| last.time == entry.time) {
+                        entries.pop_back();
+                    }
+                    entries.push_back(entry);
+                    if entries.len() > capacity {
+                        entries.pop_front();
+                    }
+                }
+                if skipped > 0 {
+                    tracing::warn!("Skipped {} unreadable lines in {:?}", skipped, path);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => tracing::warn!("Cannot read history {:?}: {}", path, e),
+        }
+        Self {
+            path,
+            capacity,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    /// Add a result
+    pub fn record(&self, text: &str, app: Option<String>) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        // Results within the same millisecond would read as refinements
+        let time = match entries.back() {
+            Some(last) => now_millis().max(last.time + 1),
+            None => now_millis(),
+        };
+        let entry = HistoryEntry {
+            time,
+            text: text.to_string(),
+            app,
+        };
+        entries.push_back(entry.clone());
+        if entries.len() > self.capacity {
+            entries.pop_front();
+        }
+        self.append(&entry, &entries)
+    }
+
+    /// Replace the newest result with its refinement
+    pub fn amend_last(&self, text: &str) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        let Some(last) = entries.back_mut() else {
+            return Ok(());
+        };
+        if last.text == text {
+            return Ok(());
+        }
+        last.text = text.to_string();
+        let entry = last.clone();
+        self.append(&entry, &entries)
+    }
+
+    /// Up to `count` entries, newest first
+    pub fn recent(&self, count: usize) -> Vec<HistoryEntry> {
+        let entries = self.entries.lock().unwrap();
+        entries.iter().rev().take(count).cloned().collect()
+    }
+
+    /// The entry `index` places back from the newest
+    pub fn get(&self, index: usize) -> Option<HistoryEntry> {
+        let entries = self.entries.lock().unwrap();
+        entries.iter().rev().nth(index).cloned()
+    }
+
+    /// Forget every entry and delete the file
+    pub fn clear(&self) -> Result<()> {
+        self.entries.lock().unwrap().clear();
+        match fs::remove_file(&self.path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).with_context(|| format!("cannot delete {:?}", self.path)),
+        }
+    }
+
+    /// Write `entry` to the end of the file, or the whole of `entries` when
+    /// the file got too big
+    fn append(&self, entry: &HistoryEntry, entries: &VecDeque<HistoryEntry>) -> Result<()> {
+        let size = fs::metadata(&self.path).map(|meta| meta.len()).unwrap_or(0);
+        if size > MAX_FILE_BYTES {
+            let mut contents = String::new();
+            for entry in entries {
+                contents.push_str(&serde_json::to_string(entry)?);
+                contents.push('\n');
+            }
+            return atomic_file::write(&self.path, contents.as_bytes());
+        }
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("cannot open {:?}", self.path))?;
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -1,7 +1,15 @@
 //! Data module for configuration and credential management
 
+pub(crate) mod atomic_file;
 mod config;
 mod credential;
+pub(crate) mod dpapi;
+mod history;
 
-pub use config::{AppConfig, GeneralConfig, HotkeyConfig, FloatingButtonConfig, AsrConfig};
+pub use config::{
+    AppConfig, GeneralConfig, HotkeyConfig, FloatingButtonConfig, AsrConfig, AudioConfig,
+    DeviceConfig, EncoderConfig,
+    NetworkConfig, PrivacyConfig, ReplaceRule, TextInsertConfig, UiConfig,
+};
 pub use credential::CredentialStore;
+pub use history::{History, HistoryEntry};
@@ -3,11 +3,14 @@
 //! Supports two modes:
 //! - CLI mode: For quick testing (run with --cli flag)
 //! - UI mode: Full application with system tray and hotkeys (default)
+//!
+//! `--portable` keeps config and credentials beside the executable instead
+//! of under %APPDATA%.
 
 // Hide console window in release builds on Windows
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use std::env;
 use std::io::{self, Write};
 use std::sync::Arc;
@@ -15,6 +18,8 @@ use tokio::sync::Mutex;
 use tracing::{error, info, warn};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+use doubao_voice_input::asr::DeviceCredentials;
+use doubao_voice_input::audio::OpusEncoder;
 use doubao_voice_input::{
     AppConfig, AsrClient, AudioCapture, CredentialStore, HotkeyManager, TextInserter,
     VoiceController,
@@ -25,11 +30,20 @@ async fn main() -> Result<()> {
     // Check for CLI mode
     let args: Vec<String> = env::args().collect();
     let cli_mode = args.iter().any(|a| a == "--cli" || a == "-c");
+    if args.iter().any(|a| a == "--portable") {
+        AppConfig::set_portable(true);
+    }
 
     if cli_mode {
         run_cli_mode().await
     } else {
-        run_ui_mode().await
+        let result = run_ui_mode().await;
+        if let Err(e) = &result {
+            // Without a console this is the only sign the app didn't start
+            doubao_voice_input::ui::alert("豆包语音输入", &format!("启动失败: {:#}", e));
+        }
+        doubao_voice_input::ui::close_notices();
+        result
     }
 }
 
@@ -50,23 +64,35 @@ async fn run_ui_mode() -> Result<()> {
 
     // Load configuration
     let config = AppConfig::load_or_default()?;
-    info!("Configuration loaded");
-
-    // Initialize credentials
-    let credential_store = CredentialStore::new(&config)?;
-    let credentials = credential_store.ensure_credentials().await?;
-    info!("Device registered: {}", &credentials.device_id[..8.min(credentials.device_id.len())]);
+    info!("Configuration loaded from {:?}", AppConfig::config_path());
+    check_encoder(&config)?;
+
+    // Initialize credentials; without a network the tray still starts and
+    // registration is retried when recording begins
+    let credential_store = Arc::new(CredentialStore::new(&config)?);
+    let credentials = match credential_store.ensure_credentials().await {
+        Ok(credentials) => {
+            info!("Device registered: {}", &credentials.device_id[..8.min(credentials.device_id.len())]);
+            credentials
+        }
+        Err(e) => {
+            error!("Device registration failed: {:#}", e);
+            doubao_voice_input::ui::notify("豆包语音输入", &CredentialStore::describe_error(&e));
+            DeviceCredentials::new_generated()
+        }
+    };
 
     // Initialize components
     let audio_capture = Arc::new(AudioCapture::new()?);
-    let text_inserter = Arc::new(TextInserter::new());
-    let asr_client = Arc::new(AsrClient::new(credentials));
+    let text_inserter = Arc::new(TextInserter::with_config(&config.text_insert));
+    let asr_client = Arc::new(
+        AsrClient::new(credentials, &config).with_credential_store(credential_store.clone()),
+    );
 
-    let voice_controller = Arc::new(Mutex::new(VoiceController::new(
-        asr_client,
-        audio_capture,
-        text_inserter,
-    )));
+    let voice_controller = Arc::new(Mutex::new(
+        VoiceController::new(asr_client, audio_capture, text_inserter, &config)
+            .with_credential_store(credential_store),
+    ));
 
     // Initialize hotkey manager
     let hotkey_manager = HotkeyManager::new(&config.hotkey)?;
@@ -94,12 +120,13 @@ async fn run_cli_mode() -> Result<()> {
     // Step 1: Load configuration
     println!("[1/5] 加载配置...");
     let config = AppConfig::load_or_default()?;
+    check_encoder(&config)?;
     info!("Configuration loaded");
     println!("      ✅ 配置加载成功");
 
     // Step 2: Initialize credential store and register device
     println!("[2/5] 初始化设备凭据...");
-    let credential_store = CredentialStore::new(&config)?;
+    let credential_store = Arc::new(CredentialStore::new(&config)?);
 
     println!("      正在注册设备或加载缓存凭据...");
     let credentials = credential_store.ensure_credentials().await?;
@@ -128,14 +155,20 @@ async fn run_cli_mode() -> Result<()> {
 
     // Step 4: Initialize components
     println!("[4/5] 初始化组件...");
-    let text_inserter = Arc::new(TextInserter::new());
-    let asr_client = Arc::new(AsrClient::new(credentials.clone()));
-
-    let voice_controller = Arc::new(Mutex::new(VoiceController::new(
-        asr_client.clone(),
-        audio_capture.clone(),
-        text_inserter.clone(),
-    )));
+    let text_inserter = Arc::new(TextInserter::with_config(&config.text_insert));
+    let asr_client = Arc::new(
+        AsrClient::new(credentials.clone(), &config).with_credential_store(credential_store.clone()),
+    );
+
+    let voice_controller = Arc::new(Mutex::new(
+        VoiceController::new(
+            asr_client.clone(),
+            audio_capture.clone(),
+            text_inserter.clone(),
+            &config,
+        )
+        .with_credential_store(credential_store.clone()),
+    ));
     println!("      ✅ ASR 客户端、文本插入器已就绪");
 
     // Step 5: Ready for testing
@@ -268,3 +301,10 @@ fn init_logging(debug: bool) {
         .with(tracing_subscriber::fmt::layer())
         .init();
 }
+
+/// Reject bad `[encoder]` settings now rather than at the first recording
+fn check_encoder(config: &AppConfig) -> Result<()> {
+    OpusEncoder::with_config(16000, 1, &config.encoder)
+        .map(|_| ())
+        .context("Invalid [encoder] settings in config.toml")
+}
@@ -3,7 +3,7 @@
 //! A floating button that shows the voice input status and allows user to trigger recording.
 //! Uses Win32 API with timer-based drag tracking for smooth operation.
 
-use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU8, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU8, Ordering};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::Arc;
 
@@ -17,6 +17,10 @@ pub enum ButtonState {
     Recording = 1,
     /// Processing (waiting for ASR result) (blue)
     Processing = 2,
+    /// Microphone lost mid-recording (flashes)
+    Error = 3,
+    /// Recording paused, silence is sent (grey)
+    Paused = 4,
 }
 
 impl From<u8> for ButtonState {
@@ -24,6 +28,8 @@ impl From<u8> for ButtonState {
         match v {
             1 => ButtonState::Recording,
             2 => ButtonState::Processing,
+            3 => ButtonState::Error,
+            4 => ButtonState::Paused,
             _ => ButtonState::Idle,
         }
     }
@@ -34,6 +40,8 @@ impl From<u8> for ButtonState {
 pub enum FloatingButtonEvent {
     /// User clicked the button to toggle recording
     ToggleRecording,
+    /// User middle-clicked the button to pause or resume recording
+    TogglePause,
     /// User requested to exit
     Exit,
 }
@@ -60,6 +68,8 @@ impl Default for FloatingButtonConfig {
 #[derive(Clone)]
 pub struct FloatingButtonStateSetter {
     state: Arc<AtomicU8>,
+    level: Arc<AtomicU32>,
+    marker: Arc<AtomicBool>,
     hwnd: Arc<AtomicI32>,
 }
 
@@ -83,6 +93,47 @@ impl FloatingButtonStateSetter {
         tracing::debug!("Floating button state: {:?}", state);
     }
 
+    /// Set the input level (0.0 - 1.0) drawn as a ring while recording
+    pub fn set_level(&self, level: f32) {
+        let level = level.clamp(0.0, 1.0);
+        let previous = f32::from_bits(self.level.swap(level.to_bits(), Ordering::SeqCst));
+        // Skip repaints the ring wouldn't visibly change for
+        let visible = (level - previous).abs() >= 0.03 && self.get_state() == ButtonState::Recording;
+        #[cfg(target_os = "windows")]
+        if visible {
+            let hwnd_val = self.hwnd.load(Ordering::SeqCst);
+            if hwnd_val != 0 {
+                unsafe {
+                    use windows::Win32::Foundation::*;
+                    use windows::Win32::Graphics::Gdi::InvalidateRect;
+                    let hwnd = HWND(hwnd_val as isize);
+                    let _ = InvalidateRect(hwnd, None, FALSE);
+                }
+            }
+        }
+        #[cfg(not(target_os = "windows"))]
+        let _ = visible;
+    }
+
+    /// Show or hide a small dot on the button, e.g. while nothing is typed
+    pub fn set_marker(&self, shown: bool) {
+        let changed = self.marker.swap(shown, Ordering::SeqCst) != shown;
+        #[cfg(target_os = "windows")]
+        if changed {
+            let hwnd_val = self.hwnd.load(Ordering::SeqCst);
+            if hwnd_val != 0 {
+                unsafe {
+                    use windows::Win32::Foundation::*;
+                    use windows::Win32::Graphics::Gdi::InvalidateRect;
+                    let hwnd = HWND(hwnd_val as isize);
+                    let _ = InvalidateRect(hwnd, None, FALSE);
+                }
+            }
+        }
+        #[cfg(not(target_os = "windows"))]
+        let _ = changed;
+    }
+
     /// Get the current state
     pub fn get_state(&self) -> ButtonState {
         self.state.load(Ordering::SeqCst).into()
@@ -92,6 +143,8 @@ impl FloatingButtonStateSetter {
 /// Floating button manager
 pub struct FloatingButton {
     state: Arc<AtomicU8>,
+    level: Arc<AtomicU32>,
+    marker: Arc<AtomicBool>,
     hwnd: Arc<AtomicI32>,
     event_tx: Sender<FloatingButtonEvent>,
     event_rx: Option<Receiver<FloatingButtonEvent>>,
@@ -103,6 +156,8 @@ impl FloatingButton {
         let (event_tx, event_rx) = channel();
         Self {
             state: Arc::new(AtomicU8::new(ButtonState::Idle as u8)),
+            level: Arc::new(AtomicU32::new(0)),
+            marker: Arc::new(AtomicBool::new(false)),
             hwnd: Arc::new(AtomicI32::new(0)),
             event_tx,
             event_rx: Some(event_rx),
@@ -113,6 +168,8 @@ impl FloatingButton {
     pub fn state_setter(&self) -> FloatingButtonStateSetter {
         FloatingButtonStateSetter {
             state: self.state.clone(),
+            level: self.level.clone(),
+            marker: self.marker.clone(),
             hwnd: self.hwnd.clone(),
         }
     }
@@ -140,10 +197,13 @@ impl FloatingButton {
         static START_CURSOR_Y: AtomicI32 = AtomicI32::new(0);
         static START_WIN_X: AtomicI32 = AtomicI32::new(0);
         static START_WIN_Y: AtomicI32 = AtomicI32::new(0);
+        static FLASH_ON: AtomicBool = AtomicBool::new(false);
 
         // Store shared state in thread-local for wndproc access
         thread_local! {
             static SHARED_STATE: std::cell::RefCell<Option<Arc<AtomicU8>>> = const { std::cell::RefCell::new(None) };
+            static SHARED_LEVEL: std::cell::RefCell<Option<Arc<AtomicU32>>> = const { std::cell::RefCell::new(None) };
+            static SHARED_MARKER: std::cell::RefCell<Option<Arc<AtomicBool>>> = const { std::cell::RefCell::new(None) };
             static EVENT_SENDER: std::cell::RefCell<Option<Sender<FloatingButtonEvent>>> = const { std::cell::RefCell::new(None) };
         }
 
@@ -153,17 +213,19 @@ impl FloatingButton {
         let window_size = config.size;
 
         SHARED_STATE.with(|s| *s.borrow_mut() = Some(state));
+        SHARED_LEVEL.with(|s| *s.borrow_mut() = Some(self.level.clone()));
+        SHARED_MARKER.with(|s| *s.borrow_mut() = Some(self.marker.clone()));
         EVENT_SENDER.with(|s| *s.borrow_mut() = Some(event_tx));
 
         // Helper function to update layered window with PNG icon
-        unsafe fn update_layered_icon(hwnd: HWND, state_val: u8) {
+        unsafe fn update_layered_icon(hwnd: HWND, state_val: u8, level: f32) {
             use windows::Win32::Foundation::*;
             use windows::Win32::Graphics::Gdi::*;
             use windows::Win32::UI::WindowsAndMessaging::*;
 
             // Load embedded PNG icon based on state
             let icon_data: &[u8] = match state_val {
-                1 => include_bytes!("../../assets/icon_recording.png"),
+                1 | 4 => include_bytes!("../../assets/icon_recording.png"),
                 2 => include_bytes!("../../assets/icon_processing.png"),
                 _ => include_bytes!("../../assets/icon_idle.png"),
             };
@@ -210,11 +272,45 @@ impl FloatingButton {
                         // Copy pixels with premultiplied alpha (required for UpdateLayeredWindow)
                         let pixel_data = bits as *mut u8;
                         let mut idx = 0usize;
-                        for pixel in rgba.pixels() {
-                            let r = pixel[0] as u32;
-                            let g = pixel[1] as u32;
-                            let b = pixel[2] as u32;
-                            let a = pixel[3] as u32;
+                        let center_x = img_w as f32 / 2.0;
+                        let center_y = img_h as f32 / 2.0;
+                        let ring_outer = center_x.min(center_y);
+                        let ring_inner = ring_outer - (img_w as f32 / 14.0).max(2.0);
+                        let marker = SHARED_MARKER.with(|s| {
+                            s.borrow().as_ref().is_some_and(|marker| marker.load(Ordering::SeqCst))
+                        });
+                        let marker_radius = (img_w as f32 / 10.0).max(3.0);
+                        let marker_x = img_w as f32 - marker_radius * 1.5;
+                        let marker_y = img_h as f32 - marker_radius * 1.5;
+                        for (x, y, pixel) in rgba.enumerate_pixels() {
+                            let (mut r, mut g, mut b, mut a) =
+                                (pixel[0] as u32, pixel[1] as u32, pixel[2] as u32, pixel[3] as u32);
+
+                            // Input level as a green arc clockwise from the top
+                            if level > 0.0 {
+                                let dx = x as f32 + 0.5 - center_x;
+                                let dy = y as f32 + 0.5 - center_y;
+                                let distance = (dx * dx + dy * dy).sqrt();
+                                let turn = (dx.atan2(-dy) / std::f32::consts::TAU).rem_euclid(1.0);
+                                if distance >= ring_inner && distance <= ring_outer && turn <= level {
+                                    (r, g, b, a) = (0x4C, 0xD9, 0x64, 255);
+                                }
+                            }
+
+                            // Paused: the recording icon in grey
+                            if state_val == ButtonState::Paused as u8 {
+                                let luma = (r * 30 + g * 59 + b * 11) / 100;
+                                (r, g, b) = (luma, luma, luma);
+                            }
+
+                            // Marker: an amber dot at the bottom right
+                            if marker {
+                                let dx = x as f32 + 0.5 - marker_x;
+                                let dy = y as f32 + 0.5 - marker_y;
+                                if dx * dx + dy * dy <= marker_radius * marker_radius {
+                                    (r, g, b, a) = (0xFF, 0xB3, 0x00, 255);
+                                }
+                            }
 
                             // Premultiply alpha
                             let pr = ((r * a) / 255) as u8;
@@ -224,7 +320,7 @@ impl FloatingButton {
                             *pixel_data.add(idx) = pb;     // B
                             *pixel_data.add(idx + 1) = pg; // G
                             *pixel_data.add(idx + 2) = pr; // R
-                            *pixel_data.add(idx + 3) = pixel[3]; // A
+                            *pixel_data.add(idx + 3) = a as u8; // A
                             idx += 4;
                         }
 
@@ -275,12 +371,14 @@ impl FloatingButton {
             const WM_LBUTTONDOWN: u32 = 0x0201;
             const WM_LBUTTONUP: u32 = 0x0202;
             const WM_RBUTTONUP: u32 = 0x0205;
+            const WM_MBUTTONUP: u32 = 0x0208;
             const DRAG_TIMER_ID: usize = 1;
+            const FLASH_TIMER_ID: usize = 2;
 
             match msg {
                 WM_CREATE => {
                     // Use UpdateLayeredWindow for per-pixel alpha, initial update
-                    update_layered_icon(hwnd, 0);
+                    update_layered_icon(hwnd, 0, 0.0);
                     LRESULT(0)
                 }
                 WM_PAINT => {
@@ -290,7 +388,25 @@ impl FloatingButton {
                     let state_val = SHARED_STATE.with(|s| {
                         s.borrow().as_ref().map(|st| st.load(Ordering::SeqCst)).unwrap_or(0)
                     });
-                    update_layered_icon(hwnd, state_val);
+                    if state_val == ButtonState::Error as u8 {
+                        // Alternate between the recording and idle icons
+                        let _ = SetTimer(hwnd, FLASH_TIMER_ID, 400, None);
+                        let lit = FLASH_ON.load(Ordering::SeqCst);
+                        update_layered_icon(hwnd, if lit { 1 } else { 0 }, 0.0);
+                    } else {
+                        let _ = KillTimer(hwnd, FLASH_TIMER_ID);
+                        let level = if state_val == ButtonState::Recording as u8 {
+                            SHARED_LEVEL.with(|s| {
+                                s.borrow()
+                                    .as_ref()
+                                    .map(|level| f32::from_bits(level.load(Ordering::SeqCst)))
+                                    .unwrap_or(0.0)
+                            })
+                        } else {
+                            0.0
+                        };
+                        update_layered_icon(hwnd, state_val, level);
+                    }
                     EndPaint(hwnd, &ps);
                     LRESULT(0)
                 }
@@ -310,6 +426,11 @@ impl FloatingButton {
                     let _ = SetTimer(hwnd, DRAG_TIMER_ID, 16, None);
                     LRESULT(0)
                 }
+                WM_TIMER if wparam.0 == FLASH_TIMER_ID => {
+                    let lit = !FLASH_ON.fetch_xor(true, Ordering::SeqCst);
+                    update_layered_icon(hwnd, if lit { 1 } else { 0 }, 0.0);
+                    LRESULT(0)
+                }
                 WM_TIMER => {
                     if wparam.0 == DRAG_TIMER_ID && MOUSE_DOWN.load(Ordering::SeqCst) {
                         let key_state = GetAsyncKeyState(0x01);
@@ -361,6 +482,14 @@ impl FloatingButton {
                     }
                     LRESULT(0)
                 }
+                WM_MBUTTONUP => {
+                    EVENT_SENDER.with(|s| {
+                        if let Some(ref tx) = *s.borrow() {
+                            let _ = tx.send(FloatingButtonEvent::TogglePause);
+                        }
+                    });
+                    LRESULT(0)
+                }
                 WM_RBUTTONUP => {
                     // Right-click to show exit confirmation
                     use windows::core::w;
@@ -383,6 +512,7 @@ impl FloatingButton {
                 }
                 WM_DESTROY => {
                     let _ = KillTimer(hwnd, DRAG_TIMER_ID);
+                    let _ = KillTimer(hwnd, FLASH_TIMER_ID);
                     PostQuitMessage(0);
                     LRESULT(0)
                 }
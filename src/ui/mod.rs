@@ -3,10 +3,12 @@
 //! Handles system tray and floating button UI.
 
 mod floating_button;
+mod notification;
 mod system_tray;
 
 pub use floating_button::{
     ButtonState, FloatingButton, FloatingButtonConfig, FloatingButtonEvent,
     FloatingButtonStateSetter,
 };
+pub use notification::{alert, close_notices, confirm, notify};
 pub use system_tray::run_app;
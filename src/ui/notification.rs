@@ -0,0 +1,247 @@
+//! Notifications
+//!
+//! Short user-facing messages shown outside the tray menu.
+
+/// How long the notification icon stays after the last notification
+#[cfg(target_os = "windows")]
+const NOTICE_LIFETIME: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Requests for the notification thread
+#[cfg(target_os = "windows")]
+enum Notice {
+    Show(String, String),
+    /// Remove the icon and quit, then answer on the channel
+    Close(std::sync::mpsc::Sender<()>),
+}
+
+/// Notifications waiting for the notification thread
+#[cfg(target_os = "windows")]
+static NOTICES: std::sync::OnceLock<std::sync::mpsc::Sender<Notice>> = std::sync::OnceLock::new();
+
+/// Show a non-blocking notification to the user
+///
+/// On Windows this is a notification-area balloon (a toast on Windows 10
+/// and later), which never takes the focus from the window being typed
+/// into; elsewhere the message is printed to the console.
+pub fn notify(title: &str, message: &str) {
+    tracing::info!("Notification: {} - {}", title, message);
+    // Tests only log them; the notification area belongs to the running app
+    if cfg!(test) {
+        return;
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        let notices = NOTICES.get_or_init(|| {
+            let (tx, rx) = std::sync::mpsc::channel();
+            std::thread::spawn(move || show_notices(rx));
+            tx
+        });
+        let _ = notices.send(Notice::Show(title.to_string(), message.to_string()));
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    println!("🔔 [{}] {}", title, message);
+}
+
+/// Remove the notification icon, waiting until it is gone
+///
+/// Call once when the app quits; notifications after this are dropped.
+pub fn close_notices() {
+    #[cfg(target_os = "windows")]
+    if let Some(notices) = NOTICES.get() {
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        if notices.send(Notice::Close(done_tx)).is_ok() {
+            let _ = done_rx.recv_timeout(std::time::Duration::from_secs(2));
+        }
+    }
+}
+
+/// Show each notice as a balloon from an icon of our own, removed again
+/// once no notice came for `NOTICE_LIFETIME`
+#[cfg(target_os = "windows")]
+fn show_notices(notices: std::sync::mpsc::Receiver<Notice>) {
+    use std::sync::mpsc::RecvTimeoutError;
+    use windows::core::w;
+    use windows::Win32::UI::Shell::{
+        Shell_NotifyIconW, NIF_ICON, NIF_INFO, NIF_TIP, NIIF_INFO, NIM_ADD, NIM_DELETE,
+        NIM_MODIFY, NOTIFYICONDATAW,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DestroyIcon, DestroyWindow, HWND_MESSAGE, WINDOW_EX_STYLE, WINDOW_STYLE,
+    };
+
+    // A message-only window identifies the icon; it gets no broadcasts,
+    // so it needs no message loop
+    let window = unsafe {
+        CreateWindowExW(
+            WINDOW_EX_STYLE(0),
+            w!("STATIC"),
+            w!("doubao-ime-win notifications"),
+            WINDOW_STYLE(0),
+            0,
+            0,
+            0,
+            0,
+            HWND_MESSAGE,
+            None,
+            None,
+            None,
+        )
+    };
+    let mut data = NOTIFYICONDATAW {
+        cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+        hWnd: window,
+        uID: 1,
+        uFlags: NIF_ICON | NIF_TIP | NIF_INFO,
+        dwInfoFlags: NIIF_INFO,
+        ..Default::default()
+    };
+    match balloon_icon() {
+        Ok(icon) => data.hIcon = icon,
+        Err(e) => tracing::warn!("Cannot create the notification icon: {}", e),
+    }
+    copy_wide(&mut data.szTip, "豆包语音输入");
+
+    let mut shown = false;
+    let mut closed = None;
+    loop {
+        let next = if shown {
+            notices.recv_timeout(NOTICE_LIFETIME)
+        } else {
+            notices.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        };
+        let (title, message) = match next {
+            Ok(Notice::Show(title, message)) => (title, message),
+            Ok(Notice::Close(done)) => {
+                closed = Some(done);
+                break;
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                unsafe {
+                    let _ = Shell_NotifyIconW(NIM_DELETE, &data);
+                }
+                shown = false;
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        copy_wide(&mut data.szInfoTitle, &title);
+        copy_wide(&mut data.szInfo, &message);
+        let action = if shown { NIM_MODIFY } else { NIM_ADD };
+        if unsafe { Shell_NotifyIconW(action, &data) }.as_bool() {
+            shown = true;
+        } else {
+            tracing::warn!("Cannot show notification: {} - {}", title, message);
+        }
+    }
+
+    unsafe {
+        if shown {
+            let _ = Shell_NotifyIconW(NIM_DELETE, &data);
+        }
+        if !data.hIcon.is_invalid() {
+            let _ = DestroyIcon(data.hIcon);
+        }
+        let _ = DestroyWindow(window);
+    }
+    if let Some(done) = closed {
+        let _ = done.send(());
+    }
+}
+
+/// The tray icon's picture as an icon handle
+#[cfg(target_os = "windows")]
+fn balloon_icon() -> windows::core::Result<windows::Win32::UI::WindowsAndMessaging::HICON> {
+    use windows::Win32::UI::WindowsAndMessaging::CreateIcon;
+
+    let (rgba, width, height) = super::system_tray::icon_rgba();
+    let bgra: Vec<u8> = rgba
+        .chunks_exact(4)
+        .flat_map(|pixel| [pixel[2], pixel[1], pixel[0], pixel[3]])
+        .collect();
+    // Transparency comes from the alpha channel; the mask is all zero
+    let mask = vec![0u8; (width * height / 8) as usize];
+    unsafe {
+        CreateIcon(
+            None,
+            width as i32,
+            height as i32,
+            1,
+            32,
+            mask.as_ptr(),
+            bgra.as_ptr(),
+        )
+    }
+}
+
+/// Copy `text` into a fixed-size UTF-16 field, cut short to fit
+#[cfg(target_os = "windows")]
+fn copy_wide(field: &mut [u16], text: &str) {
+    let wide: Vec<u16> = text.encode_utf16().take(field.len() - 1).collect();
+    field[..wide.len()].copy_from_slice(&wide);
+    field[wide.len()] = 0;
+}
+
+/// Tell the user about an error that stops the app, waiting until they
+/// close the message
+///
+/// Release builds have no console, so this is the only trace of a failed
+/// start. Elsewhere than Windows the message is printed.
+pub fn alert(title: &str, message: &str) {
+    tracing::error!("Alert: {} - {}", title, message);
+
+    #[cfg(target_os = "windows")]
+    {
+        use windows::core::PCWSTR;
+        use windows::Win32::UI::WindowsAndMessaging::{
+            MessageBoxW, MB_ICONERROR, MB_OK, MB_TOPMOST,
+        };
+        let title: Vec<u16> = title.encode_utf16().chain(std::iter::once(0)).collect();
+        let message: Vec<u16> = message.encode_utf16().chain(std::iter::once(0)).collect();
+        unsafe {
+            MessageBoxW(
+                None,
+                PCWSTR(message.as_ptr()),
+                PCWSTR(title.as_ptr()),
+                MB_OK | MB_ICONERROR | MB_TOPMOST,
+            );
+        }
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    eprintln!("❌ [{}] {}", title, message);
+}
+
+/// Ask the user an OK/Cancel question and wait for the answer
+///
+/// Blocks until the message box is closed, so call it off the UI and async
+/// threads. Elsewhere than Windows the message is printed and accepted.
+pub fn confirm(title: &str, message: &str) -> bool {
+    tracing::info!("Confirmation: {} - {}", title, message);
+
+    #[cfg(target_os = "windows")]
+    {
+        use windows::core::PCWSTR;
+        use windows::Win32::UI::WindowsAndMessaging::{
+            MessageBoxW, IDOK, MB_ICONQUESTION, MB_OKCANCEL, MB_TOPMOST,
+        };
+        let title: Vec<u16> = title.encode_utf16().chain(std::iter::once(0)).collect();
+        let message: Vec<u16> = message.encode_utf16().chain(std::iter::once(0)).collect();
+        let answer = unsafe {
+            MessageBoxW(
+                None,
+                PCWSTR(message.as_ptr()),
+                PCWSTR(title.as_ptr()),
+                MB_OKCANCEL | MB_ICONQUESTION | MB_TOPMOST,
+            )
+        };
+        answer == IDOK
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        println!("❓ [{}] {}", title, message);
+        true
+    }
+}
@@ -3,23 +3,44 @@
 //! Implements the system tray icon and menu with proper Windows message loop.
 
 use anyhow::Result;
+use std::cell::RefCell;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 use tray_icon::{
-    menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem},
-    TrayIconBuilder,
+    menu::{CheckMenuItem, Menu, MenuEvent, MenuId, MenuItem, PredefinedMenuItem, Submenu},
+    TrayIconBuilder, TrayIconEvent,
 };
 
-use crate::business::{HotkeyManager, VoiceController};
-use crate::data::AppConfig;
-use crate::ui::{ButtonState, FloatingButton, FloatingButtonConfig, FloatingButtonEvent};
+use crate::audio::AudioCapture;
+use crate::business::{HotkeyEvent, HotkeyManager, VoiceController, VoiceControllerEvent};
+use crate::data::{AppConfig, HotkeyConfig};
+use crate::ui::{confirm, notify, ButtonState, FloatingButton, FloatingButtonConfig, FloatingButtonEvent};
+
+/// Name shown in the tray tooltip and the settings box
+const APP_NAME: &str = "豆包语音输入";
+
+/// How often the microphone submenu is refreshed besides when the tray is clicked
+const MIC_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often config.toml is checked for hotkey changes
+const CONFIG_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Room tone recorded by "校准麦克风"
+const CALIBRATION_DURATION: Duration = Duration::from_secs(3);
+
+/// Newest results listed in the history submenu
+const HISTORY_MENU_ITEMS: usize = 5;
+
+/// Characters of a result shown in the history submenu
+const HISTORY_LABEL_CHARS: usize = 24;
 
 /// Run the application with system tray and floating button
 pub async fn run_app(
     config: AppConfig,
     voice_controller: Arc<Mutex<VoiceController>>,
-    _hotkey_manager: HotkeyManager,
+    mut hotkey_manager: HotkeyManager,
 ) -> Result<()> {
     // Create floating button
     let mut floating_button = FloatingButton::new();
@@ -47,25 +68,71 @@ pub async fn run_app(
     let start_item = MenuItem::new("开始语音输入", true, None);
     let stop_item = MenuItem::new("停止语音输入", true, None);
     let separator1 = PredefinedMenuItem::separator();
+    let language_menu = Submenu::new("识别语言", true);
+    let lang_zh_item = CheckMenuItem::new("中文 (zh-CN)", true, config.general.language == "zh-CN", None);
+    let lang_en_item = CheckMenuItem::new("English (en-US)", true, config.general.language == "en-US", None);
+    let mic_menu = Submenu::new("麦克风", true);
+    let mic_default_item = CheckMenuItem::new("系统默认", true, config.audio.device_name.is_none(), None);
+    let mic_separator = PredefinedMenuItem::separator();
+    let calibrate_item = MenuItem::new("校准麦克风", true, None);
+    let undo_item = MenuItem::new("撤销上一句", true, None);
+    let history_menu = Submenu::new("识别历史", true);
+    let history_separator = PredefinedMenuItem::separator();
+    let clear_history_item = MenuItem::new("清除历史记录", true, None);
+    let dry_run_item = CheckMenuItem::new("预览模式 (不输入文字)", true, config.text_insert.dry_run, None);
     let settings_item = MenuItem::new("设置...", true, None);
+    let reregister_item = MenuItem::new("重新注册设备", true, None);
     let separator2 = PredefinedMenuItem::separator();
     let quit_item = MenuItem::new("退出", true, None);
 
     let start_id = start_item.id().clone();
     let stop_id = stop_item.id().clone();
+    let lang_zh_id = lang_zh_item.id().clone();
+    let lang_en_id = lang_en_item.id().clone();
+    let mic_default_id = mic_default_item.id().clone();
+    let calibrate_id = calibrate_item.id().clone();
+    let undo_id = undo_item.id().clone();
+    let clear_history_id = clear_history_item.id().clone();
+    let dry_run_id = dry_run_item.id().clone();
     let settings_id = settings_item.id().clone();
+    let reregister_id = reregister_item.id().clone();
     let quit_id = quit_item.id().clone();
 
     menu.append(&start_item)?;
     menu.append(&stop_item)?;
+    menu.append(&undo_item)?;
+    history_menu.append(&history_separator)?;
+    history_menu.append(&clear_history_item)?;
+    menu.append(&history_menu)?;
+    menu.append(&dry_run_item)?;
     menu.append(&separator1)?;
+    language_menu.append(&lang_zh_item)?;
+    language_menu.append(&lang_en_item)?;
+    menu.append(&language_menu)?;
+    mic_menu.append(&mic_default_item)?;
+    mic_menu.append(&mic_separator)?;
+    menu.append(&mic_menu)?;
+    menu.append(&calibrate_item)?;
     menu.append(&settings_item)?;
+    menu.append(&reregister_item)?;
     menu.append(&separator2)?;
     menu.append(&quit_item)?;
 
-    let _tray_icon = TrayIconBuilder::new()
+    // Tray tooltip; the credential status is shown on the last line
+    let clipboard_only = config.text_insert.is_clipboard_only();
+    let tray_tooltip = |hotkeys: &HotkeyConfig| {
+        let tooltip = format!("{} - {}", APP_NAME, hotkey_hint(hotkeys));
+        if clipboard_only {
+            format!("{}\n仅复制到剪贴板", tooltip)
+        } else {
+            tooltip
+        }
+    };
+    let tooltip = std::cell::RefCell::new(tray_tooltip(&config.hotkey));
+    let shown_status = std::cell::Cell::new("凭据验证中");
+    let tray_icon = TrayIconBuilder::new()
         .with_menu(Box::new(menu))
-        .with_tooltip("豆包语音输入 - 双击Ctrl开始/停止")
+        .with_tooltip(format!("{}\n{}", tooltip.borrow(), shown_status.get()))
         .with_icon(icon)
         .build()?;
 
@@ -74,8 +141,116 @@ pub async fn run_app(
     // Running flag
     let running = Arc::new(AtomicBool::new(true));
 
+    // Language picked from the tray; check marks are synced on the main thread
+    let language = Arc::new(std::sync::Mutex::new(config.general.language.clone()));
+    let sync_language_checks = || {
+        let language = language.lock().unwrap();
+        lang_zh_item.set_checked(*language == "zh-CN");
+        lang_en_item.set_checked(*language == "en-US");
+    };
+
+    // Microphones: the event thread lists the devices, the main thread
+    // rebuilds the submenu and keeps its check marks on the configured one
+    let microphone = Arc::new(std::sync::Mutex::new(config.audio.device_name.clone()));
+    let listed_microphones = Arc::new(std::sync::Mutex::new(None::<Vec<String>>));
+    let microphone_ids = Arc::new(std::sync::Mutex::new(Vec::<(MenuId, String)>::new()));
+    let microphone_items = RefCell::new(Vec::<CheckMenuItem>::new());
+    let microphone_names = RefCell::new(Vec::<String>::new());
+    let sync_microphones = || {
+        if let Some(names) = listed_microphones.lock().unwrap().take() {
+            let mut items = microphone_items.borrow_mut();
+            for item in items.drain(..) {
+                let _ = mic_menu.remove(&item);
+            }
+            let mut ids = Vec::new();
+            for name in &names {
+                let item = CheckMenuItem::new(name, true, false, None);
+                if mic_menu.append(&item).is_ok() {
+                    ids.push((item.id().clone(), name.clone()));
+                    items.push(item);
+                }
+            }
+            *microphone_ids.lock().unwrap() = ids;
+            *microphone_names.borrow_mut() = names;
+        }
+        // A configured device that is gone records from the default one,
+        // but neither is checked
+        let configured = microphone.lock().unwrap();
+        let names = microphone_names.borrow();
+        let active = configured
+            .as_deref()
+            .and_then(|configured| configured_microphone(configured, &names));
+        let default_device = match configured.as_deref() {
+            Some(configured) => configured.trim().is_empty(),
+            None => true,
+        };
+        mic_default_item.set_checked(default_device);
+        for (index, item) in microphone_items.borrow().iter().enumerate() {
+            item.set_checked(active == Some(index));
+        }
+    };
+
+    // Dry run toggled from the tray; the check mark is synced on the main
+    // thread, the floating button shows a dot while it is on
+    let dry_run = Arc::new(AtomicBool::new(config.text_insert.dry_run));
+    button_state_setter.set_marker(config.text_insert.dry_run);
+    let sync_dry_run_check = || {
+        dry_run_item.set_checked(dry_run.load(Ordering::SeqCst));
+    };
+
+    // History: the event thread reads the newest results, the main thread
+    // lists them above the clear item; clicking one copies it
+    let listed_history = Arc::new(std::sync::Mutex::new(None::<Vec<String>>));
+    let history_ids = Arc::new(std::sync::Mutex::new(Vec::<(MenuId, String)>::new()));
+    let history_items = RefCell::new(Vec::<MenuItem>::new());
+    let sync_history = || {
+        let Some(texts) = listed_history.lock().unwrap().take() else {
+            return;
+        };
+        let mut items = history_items.borrow_mut();
+        for item in items.drain(..) {
+            let _ = history_menu.remove(&item);
+        }
+        let _ = history_menu.remove(&history_separator);
+        let _ = history_menu.remove(&clear_history_item);
+        let mut ids = Vec::new();
+        if texts.is_empty() {
+            let item = MenuItem::new("暂无记录", false, None);
+            if history_menu.append(&item).is_ok() {
+                items.push(item);
+            }
+        }
+        for text in texts {
+            let item = MenuItem::new(history_label(&text), true, None);
+            if history_menu.append(&item).is_ok() {
+                ids.push((item.id().clone(), text));
+                items.push(item);
+            }
+        }
+        let _ = history_menu.append(&history_separator);
+        let _ = history_menu.append(&clear_history_item);
+        *history_ids.lock().unwrap() = ids;
+    };
+
+    // Credential status for the tooltip; applied on the main thread
+    let credential_status = Arc::new(std::sync::Mutex::new(None::<&'static str>));
+    let show_tooltip = || {
+        let _ = tray_icon.set_tooltip(Some(format!("{}\n{}", tooltip.borrow(), shown_status.get())));
+    };
+    let sync_tooltip = || {
+        if let Some(status) = credential_status.lock().unwrap().take() {
+            shown_status.set(status);
+            show_tooltip();
+        }
+    };
+
     // Get menu and floating button receivers
     let menu_rx = MenuEvent::receiver();
+    let (controller_rx, mut level_rx) = {
+        let mut controller = voice_controller.lock().await;
+        controller.validate_credentials();
+        (controller.take_event_receiver(), controller.input_level())
+    };
 
     // Get tokio runtime handle for async operations
     let runtime_handle = tokio::runtime::Handle::current();
@@ -84,20 +259,46 @@ pub async fn run_app(
     let vc_for_hotkey = voice_controller.clone();
     let state_for_hotkey = button_state_setter.clone();
     let handle_for_hotkey = runtime_handle.clone();
-    _hotkey_manager.on_trigger(move || {
+    // Whether the hold key is down; a start that only gets the controller
+    // after the key came up again is dropped
+    let hold_wanted = Arc::new(AtomicBool::new(false));
+    hotkey_manager.on_trigger(move |event| {
         let vc = vc_for_hotkey.clone();
         let setter = state_for_hotkey.clone();
         let handle = handle_for_hotkey.clone();
+        let hold_wanted = hold_wanted.clone();
+        match event {
+            HotkeyEvent::Start => hold_wanted.store(true, Ordering::SeqCst),
+            HotkeyEvent::Stop | HotkeyEvent::Cancel => hold_wanted.store(false, Ordering::SeqCst),
+            HotkeyEvent::Toggle => {}
+        }
         handle.spawn(async move {
             let mut controller = vc.lock().await;
-            if controller.is_recording() {
+            let stop = match event {
+                HotkeyEvent::Toggle => controller.is_recording(),
+                HotkeyEvent::Start if !hold_wanted.load(Ordering::SeqCst) => return,
+                HotkeyEvent::Start => false,
+                HotkeyEvent::Stop => true,
+                HotkeyEvent::Cancel => {
+                    if controller.is_recording() {
+                        tracing::info!("Hotkey: cancelling voice input");
+                        controller.cancel();
+                        setter.set_state(ButtonState::Idle);
+                    }
+                    return;
+                }
+            };
+            if stop {
+                if !controller.is_recording() {
+                    return;
+                }
                 tracing::info!("Hotkey: stopping voice input");
                 setter.set_state(ButtonState::Processing);
                 if let Err(e) = controller.stop().await {
                     tracing::error!("Failed to stop voice input: {}", e);
                 }
                 setter.set_state(ButtonState::Idle);
-            } else {
+            } else if !controller.is_recording() {
                 tracing::info!("Hotkey: starting voice input");
                 if let Err(e) = controller.start().await {
                     tracing::error!("Failed to start voice input: {}", e);
@@ -108,15 +309,171 @@ pub async fn run_app(
         });
     });
 
+    let vc_for_undo = voice_controller.clone();
+    let handle_for_undo = runtime_handle.clone();
+    hotkey_manager.on_undo(move || {
+        let vc = vc_for_undo.clone();
+        handle_for_undo.spawn(async move {
+            tracing::info!("Hotkey: undoing the last result");
+            if let Err(e) = vc.lock().await.undo_last(false) {
+                tracing::error!("Failed to undo: {:#}", e);
+            }
+        });
+    });
+
+    let vc_for_reinsert = voice_controller.clone();
+    let handle_for_reinsert = runtime_handle.clone();
+    hotkey_manager.on_reinsert(move || {
+        let vc = vc_for_reinsert.clone();
+        handle_for_reinsert.spawn(async move {
+            tracing::info!("Hotkey: re-inserting the last result");
+            if let Err(e) = vc.lock().await.reinsert(0) {
+                tracing::error!("Failed to re-insert: {:#}", e);
+            }
+        });
+    });
+
+    // Hotkeys edited in config.toml: the event thread notices the change,
+    // the main thread applies it since it owns the registrations
+    let pending_hotkeys = Arc::new(std::sync::Mutex::new(None::<HotkeyConfig>));
+    // The bindings in effect, for the settings box
+    let active_hotkeys = Arc::new(std::sync::Mutex::new(config.hotkey.clone()));
+    let mut sync_hotkeys = || {
+        let Some(hotkeys) = pending_hotkeys.lock().unwrap().take() else {
+            return;
+        };
+        match hotkey_manager.reconfigure(&hotkeys) {
+            Ok(()) => {
+                *tooltip.borrow_mut() = tray_tooltip(&hotkeys);
+                show_tooltip();
+                *active_hotkeys.lock().unwrap() = hotkeys;
+                notify("快捷键已更新", "新的快捷键设置已生效");
+            }
+            Err(e) => {
+                tracing::error!("Failed to apply hotkey settings: {:#}", e);
+                notify("快捷键更新失败", &format!("{:#}, 仍使用原来的快捷键", e));
+            }
+        }
+    };
+
     // Spawn event handler thread for menu and floating button events
     let running_clone = running.clone();
+    let language_clone = language.clone();
+    let microphone_clone = microphone.clone();
+    let listed_clone = listed_microphones.clone();
+    let microphone_ids_clone = microphone_ids.clone();
+    let listed_history_clone = listed_history.clone();
+    let dry_run_clone = dry_run.clone();
+    let history_ids_clone = history_ids.clone();
+    let pending_hotkeys_clone = pending_hotkeys.clone();
+    #[cfg(target_os = "windows")]
+    let active_hotkeys_clone = active_hotkeys.clone();
+    let mut known_hotkeys = config.hotkey.clone();
+    #[cfg(target_os = "windows")]
+    let main_thread = unsafe { windows::Win32::System::Threading::GetCurrentThreadId() };
+    let tray_rx = TrayIconEvent::receiver();
+    let status_for_loop = credential_status.clone();
     let vc_clone = voice_controller.clone();
     let state_setter_clone = button_state_setter.clone();
 
     std::thread::spawn(move || {
+        let mut known_microphones: Option<Vec<String>> = None;
+        let mut next_microphone_refresh = Instant::now();
+        let mut known_history: Option<Vec<String>> = None;
+        let mut history_stale = true;
+        let mut config_modified = config_modified_time();
+        // Editors may save in several writes, so a config.toml that doesn't
+        // parse is read once more before it is reported
+        let mut retry_config = false;
+        let mut next_config_check = Instant::now() + CONFIG_CHECK_INTERVAL;
         while running_clone.load(Ordering::SeqCst) {
+            // A click on the tray icon usually opens the menu; list newly
+            // plugged microphones and recent results for it
+            let tray_clicked = tray_rx.try_recv().is_ok();
+            if tray_clicked {
+                history_stale = true;
+            }
+            // Skipped while a recording holds the controller
+            if history_stale {
+                if let Ok(controller) = vc_clone.try_lock() {
+                    history_stale = false;
+                    let texts: Vec<String> = controller
+                        .last_results(HISTORY_MENU_ITEMS)
+                        .into_iter()
+                        .map(|entry| entry.text)
+                        .collect();
+                    if known_history.as_ref() != Some(&texts) {
+                        *listed_history_clone.lock().unwrap() = Some(texts.clone());
+                        known_history = Some(texts);
+                    }
+                }
+            }
+            if tray_clicked || Instant::now() >= next_microphone_refresh {
+                next_microphone_refresh = Instant::now() + MIC_REFRESH_INTERVAL;
+                match AudioCapture::list_devices() {
+                    Ok(devices) => {
+                        let names: Vec<String> = devices.into_iter().map(|d| d.name).collect();
+                        if known_microphones.as_ref() != Some(&names) {
+                            *listed_clone.lock().unwrap() = Some(names.clone());
+                            known_microphones = Some(names);
+                        }
+                    }
+                    Err(e) => tracing::debug!("Cannot list microphones: {:#}", e),
+                }
+            }
+
+            if Instant::now() >= next_config_check {
+                next_config_check = Instant::now() + CONFIG_CHECK_INTERVAL;
+                let modified = config_modified_time();
+                if modified != config_modified || retry_config {
+                    config_modified = modified;
+                    let retrying = std::mem::take(&mut retry_config);
+                    match AppConfig::load_or_default() {
+                        Ok(reloaded) if reloaded.hotkey != known_hotkeys => {
+                            tracing::info!("Hotkey settings changed in config.toml");
+                            known_hotkeys = reloaded.hotkey.clone();
+                            *pending_hotkeys_clone.lock().unwrap() = Some(reloaded.hotkey);
+                            // Get the main thread's message loop to apply them
+                            #[cfg(target_os = "windows")]
+                            unsafe {
+                                use windows::Win32::Foundation::{LPARAM, WPARAM};
+                                use windows::Win32::UI::WindowsAndMessaging::{PostThreadMessageW, WM_NULL};
+                                let _ = PostThreadMessageW(main_thread, WM_NULL, WPARAM(0), LPARAM(0));
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(e) if !retrying => {
+                            tracing::debug!("config.toml doesn't parse yet, retrying: {:#}", e);
+                            retry_config = true;
+                        }
+                        Err(e) => {
+                            tracing::warn!("Cannot reload config.toml: {:#}", e);
+                            notify("配置文件有误", &format!("{:#}", e));
+                        }
+                    }
+                }
+            }
+
             // Check menu events
             if let Ok(event) = menu_rx.recv_timeout(std::time::Duration::from_millis(50)) {
+                // Some(None) is the default device
+                let picked_microphone = if event.id == mic_default_id {
+                    Some(None)
+                } else {
+                    microphone_ids_clone
+                        .lock()
+                        .unwrap()
+                        .iter()
+                        .find(|(id, _)| *id == event.id)
+                        .map(|(_, name)| Some(name.clone()))
+                };
+                let picked_result = history_ids_clone
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .find(|(id, _)| *id == event.id)
+                    .map(|(_, text)| text.clone());
+
                 if event.id == start_id {
                     let vc = vc_clone.clone();
                     let setter = state_setter_clone.clone();
@@ -145,16 +502,139 @@ pub async fn run_app(
                             setter.set_state(ButtonState::Idle);
                         }
                     });
+                } else if event.id == undo_id {
+                    let vc = vc_clone.clone();
+                    runtime_handle.spawn(async move {
+                        tracing::info!("Undoing the last result from menu");
+                        // The menu took the focus; go back to where the result is
+                        if let Err(e) = vc.lock().await.undo_last(true) {
+                            tracing::error!("Failed to undo: {:#}", e);
+                        }
+                    });
+                } else if let Some(text) = picked_result {
+                    let vc = vc_clone.clone();
+                    runtime_handle.spawn(async move {
+                        tracing::info!("Copying a history entry from menu");
+                        match vc.lock().await.copy_to_clipboard(&text) {
+                            Ok(()) => notify("已复制到剪贴板", &text),
+                            Err(e) => tracing::error!("Failed to copy history entry: {:#}", e),
+                        }
+                    });
+                } else if event.id == dry_run_id {
+                    let enabled = !dry_run_clone.fetch_xor(true, Ordering::SeqCst);
+                    tracing::info!("Dry run turned {} from menu", if enabled { "on" } else { "off" });
+                    state_setter_clone.set_marker(enabled);
+                    let vc = vc_clone.clone();
+                    runtime_handle.spawn(async move {
+                        if let Err(e) = vc.lock().await.set_dry_run(enabled) {
+                            tracing::error!("Failed to save dry run: {:#}", e);
+                        }
+                    });
+                } else if event.id == clear_history_id {
+                    tracing::info!("Clearing history from menu");
+                    let vc = vc_clone.clone();
+                    runtime_handle.spawn(async move {
+                        match vc.lock().await.clear_history() {
+                            Ok(()) => notify("识别历史", "历史记录已清除"),
+                            Err(e) => {
+                                tracing::error!("Failed to clear history: {:#}", e);
+                                notify("清除历史记录失败", &format!("{:#}", e));
+                            }
+                        }
+                    });
+                    history_stale = true;
+                } else if event.id == lang_zh_id || event.id == lang_en_id {
+                    let selected = if event.id == lang_zh_id { "zh-CN" } else { "en-US" };
+                    tracing::info!("Recognition language set to {} from menu", selected);
+                    if let Err(e) = save_language(selected) {
+                        tracing::error!("Failed to save language: {}", e);
+                    }
+                    *language_clone.lock().unwrap() = selected.to_string();
+                } else if let Some(device) = picked_microphone {
+                    tracing::info!(
+                        "Microphone set to {} from menu",
+                        device.as_deref().unwrap_or("the default device")
+                    );
+                    *microphone_clone.lock().unwrap() = device.clone();
+                    let vc = vc_clone.clone();
+                    runtime_handle.spawn(async move {
+                        let mut controller = vc.lock().await;
+                        if let Err(e) = controller.set_microphone(device) {
+                            tracing::error!("Failed to save microphone: {:#}", e);
+                        }
+                    });
+                } else if event.id == reregister_id {
+                    tracing::info!("Re-register device from menu");
+                    let vc = vc_clone.clone();
+                    runtime_handle.spawn(async move {
+                        let mut controller = vc.lock().await;
+                        match controller.reset_device().await {
+                            Ok(()) => notify("重新注册设备", "已注册新设备, 可以继续使用语音输入"),
+                            Err(e) => {
+                                tracing::error!("Device re-registration failed: {:#}", e);
+                                notify("重新注册设备失败", &format!("{:#}", e));
+                            }
+                        }
+                    });
+                } else if event.id == calibrate_id {
+                    tracing::info!("Calibrate microphone from menu");
+                    let vc = vc_clone.clone();
+                    runtime_handle.spawn(async move {
+                        let ready = tokio::task::spawn_blocking(|| {
+                            confirm(
+                                "校准麦克风",
+                                "点击确定后将录制 3 秒环境声音, 期间请保持安静, 不要说话",
+                            )
+                        })
+                        .await
+                        .unwrap_or(false);
+                        if !ready {
+                            return;
+                        }
+
+                        let result = vc.lock().await.calibrate_microphone(CALIBRATION_DURATION).await;
+                        let profile = match result {
+                            Ok(profile) => profile,
+                            Err(e) => {
+                                tracing::error!("Microphone calibration failed: {:#}", e);
+                                notify("麦克风校准失败", &format!("{:#}", e));
+                                return;
+                            }
+                        };
+                        let message = format!(
+                            "环境噪声: {:.1} dBFS\n峰值: {:.1} dBFS\n建议静音阈值: {:.0} dB\n\n是否保存为静音阈值?",
+                            profile.rms_floor, profile.peak, profile.recommended_threshold_db
+                        );
+                        let accepted = tokio::task::spawn_blocking(move || confirm("麦克风校准完成", &message))
+                            .await
+                            .unwrap_or(false);
+                        if accepted {
+                            let saved = vc
+                                .lock()
+                                .await
+                                .set_silence_threshold(profile.recommended_threshold_db);
+                            if let Err(e) = saved {
+                                tracing::error!("Failed to save silence threshold: {:#}", e);
+                                notify("保存静音阈值失败", &format!("{:#}", e));
+                            }
+                        }
+                    });
                 } else if event.id == settings_id {
                     tracing::info!("Settings from menu");
                     #[cfg(target_os = "windows")]
                     {
-                        use windows::core::w;
+                        use windows::core::{w, PCWSTR};
                         use windows::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_OK, MB_ICONINFORMATION};
+                        let text = format!(
+                            "{} 设置\n\n快捷键: {}\n悬浮按钮: 点击切换录音状态\n\n配置文件: %APPDATA%\\doubao-ime-win\\config.toml\n(便携模式下位于程序目录)\n修改 [hotkey] 后快捷键会自动更新, 无需重启",
+                            APP_NAME,
+                            hotkey_hint(&active_hotkeys_clone.lock().unwrap())
+                        );
+                        let text: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
                         unsafe {
                             MessageBoxW(
                                 None,
-                                w!("豆包语音输入 设置\n\n快捷键: 双击 Ctrl 开始/停止录音\n悬浮按钮: 点击切换录音状态\n\n配置文件: config.toml"),
+                                PCWSTR(text.as_ptr()),
                                 w!("设置"),
                                 MB_OK | MB_ICONINFORMATION,
                             );
@@ -170,6 +650,52 @@ pub async fn run_app(
                 }
             }
 
+            // Input level for the ring around the floating button
+            if level_rx.has_changed().unwrap_or(false) {
+                state_setter_clone.set_level(level_rx.borrow_and_update().level);
+            }
+
+            // Sessions that end on their own (length cap, server error)
+            if let Some(ref rx) = controller_rx {
+                match rx.try_recv() {
+                    Ok(VoiceControllerEvent::SessionEnded { truncated }) => {
+                        tracing::debug!("Session ended (truncated: {})", truncated);
+                        state_setter_clone.set_state(ButtonState::Idle);
+                        history_stale = true;
+                    }
+                    Ok(VoiceControllerEvent::AudioInterrupted | VoiceControllerEvent::NoInput) => {
+                        state_setter_clone.set_state(ButtonState::Error);
+                    }
+                    Ok(VoiceControllerEvent::AudioRecovered) => {
+                        state_setter_clone.set_state(ButtonState::Recording);
+                    }
+                    Ok(event @ (VoiceControllerEvent::SilenceDetected | VoiceControllerEvent::StopRequested)) => {
+                        let vc = vc_clone.clone();
+                        let setter = state_setter_clone.clone();
+                        runtime_handle.spawn(async move {
+                            let mut controller = vc.lock().await;
+                            if controller.is_recording() {
+                                if matches!(event, VoiceControllerEvent::StopRequested) {
+                                    tracing::info!("Stopping on voice command");
+                                } else {
+                                    tracing::info!("Stopping after silence");
+                                }
+                                setter.set_state(ButtonState::Processing);
+                                if let Err(e) = controller.stop().await {
+                                    tracing::error!("Failed to stop: {}", e);
+                                }
+                                setter.set_state(ButtonState::Idle);
+                            }
+                        });
+                    }
+                    Ok(VoiceControllerEvent::CredentialsChecked { valid }) => {
+                        *status_for_loop.lock().unwrap() =
+                            Some(if valid { "凭据已验证" } else { "凭据验证失败, 录音时将重试" });
+                    }
+                    Err(_) => {}
+                }
+            }
+
             // Check floating button events
             if let Some(ref rx) = floating_rx {
                 if let Ok(event) = rx.try_recv() {
@@ -196,6 +722,21 @@ pub async fn run_app(
                                 }
                             });
                         }
+                        FloatingButtonEvent::TogglePause => {
+                            let vc = vc_clone.clone();
+                            let setter = state_setter_clone.clone();
+                            runtime_handle.spawn(async move {
+                                let controller = vc.lock().await;
+                                if controller.is_recording() {
+                                    let paused = controller.toggle_pause();
+                                    setter.set_state(if paused {
+                                        ButtonState::Paused
+                                    } else {
+                                        ButtonState::Recording
+                                    });
+                                }
+                            });
+                        }
                         FloatingButtonEvent::Exit => {
                             tracing::info!("Exit from floating button");
                             running_clone.store(false, Ordering::SeqCst);
@@ -223,6 +764,12 @@ pub async fn run_app(
             while GetMessageW(&mut msg, None, 0, 0).as_bool() {
                 let _ = TranslateMessage(&msg);
                 DispatchMessageW(&msg);
+                sync_language_checks();
+                sync_dry_run_check();
+                sync_microphones();
+                sync_history();
+                sync_tooltip();
+                sync_hotkeys();
 
                 if !running.load(Ordering::SeqCst) {
                     break;
@@ -234,6 +781,12 @@ pub async fn run_app(
     #[cfg(not(target_os = "windows"))]
     {
         while running.load(Ordering::SeqCst) {
+            sync_language_checks();
+            sync_dry_run_check();
+            sync_microphones();
+            sync_history();
+            sync_tooltip();
+            sync_hotkeys();
             tokio::time::sleep(std::time::Duration::from_millis(100)).await;
         }
     }
@@ -242,8 +795,60 @@ pub async fn run_app(
     Ok(())
 }
 
+/// A history entry as listed in the menu: its start, on one line
+fn history_label(text: &str) -> String {
+    let line: String = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match line.char_indices().nth(HISTORY_LABEL_CHARS) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None => line,
+    }
+}
+
+/// Index of the device in `names` that `configured` records from: an exact
+/// name, else the first containing it, as capture picks it
+fn configured_microphone(configured: &str, names: &[String]) -> Option<usize> {
+    let needle = configured.trim().to_lowercase();
+    if needle.is_empty() {
+        return None;
+    }
+    names
+        .iter()
+        .position(|name| name.to_lowercase() == needle)
+        .or_else(|| names.iter().position(|name| name.to_lowercase().contains(&needle)))
+}
+
+/// When config.toml was last written, if it can be read
+/// How recording is started with the given `[hotkey]` settings, for the
+/// tooltip and the settings box
+fn hotkey_hint(hotkeys: &HotkeyConfig) -> String {
+    match hotkeys.mode.as_str() {
+        "combo" => format!("按 {} 开始/停止", hotkeys.combo_key),
+        "hold" => format!("按住 {} 录音", hotkeys.hold_key),
+        _ => format!("双击{}开始/停止", hotkeys.double_tap_key),
+    }
+}
+
+fn config_modified_time() -> Option<std::time::SystemTime> {
+    std::fs::metadata(AppConfig::config_path())
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+/// Persist the recognition language; the next recording picks it up
+fn save_language(language: &str) -> Result<()> {
+    let mut config = AppConfig::load_or_default()?;
+    config.general.language = language.to_string();
+    config.save()
+}
+
 /// Load the tray icon with modern appearance
 fn load_icon() -> Result<tray_icon::Icon> {
+    let (rgba, width, height) = icon_rgba();
+    Ok(tray_icon::Icon::from_rgba(rgba, width, height)?)
+}
+
+/// Pixels of the tray icon as RGBA, with its width and height
+pub(crate) fn icon_rgba() -> (Vec<u8>, u32, u32) {
     let width = 32u32;
     let height = 32u32;
     let mut rgba = Vec::with_capacity((width * height * 4) as usize);
@@ -354,6 +959,5 @@ fn load_icon() -> Result<tray_icon::Icon> {
         }
     }
 
-    let icon = tray_icon::Icon::from_rgba(rgba, width, height)?;
-    Ok(icon)
+    (rgba, width, height)
 }